@@ -0,0 +1,46 @@
+// SPDX-FileCopyrightText: 2024 Fondazione LINKS
+//
+// SPDX-License-Identifier: Apache-2.0
+
+use std::path::{Path, PathBuf};
+use eyre::{eyre, Result};
+use ethers::contract::Abigen;
+
+/// Name of the module holding the bindings of `contract`.
+pub fn module_name(contract: &str) -> String {
+    contract.to_lowercase()
+}
+
+/// Writes the bindings of `contract` to `<out_dir>/<module name>.rs`, replacing an existing file.
+/// `abi_source` is an ABI or a Hardhat artifact.
+pub fn generate(contract: &str, abi_source: &Path, out_dir: &Path) -> Result<PathBuf> {
+    let abi_source = abi_source.to_str().ok_or(eyre!("{} is not valid UTF-8", abi_source.display()))?;
+    let out_file = out_dir.join(format!("{}.rs", module_name(contract)));
+    let bindings = Abigen::new(contract, abi_source)?.generate()?.to_string();
+    std::fs::write(&out_file, without_bytecode_conversion(&bindings))?;
+    Ok(out_file)
+}
+
+/// The deployer of the bindings converts the bytecode, already `Bytes`, into `Bytes`,
+/// which clippy rejects in the crates including them.
+fn without_bytecode_conversion(bindings: &str) -> String {
+    bindings.replace("_BYTECODE.clone().into()", "_BYTECODE.clone()")
+}
+
+/// Generates the bindings of every artifact in `abi_dir`, the contract name is the file name
+/// without `.json`. Hardhat debug files (`*.dbg.json`) are skipped.
+/// Returns the generated contracts, sorted by name.
+pub fn generate_dir(abi_dir: &Path, out_dir: &Path) -> Result<Vec<String>> {
+    let mut contracts = vec![];
+    for entry in std::fs::read_dir(abi_dir)? {
+        let path = entry?.path();
+        let contract = match path.file_name().and_then(|name| name.to_str()).and_then(|name| name.strip_suffix(".json")) {
+            Some(contract) if !contract.ends_with(".dbg") => contract.to_owned(),
+            _ => continue,
+        };
+        generate(contract.as_str(), &path, out_dir)?;
+        contracts.push(contract);
+    }
+    contracts.sort();
+    Ok(contracts)
+}
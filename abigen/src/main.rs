@@ -4,20 +4,27 @@
 
 use std::path::Path;
 use eyre::Result;
-use ethers::contract::Abigen;
 use clap::Parser;
 
-/// Simple program to generate bindings for a smart contract
+/// Simple program to generate bindings for smart contracts
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Args {
     /// Path to the contract ABI JSON file to generate bindings
-    #[arg(short, long, required=true)]
-    abi_source: String,
+    #[arg(short, long, required_unless_present = "abi_dir", requires = "contract")]
+    abi_source: Option<String>,
 
     /// Contract name (expected to be CamelCase)
-    #[arg(short, long, required=true)]
-    contract: String,
+    #[arg(short, long, requires = "abi_source")]
+    contract: Option<String>,
+
+    /// Directory of ABI JSON files, bindings are generated for each of them
+    #[arg(short = 'd', long, conflicts_with = "abi_source")]
+    abi_dir: Option<String>,
+
+    /// Directory the bindings are written to
+    #[arg(short, long, default_value = "bindings")]
+    out_dir: String,
 }
 
 
@@ -25,16 +32,23 @@ fn main() -> Result<()> {
 
     // Parse command line arguments
     let args = Args::parse();
-    let abi_source = args.abi_source.as_str();
-    let contract = args.contract.as_str();
-    println!("{}",contract.to_lowercase());
-    let out_file = Path::new("../actix-server/src/contracts/").join(format!("{}.rs", contract.to_lowercase()));
-    println!("Generating bindings for contract {} from ABI file {} to {}", contract, abi_source, out_file.display());
-    if out_file.exists() {
-        std::fs::remove_file(&out_file)?;
+    let out_dir = Path::new(args.out_dir.as_str());
+    std::fs::create_dir_all(out_dir)?;
+
+    match (args.abi_dir, args.abi_source, args.contract) {
+        (Some(abi_dir), _, _) => {
+            println!("Generating bindings for the contracts in {} to {}", abi_dir, out_dir.display());
+            for contract in abigen::generate_dir(Path::new(abi_dir.as_str()), out_dir)? {
+                println!("Generated bindings for contract {}", contract);
+            }
+        },
+        (None, Some(abi_source), Some(contract)) => {
+            // Generate contract bindings using abigen
+            let out_file = abigen::generate(contract.as_str(), Path::new(abi_source.as_str()), out_dir)?;
+            println!("Generated bindings for contract {} from ABI file {} to {}", contract, abi_source, out_file.display());
+        },
+        _ => unreachable!("checked by clap"),
     }
-    // Generate contract bindings using abigen
-    Abigen::new(contract, abi_source)?.generate()?.write_to_file(out_file)?;
 
     Ok(())
 }
@@ -0,0 +1,39 @@
+// SPDX-FileCopyrightText: 2024 Fondazione LINKS
+//
+// SPDX-License-Identifier: APACHE-2.0
+
+//! Generates the bindings of the smart contracts from the artifacts in `CONTRACTS_ABI_DIR`
+//! (`../smart-contracts` by default) into `OUT_DIR`, see `src/contracts/mod.rs`.
+
+use std::fmt::Write as _;
+use std::path::PathBuf;
+
+const DEFAULT_ABI_DIR: &str = "../smart-contracts";
+/// Contracts the service interacts with
+const REQUIRED_CONTRACTS: [&str; 2] = ["Asset", "AssetFactory"];
+
+fn main() {
+    println!("cargo:rerun-if-env-changed=CONTRACTS_ABI_DIR");
+    let abi_dir = match std::env::var("CONTRACTS_ABI_DIR") {
+        Ok(abi_dir) => PathBuf::from(abi_dir),
+        Err(_) => PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(DEFAULT_ABI_DIR),
+    };
+    println!("cargo:rerun-if-changed={}", abi_dir.display());
+    let out_dir = PathBuf::from(std::env::var("OUT_DIR").expect("OUT_DIR is set by cargo"));
+
+    let contracts = abigen::generate_dir(&abi_dir, &out_dir)
+        .unwrap_or_else(|err| panic!("generating the contract bindings from {}: {err}", abi_dir.display()));
+    for contract in REQUIRED_CONTRACTS {
+        if !contracts.iter().any(|generated| generated == contract) {
+            panic!("{contract}.json is missing in {}, copy the artifacts of the smart contracts there", abi_dir.display());
+        }
+    }
+
+    // the bindings of each contract declare their own module, named after the contract
+    let mut modules = String::new();
+    for contract in contracts {
+        let bindings = out_dir.join(format!("{}.rs", abigen::module_name(contract.as_str())));
+        writeln!(modules, "include!({:?});", bindings.display().to_string()).unwrap();
+    }
+    std::fs::write(out_dir.join("contracts.rs"), modules).expect("writing the contract modules");
+}
@@ -0,0 +1,181 @@
+// SPDX-FileCopyrightText: 2024 Fondazione LINKS
+//
+// SPDX-License-Identifier: APACHE-2.0
+
+//! Maintenance of the Stronghold snapshots of the service, to be run while the service is stopped.
+//!
+//! ```text
+//! trust-admin backup <file>              encrypted backup of both snapshots and the wallet db
+//! trust-admin restore <file> [--force]   restore a backup in the paths configured in .env
+//! trust-admin verify-keys                check the key storage against the DIDs stored in Mongo
+//! trust-admin rotate-password <key-storage|wallet>
+//! ```
+//!
+//! The backup passphrase is read from `BACKUP_PASSWORD`, the new snapshot password
+//! from `NEW_STRONGHOLD_PASSWORD`.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use identity_eddsa_verifier::EdDSAJwsVerifier;
+use identity_iota::document::verifiable::JwsVerificationOptions;
+use identity_iota::iota::{IotaDID, IotaDocument, IotaIdentityClientExt};
+use identity_iota::storage::{JwkDocumentExt, JwsSignatureOptions, Storage};
+use identity_stronghold::StrongholdStorage;
+use iota_sdk::client::secret::stronghold::StrongholdSecretManager;
+use iota_sdk::client::{Client, Password};
+use trust_server::services::iota_state::MemStorage;
+use trust_server::services::key_store::ServiceJwkStorage;
+use trust_server::services::mongodb_repo::MongoRepo;
+use trust_server::services::repository::Repository;
+use trust_server::services::snapshot_backup::{create_backup, restore_backup, BackupSource};
+use trust_server::services::tenant_storage::{TenantKeyStorage, DEFAULT_TENANT_STORAGE_DIR};
+
+const USAGE: &str = "usage: trust-admin <backup <file> | restore <file> [--force] | verify-keys | rotate-password <key-storage|wallet>>";
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    dotenv::from_path(".env").expect(".env file not found");
+    dotenv::from_path(".mongo.env").expect(".mongo.env file not found");
+    env_logger::init();
+
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.iter().map(String::as_str).collect::<Vec<_>>().as_slice() {
+        ["backup", file] => backup(Path::new(file)),
+        ["restore", file] => restore(Path::new(file), false),
+        ["restore", file, "--force"] => restore(Path::new(file), true),
+        ["verify-keys"] => verify_keys().await,
+        ["rotate-password", snapshot] => rotate_password(snapshot).await,
+        _ => bail!(USAGE),
+    }
+}
+
+fn env(name: &str) -> Result<String> {
+    std::env::var(name).with_context(|| format!("${name} must be set."))
+}
+
+fn backup_sources() -> Result<Vec<BackupSource>> {
+    let mut sources = vec![
+        BackupSource { name: "key-storage".to_owned(), path: PathBuf::from(env("KEY_STORAGE_STRONGHOLD_SNAPSHOT_PATH")?) },
+        BackupSource { name: "wallet".to_owned(), path: PathBuf::from(env("STRONGHOLD_SNAPSHOT_PATH")?) },
+        BackupSource { name: "wallet-db".to_owned(), path: PathBuf::from(env("WALLET_DB_PATH")?) },
+    ];
+    let tenants = PathBuf::from(std::env::var("TENANT_STORAGE_DIR").unwrap_or_else(|_| DEFAULT_TENANT_STORAGE_DIR.to_owned()));
+    if tenants.is_dir() {
+        sources.push(BackupSource { name: "tenants".to_owned(), path: tenants });
+    }
+    Ok(sources)
+}
+
+fn backup(output: &Path) -> Result<()> {
+    let entries = create_backup(&backup_sources()?, env("BACKUP_PASSWORD")?.as_str(), output)?;
+    println!("Backed up {} files to {}", entries.len(), output.display());
+    Ok(())
+}
+
+fn restore(input: &Path, force: bool) -> Result<()> {
+    let restored = restore_backup(&backup_sources()?, env("BACKUP_PASSWORD")?.as_str(), input, force)?;
+    for path in restored.iter() {
+        println!("Restored {}", path);
+    }
+    println!("Run `trust-admin verify-keys` to check the restored key storage");
+    Ok(())
+}
+
+fn open_snapshot(path: &str, password: String) -> Result<StrongholdSecretManager> {
+    if !Path::new(path).exists() {
+        bail!("{path} does not exist")
+    }
+    Ok(StrongholdSecretManager::builder()
+        .password(Password::from(password))
+        .build(path)?)
+}
+
+/// Signs a test payload with the key of every DID stored in Mongo
+/// and verifies it against the published DID document.
+async fn verify_keys() -> Result<()> {
+    let stronghold = open_snapshot(
+        env("KEY_STORAGE_STRONGHOLD_SNAPSHOT_PATH")?.as_str(),
+        env("KEY_STORAGE_STRONGHOLD_PASSWORD")?
+    )?;
+    let stronghold_storage = StrongholdStorage::new(stronghold);
+    let jwk_storage = ServiceJwkStorage::from_env(&stronghold_storage)?;
+    let key_storage: MemStorage = Storage::new(jwk_storage.clone(), stronghold_storage);
+
+    let client = Client::builder().with_node(env("NODE_URL")?.as_str())?.finish().await?;
+    let mongo_repo = MongoRepo::init().await;
+
+    let mut dids = vec![];
+    for user in mongo_repo.get_users().await? {
+        if !user.is_self_custodied() {
+            dids.push((user.did, user.fragment, user.tenant_id));
+        }
+    }
+    for organisation in mongo_repo.get_organisations().await? {
+        dids.push((organisation.did, organisation.fragment, None));
+    }
+    if let Ok(issuer) = mongo_repo.get_issuer().await {
+        dids.push((issuer.did, issuer.fragment, None));
+    }
+    let tenant_storage = dids.iter().any(|(_, _, tenant_id)| tenant_id.is_some())
+        .then(TenantKeyStorage::from_env);
+
+    let mut failures = 0;
+    for (did, fragment, tenant_id) in dids.iter() {
+        let result = match (tenant_id, &tenant_storage) {
+            // opening a missing snapshot would create an empty one
+            (Some(tenant_id), Some(tenant_storage)) if !tenant_storage.snapshot_path(tenant_id).exists() => {
+                Err(anyhow::anyhow!("missing key storage of tenant {tenant_id}"))
+            },
+            (Some(tenant_id), Some(tenant_storage)) => match tenant_storage.key_storage(tenant_id, &jwk_storage).await {
+                Ok(tenant_key_storage) => verify_key(&client, &tenant_key_storage, did, fragment).await,
+                Err(err) => Err(err.into()),
+            },
+            _ => verify_key(&client, &key_storage, did, fragment).await,
+        };
+        match result {
+            Ok(()) => println!("OK      {did}#{fragment}"),
+            Err(err) => {
+                failures += 1;
+                println!("FAILED  {did}#{fragment}: {err:#}");
+            }
+        }
+    }
+    println!("{} keys checked, {} failed", dids.len(), failures);
+    if failures > 0 {
+        bail!("the key storage does not match the DIDs stored in the db")
+    }
+    Ok(())
+}
+
+async fn verify_key(client: &Client, key_storage: &MemStorage, did: &str, fragment: &str) -> Result<()> {
+    let document: IotaDocument = client.resolve_did(&IotaDID::try_from(did)?).await?;
+    let jws = document.create_jws(key_storage, fragment, b"trust-admin key check", &JwsSignatureOptions::default())
+        .await
+        .context("signing with the stored key")?;
+    document.verify_jws(jws.as_str(), None, &EdDSAJwsVerifier::default(), &JwsVerificationOptions::default())
+        .context("the stored key does not match the published method")?;
+    Ok(())
+}
+
+/// Re-encrypts a snapshot with the password in `NEW_STRONGHOLD_PASSWORD`,
+/// the old one is read from the variable used by the service.
+async fn rotate_password(snapshot: &str) -> Result<()> {
+    let (path_var, password_var) = match snapshot {
+        "key-storage" => ("KEY_STORAGE_STRONGHOLD_SNAPSHOT_PATH", "KEY_STORAGE_STRONGHOLD_PASSWORD"),
+        "wallet" => ("STRONGHOLD_SNAPSHOT_PATH", "STRONGHOLD_PASSWORD"),
+        _ => bail!(USAGE),
+    };
+    let path = env(path_var)?;
+    let new_password = env("NEW_STRONGHOLD_PASSWORD")?;
+
+    let stronghold = open_snapshot(path.as_str(), env(password_var)?)?;
+    stronghold.change_password(Password::from(new_password.clone())).await?;
+    stronghold.write_stronghold_snapshot(None).await?;
+    drop(stronghold);
+
+    // the snapshot must open with the new password before the old one is dropped
+    open_snapshot(path.as_str(), new_password)?;
+    println!("Password of {path} rotated, update ${password_var} before restarting the service");
+    Ok(())
+}
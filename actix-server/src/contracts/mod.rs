@@ -2,5 +2,5 @@
 //
 // SPDX-License-Identifier: Apache-2.0
 
-pub mod asset;
-pub mod assetfactory;
+// one module per artifact in `smart-contracts/`, generated by build.rs, and its items re-exported
+include!(concat!(env!("OUT_DIR"), "/contracts.rs"));
@@ -0,0 +1,161 @@
+// SPDX-FileCopyrightText: 2024 Fondazione LINKS
+//
+// SPDX-License-Identifier: APACHE-2.0
+
+use actix_web::{web, HttpResponse, get, post, put};
+use identity_iota::credential::Jwt;
+use identity_iota::did::CoreDID;
+use serde::Deserialize;
+
+use crate::dtos::{CredentialRequest, CredentialStatusRequest, CredentialVerificationRequest, IssuerResponse};
+use crate::errors::TrustServiceError;
+use crate::models::credential::CredentialState;
+use crate::services::authentication::Caller;
+use crate::services::credential_issuer::CredentialIssuer;
+use crate::services::did_resolver::DidResolver;
+use crate::services::iota_state::IotaState;
+use crate::services::repository::Repository;
+use crate::services::presentation_verifier::verify_credential;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct HolderQuery {
+    did: String,
+}
+
+/// Issues a JWT Verifiable Credential to a DID registered in the service.
+/// Only the self-attested templates can be requested by the holder itself.
+#[post("")]
+async fn issue_credential(
+    caller: Caller,
+    req: web::Json<CredentialRequest>,
+    iota_state: web::Data<IotaState>,
+    did_resolver: web::Data<DidResolver>,
+    credential_issuer: web::Data<CredentialIssuer>,
+    mongo_repo: web::Data<dyn Repository>
+) -> Result<HttpResponse, TrustServiceError> {
+    log::info!("controller: issue_credential");
+    let template = credential_issuer.templates().get(req.template.as_str())
+        .ok_or(TrustServiceError::CredentialTemplateNotFound(req.template.clone()))?;
+    if template.self_attested {
+        caller.require_did_or_admin(req.did.as_str())?;
+    } else {
+        caller.require_admin()?;
+    }
+
+    let user = mongo_repo.get_user(req.did.as_str()).await?;
+
+    // an asset can only be attested to its owner
+    if let Some(asset_id) = req.claims.get("assetId").and_then(|asset_id| asset_id.as_str()) {
+        if !user.assets.iter().any(|asset| asset.asset_id == asset_id) {
+            return Err(TrustServiceError::AssetIdNotFound(asset_id.to_owned()))
+        }
+    }
+
+    let credential = credential_issuer.issue(
+        &iota_state,
+        &did_resolver,
+        &mongo_repo,
+        req.did.as_str(),
+        req.template.as_str(),
+        req.claims.clone()
+    ).await?;
+    mongo_repo.store_credential(&credential).await?;
+
+    Ok(HttpResponse::Ok().json(credential))
+}
+
+#[get("")]
+async fn get_credentials_by_holder(
+    query: web::Query<HolderQuery>,
+    mongo_repo: web::Data<dyn Repository>
+) -> Result<HttpResponse, TrustServiceError> {
+    log::info!("controller: get_credentials_by_holder");
+    let credentials = mongo_repo.get_credentials_by_holder(query.did.as_str()).await?;
+    Ok(HttpResponse::Ok().json(credentials))
+}
+
+#[get("/issuer")]
+async fn get_issuer(
+    credential_issuer: web::Data<CredentialIssuer>,
+) -> Result<HttpResponse, TrustServiceError> {
+    log::info!("controller: get_issuer");
+    let response = IssuerResponse {
+        did: credential_issuer.issuer().did.clone(),
+        templates: credential_issuer.templates().clone(),
+    };
+    Ok(HttpResponse::Ok().json(response))
+}
+
+/// Revokes, suspends or reinstates the credential with the given index in the revocation bitmap.
+/// A revoked credential cannot be reinstated, a suspended one can.
+///
+/// The record is updated first, so that the verifications report the new status at once,
+/// and restored if the revocation bitmap cannot be published.
+#[put("/status/{index}")]
+async fn set_credential_status(
+    caller: Caller,
+    path: web::Path<u32>,
+    req: web::Json<CredentialStatusRequest>,
+    iota_state: web::Data<IotaState>,
+    credential_issuer: web::Data<CredentialIssuer>,
+    did_resolver: web::Data<DidResolver>,
+    mongo_repo: web::Data<dyn Repository>
+) -> Result<HttpResponse, TrustServiceError> {
+    log::info!("controller: set_credential_status");
+    caller.require_admin()?;
+    let status_index = path.into_inner();
+    let credential = mongo_repo.get_credential_by_status_index(status_index).await?;
+
+    let revoked = match (credential.status, req.status) {
+        (CredentialState::Revoked, _) => return Err(TrustServiceError::CredentialAlreadyRevoked),
+        (current, requested) if current == requested => None,
+        (CredentialState::Active, _) => Some(true),
+        (CredentialState::Suspended, CredentialState::Active) => Some(false),
+        // suspended to revoked, the bit is already set
+        (CredentialState::Suspended, _) => None,
+    };
+    mongo_repo.update_credential_status(status_index, req.status).await?;
+    if let Some(revoked) = revoked {
+        if let Err(err) = credential_issuer.set_revoked(&iota_state, status_index, revoked).await {
+            if let Err(restore_err) = mongo_repo.update_credential_status(status_index, credential.status).await {
+                log::error!("Credential {} left {:?} while its bit is unchanged: {}", status_index, req.status, restore_err);
+            }
+            return Err(err)
+        }
+        // the verifications read the updated bitmap from now on
+        did_resolver.invalidate(credential_issuer.issuer().did.as_str());
+    }
+
+    let credential = mongo_repo.get_credential_by_status_index(status_index).await?;
+    Ok(HttpResponse::Ok().json(credential))
+}
+
+/// Verifies a single JWT credential, including its revocation status.
+#[post("/verify")]
+async fn verify(
+    req: web::Json<CredentialVerificationRequest>,
+    did_resolver: web::Data<DidResolver>,
+    mongo_repo: web::Data<dyn Repository>,
+) -> Result<HttpResponse, TrustServiceError> {
+    log::info!("controller: verify_credential");
+    let holder_did = match req.holder.as_deref() {
+        Some(holder) => Some(CoreDID::parse(holder)?),
+        None => None,
+    };
+    let credential_jwt = Jwt::from(req.credential.clone());
+    let result = verify_credential(&did_resolver, mongo_repo.get_ref(), &credential_jwt, holder_did.as_ref(), 0).await;
+    Ok(HttpResponse::Ok().json(result))
+}
+
+pub fn scoped_config(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        // prefixes all resources and routes attached to it...
+        web::scope("/credentials")
+            .service(issue_credential)
+            .service(get_credentials_by_holder)
+            .service(get_issuer)
+            .service(set_credential_status)
+            .service(verify)
+    );
+}
@@ -3,39 +3,183 @@
 // SPDX-License-Identifier: APACHE-2.0
 
 use actix_web::get;
-use actix_web::{web, HttpResponse, post};
+use actix_web::http::header;
+use actix_web::{web, HttpRequest, HttpResponse, ResponseError, post};
+use identity_iota::document::CoreDocument;
+use serde::Deserialize;
 
+use crate::services::did_resolver::DidResolver;
 use crate::services::iota_state::IotaState;
-use crate::services::mongodb_repo::MongoRepo;
+use crate::services::repository::Repository;
+use crate::dtos::{AccessTokenResponse, CreateDidResponse, EvmLinkRequest};
+use crate::services::authentication::{new_access_token, Caller};
+use crate::services::evm_link;
 use crate::errors::TrustServiceError;
+use crate::models::did_resolution::{json_ld_document, DidDocumentMetadata, DidResolutionResult, Representation};
 use crate::models::user::User;
 
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TenantQuery {
+    tenant: Option<String>,
+}
+
+/// Creates a DID, with a `tenant` its key is kept in the key storage of that tenant.
+/// DIDs are added to a tenant by its DIDs or by the administrator, who also creates the tenant.
+/// The access token of the DID is returned only here.
 #[post("")] 
 async fn create_did(
+    caller: Option<Caller>,
+    query: web::Query<TenantQuery>,
     iota_state: web::Data<IotaState>, 
-    mongodb_repo: web::Data<MongoRepo>
+    mongodb_repo: web::Data<dyn Repository>
 ) -> Result<HttpResponse, TrustServiceError> {
     log::info!("controller: create_did");
 
-    let (iota_document, fragment) = iota_state.create_did().await?;
+    if let Some(tenant_id) = query.tenant.as_deref() {
+        caller.ok_or(TrustServiceError::AuthenticationError("creating a DID of a tenant requires authentication".to_owned()))?
+            .require_tenant(&mongodb_repo, tenant_id).await?;
+    }
+
+    let (iota_document, fragment) = iota_state.create_did_for(query.tenant.as_deref()).await?;
     log::info!("{:#}", iota_document);
     
-    let user = User { did: iota_document.id().to_string(), fragment: fragment, assets: vec![] };
+    let (access_token, access_token_hash) = new_access_token()?;
+    let user = User {
+        did: iota_document.id().to_string(),
+        fragment: fragment.clone(),
+        assets: vec![],
+        evm_account: None,
+        tenant_id: query.tenant.clone(),
+        access_token_hash: Some(access_token_hash),
+    };
     mongodb_repo.store_user(user).await?;
-    
-    Ok(HttpResponse::Ok().body(iota_document.id().to_string()))
+
+    let did = iota_document.id().to_string();
+    let response = CreateDidResponse {
+        access_token,
+        verification_method: format!("{did}#{fragment}"),
+        did,
+        fragment,
+        did_document_metadata: DidDocumentMetadata {
+            created: iota_document.metadata.created,
+            updated: iota_document.metadata.updated,
+            deactivated: iota_document.metadata.deactivated,
+            alias_output_id: None,
+        },
+        did_document: CoreDocument::from(iota_document),
+    };
+    Ok(HttpResponse::Ok().json(response))
 }
 
+/// Resolves a DID honouring the `Accept` header: `application/did+json` (default),
+/// `application/did+ld+json` or `application/did-resolution` for the full resolution result.
 #[get("/{did}")]
 async fn get_did_doc(
+    req: HttpRequest,
     path: web::Path<String>,
-    iota_state: web::Data<IotaState>, 
+    did_resolver: web::Data<DidResolver>, 
 ) -> Result<HttpResponse, TrustServiceError> {
     log::info!("controller: get_did_doc");
 
     let did = path.into_inner();    
-    let iota_document = iota_state.resolve_did(did.as_str()).await?; 
-    Ok(HttpResponse::Ok().body(iota_document.to_string()))
+    let accept = req.headers().get(header::ACCEPT).and_then(|accept| accept.to_str().ok());
+    let representation = match Representation::negotiate(accept) {
+        Some(representation) => representation,
+        None => return Ok(HttpResponse::NotAcceptable().body("representationNotSupported")),
+    };
+
+    match representation {
+        Representation::DidJson => {
+            let did_document = did_resolver.resolve(did.as_str()).await?; 
+            Ok(HttpResponse::Ok().content_type(representation.content_type()).body(serde_json::to_string(&did_document)?))
+        },
+        Representation::DidLdJson => {
+            let did_document = did_resolver.resolve(did.as_str()).await?; 
+            Ok(HttpResponse::Ok().content_type(representation.content_type()).body(json_ld_document(&did_document)?.to_string()))
+        },
+        Representation::DidResolution => {
+            // errors are reported inside the resolution metadata
+            let resolution_result = match did_resolver.resolve_with_metadata(did.as_str()).await {
+                Ok((did_document, metadata)) => DidResolutionResult::new(&did_document, metadata)?,
+                Err(err) => {
+                    log::info!("Resolution error: {}", err);
+                    let status = match err {
+                        TrustServiceError::ResolveError(_) | TrustServiceError::DidResolutionError(_) => actix_web::http::StatusCode::NOT_FOUND,
+                        _ => err.status_code(),
+                    };
+                    return Ok(HttpResponse::build(status)
+                        .content_type(representation.content_type())
+                        .body(serde_json::to_string(&DidResolutionResult::from_error(&err))?))
+                }
+            };
+            Ok(HttpResponse::Ok().content_type(representation.content_type()).body(serde_json::to_string(&resolution_result)?))
+        },
+    }
+}
+
+/// Links an EVM account to the DID, NFTs minted for its assets are transferred to the account.
+/// Only the DID itself can link an account.
+#[post("/{did}/evm-account")]
+async fn link_evm_account(
+    caller: Caller,
+    path: web::Path<String>,
+    req: web::Json<EvmLinkRequest>,
+    iota_state: web::Data<IotaState>,
+    did_resolver: web::Data<DidResolver>,
+    mongodb_repo: web::Data<dyn Repository>,
+) -> Result<HttpResponse, TrustServiceError> {
+    log::info!("controller: link_evm_account");
+
+    let did = path.into_inner();
+    caller.require_did(did.as_str())?;
+    let evm_account = evm_link::link_evm_account(
+        &iota_state,
+        &did_resolver,
+        &mongodb_repo,
+        did.as_str(),
+        &req
+    ).await?;
+    Ok(HttpResponse::Ok().json(evm_account))
+}
+
+/// Returns the EVM account linked to the DID, with the nonce its next signed NFT request must use.
+#[get("/{did}/evm-account")]
+async fn get_evm_account(
+    path: web::Path<String>,
+    mongodb_repo: web::Data<dyn Repository>,
+) -> Result<HttpResponse, TrustServiceError> {
+    log::info!("controller: get_evm_account");
+
+    let did = path.into_inner();
+    let evm_account = mongodb_repo.get_user(did.as_str()).await?.evm_account
+        .ok_or(TrustServiceError::EvmAccountNotLinked(did))?;
+    Ok(HttpResponse::Ok().json(evm_account))
+}
+
+/// Issues a new access token for a registered DID, the previous one stops working.
+#[post("/{did}/access-token")]
+async fn renew_access_token(
+    caller: Caller,
+    path: web::Path<String>,
+    mongodb_repo: web::Data<dyn Repository>,
+) -> Result<HttpResponse, TrustServiceError> {
+    log::info!("controller: renew_access_token");
+
+    let did = path.into_inner();
+    caller.require_did_or_admin(did.as_str())?;
+    let (access_token, access_token_hash) = new_access_token()?;
+    mongodb_repo.store_access_token(did.as_str(), access_token_hash).await?;
+    Ok(HttpResponse::Ok().json(AccessTokenResponse { did, access_token }))
+}
+
+/// Exposes the DID document cache statistics for monitoring.
+#[get("/cache/stats")]
+async fn get_did_cache_stats(
+    did_resolver: web::Data<DidResolver>,
+) -> Result<HttpResponse, TrustServiceError> {
+    log::info!("controller: get_did_cache_stats");
+    Ok(HttpResponse::Ok().json(did_resolver.cache_stats()))
 }
 
 // this function could be located in a different module
@@ -44,6 +188,10 @@ pub fn scoped_config(cfg: &mut web::ServiceConfig) {
         // prefixes all resources and routes attached to it...
         web::scope("/dids")
         .service(create_did)
+        .service(get_did_cache_stats)
+        .service(link_evm_account)
+        .service(get_evm_account)
+        .service(renew_access_token)
         .service(get_did_doc)            
     );
 }
\ No newline at end of file
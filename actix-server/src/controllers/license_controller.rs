@@ -0,0 +1,65 @@
+// SPDX-FileCopyrightText: 2024 Fondazione LINKS
+//
+// SPDX-License-Identifier: APACHE-2.0
+
+use actix_web::{web, HttpResponse, get, post};
+use serde::Deserialize;
+
+use crate::dtos::LicenseRequest;
+use crate::errors::TrustServiceError;
+use crate::models::license::LicenseSource;
+use crate::services::authentication::Caller;
+use crate::services::license_registry::LicenseRegistry;
+use crate::services::repository::Repository;
+
+#[derive(Deserialize)]
+struct LicenseQuery {
+    /// Only SPDX or only custom licenses
+    source: Option<LicenseSource>,
+}
+
+/// Lists the licenses NFTs can be minted with.
+#[get("")]
+async fn list_licenses(
+    query: web::Query<LicenseQuery>,
+    license_registry: web::Data<LicenseRegistry>,
+    mongo_repo: web::Data<dyn Repository>
+) -> Result<HttpResponse, TrustServiceError> {
+    log::info!("controller: list_licenses");
+    let licenses = license_registry.list(&mongo_repo, query.source).await?;
+    Ok(HttpResponse::Ok().json(licenses))
+}
+
+#[get("/{license_id}")]
+async fn get_license(
+    path: web::Path<String>,
+    license_registry: web::Data<LicenseRegistry>,
+    mongo_repo: web::Data<dyn Repository>
+) -> Result<HttpResponse, TrustServiceError> {
+    log::info!("controller: get_license");
+    let license = license_registry.resolve(&mongo_repo, path.into_inner().as_str()).await?;
+    Ok(HttpResponse::Ok().json(license))
+}
+
+/// Registers a custom license, identified by a `LicenseRef-` identifier.
+/// Licenses are registered by the administrator or by the DIDs registered with the service.
+#[post("")]
+async fn register_license(
+    caller: Caller,
+    req: web::Json<LicenseRequest>,
+    license_registry: web::Data<LicenseRegistry>,
+    mongo_repo: web::Data<dyn Repository>
+) -> Result<HttpResponse, TrustServiceError> {
+    log::info!("controller: register_license");
+    let license = license_registry.register(&mongo_repo, &caller, req.into_inner()).await?;
+    Ok(HttpResponse::Ok().json(license))
+}
+
+pub fn scoped_config(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/licenses")
+            .service(list_licenses)
+            .service(register_license)
+            .service(get_license)
+    );
+}
@@ -9,7 +9,8 @@ use actix_web::{web, HttpResponse, Error};
 use ipfs_api_backend_actix::{IpfsClient, TryFromUri};
 use crate::errors::TrustServiceError;
 use crate::models::log_model::Log;
-use crate::services::mongodb_repo::MongoRepo;
+use crate::services::audit_log::AuditLog;
+use crate::services::repository::Repository;
 use crate::services::ipfs::IpfsService;
 
 /// This function, before publishing the log file, 
@@ -19,7 +20,7 @@ use crate::services::ipfs::IpfsService;
 /// and publishes it to IPFS.
 /// It receives the CID from IPFS, and stores the new CID in the DB.
 /// When storing the CID it updates the document in the DB with the same file name or create it.
-pub async fn publish_log_internal(mongodb_repo: &MongoRepo) -> Result<(), TrustServiceError> {
+pub async fn publish_log_internal(mongodb_repo: &dyn Repository) -> Result<(), TrustServiceError> {
 
     let ipfs_client = IpfsService::new();
     
@@ -60,7 +61,7 @@ pub async fn publish_log_internal(mongodb_repo: &MongoRepo) -> Result<(), TrustS
 /// This API call the publish_log_internal to push the log to IPFS
 /*
 #[post("")]
-async fn publish_log(mongodb_repo: web::Data<MongoRepo>) -> Result<HttpResponse, TrustServiceError> {
+async fn publish_log(mongodb_repo: web::Data<dyn Repository>) -> Result<HttpResponse, TrustServiceError> {
     publish_log_internal(&mongodb_repo).await?;
     Ok(HttpResponse::Ok().body("File uploaded successfully"))
 }
@@ -71,7 +72,7 @@ async fn publish_log(mongodb_repo: web::Data<MongoRepo>) -> Result<HttpResponse,
 /// The document that contains the CID has a fixed field called name.
 /// So having that name allows to find the CID
 #[get("")]
-async fn get_log(mongodb_repo: web::Data<MongoRepo>) -> Result<HttpResponse, Error> {
+async fn get_log(mongodb_repo: web::Data<dyn Repository>) -> Result<HttpResponse, Error> {
 
     // get the CID from the DB
     let cid = mongodb_repo.get_log_cid().await?;
@@ -89,11 +90,26 @@ async fn get_log(mongodb_repo: web::Data<MongoRepo>) -> Result<HttpResponse, Err
     }
 }
 
+/// Checks that no entry of the audit log was modified, removed or reordered
+/// and that the checkpoints are signed by the service DID.
+/// A broken log is reported with 500 Internal Server Error.
+#[get("/verify")]
+async fn verify_log(audit_log: web::Data<AuditLog>) -> Result<HttpResponse, TrustServiceError> {
+    log::info!("controller: verify_log");
+    let report = audit_log.verify().await?;
+    if !report.valid {
+        log::error!("Audit log verification failed: {}", report.failures.join("; "));
+        return Ok(HttpResponse::InternalServerError().json(report))
+    }
+    Ok(HttpResponse::Ok().json(report))
+}
+
 pub fn scoped_config(cfg: &mut web::ServiceConfig) {
     cfg.service(
         // prefixes all resources and routes attached to it...
         web::scope("/log")
         //    .service(publish_log)
             .service(get_log)
+            .service(verify_log)
     );
 }
\ No newline at end of file
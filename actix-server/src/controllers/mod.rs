@@ -6,6 +6,11 @@ pub mod proof_controller;
 pub mod did_controller;
 pub mod nft_controller;
 pub mod log_controller;
+pub mod credential_controller;
+pub mod presentation_controller;
+pub mod organisation_controller;
+pub mod tenant_controller;
+pub mod license_controller;
 
 use serde::Deserialize;
 
@@ -2,94 +2,597 @@
 //
 // SPDX-License-Identifier: APACHE-2.0
 
-use std::sync::Arc;
+use std::future::IntoFuture;
 
 use actix_web::get;
-use actix_web::{web, HttpResponse, post};
-use ethers::abi::RawLog;
-use ethers::contract::EthEvent;
-use ethers::middleware::SignerMiddleware;
-use ethers::providers::Provider;
-use ethers::signers::Wallet;
-use ethers::types::Address;
+use actix_web::{web, HttpRequest, HttpResponse, post};
+use ethers::types::{Address, U256};
+use futures_util::{stream, StreamExt, TryStreamExt};
+use identity_iota::core::Timestamp;
+use serde::Deserialize;
 
 use crate::contracts::asset::Asset;
-use crate::contracts::assetfactory::{AssetData, AssetFactory, NftMintedFilter};
+use crate::contracts::assetfactory::{AssetData, AssetFactory};
 use crate::controllers::AssetQuery;
-use crate::dtos::{NftRequest, NftResponse};
-use crate::services::mongodb_repo::MongoRepo;
+use crate::models::asset::Asset as AssetRecord;
+use crate::models::signed_request::SignedRequest;
+use crate::dtos::{NftApprovalRequest, NftAuthorization, NftDetailsResponse, NftMetadataRequest, NftMetadataResponse, NftListError, NftMintResponse, NftOwnerResponse, NftPage, NftRequest, NftResponse, NftTransactionResponse, NftTransferRequest};
+use crate::services::audit_log::AuditLog;
+use crate::services::authentication::Caller;
+use crate::services::did_resolver::DidResolver;
+use crate::services::evm_networks::{EvmNetwork, EvmNetworks};
+use crate::services::iota_state::{IotaState, ProofResolver};
+use crate::services::license_registry::LicenseRegistry;
+use crate::services::repository::Repository;
+use crate::services::nft_authorization::{self, NftRequestData};
+use crate::services::nft_integrity;
+use crate::services::nft_metadata::{self, MetadataPublisher};
+use crate::services::proof_revocation;
+use crate::services::nft_service::{self, MintedNft, NftContractData, NftHolder, NftOperation};
 use crate::errors::TrustServiceError;
 
+const DEFAULT_PAGE_SIZE: u32 = 20;
+const MAX_PAGE_SIZE: u32 = 100;
+/// NFTs of a page read from the chain at the same time
+const LIST_CONCURRENCY: usize = 8;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct NftListQuery {
+    /// NFTs minted for the assets of a DID
+    did: Option<String>,
+    /// NFTs minted by an account (`getCreatorMintedNfts`)
+    creator: Option<String>,
+    /// Starting from 1
+    page: Option<u32>,
+    page_size: Option<u32>,
+    /// The default network when missing
+    network: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct NetworkQuery {
+    network: Option<String>,
+}
+
+/// Mints the NFT of an asset owned by `did`, the request being authenticated as `did`,
+/// once its proof is verified on the Tangle.
+/// A request signed with EIP-712 by the account linked to `did` is kept as evidence.
+///
+/// Every check that does not need the NFT runs before the transaction. Once the NFT is minted
+/// the request succeeds with `201`, returning the integrity report of the new contract.
 #[post("/nfts")] 
 async fn mint_nft(
+    caller: Caller,
     req: web::Json<NftRequest>, 
-    mongodb_repo: web::Data<MongoRepo>,
-    signer_data: web::Data<Arc<SignerMiddleware<Provider<ethers::providers::Http>, Wallet<ethers::core::k256::ecdsa::SigningKey>>>>,
+    proof_resolver: web::Data<dyn ProofResolver>,
+    did_resolver: web::Data<DidResolver>,
+    mongodb_repo: web::Data<dyn Repository>,
+    evm_networks: web::Data<EvmNetworks>,
+    license_registry: web::Data<LicenseRegistry>,
+    metadata_publisher: web::Data<MetadataPublisher>,
 ) -> Result<HttpResponse, TrustServiceError> {
     log::info!("controller: mint_nft");
-    // TODO: check if the did/user is the owner of the asset (assetId)
-    let asset = mongodb_repo.get_asset(req.asset_id.clone()).await?;
+    caller.require_did(req.did.as_str())?;
+    let license = license_registry.validate(&mongodb_repo, req.license.as_str()).await?;
+    let asset = nft_integrity::validate_mint(&proof_resolver, &did_resolver, &mongodb_repo, req.asset_id.as_str(), req.did.as_str()).await?;
 
-    let address: Address = std::env::var("ASSET_FACTORY_ADDR").expect("$ASSET_FACTORY_ADDR must be set.").parse().map_err(|_| TrustServiceError::ContractAddressRecoveryError)?;
-    let signer = signer_data.get_ref().clone();
-    let asset_factory_sc = AssetFactory::new(address, signer);
+    let network = evm_networks.get(req.network.as_deref())?;
+    let signed_request = match req.authorization.as_ref() {
+        Some(authorization) => {
+            let request = NftRequestData::Mint {
+                did: req.did.as_str(),
+                asset_id: req.asset_id.as_str(),
+                nft_alias: req.nft_alias.as_str(),
+                nft_symbol: req.nft_symbol.as_str(),
+                license: req.license.as_str(),
+            };
+            let domain = nft_authorization::domain(network.chain_id, network.factory_address);
+            Some(nft_authorization::verify(&mongodb_repo, &domain, &request, authorization).await?)
+        },
+        None => None,
+    };
+    // account the NFT is handed over to once minted
+    let owner_address: Option<Address> = match mongodb_repo.get_user(req.did.as_str()).await?.evm_account {
+        Some(evm_account) => Some(evm_account.address.parse().map_err(|_| TrustServiceError::AddressError)?),
+        None => None,
+    };
+    let signer = network.signer.clone();
+    let asset_factory_sc = AssetFactory::new(network.factory_address, signer.clone());
     let asset_data = AssetData { 
         name: req.nft_alias.clone(), 
         symbol: req.nft_symbol.clone(),
         proof_id: asset.proof_id.clone(), 
         did: req.did.clone(), 
         asset_id: req.asset_id.clone(),
-        license: req.license.clone()
+        // the identifier as written in the registry
+        license: license.license_id
     };
     let call = asset_factory_sc.tokenize(asset_data);
-    let pending_tx = call.send().await.map_err(|err| TrustServiceError::ContractError(err.to_string()))?;
-
-    // awaiting on the pending transaction resolves to a transaction receipt
-    let receipt = pending_tx.confirmations(1).await.map_err(|err| TrustServiceError::ContractError(err.to_string()))?;
-    let logs = receipt.ok_or(TrustServiceError::CustomError("No receipt".to_owned()))?.logs;
-
-    // reading the log   
-    for log in logs.iter() {
-        let raw_log = RawLog {
-            topics: log.topics.clone(),
-            data: log.data.to_vec(),
-        };
-        // finding the event
-        if let Ok(event) =  <NftMintedFilter as EthEvent>::decode_log(&raw_log){
-            log::info!("Nft address: {:#x}", event.istance_address);
-            // storing the address
-            mongodb_repo.store_nft_addr(req.asset_id.clone(), format!("{:#x}", event.istance_address)).await?;
-            return Ok(HttpResponse::Ok().finish());
+
+    // concurrent mints of the asset all pass the checks above, only the one reserving the asset is sent
+    let minting = async {
+        if !mongodb_repo.reserve_nft_mint(req.asset_id.as_str(), Timestamp::now_utc().to_rfc3339()).await? {
+            return Err(TrustServiceError::NftMintInProgress(req.asset_id.clone()))
+        }
+        // the manager resolves to the receipt once it has the configured confirmations
+        let sent = network.tx_manager.send("tokenize", call.tx).await;
+        // a transaction that can still be mined keeps the asset reserved until the indexer records its NFT
+        if let Err(err) = sent.as_ref() {
+            if !nft_authorization::may_be_mined(err) {
+                log::warn!("Mint of asset {} failed, its reservation is released: {}", req.asset_id, err);
+                mongodb_repo.release_nft_mint(req.asset_id.as_str()).await?;
+            }
         }
+        sent
+    };
+    let receipt = nft_authorization::relay(&mongodb_repo, req.did.as_str(), signed_request.as_ref(), minting).await?;
+    let MintedNft { nft_address, token: minted_token } = nft_service::decode_mint_logs(&receipt.logs)?;
+    log::info!("Nft address: {:#x} on network {}", nft_address, network.name);
+    // storing the address
+    let mint_block = receipt.block_number.map(|block_number| block_number.as_u64());
+    mongodb_repo.store_nft_addr(req.asset_id.clone(), format!("{:#x}", nft_address), network.name.as_str(), mint_block).await?;
+    if let Some(mut signed_request) = signed_request {
+        signed_request.transaction_hash = Some(format!("{:#x}", receipt.transaction_hash));
+        mongodb_repo.store_signed_request(req.asset_id.as_str(), signed_request).await?;
     }
-    Err(TrustServiceError::CustomError("no NftMinted event found in the receipt".to_owned()))
+
+    let mut holder = None;
+    if let Some((token_id, minted_to)) = minted_token {
+        // recorded before the handover, which can fail on its own
+        mongodb_repo.store_nft_ownership(req.asset_id.as_str(), token_id.to_string(), format!("{:#x}", minted_to)).await?;
+        holder = Some(minted_to);
+        // the NFT is minted to the service, it is handed over to the account linked to the owner DID
+        if let Some(user_address) = owner_address.filter(|_| minted_to == signer.address()) {
+            log::info!("Transferring token {} to {:#x}...", token_id, user_address);
+            let asset_sc = Asset::new(nft_address, signer.clone());
+            // the owner can ask for the transfer again, the mint stands
+            match network.tx_manager.send("nftTransfer", asset_sc.transfer_from(minted_to, user_address, token_id).tx).await {
+                Ok(_) => {
+                    mongodb_repo.store_nft_ownership(req.asset_id.as_str(), token_id.to_string(), format!("{:#x}", user_address)).await?;
+                    holder = Some(user_address);
+                },
+                Err(err) => log::error!("Failed to hand NFT {:#x} over to {:#x}: {}", nft_address, user_address, err),
+            }
+        }
+    }
+
+    let integrity = match nft_integrity::check_asset_integrity(&proof_resolver, &did_resolver, &mongodb_repo, &evm_networks, req.asset_id.as_str()).await {
+        Ok(report) => {
+            if !report.consistent {
+                log::error!("NFT {:#x} does not match asset {}: {}", nft_address, req.asset_id, report.failures());
+            }
+            Some(report)
+        },
+        Err(err) => {
+            log::error!("Failed to check the integrity of NFT {:#x}: {}", nft_address, err);
+            None
+        },
+    };
+
+    // the NFT is minted anyway, the metadata can be published again later
+    if let Err(err) = metadata_publisher.publish_metadata(&proof_resolver, &mongodb_repo, &evm_networks, req.asset_id.as_str()).await {
+        log::error!("Failed to publish the metadata of asset {}: {}", req.asset_id, err);
+    }
+    Ok(HttpResponse::Created().json(NftMintResponse {
+        asset_id: req.asset_id.clone(),
+        network: network.name.clone(),
+        nft_address: format!("{:#x}", nft_address),
+        transaction_hash: format!("{:#x}", receipt.transaction_hash),
+        token_id: minted_token.map(|(token_id, _)| token_id.to_string()),
+        owner_address: holder.map(|holder| format!("{:#x}", holder)),
+        integrity,
+    }))
 }
 
 #[get("/nfts")]
 async fn get_nft_by_asset(
+    req: HttpRequest,
     query: web::Query<AssetQuery>, 
-    mongodb_repo: web::Data<MongoRepo>,
-    signer_data: web::Data<Arc<SignerMiddleware<Provider<ethers::providers::Http>, Wallet<ethers::core::k256::ecdsa::SigningKey>>>>,
+    mongodb_repo: web::Data<dyn Repository>,
+    evm_networks: web::Data<EvmNetworks>,
+    license_registry: web::Data<LicenseRegistry>,
+    audit_log: web::Data<AuditLog>,
 ) -> Result<HttpResponse, TrustServiceError> {
     log::info!("controller: read_nft");
 
-    let asset = mongodb_repo.get_asset(query.asset_id.clone()).await?;
-    let signer = signer_data.get_ref().clone();
-    let nft_addr = asset.nft_addr.ok_or(TrustServiceError::MissingNftAddress)?;
-    let addr: Address =  nft_addr.as_str().parse().map_err(|_| TrustServiceError::ContractAddressRecoveryError)?;
+    let (proof_id, result) = match mongodb_repo.get_asset(query.asset_id.clone()).await {
+        Ok(asset) => (Some(asset.proof_id.clone()), nft_of_asset(&mongodb_repo, &evm_networks, &license_registry, asset).await),
+        Err(err) => (None, Err(err)),
+    };
+    audit_log.record_access(&req, Some(query.asset_id.as_str()), proof_id.as_deref(), &result).await?;
+
+    Ok(HttpResponse::Ok().json(result?))
+}
+
+/// Reads the NFT of the asset and checks that it tokenizes it.
+async fn nft_of_asset(
+    mongodb_repo: &dyn Repository,
+    evm_networks: &EvmNetworks,
+    license_registry: &LicenseRegistry,
+    asset: AssetRecord,
+) -> Result<NftResponse, TrustServiceError> {
+    let network = evm_networks.of_asset(&asset)?;
+    let signer = network.signer.clone();
+    let addr = nft_service::nft_address(&asset)?;
     log::info!("Nft address: {:#x}", addr);
     let asset_sc = Asset::new(addr, signer);
 
-    let license = asset_sc.get_license().await.map_err(|err| TrustServiceError::ContractError(err.to_string()))?;
-    let did = asset_sc.get_did().await.map_err(|err| TrustServiceError::ContractError(err.to_string()))?;
-    //TODO: add check query.asset_id == asset_sc.get_asset_id()
+    let NftContractData { license, did, asset_id: nft_asset_id } = nft_service::read_nft_data(&asset_sc).await?;
+    if nft_asset_id != asset.asset_id {
+        return Err(TrustServiceError::NftIntegrityError(format!("the NFT tokenizes asset {}", nft_asset_id)))
+    }
+
+    let license_record = license_registry.find(mongodb_repo, license.as_str()).await?;
+    let current_proof_id = proof_revocation::current_proof(mongodb_repo, &asset).await?;
+    Ok(NftResponse{
+        asset_id: asset.asset_id,
+        nft_address: format!("{:#x}", addr),
+        license,
+        license_record,
+        did,
+        network: network.name.clone(),
+        stale: asset.revocation.is_some(),
+        current_proof_id,
+        revocation: asset.revocation,
+    })
+}
+
+/// Compares the NFT of an asset with the asset record, the proof on the Tangle and
+/// the owner DID, reporting every mismatch.
+#[get("/nfts/{asset_id}/integrity")]
+async fn check_nft_integrity(
+    path: web::Path<String>,
+    proof_resolver: web::Data<dyn ProofResolver>,
+    did_resolver: web::Data<DidResolver>,
+    mongodb_repo: web::Data<dyn Repository>,
+    evm_networks: web::Data<EvmNetworks>,
+) -> Result<HttpResponse, TrustServiceError> {
+    log::info!("controller: check_nft_integrity");
 
-    let respose = NftResponse{ asset_id: query.asset_id.clone(), nft_address: nft_addr, license, did };
-    Ok(HttpResponse::Ok().json(respose))
+    let asset_id = path.into_inner();
+    let report = nft_integrity::check_asset_integrity(&proof_resolver, &did_resolver, &mongodb_repo, &evm_networks, asset_id.as_str()).await?;
+    Ok(HttpResponse::Ok().json(report))
+}
+
+/// ERC-721 metadata of the NFT of an asset, as pinned on IPFS.
+#[get("/nfts/{asset_id}/metadata")]
+async fn get_nft_metadata(
+    path: web::Path<String>,
+    mongodb_repo: web::Data<dyn Repository>,
+) -> Result<HttpResponse, TrustServiceError> {
+    log::info!("controller: get_nft_metadata");
+
+    let asset_id = path.into_inner();
+    let owner = mongodb_repo.get_user_by_asset(asset_id.as_str()).await?;
+    let asset = owner.assets.iter()
+        .find(|asset| asset.asset_id == asset_id)
+        .ok_or(TrustServiceError::AssetIdNotFound(asset_id.clone()))?;
+    let metadata = nft_metadata::read_metadata(asset).await?;
+    Ok(HttpResponse::Ok().json(metadata))
+}
+
+/// Generates the metadata of the NFT again, after the asset or its proof changed, on behalf of `did`.
+#[post("/nfts/{asset_id}/metadata")]
+async fn refresh_nft_metadata(
+    caller: Caller,
+    path: web::Path<String>,
+    req: web::Json<NftMetadataRequest>,
+    proof_resolver: web::Data<dyn ProofResolver>,
+    mongodb_repo: web::Data<dyn Repository>,
+    evm_networks: web::Data<EvmNetworks>,
+    metadata_publisher: web::Data<MetadataPublisher>,
+) -> Result<HttpResponse, TrustServiceError> {
+    log::info!("controller: refresh_nft_metadata");
+
+    let asset_id = path.into_inner();
+    caller.require_did(req.did.as_str())?;
+    if mongodb_repo.get_user_by_asset(asset_id.as_str()).await?.did != req.did {
+        return Err(TrustServiceError::AssetNotOwned(req.did.clone()))
+    }
+    let (metadata_uri, metadata) = metadata_publisher.publish_metadata(&proof_resolver, &mongodb_repo, &evm_networks, asset_id.as_str()).await?;
+    Ok(HttpResponse::Ok().json(NftMetadataResponse { asset_id, metadata_uri, metadata, token_uri_notice: nft_metadata::TOKEN_URI_NOTICE }))
+}
+
+/// Current holder of the NFT of an asset, as recorded by the contract.
+#[get("/nfts/{asset_id}/owner")]
+async fn get_nft_owner(
+    path: web::Path<String>,
+    mongodb_repo: web::Data<dyn Repository>,
+    evm_networks: web::Data<EvmNetworks>,
+) -> Result<HttpResponse, TrustServiceError> {
+    log::info!("controller: get_nft_owner");
+
+    let asset_id = path.into_inner();
+    let holder = nft_service::nft_holder(&mongodb_repo, &evm_networks, asset_id.as_str()).await?;
+    let asset_sc = Asset::new(holder.nft_address, evm_networks.get(Some(holder.network.as_str()))?.signer.clone());
+    let asset_owner = asset_sc.get_asset_owner().await.map_err(|err| TrustServiceError::ContractError(err.to_string()))?;
+    let approved = asset_sc.get_approved(holder.token_id).await.map_err(|err| TrustServiceError::ContractError(err.to_string()))?;
+
+    let response = NftOwnerResponse {
+        asset_id,
+        network: holder.network.clone(),
+        nft_address: format!("{:#x}", holder.nft_address),
+        token_id: holder.token_id.to_string(),
+        owner_address: format!("{:#x}", holder.owner_address),
+        owner_did: holder.owner_did().map(str::to_owned),
+        asset_owner: format!("{:#x}", asset_owner),
+        approved: (approved != Address::zero()).then(|| format!("{:#x}", approved)),
+    };
+    Ok(HttpResponse::Ok().json(response))
+}
+
+/// Transfers the NFT to an account or to the account linked to another DID, on behalf of `did`.
+/// A request signed with EIP-712 by the account linked to `did` is kept as evidence, it is
+/// required when the NFT is held by an account whose key the service does not keep.
+#[post("/nfts/{asset_id}/transfer")]
+async fn transfer_nft(
+    caller: Caller,
+    path: web::Path<String>,
+    req: web::Json<NftTransferRequest>,
+    iota_state: web::Data<IotaState>,
+    mongodb_repo: web::Data<dyn Repository>,
+    evm_networks: web::Data<EvmNetworks>,
+) -> Result<HttpResponse, TrustServiceError> {
+    log::info!("controller: transfer_nft");
+
+    let asset_id = path.into_inner();
+    caller.require_did(req.did.as_str())?;
+    let holder = owned_nft(&mongodb_repo, &evm_networks, asset_id.as_str(), &caller).await?;
+    let network = evm_networks.get(Some(holder.network.as_str()))?;
+    let to = nft_service::target_address(&mongodb_repo, req.to.as_deref(), req.to_did.as_deref()).await?;
+    let request = NftRequestData::Transfer { did: req.did.as_str(), asset_id: asset_id.as_str(), to };
+    let signed_request = verify_signed_request(&mongodb_repo, network, &holder, &request, req.authorization.as_ref()).await?;
+    log::info!("Transferring token {} of asset {} to {:#x}...", holder.token_id, asset_id, to);
+
+    let transfer = nft_service::execute(&iota_state, &mongodb_repo, network, asset_id.as_str(), &holder, NftOperation::Transfer { to });
+    let transaction_hash = nft_authorization::relay(&mongodb_repo, req.did.as_str(), signed_request.as_ref(), transfer).await?;
+    if let Some(mut signed_request) = signed_request {
+        signed_request.transaction_hash = Some(format!("{:#x}", transaction_hash));
+        mongodb_repo.store_signed_request(asset_id.as_str(), signed_request).await?;
+    }
+    Ok(HttpResponse::Ok().json(NftTransactionResponse { asset_id, transaction_hash: format!("{:#x}", transaction_hash) }))
+}
+
+/// Approves an operator for the NFT (`approve`) or for all the tokens of the owner (`setApprovalForAll`),
+/// on behalf of `did`. A request signed with EIP-712 by the account linked to `did` is kept as evidence,
+/// it is required when the NFT is held by an account whose key the service does not keep.
+#[post("/nfts/{asset_id}/approve")]
+async fn approve_nft(
+    caller: Caller,
+    path: web::Path<String>,
+    req: web::Json<NftApprovalRequest>,
+    iota_state: web::Data<IotaState>,
+    mongodb_repo: web::Data<dyn Repository>,
+    evm_networks: web::Data<EvmNetworks>,
+) -> Result<HttpResponse, TrustServiceError> {
+    log::info!("controller: approve_nft");
+
+    let asset_id = path.into_inner();
+    caller.require_did(req.did.as_str())?;
+    let holder = owned_nft(&mongodb_repo, &evm_networks, asset_id.as_str(), &caller).await?;
+    let network = evm_networks.get(Some(holder.network.as_str()))?;
+    let operator = nft_service::target_address(&mongodb_repo, req.operator.as_deref(), req.operator_did.as_deref()).await?;
+    let operation = match (req.all, req.approved) {
+        (true, approved) => NftOperation::ApproveAll { operator, approved },
+        (false, true) => NftOperation::Approve { to: operator },
+        // a token has a single approved address, revoking it clears the approval
+        (false, false) => NftOperation::Approve { to: Address::zero() },
+    };
+    nft_service::check_operation(network, &holder, &operation)?;
+    let request = NftRequestData::Approval { did: req.did.as_str(), asset_id: asset_id.as_str(), operator, all: req.all, approved: req.approved };
+    let signed_request = verify_signed_request(&mongodb_repo, network, &holder, &request, req.authorization.as_ref()).await?;
+
+    let approval = nft_service::execute(&iota_state, &mongodb_repo, network, asset_id.as_str(), &holder, operation);
+    let transaction_hash = nft_authorization::relay(&mongodb_repo, req.did.as_str(), signed_request.as_ref(), approval).await?;
+    if let Some(mut signed_request) = signed_request {
+        signed_request.transaction_hash = Some(format!("{:#x}", transaction_hash));
+        mongodb_repo.store_signed_request(asset_id.as_str(), signed_request).await?;
+    }
+    Ok(HttpResponse::Ok().json(NftTransactionResponse { asset_id, transaction_hash: format!("{:#x}", transaction_hash) }))
+}
+
+/// Lists the asset NFTs registered in the AssetFactory, or only those minted
+/// for a DID or by a creator account, oldest first.
+#[get("/nft-contracts")]
+async fn list_nfts(
+    query: web::Query<NftListQuery>,
+    mongodb_repo: web::Data<dyn Repository>,
+    evm_networks: web::Data<EvmNetworks>,
+    license_registry: web::Data<LicenseRegistry>,
+) -> Result<HttpResponse, TrustServiceError> {
+    log::info!("controller: list_nfts");
+
+    let network = evm_networks.get(query.network.as_deref())?;
+    let page = query.page.unwrap_or(1).max(1);
+    let page_size = query.page_size.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+    let start = (page - 1) as usize * page_size as usize;
+    let asset_factory_sc = AssetFactory::new(network.factory_address, network.signer.clone());
+
+    let (total, nft_addresses): (u64, Vec<Address>) = match (query.did.as_deref(), query.creator.as_deref()) {
+        (Some(did), None) => {
+            let mut nft_addresses = vec![];
+            for asset in mongodb_repo.get_user(did).await?.assets {
+                let on_network = evm_networks.of_asset(&asset).map_or(false, |asset_network| asset_network.name == network.name);
+                if let Some(nft_addr) = asset.nft_addr.filter(|_| on_network) {
+                    nft_addresses.push(nft_addr.parse().map_err(|_| TrustServiceError::ContractAddressRecoveryError)?);
+                }
+            }
+            (nft_addresses.len() as u64, nft_addresses.into_iter().skip(start).take(page_size as usize).collect())
+        },
+        (None, Some(creator)) => {
+            let creator: Address = creator.parse().map_err(|_| TrustServiceError::InvalidNftTarget(creator.to_owned()))?;
+            let nft_addresses = asset_factory_sc.get_creator_minted_nfts(creator).await.map_err(|err| TrustServiceError::ContractError(err.to_string()))?;
+            (nft_addresses.len() as u64, nft_addresses.into_iter().skip(start).take(page_size as usize).collect())
+        },
+        (None, None) => {
+            // the registry is read by index, only the requested page is fetched
+            let total = asset_factory_sc.get_count().await.map_err(|err| TrustServiceError::ContractError(err.to_string()))?.as_u64();
+            let asset_factory_sc = &asset_factory_sc;
+            let nft_addresses: Vec<Address> = stream::iter((start as u64..total).take(page_size as usize))
+                .map(|index| async move {
+                    asset_factory_sc.assets(U256::from(index)).call().await.map_err(|err| TrustServiceError::ContractError(err.to_string()))
+                })
+                .buffered(LIST_CONCURRENCY)
+                .try_collect()
+                .await?;
+            (total, nft_addresses)
+        },
+        (Some(_), Some(_)) => return Err(TrustServiceError::InvalidNftTarget("did and creator cannot be combined".to_owned())),
+    };
+
+    // an NFT that cannot be read is reported on its own, the rest of the page is still returned
+    let results: Vec<(Address, Result<NftDetailsResponse, TrustServiceError>)> = stream::iter(nft_addresses)
+        .map(|nft_address| {
+            let (mongodb_repo, license_registry) = (&mongodb_repo, &license_registry);
+            async move { (nft_address, nft_details(mongodb_repo, license_registry, network, nft_address, false).await) }
+        })
+        .buffered(LIST_CONCURRENCY)
+        .collect()
+        .await;
+    let (mut items, mut errors) = (vec![], vec![]);
+    for (nft_address, result) in results {
+        match result {
+            Ok(details) => items.push(details),
+            Err(err) => {
+                log::warn!("Failed to read NFT {:#x}: {}", nft_address, err);
+                errors.push(NftListError { nft_address: format!("{:#x}", nft_address), error: err.to_string() });
+            },
+        }
+    }
+    Ok(HttpResponse::Ok().json(NftPage { total, page, page_size, items, errors }))
+}
+
+/// Details of an asset NFT, read from its contract and from the asset record in the db.
+#[get("/nft-contracts/{nft_address}")]
+async fn get_nft_details(
+    path: web::Path<String>,
+    query: web::Query<NetworkQuery>,
+    mongodb_repo: web::Data<dyn Repository>,
+    evm_networks: web::Data<EvmNetworks>,
+    license_registry: web::Data<LicenseRegistry>,
+) -> Result<HttpResponse, TrustServiceError> {
+    log::info!("controller: get_nft_details");
+
+    let nft_address = path.into_inner();
+    let nft_address: Address = nft_address.parse().map_err(|_| TrustServiceError::InvalidNftTarget(nft_address))?;
+    let details = nft_details(&mongodb_repo, &license_registry, evm_networks.get(query.network.as_deref())?, nft_address, true).await?;
+    Ok(HttpResponse::Ok().json(details))
+}
+
+/// Reads the contract of an NFT, with independent calls sent together. With `lookup_token_id`
+/// a token id that was never recorded is looked up in the mint event of the contract.
+async fn nft_details(
+    mongodb_repo: &dyn Repository,
+    license_registry: &LicenseRegistry,
+    network: &EvmNetwork,
+    nft_address: Address,
+    lookup_token_id: bool
+) -> Result<NftDetailsResponse, TrustServiceError> {
+    // the same address can be taken by a contract on another network
+    let record = mongodb_repo.get_asset_by_nft_addr(format!("{:#x}", nft_address).as_str(), network.name.as_str(), network.is_default()).await?;
+    let asset_factory_sc = AssetFactory::new(network.factory_address, network.signer.clone());
+    let asset_sc = Asset::new(nft_address, network.signer.clone());
+
+    let (name, symbol, license, did, asset_id, factory_owner) = futures_util::try_join!(
+        asset_sc.name().into_future(),
+        asset_sc.symbol().into_future(),
+        asset_sc.get_license().into_future(),
+        asset_sc.get_did().into_future(),
+        asset_sc.get_asset_id().into_future(),
+        asset_factory_sc.asset_to_owner(nft_address).into_future(),
+    ).map_err(|err| TrustServiceError::ContractError(err.to_string()))?;
+    // NFTs minted before the registry can carry licenses it does not know
+    let license_record = license_registry.find(mongodb_repo, license.as_str()).await?;
+
+    // a contract whose token was never minted or was burnt has no owner nor URI,
+    // the token id is known only for the NFTs minted by the service
+    let token_id = match record.as_ref() {
+        Some((_, asset)) if lookup_token_id => nft_service::token_id(mongodb_repo, &asset_sc, asset).await.ok(),
+        Some((_, asset)) => nft_service::recorded_token_id(asset).ok().flatten(),
+        None => None,
+    };
+    let (owner_address, token_uri) = match token_id {
+        Some(token_id) => {
+            let (owner_of, token_uri) = futures_util::join!(asset_sc.owner_of(token_id).into_future(), asset_sc.token_uri(token_id).into_future());
+            (owner_of.ok(), token_uri.ok().filter(|token_uri| !token_uri.is_empty()))
+        },
+        None => (None, None),
+    };
+
+    let (asset_owner_did, asset) = match record {
+        Some((asset_owner_did, asset)) => (Some(asset_owner_did), Some(asset)),
+        None => (None, None),
+    };
+    Ok(NftDetailsResponse {
+        network: network.name.clone(),
+        nft_address: format!("{:#x}", nft_address),
+        name,
+        symbol,
+        token_id: token_id.map(|token_id| token_id.to_string()),
+        token_uri,
+        license,
+        license_record,
+        did,
+        asset_id,
+        owner_address: owner_address.map(|owner_address| format!("{:#x}", owner_address)),
+        factory_owner: format!("{:#x}", factory_owner),
+        asset_owner_did,
+        asset,
+    })
+}
+
+/// Verifies the signed request of an operation on the NFT. Without a signature the authenticated
+/// owner DID is trusted, unless the NFT is held by an account whose key the service does not keep:
+/// then only a signature of that account shows that its holder asked for the operation.
+async fn verify_signed_request(
+    mongodb_repo: &dyn Repository,
+    network: &EvmNetwork,
+    holder: &NftHolder,
+    request: &NftRequestData<'_>,
+    authorization: Option<&NftAuthorization>
+) -> Result<Option<SignedRequest>, TrustServiceError> {
+    match authorization {
+        Some(authorization) => {
+            let domain = nft_authorization::domain(network.chain_id, network.factory_address);
+            Ok(Some(nft_authorization::verify(mongodb_repo, &domain, request, authorization).await?))
+        },
+        None if nft_service::self_custodied(network, holder) => Err(TrustServiceError::InvalidSignedRequest(format!(
+            "the NFT is held by {:#x}, the request must be signed by it", holder.owner_address
+        ))),
+        None => Ok(None),
+    }
+}
+
+/// Holder of the NFT, only if the caller is the DID entitled to manage it and the NFT is not flagged.
+async fn owned_nft(
+    mongodb_repo: &dyn Repository,
+    evm_networks: &EvmNetworks,
+    asset_id: &str,
+    caller: &Caller
+) -> Result<NftHolder, TrustServiceError> {
+    let holder = nft_service::nft_holder(mongodb_repo, evm_networks, asset_id).await?;
+    match caller.did() {
+        Some(did) if holder.owner_did() == Some(did) => (),
+        _ => return Err(TrustServiceError::NotNftOwner(caller.to_string())),
+    }
+    // flagged NFTs are frozen as far as the service is concerned
+    if let Some(proof_id) = holder.revoked_proof.as_ref() {
+        return Err(TrustServiceError::ProofRevoked(proof_id.clone()))
+    }
+    Ok(holder)
 }
 
 pub fn scoped_config(cfg: &mut web::ServiceConfig) {
     cfg
     .service(mint_nft)
-    .service(get_nft_by_asset);
+    .service(get_nft_by_asset)
+    .service(list_nfts)
+    .service(get_nft_details)
+    .service(check_nft_integrity)
+    .service(get_nft_metadata)
+    .service(refresh_nft_metadata)
+    .service(get_nft_owner)
+    .service(transfer_nft)
+    .service(approve_nft);
 }
\ No newline at end of file
@@ -0,0 +1,87 @@
+// SPDX-FileCopyrightText: 2024 Fondazione LINKS
+//
+// SPDX-License-Identifier: APACHE-2.0
+
+use actix_web::{web, HttpResponse, delete, get, post};
+
+use crate::dtos::{MemberRequest, OrganisationRequest};
+use crate::errors::TrustServiceError;
+use crate::services::authentication::Caller;
+use crate::services::did_resolver::DidResolver;
+use crate::services::iota_state::IotaState;
+use crate::services::repository::Repository;
+use crate::services::organisation_service;
+
+/// Creates an organisation with its own DID, only the administrator can create it.
+#[post("")]
+async fn create_organisation(
+    caller: Caller,
+    req: web::Json<OrganisationRequest>,
+    iota_state: web::Data<IotaState>,
+    mongo_repo: web::Data<dyn Repository>
+) -> Result<HttpResponse, TrustServiceError> {
+    log::info!("controller: create_organisation");
+    caller.require_admin()?;
+    let organisation = organisation_service::create_organisation(&iota_state, &mongo_repo, req.name.clone()).await?;
+    Ok(HttpResponse::Ok().json(organisation))
+}
+
+/// Returns the organisation with its current and past members.
+#[get("/{did}")]
+async fn get_organisation(
+    path: web::Path<String>,
+    mongo_repo: web::Data<dyn Repository>
+) -> Result<HttpResponse, TrustServiceError> {
+    log::info!("controller: get_organisation");
+    let organisation = mongo_repo.get_organisation(path.into_inner().as_str()).await?;
+    Ok(HttpResponse::Ok().json(organisation))
+}
+
+/// Delegates the proof-publishing rights of the organisation to a member DID.
+/// Only the organisation DID or the administrator can add members.
+#[post("/{did}/members")]
+async fn add_member(
+    caller: Caller,
+    path: web::Path<String>,
+    req: web::Json<MemberRequest>,
+    iota_state: web::Data<IotaState>,
+    did_resolver: web::Data<DidResolver>,
+    mongo_repo: web::Data<dyn Repository>
+) -> Result<HttpResponse, TrustServiceError> {
+    log::info!("controller: add_member");
+    let organisation_did = path.into_inner();
+    caller.require_did_or_admin(organisation_did.as_str())?;
+    let membership = organisation_service::add_member(
+        &iota_state,
+        &did_resolver,
+        &mongo_repo,
+        organisation_did.as_str(),
+        req.member_did.as_str(),
+        req.validity_days
+    ).await?;
+    Ok(HttpResponse::Ok().json(membership))
+}
+
+/// Ends the delegation of a member, only the organisation DID or the administrator can remove it.
+#[delete("/{did}/members/{member_did}")]
+async fn remove_member(
+    caller: Caller,
+    path: web::Path<(String, String)>,
+    mongo_repo: web::Data<dyn Repository>
+) -> Result<HttpResponse, TrustServiceError> {
+    log::info!("controller: remove_member");
+    let (organisation_did, member_did) = path.into_inner();
+    caller.require_did_or_admin(organisation_did.as_str())?;
+    organisation_service::remove_member(&mongo_repo, organisation_did.as_str(), member_did.as_str()).await?;
+    Ok(HttpResponse::Ok().finish())
+}
+
+pub fn scoped_config(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/organisations")
+            .service(create_organisation)
+            .service(get_organisation)
+            .service(add_member)
+            .service(remove_member)
+    );
+}
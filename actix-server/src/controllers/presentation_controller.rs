@@ -0,0 +1,43 @@
+// SPDX-FileCopyrightText: 2024 Fondazione LINKS
+//
+// SPDX-License-Identifier: APACHE-2.0
+
+use actix_web::{web, HttpResponse, post};
+use identity_iota::credential::Jwt;
+
+use crate::dtos::PresentationVerificationRequest;
+use crate::errors::TrustServiceError;
+use crate::services::did_resolver::DidResolver;
+use crate::services::repository::Repository;
+use crate::services::presentation_verifier::verify_presentation;
+
+/// Verifies a JWT Verifiable Presentation and the credentials it contains.
+/// The outcome of every check is returned, an invalid presentation is not an HTTP error.
+#[post("/verify")]
+async fn verify(
+    req: web::Json<PresentationVerificationRequest>,
+    did_resolver: web::Data<DidResolver>,
+    mongo_repo: web::Data<dyn Repository>,
+) -> Result<HttpResponse, TrustServiceError> {
+    log::info!("controller: verify_presentation");
+
+    let presentation_jwt = Jwt::from(req.presentation.clone());
+    let response = verify_presentation(
+        &did_resolver,
+        mongo_repo.get_ref(),
+        &presentation_jwt,
+        req.challenge.as_deref(),
+        req.audience.as_deref()
+    ).await;
+    log::info!("Presentation valid: {}", response.valid);
+
+    Ok(HttpResponse::Ok().json(response))
+}
+
+pub fn scoped_config(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        // prefixes all resources and routes attached to it...
+        web::scope("/presentations")
+            .service(verify)
+    );
+}
@@ -2,33 +2,43 @@
 //
 // SPDX-License-Identifier: APACHE-2.0
 
-use actix_web::{web, HttpResponse, get, post};
-use identity_iota::iota::IotaDocument;
+use actix_web::{web, HttpRequest, HttpResponse, get, post};
+use identity_iota::core::Timestamp;
+use identity_iota::document::CoreDocument;
 
 use crate::controllers::AssetQuery;
+use crate::services::audit_log::AuditLog;
+use crate::services::authentication::Caller;
+use crate::services::did_resolver::DidResolver;
 use crate::services::iota_state::IotaState;
-use crate::services::mongodb_repo::MongoRepo;
-use crate::dtos::ProofRequest;
+use crate::services::repository::Repository;
+use crate::services::organisation_service::{active_membership, verify_delegation};
+use crate::services::proof_revocation;
+use crate::dtos::{ProofRequest, ProofRevocationRequest, SignedProofRequest};
 use crate::errors::TrustServiceError;
 use crate::models::tangle_proof::TangleProof;
+use crate::models::user::User;
 
 
 #[get("/{proof_id}")]
 async fn get_proof(
+    req: HttpRequest,
     path: web::Path<String>,
     iota_state: web::Data<IotaState>,
-    mongo_repo: web::Data<MongoRepo>
+    did_resolver: web::Data<DidResolver>,
+    mongo_repo: web::Data<dyn Repository>,
+    audit_log: web::Data<AuditLog>,
 ) -> Result<HttpResponse, TrustServiceError> {
     // TODO: check if it is a proof in the db
     let proof_id = path.into_inner();
-    let proof = iota_state.resolve_proof(proof_id.clone()).await?;
-    
-    let publisher_document: IotaDocument = iota_state.resolve_did(proof.did_publisher.as_str()).await?;
-    //log::warn!("TROVATO2: {:?}", proof.did_publisher.as_str());
-    mongo_repo.get_asset_by_proof(proof_id.clone()).await?;
-    proof.verify(&publisher_document)?;
+    let (asset_id, result) = match mongo_repo.get_asset_by_proof(proof_id.clone()).await {
+        Ok(asset) => (Some(asset.asset_id), verified_proof(&iota_state, &did_resolver, &mongo_repo, proof_id.clone()).await),
+        Err(err) => (None, Err(err)),
+    };
+    // the proof is only returned once the access is recorded
+    audit_log.record_access(&req, asset_id.as_deref(), Some(proof_id.as_str()), &result).await?;
 
-    Ok(HttpResponse::Ok().json(proof))
+    Ok(HttpResponse::Ok().json(result?))
 }
 
 // this handler gets called if the query deserializes into `Info` successfully
@@ -36,49 +46,180 @@ async fn get_proof(
 //TODO: when sending a request the url should be encoded
 #[get("")]
 async fn get_proof_by_asset(
+    req: HttpRequest,
     query: web::Query<AssetQuery>, 
     iota_state: web::Data<IotaState>, 
-    mongo_repo: web::Data<MongoRepo>
+    did_resolver: web::Data<DidResolver>,
+    mongo_repo: web::Data<dyn Repository>,
+    audit_log: web::Data<AuditLog>,
 ) -> Result<HttpResponse, TrustServiceError> {
     log::info!("controller: get_proof_by_asset");
 
-    let asset = mongo_repo.get_asset(query.asset_id.clone()).await?;
-    let proof = iota_state.resolve_proof(asset.proof_id).await?;
-    
-    let publisher_document: IotaDocument = iota_state.resolve_did(proof.did_publisher.as_str()).await?;
-    proof.verify(&publisher_document)?;
+    let (proof_id, result) = match mongo_repo.get_asset(query.asset_id.clone()).await {
+        Ok(asset) => (Some(asset.proof_id.clone()), verified_proof(&iota_state, &did_resolver, &mongo_repo, asset.proof_id).await),
+        Err(err) => (None, Err(err)),
+    };
+    audit_log.record_access(&req, Some(query.asset_id.as_str()), proof_id.as_deref(), &result).await?;
 
-    Ok(HttpResponse::Ok().json(proof))
+    Ok(HttpResponse::Ok().json(result?))
 }
 
+/// Resolves the proof on the Tangle and checks the signature of the publisher and its delegation.
+async fn verified_proof(
+    iota_state: &IotaState,
+    did_resolver: &DidResolver,
+    mongo_repo: &dyn Repository,
+    proof_id: String,
+) -> Result<TangleProof, TrustServiceError> {
+    let proof = iota_state.resolve_proof(proof_id).await?;
+    let publisher_document: CoreDocument = did_resolver.resolve(proof.did_publisher.as_str()).await?;
+    proof.verify(&publisher_document)?;
+    verify_delegation(did_resolver, mongo_repo, &proof).await?;
+    Ok(proof)
+}
+
+/// Publishes a proof signed with the custodial keys of the DID. Superseding a proof
+/// requires the request to be authenticated as the DID.
 #[post("")] 
 async fn create_proof(
+    caller: Option<Caller>,
     proof_dto: web::Json<ProofRequest>, 
     iota_state: web::Data<IotaState>, 
-    mongo_repo: web::Data<MongoRepo>
+    did_resolver: web::Data<DidResolver>,
+    mongo_repo: web::Data<dyn Repository>
 ) -> Result<HttpResponse, TrustServiceError> {
     let did = proof_dto.did.as_str();
+    if proof_dto.supersedes.is_some() {
+        caller.ok_or(TrustServiceError::AuthenticationError("superseding a proof requires authentication".to_owned()))?
+            .require_did(did)?;
+    }
     let user = mongo_repo.get_user(did).await?;
+    if user.is_self_custodied() {
+        return Err(TrustServiceError::SelfCustodiedDid(did.to_owned()))
+    }
+    check_new_asset(&mongo_repo, proof_dto.asset_id.as_str()).await?;
     // Resolve the published DID Document
-    let user_doc = iota_state.resolve_did(did).await?;
+    let user_doc = did_resolver.resolve(did).await?;
 
     log::info!("Creating trust proof...");
+    let key_storage = iota_state.key_storage_for(user.tenant_id.as_deref()).await?;
     let proof = TangleProof::new(
-        &iota_state.key_storage,
+        &key_storage,
         &user.fragment, 
         &proof_dto.metadata_hash, 
         &proof_dto.asset_hash, 
         &user_doc, 
         did.to_string()
     ).await?;
+    let proof = attach_delegation(&mongo_repo, proof, proof_dto.organisation.as_deref()).await?;
+    let superseded = match proof_dto.supersedes.as_deref() {
+        Some(superseded) => Some(proof_revocation::revocable_asset(&mongo_repo, superseded, did).await?),
+        None => None,
+    };
 
     log::info!("\n{:#?}", proof);
     let proof_id = iota_state.publish_proof(proof).await?.to_string();
 
     mongo_repo.store_proof_relationship(did, proof_id.clone(), proof_dto.asset_id.clone()).await?;
+    if let Some(asset) = superseded {
+        proof_revocation::revoke(&mongo_repo, &asset, None, Some(proof_id.clone())).await?;
+    }
     Ok(HttpResponse::Ok().body(proof_id)) 
 }
 
+/// Anchors a proof signed by the publisher in its own environment.
+/// The service does not touch the custodial keys, it only checks the
+/// signature and the payload before publishing it on the Tangle.
+/// A DID unknown to the service is registered as a self-custodied publisher
+/// once its proof is verified. Superseding a proof requires the request to be
+/// authenticated as the DID, as the JWS of a published proof can be read by anyone.
+#[post("/signed")]
+async fn submit_signed_proof(
+    caller: Option<Caller>,
+    proof_dto: web::Json<SignedProofRequest>,
+    iota_state: web::Data<IotaState>,
+    did_resolver: web::Data<DidResolver>,
+    mongo_repo: web::Data<dyn Repository>
+) -> Result<HttpResponse, TrustServiceError> {
+    log::info!("controller: submit_signed_proof");
+    let did = proof_dto.did.as_str();
+    if proof_dto.supersedes.is_some() {
+        caller.ok_or(TrustServiceError::AuthenticationError("superseding a proof requires authentication".to_owned()))?
+            .require_did(did)?;
+    }
+    let publisher_document = did_resolver.resolve(did).await?;
+    check_new_asset(&mongo_repo, proof_dto.asset_id.as_str()).await?;
+
+    log::info!("Checking client-signed trust proof...");
+    let proof = TangleProof::from_signed_jws(&proof_dto, &publisher_document)?;
+    let proof = attach_delegation(&mongo_repo, proof, proof_dto.organisation.as_deref()).await?;
+    let superseded = match proof_dto.supersedes.as_deref() {
+        Some(superseded) => Some(proof_revocation::revocable_asset(&mongo_repo, superseded, did).await?),
+        None => None,
+    };
+
+    // publishers with DIDs not created by the service are registered by their first proof
+    match mongo_repo.get_user(did).await {
+        Ok(_) => (),
+        Err(TrustServiceError::UserDidNotFound) => {
+            log::info!("Registering {} as a self-custodied publisher", did);
+            mongo_repo.store_user(User::self_custodied(did)).await?;
+        },
+        Err(err) => return Err(err),
+    }
+
+    log::info!("\n{:#?}", proof);
+    let proof_id = iota_state.publish_proof(proof).await?.to_string();
+
+    mongo_repo.store_proof_relationship(did, proof_id.clone(), proof_dto.asset_id.clone()).await?;
+    if let Some(asset) = superseded {
+        proof_revocation::revoke(&mongo_repo, &asset, None, Some(proof_id.clone())).await?;
+    }
+    Ok(HttpResponse::Ok().body(proof_id))
+}
+
+/// Revokes a proof on behalf of the DID owning its asset, the request is authenticated as the DID.
+/// The NFT of the asset, if any, is flagged as stale.
+#[post("/{proof_id}/revoke")]
+async fn revoke_proof(
+    caller: Caller,
+    path: web::Path<String>,
+    req: web::Json<ProofRevocationRequest>,
+    mongo_repo: web::Data<dyn Repository>
+) -> Result<HttpResponse, TrustServiceError> {
+    log::info!("controller: revoke_proof");
+    let proof_id = path.into_inner();
+    caller.require_did(req.did.as_str())?;
+    let asset = proof_revocation::revocable_asset(&mongo_repo, proof_id.as_str(), req.did.as_str()).await?;
+    let revocation = proof_revocation::revoke(&mongo_repo, &asset, req.into_inner().reason, None).await?;
+    Ok(HttpResponse::Ok().json(revocation))
+}
+
+/// Refuses an asset id already notarized, before the proof is published on the Tangle.
+/// A new version of a dataset is notarized under a new asset id and supersedes the previous proof.
+async fn check_new_asset(mongo_repo: &dyn Repository, asset_id: &str) -> Result<(), TrustServiceError> {
+    if mongo_repo.asset_exists(asset_id).await? {
+        return Err(TrustServiceError::AssetIdAlreadyExists(asset_id.to_owned()))
+    }
+    Ok(())
+}
+
+/// Adds the delegation of the publisher when the proof is published on behalf of an organisation.
+async fn attach_delegation(
+    mongo_repo: &dyn Repository,
+    proof: TangleProof,
+    organisation_did: Option<&str>
+) -> Result<TangleProof, TrustServiceError> {
+    let organisation_did = match organisation_did {
+        Some(organisation_did) => organisation_did,
+        None => return Ok(proof),
+    };
+    let organisation = mongo_repo.get_organisation(organisation_did).await?;
+    let signed_at = Timestamp::now_utc();
+    let membership = active_membership(&organisation, proof.did_publisher.as_str(), signed_at)
+        .ok_or(TrustServiceError::MembershipNotFound(proof.did_publisher.clone()))?;
+    Ok(proof.with_delegation(organisation.did.clone(), membership.delegation.clone(), signed_at))
+}
 
 // this function could be located in a different module
 pub fn scoped_config(cfg: &mut web::ServiceConfig) {
@@ -88,6 +229,8 @@ pub fn scoped_config(cfg: &mut web::ServiceConfig) {
             .service(get_proof)
             .service(get_proof_by_asset)
             .service(create_proof)
+            .service(submit_signed_proof)
+            .service(revoke_proof)
             
     );
 }
\ No newline at end of file
@@ -0,0 +1,69 @@
+// SPDX-FileCopyrightText: 2024 Fondazione LINKS
+//
+// SPDX-License-Identifier: APACHE-2.0
+
+use actix_web::http::header::{ContentDisposition, DispositionParam, DispositionType};
+use actix_web::{web, HttpResponse, delete, post};
+
+use crate::dtos::TenantExportRequest;
+use crate::errors::TrustServiceError;
+use crate::services::authentication::Caller;
+use crate::services::iota_state::IotaState;
+use crate::services::repository::Repository;
+
+/// Exports the key material of a tenant: its Stronghold snapshot, the snapshot
+/// password and its users, in an archive encrypted with the given passphrase.
+/// Only a DID of the tenant or the administrator can export it.
+#[post("/{tenant}/export")]
+async fn export_tenant(
+    caller: Caller,
+    path: web::Path<String>,
+    req: web::Json<TenantExportRequest>,
+    iota_state: web::Data<IotaState>,
+    mongo_repo: web::Data<dyn Repository>
+) -> Result<HttpResponse, TrustServiceError> {
+    log::info!("controller: export_tenant");
+    let tenant_id = path.into_inner();
+    caller.require_tenant(&mongo_repo, tenant_id.as_str()).await?;
+    let users = mongo_repo.get_users_by_tenant(tenant_id.as_str()).await?;
+    let archive = iota_state.tenant_storage().export(
+        tenant_id.as_str(),
+        vec![("users.json".to_owned(), serde_json::to_vec(&users)?)],
+        req.passphrase.as_str()
+    ).await?;
+
+    Ok(HttpResponse::Ok()
+        .content_type("application/octet-stream")
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename(format!("{tenant_id}.backup"))],
+        })
+        .body(archive))
+}
+
+/// Offboards a tenant deleting its key storage and its users,
+/// its DIDs stay on the Tangle but can no longer be used by the service.
+/// Only a DID of the tenant or the administrator can remove it.
+#[delete("/{tenant}")]
+async fn remove_tenant(
+    caller: Caller,
+    path: web::Path<String>,
+    iota_state: web::Data<IotaState>,
+    mongo_repo: web::Data<dyn Repository>
+) -> Result<HttpResponse, TrustServiceError> {
+    log::info!("controller: remove_tenant");
+    let tenant_id = path.into_inner();
+    caller.require_tenant(&mongo_repo, tenant_id.as_str()).await?;
+    iota_state.tenant_storage().remove(tenant_id.as_str()).await?;
+    let deleted = mongo_repo.delete_users_by_tenant(tenant_id.as_str()).await?;
+    log::info!("Tenant {} removed with {} users", tenant_id, deleted);
+    Ok(HttpResponse::Ok().finish())
+}
+
+pub fn scoped_config(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/tenants")
+            .service(export_tenant)
+            .service(remove_tenant)
+    );
+}
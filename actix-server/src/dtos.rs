@@ -2,7 +2,39 @@
 //
 // SPDX-License-Identifier: APACHE-2.0
 
+use std::collections::HashMap;
+
+use identity_iota::document::CoreDocument;
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::models::asset::Asset;
+use crate::models::credential::{CredentialState, CredentialTemplate};
+use crate::models::did_resolution::DidDocumentMetadata;
+use crate::models::license::License;
+use crate::models::proof_revocation::ProofRevocation;
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateDidResponse {
+    pub did: String,
+    pub did_document: CoreDocument,
+    pub did_document_metadata: DidDocumentMetadata,
+    /// Fragment of the verification method generated for the DID
+    pub fragment: String,
+    /// Full DID URL of the generated verification method
+    pub verification_method: String,
+    /// Bearer token authenticating the DID, returned only once
+    pub access_token: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AccessTokenResponse {
+    pub did: String,
+    /// Bearer token authenticating the DID, returned only once
+    pub access_token: String,
+}
 
 #[derive(Debug, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
@@ -10,7 +42,74 @@ pub struct ProofRequest {
     pub asset_id: String,
     pub asset_hash: String,
     pub metadata_hash: String,
-    pub did: String
+    pub did: String,
+    /// Organisation on whose behalf the member publishes the proof
+    #[serde(default)]
+    pub organisation: Option<String>,
+    /// Proof of the previous version of the dataset, marked as superseded
+    #[serde(default)]
+    pub supersedes: Option<String>
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SignedProofRequest {
+    pub asset_id: String,
+    pub asset_hash: String,
+    pub metadata_hash: String,
+    pub did: String,
+    /// Compact JWS over the `SignedProofPayload` of the request, signed by the publisher
+    pub jws: String,
+    /// Organisation on whose behalf the member publishes the proof
+    #[serde(default)]
+    pub organisation: Option<String>,
+    /// Proof of the previous version of the dataset, marked as superseded
+    #[serde(default)]
+    pub supersedes: Option<String>
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProofRevocationRequest {
+    /// DID owning the asset of the proof, the request is authenticated as this DID
+    pub did: String,
+    pub reason: Option<String>
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OrganisationRequest {
+    pub name: String
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MemberRequest {
+    /// DID of the member, must be registered in the service
+    pub member_did: String,
+    /// Validity of the delegation, unlimited when missing
+    pub validity_days: Option<u32>
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TenantExportRequest {
+    /// Passphrase the export archive is encrypted with
+    pub passphrase: String
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EvmLinkRequest {
+    /// Account to link, omitted to let the service derive a custodial account
+    pub address: Option<String>,
+    /// EIP-191 signature of the link message by `address`
+    pub signature: Option<String>,
+    /// JWS of the DID over the link message, required for DIDs holding their own keys
+    pub did_proof: Option<String>,
+    /// Replaces the account already linked to the DID
+    #[serde(default)]
+    pub replace: bool
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -20,7 +119,23 @@ pub struct NftRequest {
     pub nft_alias: String,
     pub nft_symbol: String,
     pub license: String,
-    pub did: String
+    pub did: String,
+    /// EVM network to mint on, the default one when missing
+    pub network: Option<String>,
+    /// EIP-712 signature of the request by the EVM account linked to `did`
+    pub authorization: Option<NftAuthorization>
+}
+
+/// EIP-712 signature of an NFT request, see `services::nft_authorization`.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NftAuthorization {
+    /// 0x prefixed hex signature
+    pub signature: String,
+    /// Signature nonce of the account, starting from 0
+    pub nonce: u64,
+    /// Unix time in seconds after which the request is refused
+    pub deadline: u64
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -29,5 +144,310 @@ pub struct NftResponse {
     pub asset_id: String,
     pub nft_address: String,
     pub license: String,
+    /// Registry record of `license`, missing for licenses the registry does not know
+    pub license_record: Option<License>,
+    pub did: String,
+    pub network: String,
+    /// The proof of the asset is revoked or superseded
+    pub stale: bool,
+    /// Latest proof of the dataset, missing when the proof was revoked
+    pub current_proof_id: Option<String>,
+    pub revocation: Option<ProofRevocation>
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LicenseRequest {
+    /// Identifier of the custom license, starting with `LicenseRef-`
+    pub license_id: String,
+    pub name: String,
+    /// Full text of the license, alternative to `url`
+    pub text: Option<String>,
+    /// Location of the text of the license, fetched to compute its hash
+    pub url: Option<String>,
+    /// Expected hex SHA-256 of the text, checked when given
+    pub content_hash: Option<String>
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NftTransferRequest {
+    /// DID owning the NFT, the request is authenticated as this DID
+    pub did: String,
+    /// Recipient account, alternative to `toDid`
+    pub to: Option<String>,
+    /// DID whose linked EVM account receives the NFT
+    pub to_did: Option<String>,
+    /// EIP-712 signature of the request by the EVM account linked to `did`
+    pub authorization: Option<NftAuthorization>
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NftApprovalRequest {
+    /// DID owning the NFT, the request is authenticated as this DID
+    pub did: String,
+    /// Account to approve, alternative to `operatorDid`
+    pub operator: Option<String>,
+    /// DID whose linked EVM account is approved
+    pub operator_did: Option<String>,
+    /// Approves the operator for all the tokens of the owner (`setApprovalForAll`)
+    #[serde(default)]
+    pub all: bool,
+    /// `false` revokes the approval
+    #[serde(default = "default_true")]
+    pub approved: bool,
+    /// EIP-712 signature of the request by the EVM account linked to `did`
+    pub authorization: Option<NftAuthorization>
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// NFT minted for an asset. The integrity report of the new contract is returned as is,
+/// a mismatch cannot undo the mint.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NftMintResponse {
+    pub asset_id: String,
+    pub network: String,
+    pub nft_address: String,
+    pub transaction_hash: String,
+    pub token_id: Option<String>,
+    /// Holder of the token, still the service if the handover to the account linked to the owner DID failed
+    pub owner_address: Option<String>,
+    /// Missing when the check could not run
+    pub integrity: Option<NftIntegrityReport>
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NftTransactionResponse {
+    pub asset_id: String,
+    pub transaction_hash: String
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NftOwnerResponse {
+    pub asset_id: String,
+    pub network: String,
+    pub nft_address: String,
+    pub token_id: String,
+    /// Current holder of the token (`ownerOf`)
+    pub owner_address: String,
+    /// DID entitled to transfer the token, if any
+    pub owner_did: Option<String>,
+    /// Account the NFT was minted for (`getAssetOwner`)
+    pub asset_owner: String,
+    pub approved: Option<String>
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NftDetailsResponse {
+    pub network: String,
+    pub nft_address: String,
+    pub name: String,
+    pub symbol: String,
+    pub token_id: Option<String>,
+    pub token_uri: Option<String>,
+    pub license: String,
+    /// Registry record of `license`, missing for licenses the registry does not know
+    pub license_record: Option<License>,
+    pub did: String,
+    pub asset_id: String,
+    /// Current holder of the token (`ownerOf`)
+    pub owner_address: Option<String>,
+    /// Owner registered by the factory (`assetToOwner`)
+    pub factory_owner: String,
+    /// DID owning the asset in the db
+    pub asset_owner_did: Option<String>,
+    /// Record of the asset in the db, missing for NFTs minted outside the service
+    pub asset: Option<Asset>
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NftPage {
+    pub total: u64,
+    pub page: u32,
+    pub page_size: u32,
+    pub items: Vec<NftDetailsResponse>,
+    /// NFTs of the page that could not be read
+    pub errors: Vec<NftListError>
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NftListError {
+    pub nft_address: String,
+    pub error: String
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NftMetadataRequest {
+    /// DID owning the asset
     pub did: String
 }
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NftMetadataResponse {
+    pub asset_id: String,
+    /// `ipfs://` URI of the metadata
+    pub metadata_uri: String,
+    pub metadata: Value,
+    /// The URI is not stored on-chain, see `nft_metadata::TOKEN_URI_NOTICE`
+    pub token_uri_notice: &'static str
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IntegrityCheck {
+    pub check: String,
+    pub passed: bool,
+    pub expected: Option<String>,
+    pub actual: Option<String>,
+    pub message: Option<String>
+}
+
+impl IntegrityCheck {
+    /// Check comparing a value read from the chain or the Tangle with the expected one.
+    pub fn compare(check: &str, expected: &str, actual: &str) -> Self {
+        IntegrityCheck {
+            check: check.to_owned(),
+            passed: expected == actual,
+            expected: Some(expected.to_owned()),
+            actual: Some(actual.to_owned()),
+            message: None
+        }
+    }
+
+    pub fn outcome(check: &str, result: Result<(), String>) -> Self {
+        IntegrityCheck { check: check.to_owned(), passed: result.is_ok(), message: result.err(), ..Default::default() }
+    }
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NftIntegrityReport {
+    pub asset_id: String,
+    pub nft_address: Option<String>,
+    /// All the checks passed
+    pub consistent: bool,
+    pub checks: Vec<IntegrityCheck>
+}
+
+impl NftIntegrityReport {
+    /// Names and messages of the failed checks.
+    pub fn failures(&self) -> String {
+        self.checks.iter()
+            .filter(|check| !check.passed)
+            .map(|check| match (&check.message, &check.expected, &check.actual) {
+                (Some(message), _, _) => format!("{}: {}", check.check, message),
+                (None, Some(expected), Some(actual)) => format!("{}: expected {}, found {}", check.check, expected, actual),
+                _ => check.check.clone(),
+            })
+            .collect::<Vec<_>>()
+            .join("; ")
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CredentialRequest {
+    /// DID of the holder, must be registered in the service
+    pub did: String,
+    /// Name of the credential template
+    pub template: String,
+    #[serde(default)]
+    pub claims: Map<String, Value>
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IssuerResponse {
+    pub did: String,
+    pub templates: HashMap<String, CredentialTemplate>
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PresentationVerificationRequest {
+    /// Verifiable Presentation encoded as a JWT
+    pub presentation: String,
+    /// Challenge expected as the nonce of the presentation
+    pub challenge: Option<String>,
+    /// Expected audience of the presentation
+    pub audience: Option<String>
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CredentialCheck {
+    pub check: String,
+    pub message: String
+}
+
+impl CredentialCheck {
+    pub fn new(check: &str, message: String) -> Self {
+        CredentialCheck { check: check.to_owned(), message }
+    }
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CredentialVerificationResult {
+    /// Position of the credential in the presentation
+    pub index: usize,
+    pub valid: bool,
+    pub credential_id: Option<String>,
+    pub issuer: Option<String>,
+    pub types: Vec<String>,
+    pub subject: Option<Value>,
+    pub errors: Vec<CredentialCheck>
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PresentationVerificationResponse {
+    pub valid: bool,
+    pub holder: Option<String>,
+    /// Errors of the presentation itself (signature, challenge, audience, dates)
+    pub errors: Vec<String>,
+    pub credentials: Vec<CredentialVerificationResult>
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CredentialStatusRequest {
+    pub status: CredentialState
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CredentialVerificationRequest {
+    /// Verifiable Credential encoded as a JWT
+    pub credential: String,
+    /// If present, must be the subject of the credential
+    pub holder: Option<String>
+}
+
+/// Outcome of the verification of the audit log.
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditLogReport {
+    /// Readable entries, checkpoints included
+    pub entries: u64,
+    pub checkpoints: u64,
+    /// Last checkpoint with a valid signature
+    pub last_checkpoint: Option<u64>,
+    /// Entries after the last valid checkpoint
+    pub unsigned_entries: u64,
+    /// No modified, missing or reordered entry was found
+    pub valid: bool,
+    pub failures: Vec<String>,
+}
@@ -7,6 +7,8 @@ use actix_web::{HttpResponse, ResponseError, http::header::ContentType};
 use log::error;
 use reqwest::StatusCode;
 
+use crate::services::evm_signer::StrongholdSignerError;
+
 #[derive(thiserror::Error, Debug)]
 pub enum TrustServiceError {
 
@@ -20,6 +22,8 @@ pub enum TrustServiceError {
     ProofIdNotFound,
     #[error("Asset id: {0} not found")]   
     AssetIdNotFound(String),
+    #[error("Asset id: {0} is already notarized, a new version of the dataset needs a new asset id")]
+    AssetIdAlreadyExists(String),
     #[error("Iota Client Error")]
     IotaClientError(#[from] iota_sdk::client::Error),
     #[error("Resolve Error")]
@@ -30,8 +34,48 @@ pub enum TrustServiceError {
     WalletError(#[from] iota_sdk::wallet::Error),
     #[error("Did Error")]
     DidError(#[from] identity_iota::did::Error),
+    #[error("Invalid DID: {0}")]
+    InvalidDid(String),
+    #[error("Unsupported DID method: {0}")]
+    UnsupportedDidMethod(String),
+    #[error("{0} holds its own keys, its proofs are submitted already signed")]
+    SelfCustodiedDid(String),
+    #[error("DID resolution error")]
+    DidResolutionError(#[from] identity_iota::resolver::Error),
+    #[error("Error fetching did:web document: {0}")]
+    DidWebFetchError(String),
+    #[error("DID document error")]
+    DocumentError(#[from] identity_iota::document::Error),
+    #[error("Verification method error")]
+    VerificationMethodError(#[from] identity_iota::verification::Error),
     #[error("Error during insert")]   
     InsertError,
+    #[error("Issuer DID not found")]
+    IssuerNotFound,
+    #[error("Credential template {0} not found")]
+    CredentialTemplateNotFound(String),
+    #[error("Missing claim: {0}")]
+    MissingClaim(String),
+    #[error("Claim {0} is set by the credential template")]
+    ReservedClaim(String),
+    #[error("Credential error")]
+    CredentialError(#[from] identity_iota::credential::Error),
+    #[error("Credential {0} not found")]
+    CredentialNotFound(String),
+    #[error("Credential already revoked")]
+    CredentialAlreadyRevoked,
+    #[error("Invalid tenant id: {0}")]
+    InvalidTenant(String),
+    #[error("Tenant {0} not found")]
+    TenantNotFound(String),
+    #[error("Organisation {0} not found")]
+    OrganisationNotFound(String),
+    #[error("{0} is not an active member of the organisation")]
+    MembershipNotFound(String),
+    #[error("{0} is already a member of the organisation")]
+    MemberAlreadyActive(String),
+    #[error("Delegation not valid: {0}")]
+    DelegationNotValid(String),
     #[error("Jwk error")]
     JwkError(#[from]identity_iota::storage::JwkStorageDocumentError),
     #[error("Mongo db Error")]
@@ -44,11 +88,63 @@ pub enum TrustServiceError {
     MissingNftAddress,
     #[error("Error converting to Address")]
     AddressError,
+    #[error("Invalid EVM account signature")]
+    InvalidEvmSignature,
+    #[error("An EVM account is already linked to the DID")]
+    EvmAccountAlreadyLinked,
+    #[error("Invalid proof of control of the DID: {0}")]
+    InvalidDidProof(String),
+    #[error("EVM signer error: {0}")]
+    EvmSignerError(String),
+    #[error("No EVM account is linked to {0}")]
+    EvmAccountNotLinked(String),
+    #[error("{0} does not own the NFT")]
+    NotNftOwner(String),
+    #[error("The NFT is held by {0}, which has not approved the service")]
+    NftNotManaged(String),
+    #[error("Invalid NFT target: {0}")]
+    InvalidNftTarget(String),
+    #[error("Unsupported NFT operation: {0}")]
+    UnsupportedNftOperation(String),
+    #[error("{0} does not own the asset")]
+    AssetNotOwned(String),
+    #[error("An NFT is already minted for asset {0}")]
+    NftAlreadyMinted(String),
+    #[error("The NFT of asset {0} is already being minted")]
+    NftMintInProgress(String),
+    #[error("NFT integrity check failed: {0}")]
+    NftIntegrityError(String),
+    #[error("Transaction {0} not confirmed in time")]
+    TransactionTimeout(String),
+    #[error("Transaction {0} is not mined and its fees are at GAS_MAX_FEE_GWEI")]
+    TransactionFeeCap(String),
+    #[error("Transaction {0} was sent but its outcome is unknown: {1}")]
+    TransactionPending(String, String),
+    #[error("Unknown EVM network {0}")]
+    UnknownEvmNetwork(String),
+    #[error("Unknown license {0}, use an SPDX identifier or a registered LicenseRef-")]
+    UnknownLicense(String),
+    #[error("Invalid license: {0}")]
+    InvalidLicense(String),
+    #[error("License {0} is already registered")]
+    LicenseAlreadyRegistered(String),
+    #[error("Invalid signed request: {0}")]
+    InvalidSignedRequest(String),
+    #[error("Proof {0} is revoked or superseded")]
+    ProofRevoked(String),
+    #[error("Incompatible EVM network {0}")]
+    IncompatibleContract(String),
+    #[error("Authentication failed: {0}")]
+    AuthenticationError(String),
+    #[error("{0} is not authorized for this operation")]
+    NotAuthorized(String),
     
     #[error("Error converting OutputId")]
     IotaBlockError(#[from]identity_iota::iota::block::Error),
     #[error("Proof signature not valid")]
     ProofSignatureNotValid,
+    #[error("Invalid proof payload: {0}")]
+    InvalidProofPayload(String),
     #[error("Error serde_json")]
     SerdeJsonError(#[from]serde_json::Error),
     #[error("Error: {0}")]
@@ -76,6 +172,8 @@ pub enum TrustServiceError {
     FileOpenError,
     #[error("Error while writing file")]
     FileWriteError,
+    #[error("Audit log corrupted: {0}")]
+    AuditLogCorrupted(String),
 }
 
 impl From<MultipartError> for TrustServiceError {
@@ -84,6 +182,12 @@ impl From<MultipartError> for TrustServiceError {
     }
 }
 
+impl From<StrongholdSignerError> for TrustServiceError {
+    fn from(err: StrongholdSignerError) -> TrustServiceError {
+        TrustServiceError::EvmSignerError(err.to_string())
+    }
+}
+
 impl ResponseError for TrustServiceError {
 
     fn error_response(&self) -> HttpResponse {
@@ -98,15 +202,37 @@ impl ResponseError for TrustServiceError {
             TrustServiceError::IotaClientError(_) => StatusCode::INTERNAL_SERVER_ERROR,
             TrustServiceError::ResolveError(_) => StatusCode::INTERNAL_SERVER_ERROR,
             TrustServiceError::DidError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            TrustServiceError::InvalidDid(_) => StatusCode::BAD_REQUEST,
+            TrustServiceError::UnsupportedDidMethod(_) => StatusCode::BAD_REQUEST,
+            TrustServiceError::SelfCustodiedDid(_) => StatusCode::BAD_REQUEST,
+            TrustServiceError::DidResolutionError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            TrustServiceError::DidWebFetchError(_) => StatusCode::BAD_GATEWAY,
+            TrustServiceError::DocumentError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            TrustServiceError::VerificationMethodError(_) => StatusCode::INTERNAL_SERVER_ERROR,
             TrustServiceError::InsertError => StatusCode::INTERNAL_SERVER_ERROR,
+            TrustServiceError::IssuerNotFound => StatusCode::INTERNAL_SERVER_ERROR,
+            TrustServiceError::CredentialTemplateNotFound(_) => StatusCode::BAD_REQUEST,
+            TrustServiceError::MissingClaim(_) => StatusCode::BAD_REQUEST,
+            TrustServiceError::ReservedClaim(_) => StatusCode::BAD_REQUEST,
+            TrustServiceError::CredentialError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            TrustServiceError::CredentialNotFound(_) => StatusCode::NOT_FOUND,
+            TrustServiceError::CredentialAlreadyRevoked => StatusCode::CONFLICT,
+            TrustServiceError::InvalidTenant(_) => StatusCode::BAD_REQUEST,
+            TrustServiceError::TenantNotFound(_) => StatusCode::NOT_FOUND,
+            TrustServiceError::OrganisationNotFound(_) => StatusCode::NOT_FOUND,
+            TrustServiceError::MembershipNotFound(_) => StatusCode::FORBIDDEN,
+            TrustServiceError::MemberAlreadyActive(_) => StatusCode::CONFLICT,
+            TrustServiceError::DelegationNotValid(_) => StatusCode::NOT_ACCEPTABLE,
             TrustServiceError::JwkError(_) => StatusCode::INTERNAL_SERVER_ERROR,
             TrustServiceError::UserDidNotFound => StatusCode::NOT_FOUND,
             TrustServiceError::MongoDbError(_) => StatusCode::INTERNAL_SERVER_ERROR,
             TrustServiceError::ProofIdNotFound => StatusCode::NOT_FOUND,
             TrustServiceError::AssetIdNotFound(_) => StatusCode::NOT_FOUND,
+            TrustServiceError::AssetIdAlreadyExists(_) => StatusCode::CONFLICT,
             TrustServiceError::IotaBlockError(_) => StatusCode::INTERNAL_SERVER_ERROR,
             TrustServiceError::SerdeJsonError(_) => StatusCode::INTERNAL_SERVER_ERROR,
             TrustServiceError::ProofSignatureNotValid => StatusCode::NOT_ACCEPTABLE,
+            TrustServiceError::InvalidProofPayload(_) => StatusCode::BAD_REQUEST,
             TrustServiceError::IdentityCoreError(_) => StatusCode::INTERNAL_SERVER_ERROR,
             TrustServiceError::WalletError(_) => StatusCode::INTERNAL_SERVER_ERROR,
             TrustServiceError::WriteProofError => StatusCode::INTERNAL_SERVER_ERROR,
@@ -115,6 +241,31 @@ impl ResponseError for TrustServiceError {
             TrustServiceError::ContractError(_) => StatusCode::INTERNAL_SERVER_ERROR,
             TrustServiceError::MissingNftAddress => StatusCode::BAD_REQUEST,
             TrustServiceError::AddressError => StatusCode::INTERNAL_SERVER_ERROR,
+            TrustServiceError::InvalidEvmSignature => StatusCode::BAD_REQUEST,
+            TrustServiceError::EvmAccountAlreadyLinked => StatusCode::CONFLICT,
+            TrustServiceError::InvalidDidProof(_) => StatusCode::UNAUTHORIZED,
+            TrustServiceError::EvmSignerError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            TrustServiceError::EvmAccountNotLinked(_) => StatusCode::CONFLICT,
+            TrustServiceError::NotNftOwner(_) => StatusCode::FORBIDDEN,
+            TrustServiceError::NftNotManaged(_) => StatusCode::CONFLICT,
+            TrustServiceError::InvalidNftTarget(_) => StatusCode::BAD_REQUEST,
+            TrustServiceError::UnsupportedNftOperation(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            TrustServiceError::AssetNotOwned(_) => StatusCode::FORBIDDEN,
+            TrustServiceError::NftAlreadyMinted(_) => StatusCode::CONFLICT,
+            TrustServiceError::NftMintInProgress(_) => StatusCode::CONFLICT,
+            TrustServiceError::NftIntegrityError(_) => StatusCode::CONFLICT,
+            TrustServiceError::TransactionTimeout(_) => StatusCode::GATEWAY_TIMEOUT,
+            TrustServiceError::TransactionFeeCap(_) => StatusCode::SERVICE_UNAVAILABLE,
+            TrustServiceError::TransactionPending(..) => StatusCode::BAD_GATEWAY,
+            TrustServiceError::UnknownEvmNetwork(_) => StatusCode::BAD_REQUEST,
+            TrustServiceError::UnknownLicense(_) => StatusCode::BAD_REQUEST,
+            TrustServiceError::InvalidLicense(_) => StatusCode::BAD_REQUEST,
+            TrustServiceError::LicenseAlreadyRegistered(_) => StatusCode::CONFLICT,
+            TrustServiceError::InvalidSignedRequest(_) => StatusCode::UNAUTHORIZED,
+            TrustServiceError::ProofRevoked(_) => StatusCode::CONFLICT,
+            TrustServiceError::IncompatibleContract(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            TrustServiceError::AuthenticationError(_) => StatusCode::UNAUTHORIZED,
+            TrustServiceError::NotAuthorized(_) => StatusCode::FORBIDDEN,
             TrustServiceError::CustomError(_) => StatusCode::INTERNAL_SERVER_ERROR,
             TrustServiceError::MongoFileNotFound => StatusCode::NOT_FOUND,
             TrustServiceError::IpfsUploadError => StatusCode::INTERNAL_SERVER_ERROR,
@@ -125,6 +276,7 @@ impl ResponseError for TrustServiceError {
             TrustServiceError::MultipartError(_) => StatusCode::BAD_REQUEST,
             TrustServiceError::FileOpenError => StatusCode::INTERNAL_SERVER_ERROR,
             TrustServiceError::FileWriteError => StatusCode::INTERNAL_SERVER_ERROR,
+            TrustServiceError::AuditLogCorrupted(_) => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
 }
\ No newline at end of file
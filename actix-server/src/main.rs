@@ -6,10 +6,16 @@ use std::env;
 use std::sync::Arc;
 
 use actix_web::{web, App, HttpServer, middleware::Logger};
-use ethers::{middleware::SignerMiddleware, providers::{Http, Provider}, signers::{LocalWallet, Signer}};
 use log::log;
-use trust_server::{controllers::{did_controller, nft_controller, proof_controller}, services::{iota_state::IotaState, mongodb_repo::MongoRepo}};
-use trust_server::controllers::log_controller;
+use trust_server::{controllers::{did_controller, nft_controller, proof_controller}, services::{did_resolver::DidResolver, iota_state::{IotaState, ProofResolver}, mongodb_repo::MongoRepo, repository::Repository}};
+use trust_server::controllers::{credential_controller, license_controller, log_controller, organisation_controller, presentation_controller, tenant_controller};
+use trust_server::services::audit_log::{AuditLog, IssuerCheckpointSigner};
+use trust_server::services::authentication::Authenticator;
+use trust_server::services::credential_issuer::CredentialIssuer;
+use trust_server::services::evm_indexer::EvmIndexer;
+use trust_server::services::evm_networks::EvmNetworks;
+use trust_server::services::license_registry::LicenseRegistry;
+use trust_server::services::nft_metadata::MetadataPublisher;
 
 #[actix_web::main]
 async fn main() -> anyhow::Result<()> {
@@ -33,37 +39,70 @@ async fn main() -> anyhow::Result<()> {
     //let address = std::env::var("ADDR").expect("$ADDR must be set.");
     let port = std::env::var("PORT").expect("$PORT must be set.").parse::<u16>()?;
     
-    let db: MongoRepo = MongoRepo::init().await;
-    let db_data: web::Data<MongoRepo> = web::Data::new(db);
+    let db: Arc<dyn Repository> = Arc::new(MongoRepo::init().await);
+    let db_data: web::Data<dyn Repository> = web::Data::from(db);
 
     let iota_state: IotaState = IotaState::init().await?;
+    let did_resolver: DidResolver = DidResolver::new(iota_state.client().clone(), iota_state.did_cache());
+    let did_resolver_data: web::Data<DidResolver> = web::Data::new(did_resolver);
+    let credential_issuer: CredentialIssuer = CredentialIssuer::init(&iota_state, db_data.get_ref()).await?;
     let iota_state_data: web::Data<IotaState> = web::Data::new(iota_state);
+    let proof_resolver_data: web::Data<dyn ProofResolver> = web::Data::from(iota_state_data.clone().into_inner() as Arc<dyn ProofResolver>);
+    // the accesses to the assets are logged with checkpoints signed by the issuer DID
+    let checkpoint_signer = IssuerCheckpointSigner::new(
+        iota_state_data.clone().into_inner(),
+        did_resolver_data.clone().into_inner(),
+        credential_issuer.issuer()
+    );
+    let audit_log = AuditLog::init(
+        Arc::new(checkpoint_signer),
+        did_resolver_data.clone().into_inner(),
+        db_data.clone().into_inner(),
+    ).await?;
+    let audit_log_data: web::Data<AuditLog> = web::Data::new(audit_log);
+    let authenticator = Authenticator::from_env(did_resolver_data.clone().into_inner(), db_data.clone().into_inner())?;
+    let authenticator_data: web::Data<Authenticator> = web::Data::new(authenticator);
+    let credential_issuer_data: web::Data<CredentialIssuer> = web::Data::new(credential_issuer);
+    let license_registry_data: web::Data<LicenseRegistry> = web::Data::new(LicenseRegistry::init()?);
+    let metadata_publisher_data: web::Data<MetadataPublisher> = web::Data::new(MetadataPublisher::from_env()?);
 
-    // Initialize provider
-    let rpc_provider =  std::env::var("RPC_PROVIDER").expect("$RPC_PROVIDER must be set.");
-    let chain_id = std::env::var("CHAIN_ID").expect("$CHAIN_ID must be set.");
-    // Transactions will be signed with the private key below
-    let local_wallet = std::env::var("L2_PRIVATE_KEY").expect("$L2_PRIVATE_KEY must be set")
-    .parse::<LocalWallet>()?
-    .with_chain_id(chain_id.parse::<u64>().expect("CHAIN_ID is not a number"));
-
-
-    log::info!("Initializing custom provider");
-    let provider = Provider::<Http>::try_from(rpc_provider)?;
-    let signer = Arc::new(SignerMiddleware::new(provider, local_wallet));
-    let signer_data = web::Data::new(signer);
+    // Initialize the providers and the wallets of the EVM networks
+    log::info!("Initializing EVM networks");
+    let evm_networks = EvmNetworks::from_env(db_data.clone().into_inner())?;
+    for network in evm_networks.iter() {
+        // refuse to start against contracts the bindings do not match
+        network.check_contracts().await?;
+        // transactions sent before a restart are settled before accepting new ones
+        network.tx_manager.recover().await?;
+        if let Some(evm_indexer) = EvmIndexer::from_env(&evm_networks, network, db_data.clone().into_inner())? {
+            actix_web::rt::spawn(evm_indexer.run());
+        }
+    }
+    let evm_networks_data = web::Data::new(evm_networks);
 
     log::info!("Starting up on {}:{}", address, port);
     HttpServer::new(move || {
         App::new()
             .app_data(iota_state_data.clone())
+            .app_data(proof_resolver_data.clone())
+            .app_data(did_resolver_data.clone())
+            .app_data(credential_issuer_data.clone())
             .app_data(db_data.clone())
-            .app_data(signer_data.clone())
+            .app_data(evm_networks_data.clone())
+            .app_data(license_registry_data.clone())
+            .app_data(metadata_publisher_data.clone())
+            .app_data(audit_log_data.clone())
+            .app_data(authenticator_data.clone())
             .service(web::scope("/api")
                 .configure(did_controller::scoped_config)
                 .configure(proof_controller::scoped_config)
                 .configure(nft_controller::scoped_config)
                 .configure(log_controller::scoped_config)
+                .configure(credential_controller::scoped_config)
+                .configure(presentation_controller::scoped_config)
+                .configure(organisation_controller::scoped_config)
+                .configure(tenant_controller::scoped_config)
+                .configure(license_controller::scoped_config)
             )
             .wrap(Logger::default())
     })
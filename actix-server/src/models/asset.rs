@@ -5,12 +5,57 @@
 use serde::{Serialize, Deserialize};
 use mongodb::bson::{Bson, Document};
 
-#[derive(Debug, Serialize, Deserialize)]
+use super::proof_revocation::ProofRevocation;
+use super::signed_request::SignedRequest;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Asset{
     pub asset_id: String,
     pub proof_id: String,
     pub nft_addr: Option<String>,
+    #[serde(default)]
+    pub token_id: Option<String>,
+    /// EVM address owning the NFT
+    #[serde(default)]
+    pub owner_address: Option<String>,
+    /// Address approved for the token, cleared on transfer
+    #[serde(default)]
+    pub approved: Option<String>,
+    /// Operators approved for all the tokens of the owner, cleared on transfer
+    #[serde(default)]
+    pub operators: Vec<String>,
+    /// `ipfs://` URI of the ERC-721 metadata of the NFT
+    #[serde(default)]
+    pub metadata_uri: Option<String>,
+    /// EVM network the NFT was minted on, the default one when missing
+    #[serde(default)]
+    pub network: Option<String>,
+    /// Block of the `NftMinted` event of the NFT, which carries its proof id
+    #[serde(default)]
+    pub mint_block: Option<u64>,
+    /// Time a mint of the NFT started, set while the asset is reserved for it
+    #[serde(default)]
+    pub minting: Option<String>,
+    /// Set once the token is transferred to the zero address, the NFT then has no owner
+    #[serde(default)]
+    pub burned: bool,
+    /// Mint and transfer requests signed by the owner with EIP-712
+    #[serde(default)]
+    pub signed_requests: Vec<SignedRequest>,
+    /// Set once the proof is revoked or superseded, its NFT is then stale
+    #[serde(default)]
+    pub revocation: Option<ProofRevocation>,
+}
+
+impl Asset {
+    /// Whether the NFT of the asset lives on `network`, assets without a network are on the default one.
+    pub fn is_on_network(&self, network: &str, default_network: bool) -> bool {
+        match self.network.as_deref() {
+            Some(asset_network) => asset_network == network,
+            None => default_network,
+        }
+    }
 }
 
 impl From<Asset> for Bson {
@@ -19,6 +64,17 @@ impl From<Asset> for Bson {
         document.insert("assetId", asset.asset_id);
         document.insert("proofId", asset.proof_id);
         document.insert("nftAddr", asset.nft_addr);
+        document.insert("tokenId", asset.token_id);
+        document.insert("ownerAddress", asset.owner_address);
+        document.insert("approved", asset.approved);
+        document.insert("operators", asset.operators);
+        document.insert("metadataUri", asset.metadata_uri);
+        document.insert("network", asset.network);
+        document.insert("mintBlock", asset.mint_block.map(|mint_block| mint_block as i64));
+        document.insert("minting", asset.minting);
+        document.insert("burned", asset.burned);
+        document.insert("signedRequests", asset.signed_requests);
+        document.insert("revocation", asset.revocation);
         Bson::Document(document)
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,77 @@
+// SPDX-FileCopyrightText: 2024 Fondazione LINKS
+//
+// SPDX-License-Identifier: APACHE-2.0
+
+use identity_iota::verification::jwk::Jwk;
+use serde::{Serialize, Deserialize};
+
+/// Previous hash of the first entry of the log
+pub const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// A line of the audit log, chained to the previous one through `prev_hash`.
+///
+/// `hash` is the SHA-256 of the JSON serialization of the entry with an empty `hash`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditEntry {
+    pub seq: u64,
+    pub timestamp: String,
+    #[serde(flatten)]
+    pub record: AuditRecord,
+    pub prev_hash: String,
+    pub hash: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum AuditRecord {
+    /// Read of an asset or of its proof
+    #[serde(rename_all = "camelCase")]
+    Access {
+        /// DID declared by the caller in the `X-Requester-Did` header, not authenticated
+        requester: Option<String>,
+        remote_address: Option<String>,
+        /// Method and path of the request
+        endpoint: String,
+        asset_id: Option<String>,
+        proof_id: Option<String>,
+        /// Status code returned to the caller
+        status: u16,
+        error: Option<String>,
+    },
+    /// Signature of the service DID over the chain up to `prev_hash`
+    #[serde(rename_all = "camelCase")]
+    Checkpoint {
+        signer: String,
+        /// Compact JWS over [`CheckpointPayload`]
+        jws: String,
+    },
+}
+
+/// Payload signed in a checkpoint.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct CheckpointPayload {
+    pub seq: u64,
+    pub prev_hash: String,
+}
+
+/// Last checkpoint of a log, kept out of the log file so that truncating the file or
+/// rebuilding its chain is detected.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditAnchor {
+    /// Path of the log file
+    pub log: String,
+    pub seq: u64,
+    pub hash: String,
+    /// Keys that signed the checkpoints, kept to verify them once the DID rotates its keys
+    pub keys: Vec<CheckpointKey>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CheckpointKey {
+    /// DID URL of the verification method
+    pub kid: String,
+    pub jwk: Jwk,
+}
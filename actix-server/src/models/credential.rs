@@ -0,0 +1,67 @@
+// SPDX-FileCopyrightText: 2024 Fondazione LINKS
+//
+// SPDX-License-Identifier: APACHE-2.0
+
+use serde::{Serialize, Deserialize};
+use serde_json::{Map, Value};
+
+/// DID used by the service to issue credentials, its key lives in the key storage.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Issuer{
+    pub did: String,
+    pub fragment: String,
+    /// Next free index of the revocation bitmap
+    #[serde(default)]
+    pub next_status_index: u32,
+}
+
+/// Shape of a credential the service is able to issue, loaded from the templates file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CredentialTemplate{
+    /// Credential types added after `VerifiableCredential`
+    pub types: Vec<String>,
+    /// Additional JSON-LD contexts
+    #[serde(default)]
+    pub contexts: Vec<String>,
+    /// Claims that must be provided by the issuance request
+    #[serde(default)]
+    pub required_claims: Vec<String>,
+    /// Claims added to every credential issued with this template
+    #[serde(default)]
+    pub static_claims: Map<String, Value>,
+    pub validity_days: Option<u32>,
+    /// Holders may request the credential for themselves, the other templates
+    /// are issued by the administrator only
+    #[serde(default)]
+    pub self_attested: bool,
+}
+
+/// Status of an issued credential, both revoked and suspended credentials
+/// have their bit set in the revocation bitmap but only suspension can be lifted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum CredentialState{
+    #[default]
+    Active,
+    Suspended,
+    Revoked,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IssuedCredential{
+    pub credential_id: String,
+    pub issuer_did: String,
+    pub holder_did: String,
+    pub template: String,
+    pub types: Vec<String>,
+    pub issuance_date: String,
+    pub expiration_date: Option<String>,
+    pub jwt: String,
+    /// Index of the credential in the revocation bitmap of the issuer
+    pub status_index: Option<u32>,
+    #[serde(default)]
+    pub status: CredentialState,
+}
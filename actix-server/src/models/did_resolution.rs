@@ -0,0 +1,158 @@
+// SPDX-FileCopyrightText: 2024 Fondazione LINKS
+//
+// SPDX-License-Identifier: APACHE-2.0
+
+use identity_iota::core::Timestamp;
+use identity_iota::document::CoreDocument;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::errors::TrustServiceError;
+
+pub const DID_JSON: &str = "application/did+json";
+pub const DID_LD_JSON: &str = "application/did+ld+json";
+pub const DID_RESOLUTION: &str = "application/did-resolution";
+pub const DID_RESOLUTION_PROFILE: &str = "https://w3id.org/did-resolution";
+pub const DID_CONTEXT: &str = "https://www.w3.org/ns/did/v1";
+pub const JWS_2020_CONTEXT: &str = "https://w3id.org/security/suites/jws-2020/v1";
+pub const DID_RESOLUTION_CONTEXT: &str = "https://w3id.org/did-resolution/v1";
+
+/// Representations of a DID document supported by the service.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Representation {
+    DidJson,
+    DidLdJson,
+    DidResolution,
+}
+
+impl Representation {
+
+    /// Picks the representation to return according to an `Accept` header,
+    /// `application/did+json` is used when the header is missing or accepts anything.
+    pub fn negotiate(accept: Option<&str>) -> Option<Self> {
+        let accept = match accept {
+            Some(accept) if !accept.trim().is_empty() => accept,
+            _ => return Some(Representation::DidJson),
+        };
+
+        let mut media_ranges: Vec<(f32, Option<Self>)> = accept.split(',')
+            .map(|media_range| {
+                let mut params = media_range.split(';').map(str::trim);
+                let media_type = params.next().unwrap_or_default().to_ascii_lowercase();
+                let mut quality = 1.0;
+                let mut profile = None;
+                for param in params {
+                    match param.split_once('=') {
+                        Some(("q", q)) => quality = q.parse().unwrap_or(0.0),
+                        Some(("profile", p)) => profile = Some(p.trim_matches('"').to_owned()),
+                        _ => (),
+                    }
+                }
+                let representation = match media_type.as_str() {
+                    DID_JSON | "application/json" | "application/*" | "*/*" => Some(Representation::DidJson),
+                    DID_LD_JSON => Some(Representation::DidLdJson),
+                    DID_RESOLUTION => Some(Representation::DidResolution),
+                    "application/ld+json" if profile.as_deref() == Some(DID_RESOLUTION_PROFILE) => Some(Representation::DidResolution),
+                    "application/ld+json" => Some(Representation::DidLdJson),
+                    _ => None,
+                };
+                (quality, representation)
+            })
+            .filter(|(quality, _)| *quality > 0.0)
+            .collect();
+
+        // stable sort, among equal qualities the first listed wins
+        media_ranges.sort_by(|(q1, _), (q2, _)| q2.partial_cmp(q1).unwrap_or(std::cmp::Ordering::Equal));
+        media_ranges.into_iter().find_map(|(_, representation)| representation)
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Representation::DidJson => DID_JSON,
+            Representation::DidLdJson => DID_LD_JSON,
+            Representation::DidResolution => DID_RESOLUTION,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DidDocumentMetadata {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub created: Option<Timestamp>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub updated: Option<Timestamp>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deactivated: Option<bool>,
+    /// Id of the Alias Output holding an IOTA DID document
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alias_output_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DidResolutionMetadata {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error_message: Option<String>,
+    pub retrieved: Timestamp,
+}
+
+/// Resolution result as defined by the [DID Resolution](https://w3c-ccg.github.io/did-resolution/) specification.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DidResolutionResult {
+    #[serde(rename = "@context")]
+    pub context: String,
+    pub did_document: Option<Value>,
+    pub did_resolution_metadata: DidResolutionMetadata,
+    pub did_document_metadata: DidDocumentMetadata,
+}
+
+impl DidResolutionResult {
+
+    pub fn new(document: &CoreDocument, metadata: DidDocumentMetadata) -> Result<Self, TrustServiceError> {
+        Ok(DidResolutionResult {
+            context: DID_RESOLUTION_CONTEXT.to_owned(),
+            did_document: Some(json_ld_document(document)?),
+            did_resolution_metadata: DidResolutionMetadata {
+                content_type: Some(DID_LD_JSON.to_owned()),
+                retrieved: Timestamp::now_utc(),
+                ..Default::default()
+            },
+            did_document_metadata: metadata,
+        })
+    }
+
+    pub fn from_error(error: &TrustServiceError) -> Self {
+        let code = match error {
+            TrustServiceError::InvalidDid(_) | TrustServiceError::DidError(_) => "invalidDid",
+            TrustServiceError::UnsupportedDidMethod(_) => "methodNotSupported",
+            TrustServiceError::ResolveError(_) | TrustServiceError::DidResolutionError(_) => "notFound",
+            _ => "internalError",
+        };
+        DidResolutionResult {
+            context: DID_RESOLUTION_CONTEXT.to_owned(),
+            did_document: None,
+            did_resolution_metadata: DidResolutionMetadata {
+                error: Some(code.to_owned()),
+                error_message: Some(error.to_string()),
+                retrieved: Timestamp::now_utc(),
+                ..Default::default()
+            },
+            did_document_metadata: DidDocumentMetadata::default(),
+        }
+    }
+}
+
+/// Serializes a DID document adding the JSON-LD context of the DID core and JsonWebKey2020 vocabularies.
+pub fn json_ld_document(document: &CoreDocument) -> Result<Value, TrustServiceError> {
+    let mut value = serde_json::to_value(document)?;
+    if let Some(object) = value.as_object_mut() {
+        object.insert("@context".to_owned(), serde_json::json!([DID_CONTEXT, JWS_2020_CONTEXT]));
+    }
+    Ok(value)
+}
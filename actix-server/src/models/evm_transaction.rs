@@ -0,0 +1,43 @@
+// SPDX-FileCopyrightText: 2024 Fondazione LINKS
+//
+// SPDX-License-Identifier: APACHE-2.0
+
+use serde::{Serialize, Deserialize};
+
+/// Transaction sent by the service wallet, recorded from the broadcast to the confirmation.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EvmTransaction {
+    /// Operation that originated the transaction, e.g. `tokenize`
+    pub label: String,
+    pub chain_id: u64,
+    pub from: String,
+    pub to: String,
+    pub nonce: u64,
+    /// Hash of the last broadcast
+    pub hash: String,
+    /// Hashes of the broadcasts replaced with higher fees
+    pub replaced: Vec<String>,
+    /// Calldata, value and gas limit, kept to broadcast the transaction again after a restart
+    pub data: String,
+    pub value: String,
+    pub gas: String,
+    /// Gas price, or max fee per gas with EIP-1559, of the last broadcast
+    pub max_fee: Option<String>,
+    /// Priority fee per gas of the last broadcast, EIP-1559 only
+    pub priority_fee: Option<String>,
+    pub status: TransactionStatus,
+    pub block_number: Option<u64>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum TransactionStatus {
+    Pending,
+    Confirmed,
+    Reverted,
+    /// Still unconfirmed after the last replacement
+    Stuck,
+}
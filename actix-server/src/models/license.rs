@@ -0,0 +1,37 @@
+// SPDX-FileCopyrightText: 2024 Fondazione LINKS
+//
+// SPDX-License-Identifier: APACHE-2.0
+
+use serde::{Serialize, Deserialize};
+
+/// License an asset NFT can be released under.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct License {
+    /// SPDX identifier, or `LicenseRef-` identifier of a custom license
+    pub license_id: String,
+    pub name: String,
+    pub source: LicenseSource,
+    /// SPDX reference page, or location of the text of a custom license
+    pub url: Option<String>,
+    /// Text of a custom license, when registered with it
+    pub text: Option<String>,
+    /// Hex SHA-256 of the text of a custom license
+    pub content_hash: Option<String>,
+    #[serde(default)]
+    pub osi_approved: bool,
+    /// Deprecated SPDX identifiers are not accepted for new NFTs
+    #[serde(default)]
+    pub deprecated: bool,
+    pub created_at: Option<String>,
+    /// DID that registered a custom license, none when registered by the administrator
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub registered_by: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum LicenseSource {
+    Spdx,
+    Custom,
+}
@@ -5,4 +5,12 @@
 pub mod user;
 pub mod asset;
 pub mod tangle_proof;
-pub mod log_model;
\ No newline at end of file
+pub mod log_model;
+pub mod did_resolution;
+pub mod credential;
+pub mod organisation;
+pub mod evm_transaction;
+pub mod license;
+pub mod signed_request;
+pub mod proof_revocation;
+pub mod audit_entry;
@@ -0,0 +1,44 @@
+// SPDX-FileCopyrightText: 2024 Fondazione LINKS
+//
+// SPDX-License-Identifier: APACHE-2.0
+
+use identity_iota::core::Timestamp;
+use serde::{Serialize, Deserialize};
+
+/// DID of an organisation publishing proofs through its members, its key lives in the key storage.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Organisation{
+    pub did: String,
+    pub fragment: String,
+    pub name: String,
+    #[serde(default)]
+    pub members: Vec<Membership>,
+}
+
+/// Delegation of the proof-publishing rights of an organisation to a member DID.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Membership{
+    pub member_did: String,
+    pub credential_id: String,
+    /// Delegation credential signed by the organisation, encoded as a JWT
+    pub delegation: String,
+    pub valid_from: String,
+    pub valid_until: Option<String>,
+    /// Set when the organisation removes the member, proofs signed afterwards are not valid
+    pub revoked_at: Option<String>,
+}
+
+impl Membership {
+    /// Whether the delegation was in force at `timestamp`.
+    pub fn is_active_at(&self, timestamp: Timestamp) -> bool {
+        let before = |date: &Option<String>| match date {
+            Some(date) => Timestamp::parse(date).map_or(false, |date| timestamp < date),
+            None => true,
+        };
+        Timestamp::parse(&self.valid_from).map_or(false, |valid_from| valid_from <= timestamp)
+            && before(&self.valid_until)
+            && before(&self.revoked_at)
+    }
+}
@@ -0,0 +1,58 @@
+// SPDX-FileCopyrightText: 2024 Fondazione LINKS
+//
+// SPDX-License-Identifier: APACHE-2.0
+
+use serde::{Serialize, Deserialize};
+use mongodb::bson::{Bson, Document};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ProofState {
+    /// Withdrawn by the publisher
+    Revoked,
+    /// Replaced by a proof of a new version of the dataset
+    Superseded,
+}
+
+/// Action taken on the NFT of an asset whose proof is no longer current.
+///
+/// The deployed `Asset` contract can neither burn nor pause tokens, so the NFT
+/// is flagged in the db and the service refuses to operate on it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum NftAction {
+    Flagged,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProofRevocation {
+    pub state: ProofState,
+    pub reason: Option<String>,
+    /// Proof replacing the revoked one
+    pub superseded_by: Option<String>,
+    pub revoked_at: String,
+    /// Missing when no NFT was minted for the asset
+    pub nft_action: Option<NftAction>,
+}
+
+impl ProofState {
+    fn as_str(&self) -> &'static str {
+        match self {
+            ProofState::Revoked => "revoked",
+            ProofState::Superseded => "superseded",
+        }
+    }
+}
+
+impl From<ProofRevocation> for Bson {
+    fn from(revocation: ProofRevocation) -> Self {
+        let mut document = Document::new();
+        document.insert("state", revocation.state.as_str());
+        document.insert("reason", revocation.reason);
+        document.insert("supersededBy", revocation.superseded_by);
+        document.insert("revokedAt", revocation.revoked_at);
+        document.insert("nftAction", revocation.nft_action.map(|_| "flagged"));
+        Bson::Document(document)
+    }
+}
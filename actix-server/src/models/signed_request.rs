@@ -0,0 +1,42 @@
+// SPDX-FileCopyrightText: 2024 Fondazione LINKS
+//
+// SPDX-License-Identifier: APACHE-2.0
+
+use serde::{Serialize, Deserialize};
+use mongodb::bson::{Bson, Document};
+
+/// EIP-712 signature of a user over an NFT request, kept with the asset as
+/// evidence that the holder of the linked EVM account asked for it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SignedRequest {
+    /// Primary type of the typed data, `MintRequest`, `TransferRequest` or `ApprovalRequest`
+    pub primary_type: String,
+    /// EVM account that signed the request
+    pub signer: String,
+    pub signature: String,
+    /// EIP-712 digest the signature is over
+    pub digest: String,
+    pub chain_id: u64,
+    pub nonce: u64,
+    pub deadline: u64,
+    /// Transaction relayed by the service for the request
+    pub transaction_hash: Option<String>,
+    pub verified_at: String,
+}
+
+impl From<SignedRequest> for Bson {
+    fn from(request: SignedRequest) -> Self {
+        let mut document = Document::new();
+        document.insert("primaryType", request.primary_type);
+        document.insert("signer", request.signer);
+        document.insert("signature", request.signature);
+        document.insert("digest", request.digest);
+        document.insert("chainId", request.chain_id as i64);
+        document.insert("nonce", request.nonce as i64);
+        document.insert("deadline", request.deadline as i64);
+        document.insert("transactionHash", request.transaction_hash);
+        document.insert("verifiedAt", request.verified_at);
+        Bson::Document(document)
+    }
+}
@@ -6,9 +6,10 @@ use crypto::hashes::Digest;
 use crypto::hashes::blake2b::Blake2b256;
 use base64::{Engine as _, engine::general_purpose};
 
+use identity_iota::core::Timestamp;
 use identity_iota::credential::Jws;
 use identity_iota::document::verifiable::JwsVerificationOptions;
-use identity_iota::prelude::IotaDocument;
+use identity_iota::document::CoreDocument;
 use identity_iota::storage::JwkDocumentExt;
 use identity_iota::storage::JwsSignatureOptions;
 use identity_eddsa_verifier::EdDSAJwsVerifier;
@@ -16,9 +17,13 @@ use serde::Serialize;
 use serde::Deserialize;
 use serde_json::json;
 
+use crate::dtos::SignedProofRequest;
 use crate::services::iota_state::MemStorage;
 use crate::errors::TrustServiceError;
 
+/// Seconds a signed proof payload can be submitted before or after its `iat`.
+pub const SIGNED_PROOF_MAX_AGE_SECS: u64 = 300;
+
 
 #[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
@@ -27,6 +32,54 @@ pub struct TangleProof {
     dataset_digest: String,
     pub jws: String,
     pub did_publisher: String, //TODO: beware of pub
+    /// Organisation on whose behalf the publisher signed the proof
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub organisation_did: Option<String>,
+    /// Delegation credential of the publisher, signed by the organisation
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub delegation: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signed_at: Option<String>,
+}
+
+/// Payload signed by a publisher holding its own keys. Besides the digests it binds the
+/// asset id, the organisation and the superseded proof of the request, and the signing time.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct SignedProofPayload {
+    pub metadata_hash: String,
+    pub dataset_hash: String,
+    pub asset_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub organisation: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub supersedes: Option<String>,
+    /// Unix time of the signature
+    pub iat: i64,
+}
+
+impl SignedProofPayload {
+    /// Payload to sign for `request`, at `iat`.
+    pub fn for_request(request: &SignedProofRequest, iat: i64) -> Self {
+        SignedProofPayload {
+            metadata_hash: request.metadata_hash.clone(),
+            dataset_hash: request.asset_hash.clone(),
+            asset_id: request.asset_id.clone(),
+            organisation: request.organisation.clone(),
+            supersedes: request.supersedes.clone(),
+            iat,
+        }
+    }
+
+    fn validate(&self) -> Result<(), TrustServiceError> {
+        for (name, digest) in [("metadataHash", &self.metadata_hash), ("datasetHash", &self.dataset_hash)] {
+            let hex_digest = digest.strip_prefix("0x").unwrap_or(digest);
+            if hex_digest.is_empty() || !hex_digest.chars().all(|c| c.is_ascii_hexdigit()) {
+                return Err(TrustServiceError::InvalidProofPayload(format!("{name} is not a hex encoded digest")))
+            }
+        }
+        Ok(())
+    }
 }
 
 // TODO: implement two new, one that compute the hash, one that take as input a whole message
@@ -37,7 +90,7 @@ impl TangleProof {
         fragment: &String,
         metadata_digest: &String,
         dataset_digest: &String,
-        iota_document: &IotaDocument,
+        publisher_document: &CoreDocument,
         did_publisher: String
     ) -> Result<Self, TrustServiceError> {
 
@@ -53,10 +106,10 @@ impl TangleProof {
         });
 
         // Compute signature
-        let jws = iota_document.create_jws(&storage, &fragment, serde_json::to_vec(&payload).unwrap().as_slice(), &JwsSignatureOptions::default()).await?;
+        let jws = publisher_document.create_jws(&storage, &fragment, serde_json::to_vec(&payload).unwrap().as_slice(), &JwsSignatureOptions::default()).await?;
         // Verify signature
-        let _decoded_jws = iota_document.verify_jws(
-            &jws,
+        let _decoded_jws = publisher_document.verify_jws(
+            jws.as_str(),
             None,
             &EdDSAJwsVerifier::default(),
             &JwsVerificationOptions::default(),
@@ -66,14 +119,78 @@ impl TangleProof {
             dataset_digest: dataset_digest.clone(), 
             jws: jws.into(),
             did_publisher: did_publisher,
+            organisation_did: None,
+            delegation: None,
+            signed_at: None,
+        })
+
+    }
+
+    /// Builds a proof from a compact JWS signed by the publisher outside the service.
+    ///
+    /// The signature is checked against the publisher's DID document and the payload
+    /// must be the [`SignedProofPayload`] of the request: well-formed digests, the same
+    /// asset id, organisation and superseded proof, signed in the last
+    /// [`SIGNED_PROOF_MAX_AGE_SECS`]. A JWS read from the Tangle cannot anchor another asset.
+    pub fn from_signed_jws(
+        request: &SignedProofRequest,
+        publisher_document: &CoreDocument,
+    ) -> Result<Self, TrustServiceError> {
+
+        let jws = Jws::from(request.jws.clone());
+        let decoded_jws = publisher_document.verify_jws(
+            jws.as_str(),
+            None,
+            &EdDSAJwsVerifier::default(),
+            &JwsVerificationOptions::default(),
+        ).map_err(|_| TrustServiceError::ProofSignatureNotValid)?;
+
+        let payload: SignedProofPayload = serde_json::from_slice(&decoded_jws.claims)
+            .map_err(|err| TrustServiceError::InvalidProofPayload(err.to_string()))?;
+        payload.validate()?;
+
+        if payload.metadata_hash != request.metadata_hash || payload.dataset_hash != request.asset_hash {
+            return Err(TrustServiceError::InvalidProofPayload("signed digests do not match the request".to_owned()))
+        }
+        if payload.asset_id != request.asset_id || payload.organisation != request.organisation || payload.supersedes != request.supersedes {
+            return Err(TrustServiceError::InvalidProofPayload("signed asset id, organisation or superseded proof do not match the request".to_owned()))
+        }
+        if Timestamp::now_utc().to_unix().abs_diff(payload.iat) > SIGNED_PROOF_MAX_AGE_SECS {
+            return Err(TrustServiceError::InvalidProofPayload(format!("iat is more than {SIGNED_PROOF_MAX_AGE_SECS}s away from now")))
+        }
+
+        Ok(Self{
+            metadata_digest: payload.metadata_hash,
+            dataset_digest: payload.dataset_hash,
+            jws: jws.into(),
+            did_publisher: request.did.clone(),
+            organisation_did: None,
+            delegation: None,
+            signed_at: None,
         })
+    }
+
+    /// Records that the proof is published on behalf of an organisation,
+    /// the delegation must be in force at the signing time.
+    pub fn with_delegation(mut self, organisation_did: String, delegation: String, signed_at: Timestamp) -> Self {
+        self.organisation_did = Some(organisation_did);
+        self.delegation = Some(delegation);
+        self.signed_at = Some(signed_at.to_rfc3339());
+        self
+    }
+
+    pub fn metadata_digest(&self) -> &str {
+        &self.metadata_digest
+    }
 
+    pub fn dataset_digest(&self) -> &str {
+        &self.dataset_digest
     }
 
-    pub fn verify(&self, iota_document: &IotaDocument) -> Result<(), TrustServiceError> {
+    pub fn verify(&self, publisher_document: &CoreDocument) -> Result<(), TrustServiceError> {
         log::info!("Verifying proof...");
-        if iota_document.verify_jws(
-            &Jws::from(self.jws.clone()),
+        if publisher_document.verify_jws(
+            self.jws.as_str(),
             None,
             &EdDSAJwsVerifier::default(),
             &JwsVerificationOptions::default(),
@@ -6,10 +6,51 @@ use serde::{Serialize, Deserialize};
 
 use super::asset::Asset;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct User{
     pub did: String,
+    /// Fragment of the DID key in the key storage, empty for the DIDs holding their own keys
     pub fragment: String,
-    pub assets: Vec<Asset>
-}
\ No newline at end of file
+    pub assets: Vec<Asset>,
+    #[serde(default)]
+    pub evm_account: Option<EvmAccount>,
+    /// Tenant whose key storage holds the DID key, the shared key storage when missing
+    #[serde(default)]
+    pub tenant_id: Option<String>,
+    /// Hex SHA-256 of the access token of the DID, only DIDs created by the service get one
+    #[serde(default)]
+    pub access_token_hash: Option<String>,
+}
+
+impl User {
+    /// Publisher registered by its first signed proof, the service holds none of its keys.
+    pub fn self_custodied(did: &str) -> Self {
+        User { did: did.to_owned(), fragment: String::new(), assets: vec![], evm_account: None, tenant_id: None, access_token_hash: None }
+    }
+
+    pub fn is_self_custodied(&self) -> bool {
+        self.fragment.is_empty()
+    }
+}
+
+/// EVM account linked to the DID of a user, NFTs of its assets are transferred to it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EvmAccount{
+    /// Checksum-less hex address, 0x prefixed
+    pub address: String,
+    /// The key is derived and kept in the key storage of the service
+    pub custodial: bool,
+    /// Address index of the custodial key
+    pub address_index: Option<u32>,
+    /// DID URL of the verification method that binds the account to the DID,
+    /// only DIDs controlled by the service get one
+    pub verification_method: Option<String>,
+    /// Nonce expected in the next EIP-712 request signed with the account
+    #[serde(default)]
+    pub signature_nonce: u64,
+    /// JWS of the DID over the link message, for DIDs holding their own keys
+    #[serde(default)]
+    pub did_proof: Option<String>,
+}
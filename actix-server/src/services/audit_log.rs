@@ -0,0 +1,470 @@
+// SPDX-FileCopyrightText: 2024 Fondazione LINKS
+//
+// SPDX-License-Identifier: APACHE-2.0
+
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use actix_web::{web, HttpRequest, ResponseError};
+use async_trait::async_trait;
+use crypto::hashes::sha::{SHA256, SHA256_LEN};
+use identity_eddsa_verifier::EdDSAJwsVerifier;
+use identity_iota::core::Timestamp;
+use identity_iota::did::DIDUrl;
+use identity_iota::document::CoreDocument;
+use identity_iota::storage::{JwkDocumentExt, JwsSignatureOptions};
+use identity_iota::verification::jwk::Jwk;
+use identity_iota::verification::jws::Decoder;
+use tokio::sync::Mutex;
+
+use crate::controllers::log_controller::publish_log_internal;
+use crate::dtos::AuditLogReport;
+use crate::errors::TrustServiceError;
+use crate::models::audit_entry::{AuditAnchor, AuditEntry, AuditRecord, CheckpointKey, CheckpointPayload, GENESIS_HASH};
+use crate::models::credential::Issuer;
+use crate::services::did_resolver::DidResolver;
+use crate::services::iota_state::IotaState;
+use crate::services::repository::Repository;
+use crate::utils::env_u64;
+
+pub const DEFAULT_LOG_FILE_NAME: &str = "dlog.log";
+/// Header carrying the DID of the caller, recorded as declared
+pub const REQUESTER_DID_HEADER: &str = "X-Requester-Did";
+const DEFAULT_CHECKPOINT_ENTRIES: u64 = 100;
+
+/// Last entry appended to the log
+struct ChainHead {
+    next_seq: u64,
+    last_hash: String,
+    /// Entries appended after the last checkpoint
+    since_checkpoint: u64,
+    /// Last anchored checkpoint
+    anchor: Option<AuditAnchor>,
+}
+
+/// Chain rebuilt from the content of the log file.
+struct WalkedChain {
+    report: AuditLogReport,
+    head: ChainHead,
+    /// Last checkpoint with a valid signature
+    last_checkpoint: Option<AuditEntry>,
+}
+
+/// Signs the checkpoints of the audit log with a key of the service DID.
+#[async_trait]
+pub trait CheckpointSigner: Send + Sync {
+    /// DID the checkpoints are signed by
+    fn did(&self) -> &str;
+
+    /// Compact JWS of `payload`, verifiable against the document of [`CheckpointSigner::did`].
+    async fn sign(&self, payload: &[u8]) -> Result<String, TrustServiceError>;
+}
+
+/// Signs the checkpoints with the key of the issuer DID kept by the service.
+pub struct IssuerCheckpointSigner {
+    iota_state: Arc<IotaState>,
+    did_resolver: Arc<DidResolver>,
+    issuer: Issuer,
+}
+
+impl IssuerCheckpointSigner {
+    pub fn new(iota_state: Arc<IotaState>, did_resolver: Arc<DidResolver>, issuer: &Issuer) -> Self {
+        IssuerCheckpointSigner { iota_state, did_resolver, issuer: issuer.clone() }
+    }
+}
+
+#[async_trait]
+impl CheckpointSigner for IssuerCheckpointSigner {
+    fn did(&self) -> &str {
+        self.issuer.did.as_str()
+    }
+
+    async fn sign(&self, payload: &[u8]) -> Result<String, TrustServiceError> {
+        let issuer_document = self.did_resolver.resolve(self.issuer.did.as_str()).await?;
+        let jws = issuer_document.create_jws(
+            &self.iota_state.key_storage,
+            &self.issuer.fragment,
+            payload,
+            &JwsSignatureOptions::default()
+        ).await?;
+        Ok(jws.as_str().to_owned())
+    }
+}
+
+/// Append-only log of the accesses to the assets, kept in the `LOG_FILE_NAME` file
+/// and published to IPFS after every access.
+///
+/// Every entry carries the hash of the previous one, so that changing, removing or
+/// reordering entries breaks the chain. Every `AUDIT_CHECKPOINT_ENTRIES` entries the
+/// service DID signs the head of the chain, which cannot be rebuilt without its key.
+/// The last checkpoint is anchored in the repository, out of reach of whoever edits the
+/// file, so that truncating the log or replacing it is detected as well.
+pub struct AuditLog {
+    path: PathBuf,
+    checkpoint_entries: u64,
+    signer: Arc<dyn CheckpointSigner>,
+    did_resolver: Arc<DidResolver>,
+    mongo_repo: Arc<dyn Repository>,
+    /// Serializes the appends
+    head: Mutex<ChainHead>,
+}
+
+impl AuditLog {
+
+    /// Opens the log at `LOG_FILE_NAME`, checkpoints are signed by `signer`
+    /// every `AUDIT_CHECKPOINT_ENTRIES` entries.
+    pub async fn init(
+        signer: Arc<dyn CheckpointSigner>,
+        did_resolver: Arc<DidResolver>,
+        mongo_repo: Arc<dyn Repository>,
+    ) -> Result<Self, TrustServiceError> {
+        log::info!("Init audit log");
+        let path = PathBuf::from(std::env::var("LOG_FILE_NAME").unwrap_or_else(|_| DEFAULT_LOG_FILE_NAME.to_owned()));
+        let checkpoint_entries = env_u64("AUDIT_CHECKPOINT_ENTRIES", DEFAULT_CHECKPOINT_ENTRIES)?;
+        Self::open(path, checkpoint_entries, signer, did_resolver, mongo_repo).await
+    }
+
+    /// Recovers the head of the chain from the log file at `path`, after checking the whole
+    /// chain and that it still holds the anchored checkpoint. A broken log is not opened:
+    /// appending to it would hide where it was changed.
+    ///
+    /// A log written before the entries were chained is moved aside to `<path>.legacy`,
+    /// unless a checkpoint of the chained log was anchored.
+    pub async fn open(
+        path: PathBuf,
+        checkpoint_entries: u64,
+        signer: Arc<dyn CheckpointSigner>,
+        did_resolver: Arc<DidResolver>,
+        mongo_repo: Arc<dyn Repository>,
+    ) -> Result<Self, TrustServiceError> {
+        let checkpoint_entries = checkpoint_entries.max(1);
+        if let Some(parent_dir) = path.parent().filter(|parent_dir| !parent_dir.as_os_str().is_empty()) {
+            fs::create_dir_all(parent_dir).map_err(|_| TrustServiceError::FileOpenError)?;
+        }
+
+        let anchor = mongo_repo.get_audit_anchor(path.display().to_string().as_str()).await?;
+        let mut head = ChainHead { next_seq: 0, last_hash: GENESIS_HASH.to_owned(), since_checkpoint: 0, anchor: anchor.clone() };
+        let mut last_checkpoint = None;
+        match read_log(&path).await? {
+            Some(content) if is_chained(&content) => {
+                let signer_document = did_resolver.resolve(signer.did()).await?;
+                let walked = walk_chain(&content, signer.did(), &signer_document, anchor.as_ref())?;
+                if !walked.report.valid {
+                    return Err(TrustServiceError::AuditLogCorrupted(format!("{}: {}", path.display(), walked.report.failures.join("; "))))
+                }
+                head = walked.head;
+                last_checkpoint = walked.last_checkpoint;
+            },
+            Some(_) if anchor.is_some() => {
+                return Err(TrustServiceError::AuditLogCorrupted(format!("{} is not a chained audit log", path.display())))
+            },
+            Some(_) => {
+                let mut legacy_path = path.clone().into_os_string();
+                legacy_path.push(".legacy");
+                let legacy_path = PathBuf::from(legacy_path);
+                log::warn!("{} is not a chained audit log, moving it to {}", path.display(), legacy_path.display());
+                fs::rename(&path, &legacy_path).map_err(|_| TrustServiceError::FileWriteError)?;
+            },
+            None => if let Some(anchor) = &anchor {
+                return Err(TrustServiceError::AuditLogCorrupted(format!("{} is missing, checkpoint {} was anchored", path.display(), anchor.seq)))
+            },
+        }
+        log::info!("Audit log {}: next entry {}", path.display(), head.next_seq);
+
+        let audit_log = AuditLog {
+            path,
+            checkpoint_entries,
+            signer,
+            did_resolver,
+            mongo_repo,
+            head: Mutex::new(head),
+        };
+        let mut head = audit_log.head.lock().await;
+        // checkpoints written before the anchors were kept, or whose anchor was not stored
+        let unanchored = last_checkpoint.filter(|checkpoint| head.anchor.as_ref().map_or(true, |anchor| anchor.seq < checkpoint.seq));
+        if let Some(checkpoint) = unanchored {
+            audit_log.anchor(&mut head, &checkpoint).await?;
+        }
+        // seals the entries written before the restart
+        if head.since_checkpoint > 0 {
+            if let Err(err) = audit_log.append_checkpoint(&mut head).await {
+                log::error!("Failed to sign audit log checkpoint: {}", err);
+            }
+        }
+        drop(head);
+        Ok(audit_log)
+    }
+
+    /// Appends the access to an asset and publishes the log to IPFS.
+    ///
+    /// The caller must not serve the asset when the access could not be written.
+    pub async fn record_access<T>(
+        &self,
+        req: &HttpRequest,
+        asset_id: Option<&str>,
+        proof_id: Option<&str>,
+        result: &Result<T, TrustServiceError>,
+    ) -> Result<(), TrustServiceError> {
+        let requester = req.headers().get(REQUESTER_DID_HEADER)
+            .and_then(|requester| requester.to_str().ok())
+            .map(|requester| requester.to_owned());
+        let (status, error) = match result {
+            Ok(_) => (200, None),
+            Err(err) => (err.status_code().as_u16(), Some(err.to_string())),
+        };
+        let record = AuditRecord::Access {
+            requester,
+            remote_address: req.peer_addr().map(|peer_addr| peer_addr.to_string()),
+            endpoint: format!("{} {}", req.method(), req.uri()),
+            asset_id: asset_id.map(|asset_id| asset_id.to_owned()),
+            proof_id: proof_id.map(|proof_id| proof_id.to_owned()),
+            status,
+            error,
+        };
+
+        let mut head = self.head.lock().await;
+        self.append(&mut head, record).await?;
+        if head.since_checkpoint >= self.checkpoint_entries {
+            // the access is already written, the checkpoint is tried again with the next one
+            if let Err(err) = self.append_checkpoint(&mut head).await {
+                log::error!("Failed to sign audit log checkpoint: {}", err);
+            }
+        }
+        drop(head);
+
+        log::info!("Pushing to IPFS");
+        match publish_log_internal(&self.mongo_repo).await {
+            Ok(_) => log::info!("Log successfully published to IPFS"),
+            Err(e) => log::error!("Failed to publish log to IPFS: {}. Continuing with asset retrieval.", e),
+        }
+        Ok(())
+    }
+
+    /// Walks the whole log checking the sequence numbers, the hashes, the links between
+    /// the entries, the signatures of the checkpoints and that the anchored checkpoint is still there.
+    ///
+    /// Entries after the last checkpoint are only protected by the chain: removing them
+    /// is detected while the service runs, as the file no longer ends with the last entry written.
+    pub async fn verify(&self) -> Result<AuditLogReport, TrustServiceError> {
+        log::info!("Verifying audit log {}...", self.path.display());
+        let signer_document = self.did_resolver.resolve(self.signer.did()).await?;
+        // no entry is appended while the file is read
+        let head = self.head.lock().await;
+        let content = read_log(&self.path).await?.unwrap_or_default();
+
+        let mut walked = walk_chain(&content, self.signer.did(), &signer_document, head.anchor.as_ref())?;
+        if walked.head.next_seq != head.next_seq || walked.head.last_hash != head.last_hash {
+            walked.report.failures.push(format!("the log ends after {} entries, {} were written", walked.head.next_seq, head.next_seq));
+        }
+        walked.report.valid = walked.report.failures.is_empty();
+        Ok(walked.report)
+    }
+
+    /// Appends the entry of `record` and returns it. The file is written and synced on the
+    /// blocking thread pool, the lock on `head` keeps the appends in order.
+    async fn append(&self, head: &mut ChainHead, record: AuditRecord) -> Result<AuditEntry, TrustServiceError> {
+        let is_checkpoint = matches!(record, AuditRecord::Checkpoint { .. });
+        let mut entry = AuditEntry {
+            seq: head.next_seq,
+            timestamp: Timestamp::now_utc().to_rfc3339(),
+            record,
+            prev_hash: head.last_hash.clone(),
+            hash: String::new(),
+        };
+        entry.hash = entry_hash(&entry)?;
+
+        let mut line = serde_json::to_string(&entry)?;
+        line.push('\n');
+        let path = self.path.clone();
+        web::block(move || {
+            let mut file = File::options().create(true).append(true).open(&path).map_err(|_| TrustServiceError::FileOpenError)?;
+            file.write_all(line.as_bytes()).map_err(|_| TrustServiceError::FileWriteError)?;
+            file.sync_data().map_err(|_| TrustServiceError::FileWriteError)
+        }).await.map_err(|_| TrustServiceError::FileWriteError)??;
+
+        head.next_seq += 1;
+        head.last_hash = entry.hash.clone();
+        head.since_checkpoint = if is_checkpoint { 0 } else { head.since_checkpoint + 1 };
+        Ok(entry)
+    }
+
+    async fn append_checkpoint(&self, head: &mut ChainHead) -> Result<(), TrustServiceError> {
+        log::info!("Signing audit log checkpoint {}...", head.next_seq);
+        let payload = CheckpointPayload { seq: head.next_seq, prev_hash: head.last_hash.clone() };
+        let jws = self.signer.sign(serde_json::to_vec(&payload)?.as_slice()).await?;
+        let checkpoint = self.append(head, AuditRecord::Checkpoint { signer: self.signer.did().to_owned(), jws }).await?;
+        self.anchor(head, &checkpoint).await
+    }
+
+    /// Stores `checkpoint` as the last one of the log, along with the key that signed it.
+    async fn anchor(&self, head: &mut ChainHead, checkpoint: &AuditEntry) -> Result<(), TrustServiceError> {
+        let AuditRecord::Checkpoint { jws, .. } = &checkpoint.record else {
+            return Err(TrustServiceError::CustomError(format!("audit entry {} is not a checkpoint", checkpoint.seq)))
+        };
+        let kid = jws_kid(jws).map_err(TrustServiceError::CustomError)?;
+        let mut anchor = head.anchor.clone().unwrap_or_else(|| AuditAnchor {
+            log: self.path.display().to_string(),
+            seq: 0,
+            hash: String::new(),
+            keys: vec![],
+        });
+        if !anchor.keys.iter().any(|key| key.kid == kid) {
+            let signer_document = self.did_resolver.resolve(self.signer.did()).await?;
+            let jwk = method_jwk(&signer_document, kid.as_str())
+                .ok_or(TrustServiceError::CustomError(format!("{kid} is not a key of {}", self.signer.did())))?
+                .clone();
+            anchor.keys.push(CheckpointKey { kid, jwk });
+        }
+        anchor.seq = checkpoint.seq;
+        anchor.hash = checkpoint.hash.clone();
+        self.mongo_repo.store_audit_anchor(&anchor).await?;
+        log::info!("Audit log checkpoint {} anchored", anchor.seq);
+        head.anchor = Some(anchor);
+        Ok(())
+    }
+}
+
+/// Content of the log file, `None` when there is no file.
+async fn read_log(path: &Path) -> Result<Option<String>, TrustServiceError> {
+    let path = path.to_owned();
+    web::block(move || match fs::read_to_string(&path) {
+        Ok(content) => Ok(Some(content)),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(_) => Err(TrustServiceError::FileOpenError),
+    }).await.map_err(|_| TrustServiceError::FileOpenError)?
+}
+
+/// Whether the log starts with a chained entry, an empty log is.
+fn is_chained(content: &str) -> bool {
+    match content.lines().find(|line| !line.trim().is_empty()) {
+        Some(first_line) => serde_json::from_str::<AuditEntry>(first_line).is_ok(),
+        None => true,
+    }
+}
+
+/// Walks the entries of `content`, reporting every broken link and checking that the
+/// entry at the sequence number of `anchor` is the anchored checkpoint.
+fn walk_chain(
+    content: &str,
+    service_did: &str,
+    signer_document: &CoreDocument,
+    anchor: Option<&AuditAnchor>,
+) -> Result<WalkedChain, TrustServiceError> {
+    let keys = anchor.map(|anchor| anchor.keys.as_slice()).unwrap_or_default();
+    let mut report = AuditLogReport::default();
+    let mut head = ChainHead { next_seq: 0, last_hash: GENESIS_HASH.to_owned(), since_checkpoint: 0, anchor: anchor.cloned() };
+    let mut last_checkpoint = None;
+    let mut anchor_found = false;
+    for (line_index, line) in content.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()) {
+        let line_number = line_index + 1;
+        let entry: AuditEntry = match serde_json::from_str(line) {
+            Ok(entry) => entry,
+            Err(err) => {
+                report.failures.push(format!("line {line_number}: not an audit entry: {err}"));
+                continue
+            },
+        };
+        report.entries += 1;
+        if entry.seq != head.next_seq {
+            report.failures.push(format!("line {line_number}: entry {} found, expected {}", entry.seq, head.next_seq));
+        }
+        if entry.prev_hash != head.last_hash {
+            report.failures.push(format!("line {line_number}: entry {} is not linked to the previous entry", entry.seq));
+        }
+        if entry_hash(&entry)? != entry.hash {
+            report.failures.push(format!("line {line_number}: entry {} was modified", entry.seq));
+        }
+        if let Some(anchor) = anchor.filter(|anchor| anchor.seq == entry.seq) {
+            anchor_found = true;
+            if anchor.hash != entry.hash {
+                report.failures.push(format!("line {line_number}: entry {} is not the anchored checkpoint", entry.seq));
+            }
+        }
+        head.since_checkpoint += 1;
+        if let AuditRecord::Checkpoint { signer, jws } = &entry.record {
+            report.checkpoints += 1;
+            head.since_checkpoint = 0;
+            match verify_checkpoint(service_did, signer_document, keys, &entry, signer, jws) {
+                Ok(()) => {
+                    report.last_checkpoint = Some(entry.seq);
+                    last_checkpoint = Some(entry.clone());
+                },
+                Err(reason) => report.failures.push(format!("line {line_number}: checkpoint {}: {reason}", entry.seq)),
+            }
+        }
+        head.next_seq = entry.seq + 1;
+        head.last_hash = entry.hash;
+    }
+
+    if let Some(anchor) = anchor.filter(|_| !anchor_found) {
+        report.failures.push(format!("the anchored checkpoint {} is missing", anchor.seq));
+    }
+    report.unsigned_entries = match report.last_checkpoint {
+        Some(last_checkpoint) => head.next_seq.saturating_sub(last_checkpoint + 1),
+        None => head.next_seq,
+    };
+    report.valid = report.failures.is_empty();
+    Ok(WalkedChain { report, head, last_checkpoint })
+}
+
+/// Checks that the checkpoint is signed by the service DID over the position it is found at.
+///
+/// The key is selected by the `kid` of the signature: the one kept in the anchor when the
+/// checkpoint was signed, otherwise the method of the current document of the service DID.
+fn verify_checkpoint(
+    service_did: &str,
+    signer_document: &CoreDocument,
+    keys: &[CheckpointKey],
+    entry: &AuditEntry,
+    signer: &str,
+    jws: &str,
+) -> Result<(), String> {
+    if signer != service_did {
+        return Err(format!("signed by {signer}, not by the service"))
+    }
+    let kid = jws_kid(jws)?;
+    let method_url = DIDUrl::parse(kid.as_str()).map_err(|_| format!("the kid {kid} is not a DID URL"))?;
+    if method_url.did().to_string() != service_did {
+        return Err(format!("signed with {kid}, not with a key of the service"))
+    }
+    let jwk = keys.iter().find(|key| key.kid == kid).map(|key| &key.jwk)
+        .or_else(|| method_jwk(signer_document, kid.as_str()))
+        .ok_or(format!("key {kid} not found"))?;
+
+    let decoded_jws = Decoder::new().decode_compact_serialization(jws.as_bytes(), None)
+        .and_then(|decoded_jws| decoded_jws.verify(&EdDSAJwsVerifier::default(), jwk))
+        .map_err(|_| "signature not valid".to_owned())?;
+    let payload: CheckpointPayload = serde_json::from_slice(&decoded_jws.claims).map_err(|err| err.to_string())?;
+    if payload.seq != entry.seq || payload.prev_hash != entry.prev_hash {
+        return Err(format!("signed for entry {}", payload.seq))
+    }
+    Ok(())
+}
+
+/// `kid` in the protected header of a compact JWS.
+fn jws_kid(jws: &str) -> Result<String, String> {
+    let decoded_jws = Decoder::new().decode_compact_serialization(jws.as_bytes(), None)
+        .map_err(|err| format!("malformed signature: {err}"))?;
+    decoded_jws.protected_header()
+        .and_then(|header| header.kid())
+        .map(|kid| kid.to_owned())
+        .ok_or("the signature has no kid".to_owned())
+}
+
+/// Public key of the verification method `kid` of `document`.
+fn method_jwk<'a>(document: &'a CoreDocument, kid: &str) -> Option<&'a Jwk> {
+    document.resolve_method(kid, None).and_then(|method| method.data().try_public_key_jwk().ok())
+}
+
+fn entry_hash(entry: &AuditEntry) -> Result<String, TrustServiceError> {
+    let unhashed = AuditEntry { hash: String::new(), ..entry.clone() };
+    Ok(sha256_hex(&serde_json::to_vec(&unhashed)?))
+}
+
+fn sha256_hex(content: &[u8]) -> String {
+    let mut digest = [0u8; SHA256_LEN];
+    SHA256(content, &mut digest);
+    digest.iter().map(|byte| format!("{byte:02x}")).collect()
+}
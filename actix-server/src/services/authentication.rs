@@ -0,0 +1,245 @@
+// SPDX-FileCopyrightText: 2024 Fondazione LINKS
+//
+// SPDX-License-Identifier: APACHE-2.0
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use actix_web::dev::Payload;
+use actix_web::http::header;
+use actix_web::{web, FromRequest, HttpRequest};
+use crypto::hashes::sha::{SHA256, SHA256_LEN};
+use futures_util::future::LocalBoxFuture;
+use identity_eddsa_verifier::EdDSAJwsVerifier;
+use identity_iota::did::DIDUrl;
+use identity_iota::document::verifiable::JwsVerificationOptions;
+use identity_iota::verification::jws::Decoder;
+use serde::Deserialize;
+
+use crate::errors::TrustServiceError;
+use crate::services::did_resolver::DidResolver;
+use crate::services::repository::Repository;
+use crate::utils::env_u64;
+
+pub const BEARER_SCHEME: &str = "Bearer";
+pub const DID_SCHEME: &str = "DID";
+pub const DEFAULT_AUTH_MAX_AGE_SECS: u64 = 300;
+const ACCESS_TOKEN_LEN: usize = 32;
+
+/// Authenticated author of a request, see [`Authenticator`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Caller {
+    /// Holder of `ADMIN_API_KEY`
+    Admin,
+    Did(String),
+}
+
+impl Caller {
+
+    pub fn did(&self) -> Option<&str> {
+        match self {
+            Caller::Admin => None,
+            Caller::Did(did) => Some(did.as_str()),
+        }
+    }
+
+    pub fn is_admin(&self) -> bool {
+        matches!(self, Caller::Admin)
+    }
+
+    /// Refuses callers other than `did`, the administrator included.
+    pub fn require_did(&self, did: &str) -> Result<(), TrustServiceError> {
+        match self {
+            Caller::Did(caller) if caller == did => Ok(()),
+            _ => Err(TrustServiceError::NotAuthorized(self.to_string())),
+        }
+    }
+
+    /// Refuses callers other than `did` and the administrator.
+    pub fn require_did_or_admin(&self, did: &str) -> Result<(), TrustServiceError> {
+        match self {
+            Caller::Admin => Ok(()),
+            _ => self.require_did(did),
+        }
+    }
+
+    pub fn require_admin(&self) -> Result<(), TrustServiceError> {
+        match self {
+            Caller::Admin => Ok(()),
+            _ => Err(TrustServiceError::NotAuthorized(self.to_string())),
+        }
+    }
+
+    /// Refuses callers other than the DIDs of the tenant and the administrator.
+    pub async fn require_tenant(&self, mongo_repo: &dyn Repository, tenant_id: &str) -> Result<(), TrustServiceError> {
+        let did = match self {
+            Caller::Admin => return Ok(()),
+            Caller::Did(did) => did,
+        };
+        match mongo_repo.get_user(did).await {
+            Ok(user) if user.tenant_id.as_deref() == Some(tenant_id) => Ok(()),
+            Ok(_) | Err(TrustServiceError::UserDidNotFound) => Err(TrustServiceError::NotAuthorized(did.clone())),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+impl std::fmt::Display for Caller {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Caller::Admin => write!(f, "the administrator"),
+            Caller::Did(did) => write!(f, "{did}"),
+        }
+    }
+}
+
+/// Claims of a request signed with a DID, bound to its method and path.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RequestClaims {
+    /// HTTP method
+    htm: String,
+    /// Path of the request, e.g. `/api/tenants/acme/export`
+    htu: String,
+    /// Unix time of the signature
+    iat: u64,
+    /// Random id, a signature is accepted only once
+    jti: String,
+}
+
+/// Authenticates the callers from the `Authorization` header:
+///
+/// - `Bearer <ADMIN_API_KEY>`: the administrator;
+/// - `Bearer <access token>`: a DID created by the service, the token is returned on creation;
+/// - `DID <compact JWS>`: any resolvable DID, the JWS is signed with a verification method of
+///   the DID (named by `kid`) over [`RequestClaims`] and is valid for `AUTH_MAX_AGE_SECS`.
+pub struct Authenticator {
+    admin_key_hash: Option<[u8; SHA256_LEN]>,
+    max_age: u64,
+    did_resolver: Arc<DidResolver>,
+    mongo_repo: Arc<dyn Repository>,
+    // jti of the signed requests still in their validity window, with their expiry
+    used_jtis: Mutex<HashMap<String, u64>>,
+}
+
+impl Authenticator {
+
+    pub fn from_env(did_resolver: Arc<DidResolver>, mongo_repo: Arc<dyn Repository>) -> Result<Self, TrustServiceError> {
+        let admin_key_hash = match std::env::var("ADMIN_API_KEY") {
+            Ok(admin_key) if !admin_key.is_empty() => Some(sha256(admin_key.as_bytes())),
+            _ => {
+                log::warn!("ADMIN_API_KEY is not set, the administrative endpoints are disabled");
+                None
+            }
+        };
+        let max_age = env_u64("AUTH_MAX_AGE_SECS", DEFAULT_AUTH_MAX_AGE_SECS)?;
+        Ok(Authenticator { admin_key_hash, max_age, did_resolver, mongo_repo, used_jtis: Mutex::new(HashMap::new()) })
+    }
+
+    pub async fn authenticate(&self, req: &HttpRequest) -> Result<Caller, TrustServiceError> {
+        let authorization = req.headers().get(header::AUTHORIZATION)
+            .ok_or(TrustServiceError::AuthenticationError("missing Authorization header".to_owned()))?
+            .to_str()
+            .map_err(|_| TrustServiceError::AuthenticationError("malformed Authorization header".to_owned()))?;
+        let (scheme, credential) = authorization.split_once(' ')
+            .ok_or(TrustServiceError::AuthenticationError("malformed Authorization header".to_owned()))?;
+
+        match scheme {
+            BEARER_SCHEME => self.authenticate_token(credential.trim()).await,
+            DID_SCHEME => self.authenticate_signature(req, credential.trim()).await.map(Caller::Did),
+            _ => Err(TrustServiceError::AuthenticationError(format!("unsupported scheme {scheme}"))),
+        }
+    }
+
+    async fn authenticate_token(&self, token: &str) -> Result<Caller, TrustServiceError> {
+        let token_hash = sha256(token.as_bytes());
+        if let Some(admin_key_hash) = self.admin_key_hash.as_ref() {
+            if constant_time_eq(&token_hash, admin_key_hash) {
+                return Ok(Caller::Admin)
+            }
+        }
+        match self.mongo_repo.get_user_by_access_token(hex(&token_hash).as_str()).await? {
+            Some(user) => Ok(Caller::Did(user.did)),
+            None => Err(TrustServiceError::AuthenticationError("invalid access token".to_owned())),
+        }
+    }
+
+    /// Checks a request signed with a verification method of a DID, returning the DID.
+    async fn authenticate_signature(&self, req: &HttpRequest, jws: &str) -> Result<String, TrustServiceError> {
+        let decoded = Decoder::new().decode_compact_serialization(jws.as_bytes(), None)
+            .map_err(|err| TrustServiceError::AuthenticationError(format!("malformed request signature: {err}")))?;
+        let kid = decoded.protected_header().and_then(|header| header.kid())
+            .ok_or(TrustServiceError::AuthenticationError("the request signature has no kid".to_owned()))?;
+        let method_url = DIDUrl::parse(kid)
+            .map_err(|_| TrustServiceError::AuthenticationError("the kid of the request signature is not a DID URL".to_owned()))?;
+        let did = method_url.did().to_string();
+
+        let document = self.did_resolver.resolve(did.as_str()).await?;
+        let verified = document.verify_jws(jws, None, &EdDSAJwsVerifier::default(), &JwsVerificationOptions::default())
+            .map_err(|_| TrustServiceError::AuthenticationError(format!("invalid request signature of {did}")))?;
+        let claims: RequestClaims = serde_json::from_slice(&verified.claims)
+            .map_err(|err| TrustServiceError::AuthenticationError(format!("invalid request claims: {err}")))?;
+
+        if !claims.htm.eq_ignore_ascii_case(req.method().as_str()) || claims.htu != req.path() {
+            return Err(TrustServiceError::AuthenticationError("the signature is for another request".to_owned()))
+        }
+        let now = unix_time();
+        if now.abs_diff(claims.iat) > self.max_age {
+            return Err(TrustServiceError::AuthenticationError("the request signature is expired".to_owned()))
+        }
+        self.consume_jti(format!("{did} {}", claims.jti), claims.iat.saturating_add(self.max_age), now)?;
+        Ok(did)
+    }
+
+    fn consume_jti(&self, jti: String, expiry: u64, now: u64) -> Result<(), TrustServiceError> {
+        let mut used_jtis = self.used_jtis.lock()
+            .map_err(|_| TrustServiceError::CustomError("replay cache poisoned".to_owned()))?;
+        used_jtis.retain(|_, used_expiry| *used_expiry >= now);
+        if used_jtis.insert(jti, expiry).is_some() {
+            return Err(TrustServiceError::AuthenticationError("the request signature was already used".to_owned()))
+        }
+        Ok(())
+    }
+}
+
+impl FromRequest for Caller {
+    type Error = TrustServiceError;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let req = req.clone();
+        Box::pin(async move {
+            let authenticator = req.app_data::<web::Data<Authenticator>>()
+                .ok_or(TrustServiceError::CustomError("authenticator not configured".to_owned()))?;
+            authenticator.authenticate(&req).await
+        })
+    }
+}
+
+/// New access token of a DID, with the hex SHA-256 kept in its place.
+pub fn new_access_token() -> Result<(String, String), TrustServiceError> {
+    let mut token = [0u8; ACCESS_TOKEN_LEN];
+    crypto::utils::rand::fill(&mut token).map_err(|err| TrustServiceError::CustomError(err.to_string()))?;
+    let token = hex(&token);
+    let token_hash = hex(&sha256(token.as_bytes()));
+    Ok((token, token_hash))
+}
+
+fn sha256(content: &[u8]) -> [u8; SHA256_LEN] {
+    let mut digest = [0u8; SHA256_LEN];
+    SHA256(content, &mut digest);
+    digest
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|elapsed| elapsed.as_secs()).unwrap_or(0)
+}
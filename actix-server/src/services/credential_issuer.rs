@@ -0,0 +1,205 @@
+// SPDX-FileCopyrightText: 2024 Fondazione LINKS
+//
+// SPDX-License-Identifier: APACHE-2.0
+
+use std::collections::HashMap;
+
+use identity_iota::core::{Duration, FromJson, Timestamp, Url};
+use identity_iota::credential::{Credential, CredentialBuilder, Jwt, RevocationBitmap, RevocationBitmapStatus, Status, Subject};
+use identity_iota::did::DIDUrl;
+use identity_iota::storage::{JwkDocumentExt, JwsSignatureOptions};
+use serde_json::{Map, Value};
+use tokio::sync::Mutex;
+
+use crate::errors::TrustServiceError;
+use crate::models::credential::{CredentialState, CredentialTemplate, IssuedCredential, Issuer};
+use crate::services::did_resolver::DidResolver;
+use crate::services::iota_state::IotaState;
+use crate::services::repository::Repository;
+
+pub const DEFAULT_TEMPLATES_PATH: &str = "./credential_templates.json";
+pub const REVOCATION_SERVICE_FRAGMENT: &str = "revocation";
+
+/// Issues JWT Verifiable Credentials signed with the service's own DID.
+///
+/// Every credential gets an index in the `RevocationBitmap2022` service of the
+/// issuer DID document, used to revoke or suspend it.
+pub struct CredentialIssuer {
+    issuer: Issuer,
+    templates: HashMap<String, CredentialTemplate>,
+    revocation_service: DIDUrl,
+    /// Serializes the updates of the revocation bitmap
+    revocation_lock: Mutex<()>,
+}
+
+impl CredentialIssuer {
+
+    /// Loads the credential templates and recovers the issuer DID from the db,
+    /// creating and publishing it the first time the service starts.
+    pub async fn init(iota_state: &IotaState, mongo_repo: &dyn Repository) -> Result<Self, TrustServiceError> {
+        log::info!("Init credential issuer");
+        let templates_path = std::env::var("CREDENTIAL_TEMPLATES_PATH").unwrap_or_else(|_| DEFAULT_TEMPLATES_PATH.to_owned());
+        let templates_file = std::fs::read(&templates_path).map_err(|_| TrustServiceError::FileOpenError)?;
+        let templates: HashMap<String, CredentialTemplate> = serde_json::from_slice(&templates_file)?;
+        log::info!("Loaded {} credential templates from {}", templates.len(), templates_path);
+
+        let issuer = match mongo_repo.get_issuer().await {
+            Ok(issuer) => issuer,
+            Err(TrustServiceError::IssuerNotFound) => {
+                log::info!("Creating issuer DID...");
+                let (document, fragment) = iota_state.create_did().await?;
+                let issuer = Issuer { did: document.id().to_string(), fragment, next_status_index: 0 };
+                mongo_repo.store_issuer(issuer.clone()).await?;
+                issuer
+            },
+            Err(err) => return Err(err),
+        };
+        log::info!("Issuer DID: {}", issuer.did);
+
+        let mut issuer_document = iota_state.resolve_did(issuer.did.as_str()).await?;
+        let revocation_service = issuer_document.id().to_url().join(format!("#{REVOCATION_SERVICE_FRAGMENT}"))?;
+        if issuer_document.resolve_service(REVOCATION_SERVICE_FRAGMENT).is_none() {
+            log::info!("Publishing revocation bitmap service...");
+            let service = RevocationBitmap::new().to_service(revocation_service.clone())?;
+            issuer_document.insert_service(service)?;
+            iota_state.update_did(issuer_document).await?;
+        }
+
+        Ok(CredentialIssuer { issuer, templates, revocation_service, revocation_lock: Mutex::new(()) })
+    }
+
+    pub fn issuer(&self) -> &Issuer {
+        &self.issuer
+    }
+
+    pub fn templates(&self) -> &HashMap<String, CredentialTemplate> {
+        &self.templates
+    }
+
+    /// Issues a credential to `holder_did` with the static claims of the template
+    /// and the claims of the request, see [`subject_claims`].
+    pub async fn issue(
+        &self,
+        iota_state: &IotaState,
+        did_resolver: &DidResolver,
+        mongo_repo: &dyn Repository,
+        holder_did: &str,
+        template_name: &str,
+        claims: Map<String, Value>,
+    ) -> Result<IssuedCredential, TrustServiceError> {
+        let template = self.templates.get(template_name)
+            .ok_or(TrustServiceError::CredentialTemplateNotFound(template_name.to_owned()))?;
+
+        let subject = Subject::from_json_value(Value::Object(subject_claims(template, holder_did, claims)?))?;
+
+        let credential_id = new_credential_id()?;
+
+        let issuance_date = Timestamp::now_utc();
+        let expiration_date = match template.validity_days {
+            Some(days) => Some(issuance_date.checked_add(Duration::days(days)).ok_or(TrustServiceError::CustomError("invalid validity period".to_owned()))?),
+            None => None,
+        };
+
+        let status_index = mongo_repo.allocate_status_index().await?;
+        let status: Status = RevocationBitmapStatus::new(self.revocation_service.clone(), status_index).into();
+
+        let mut builder = CredentialBuilder::default()
+            .id(Url::parse(credential_id.as_str())?)
+            .issuer(Url::parse(self.issuer.did.as_str())?)
+            .subject(subject)
+            .status(status)
+            .issuance_date(issuance_date);
+        for context in template.contexts.iter() {
+            builder = builder.context(Url::parse(context.as_str())?);
+        }
+        for credential_type in template.types.iter() {
+            builder = builder.type_(credential_type.clone());
+        }
+        if let Some(expiration_date) = expiration_date {
+            builder = builder.expiration_date(expiration_date);
+        }
+        let credential: Credential = builder.build()?;
+
+        log::info!("Signing credential {}...", credential_id);
+        let issuer_document = did_resolver.resolve(self.issuer.did.as_str()).await?;
+        let jwt: Jwt = issuer_document.create_credential_jwt(
+            &credential,
+            &iota_state.key_storage,
+            &self.issuer.fragment,
+            &JwsSignatureOptions::default(),
+            None
+        ).await?;
+
+        Ok(IssuedCredential {
+            credential_id,
+            issuer_did: self.issuer.did.clone(),
+            holder_did: holder_did.to_owned(),
+            template: template_name.to_owned(),
+            types: credential.types.iter().cloned().collect(),
+            issuance_date: issuance_date.to_rfc3339(),
+            expiration_date: expiration_date.map(|date| date.to_rfc3339()),
+            jwt: jwt.as_str().to_owned(),
+            status_index: Some(status_index),
+            status: CredentialState::Active,
+        })
+    }
+
+    /// Sets or clears the bit of a credential in the revocation bitmap
+    /// and publishes the updated issuer DID document.
+    pub async fn set_revoked(
+        &self,
+        iota_state: &IotaState,
+        status_index: u32,
+        revoked: bool,
+    ) -> Result<(), TrustServiceError> {
+        let _guard = self.revocation_lock.lock().await;
+
+        let mut issuer_document = iota_state.resolve_did(self.issuer.did.as_str()).await?;
+        if revoked {
+            log::info!("Revoking credential with index {}...", status_index);
+            issuer_document.revoke_credentials(REVOCATION_SERVICE_FRAGMENT, &[status_index])?;
+        } else {
+            log::info!("Reinstating credential with index {}...", status_index);
+            issuer_document.unrevoke_credentials(REVOCATION_SERVICE_FRAGMENT, &[status_index])?;
+        }
+        iota_state.update_did(issuer_document).await?;
+        Ok(())
+    }
+}
+
+/// Claims of the subject of a credential issued to `holder_did` with `template`.
+/// The request cannot set `id` nor override the static claims of the template.
+pub fn subject_claims(
+    template: &CredentialTemplate,
+    holder_did: &str,
+    claims: Map<String, Value>,
+) -> Result<Map<String, Value>, TrustServiceError> {
+    for required_claim in template.required_claims.iter() {
+        if !claims.contains_key(required_claim) {
+            return Err(TrustServiceError::MissingClaim(required_claim.clone()))
+        }
+    }
+    if let Some(reserved) = claims.keys().find(|claim| *claim == "id" || template.static_claims.contains_key(*claim)) {
+        return Err(TrustServiceError::ReservedClaim(reserved.clone()))
+    }
+
+    let mut subject_claims = template.static_claims.clone();
+    subject_claims.extend(claims);
+    subject_claims.insert("id".to_owned(), Value::String(holder_did.to_owned()));
+    Ok(subject_claims)
+}
+
+/// Random `urn:uuid` identifier for a new credential.
+pub(crate) fn new_credential_id() -> Result<String, TrustServiceError> {
+    let mut random_id = [0u8; 16];
+    crypto::utils::rand::fill(&mut random_id).map_err(|err| TrustServiceError::CustomError(err.to_string()))?;
+    Ok(format!("urn:uuid:{}", uuid_from_bytes(random_id)))
+}
+
+/// Formats 16 random bytes as a version 4 UUID.
+fn uuid_from_bytes(mut bytes: [u8; 16]) -> String {
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let hex: String = bytes.iter().map(|byte| format!("{byte:02x}")).collect();
+    format!("{}-{}-{}-{}-{}", &hex[0..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..32])
+}
@@ -0,0 +1,132 @@
+// SPDX-FileCopyrightText: 2024 Fondazione LINKS
+//
+// SPDX-License-Identifier: APACHE-2.0
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use identity_iota::document::CoreDocument;
+use serde::Serialize;
+
+use crate::errors::TrustServiceError;
+use crate::utils::{env_u64, env_usize};
+
+pub const DEFAULT_TTL_SECS: u64 = 300;
+pub const DEFAULT_MAX_SIZE: usize = 1000;
+
+struct CacheEntry {
+    document: CoreDocument,
+    inserted_at: Instant,
+}
+
+#[derive(Debug, Default, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DidCacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+    pub invalidations: u64,
+    pub entries: usize,
+    pub max_size: usize,
+    pub ttl_secs: u64,
+}
+
+struct DidCacheInner {
+    entries: HashMap<String, CacheEntry>,
+    stats: DidCacheStats,
+}
+
+/// In-memory cache of resolved DID documents.
+///
+/// Entries expire after the configured TTL and the oldest entry is evicted
+/// when the cache is full. Every DID updated or deactivated by the service
+/// must be invalidated, otherwise a stale document would be served until it expires.
+pub struct DidCache {
+    ttl: Duration,
+    max_size: usize,
+    inner: Mutex<DidCacheInner>,
+}
+
+impl DidCache {
+
+    pub fn new(ttl: Duration, max_size: usize) -> Self {
+        let stats = DidCacheStats { max_size, ttl_secs: ttl.as_secs(), ..Default::default() };
+        DidCache { ttl, max_size, inner: Mutex::new(DidCacheInner { entries: HashMap::new(), stats }) }
+    }
+
+    /// Reads `DID_CACHE_TTL_SECS` and `DID_CACHE_MAX_SIZE`, a TTL or size of 0 disables the cache.
+    pub fn from_env() -> Result<Self, TrustServiceError> {
+        let ttl = env_u64("DID_CACHE_TTL_SECS", DEFAULT_TTL_SECS)?;
+        let max_size = env_usize("DID_CACHE_MAX_SIZE", DEFAULT_MAX_SIZE)?;
+        log::info!("DID cache ttl: {}s, max size: {}", ttl, max_size);
+        Ok(Self::new(Duration::from_secs(ttl), max_size))
+    }
+
+    fn is_disabled(&self) -> bool {
+        self.ttl.is_zero() || self.max_size == 0
+    }
+
+    pub fn get(&self, did: &str) -> Option<CoreDocument> {
+        let mut inner = self.inner.lock().expect("DID cache lock poisoned");
+        let cached = match inner.entries.get(did) {
+            Some(entry) if entry.inserted_at.elapsed() < self.ttl => Some(entry.document.clone()),
+            Some(_) => {
+                inner.entries.remove(did);
+                inner.stats.evictions += 1;
+                None
+            },
+            None => None,
+        };
+        match cached {
+            Some(_) => inner.stats.hits += 1,
+            None => inner.stats.misses += 1,
+        }
+        cached
+    }
+
+    pub fn insert(&self, did: &str, document: CoreDocument) {
+        if self.is_disabled() {
+            return
+        }
+        let mut inner = self.inner.lock().expect("DID cache lock poisoned");
+        if !inner.entries.contains_key(did) && inner.entries.len() >= self.max_size {
+            let ttl = self.ttl;
+            let before = inner.entries.len();
+            inner.entries.retain(|_, entry| entry.inserted_at.elapsed() < ttl);
+            let mut evicted = (before - inner.entries.len()) as u64;
+
+            if inner.entries.len() >= self.max_size {
+                let oldest = inner.entries.iter()
+                    .min_by_key(|(_, entry)| entry.inserted_at)
+                    .map(|(did, _)| did.clone());
+                if let Some(oldest) = oldest {
+                    inner.entries.remove(&oldest);
+                    evicted += 1;
+                }
+            }
+            inner.stats.evictions += evicted;
+        }
+        inner.entries.insert(did.to_owned(), CacheEntry { document, inserted_at: Instant::now() });
+    }
+
+    /// Drops the cached document of `did`, to be called whenever the service changes it.
+    pub fn invalidate(&self, did: &str) {
+        let mut inner = self.inner.lock().expect("DID cache lock poisoned");
+        if inner.entries.remove(did).is_some() {
+            log::info!("Invalidated cached document of {}", did);
+            inner.stats.invalidations += 1;
+        }
+    }
+
+    pub fn clear(&self) {
+        let mut inner = self.inner.lock().expect("DID cache lock poisoned");
+        inner.stats.invalidations += inner.entries.len() as u64;
+        inner.entries.clear();
+    }
+
+    pub fn stats(&self) -> DidCacheStats {
+        let inner = self.inner.lock().expect("DID cache lock poisoned");
+        DidCacheStats { entries: inner.entries.len(), ..inner.stats.clone() }
+    }
+}
@@ -0,0 +1,287 @@
+// SPDX-FileCopyrightText: 2024 Fondazione LINKS
+//
+// SPDX-License-Identifier: APACHE-2.0
+
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+
+use identity_iota::core::BaseEncoding;
+use identity_iota::did::{CoreDID, DIDUrl, DID};
+use identity_iota::document::CoreDocument;
+use identity_iota::iota::block::output::AliasId;
+use identity_iota::iota::{IotaDID, IotaIdentityClientExt};
+use identity_iota::resolver::Resolver;
+use identity_iota::verification::jwk::{Jwk, JwkParamsOkp};
+use identity_iota::verification::jwu;
+use identity_iota::verification::{MethodRelationship, MethodScope, VerificationMethod};
+use iota_sdk::client::Client;
+use reqwest::redirect::Policy;
+
+use crate::errors::TrustServiceError;
+use crate::models::did_resolution::DidDocumentMetadata;
+use crate::services::did_cache::{DidCache, DidCacheStats};
+
+/// Multicodec prefix of an Ed25519 public key (varint of 0xed).
+const ED25519_MULTICODEC: [u8; 2] = [0xed, 0x01];
+pub const SUPPORTED_METHODS: [&str; 4] = ["iota", "key", "jwk", "web"];
+/// Limits of the download of a `did:web` document
+const DID_WEB_TIMEOUT: Duration = Duration::from_secs(10);
+const DID_WEB_MAX_REDIRECTS: usize = 3;
+pub const MAX_DID_WEB_DOCUMENT_LEN: usize = 128 * 1024;
+
+/// Resolves DIDs of any supported method into a method-agnostic `CoreDocument`.
+///
+/// The IOTA method is resolved against the node, `did:key` and `did:jwk` are
+/// expanded locally, `did:web` documents are fetched over HTTPS.
+/// Resolved documents are kept in a [`DidCache`] shared with `IotaState`.
+pub struct DidResolver {
+    resolver: Resolver<CoreDocument>,
+    cache: Arc<DidCache>,
+    client: Client,
+}
+
+impl DidResolver {
+
+    pub fn new(client: Client, cache: Arc<DidCache>) -> Self {
+        // did:web is always https, a plain http stand-in can be used for local testing
+        let web_scheme = std::env::var("DID_WEB_SCHEME").unwrap_or_else(|_| "https".to_owned());
+        Self::with_did_web_scheme(client, cache, web_scheme.as_str())
+    }
+
+    /// Resolver fetching the `did:web` documents over `web_scheme`. Only with `http`, meant
+    /// for a local stand-in server, the documents can be on loopback and private addresses.
+    pub fn with_did_web_scheme(client: Client, cache: Arc<DidCache>, web_scheme: &str) -> Self {
+        let web_scheme = web_scheme.to_owned();
+        let mut resolver: Resolver<CoreDocument> = Resolver::new();
+        resolver.attach_iota_handler(client.clone());
+        resolver.attach_handler("key".to_owned(), |did: CoreDID| async move { Self::expand_did_key(did) });
+        resolver.attach_handler("jwk".to_owned(), |did: CoreDID| async move { Self::expand_did_jwk(did) });
+        resolver.attach_handler("web".to_owned(), move |did: CoreDID| {
+            let web_scheme = web_scheme.clone();
+            async move { Self::fetch_did_web(&web_scheme, did).await }
+        });
+
+        DidResolver { resolver, cache, client }
+    }
+
+    pub async fn resolve(
+        &self,
+        did: &str
+    ) -> Result<CoreDocument, TrustServiceError> {
+        log::info!("Resolving did...");
+        log::info!("DID: {}", did);
+        if let Some(document) = self.cache.get(did) {
+            log::info!("DID document found in cache");
+            return Ok(document)
+        }
+
+        let did = CoreDID::parse(did)?;
+        if !SUPPORTED_METHODS.contains(&did.method()) {
+            return Err(TrustServiceError::UnsupportedDidMethod(did.method().to_owned()))
+        }
+
+        match self.resolver.resolve(&did).await {
+            Ok(document) => {
+                self.cache.insert(did.as_str(), document.clone());
+                Ok(document)
+            },
+            Err(err) => {
+                log::info!("Error {}", err);
+                Err(TrustServiceError::DidResolutionError(err))
+            },
+        }
+    }
+
+    /// Resolves a DID together with its document metadata.
+    ///
+    /// IOTA documents are always read from the node, as the metadata
+    /// and the Alias Output id are not kept in the cache.
+    pub async fn resolve_with_metadata(
+        &self,
+        did: &str
+    ) -> Result<(CoreDocument, DidDocumentMetadata), TrustServiceError> {
+        if !did.starts_with("did:iota:") {
+            let document = self.resolve(did).await?;
+            return Ok((document, DidDocumentMetadata::default()))
+        }
+
+        log::info!("Resolving did with metadata...");
+        let iota_did = IotaDID::try_from(did)?;
+        let iota_document = self.client.resolve_did(&iota_did).await?;
+        let alias_output_id = match self.client.alias_output_id(AliasId::from(&iota_did)).await {
+            Ok(output_id) => Some(output_id.to_string()),
+            Err(err) => {
+                log::warn!("Alias Output id of {} not found: {}", did, err);
+                None
+            }
+        };
+
+        let metadata = DidDocumentMetadata {
+            created: iota_document.metadata.created,
+            updated: iota_document.metadata.updated,
+            deactivated: iota_document.metadata.deactivated,
+            alias_output_id,
+        };
+        let document = CoreDocument::from(iota_document);
+        self.cache.insert(did, document.clone());
+        Ok((document, metadata))
+    }
+
+    pub fn invalidate(&self, did: &str) {
+        self.cache.invalidate(did);
+    }
+
+    pub fn cache_stats(&self) -> DidCacheStats {
+        self.cache.stats()
+    }
+
+    /// Expands a `did:key` holding an Ed25519 public key,
+    /// the only key type the service is able to verify.
+    fn expand_did_key(did: CoreDID) -> Result<CoreDocument, TrustServiceError> {
+        let multibase_key = did.method_id();
+        let decoded = BaseEncoding::decode_multibase(multibase_key)
+            .map_err(|_| TrustServiceError::InvalidDid(did.to_string()))?;
+        let public_key = decoded.strip_prefix(&ED25519_MULTICODEC)
+            .filter(|key| key.len() == 32)
+            .ok_or(TrustServiceError::InvalidDid(did.to_string()))?;
+
+        let mut params = JwkParamsOkp::new();
+        params.crv = "Ed25519".to_owned();
+        params.x = jwu::encode_b64(public_key);
+        let fragment = multibase_key.to_owned();
+        Self::single_key_document(did, Jwk::from_params(params), &fragment)
+    }
+
+    /// Expands a `did:jwk`, whose method id is the base64url encoded public JWK.
+    fn expand_did_jwk(did: CoreDID) -> Result<CoreDocument, TrustServiceError> {
+        let encoded_jwk = jwu::decode_b64(did.method_id())
+            .map_err(|_| TrustServiceError::InvalidDid(did.to_string()))?;
+        let jwk: Jwk = serde_json::from_slice(&encoded_jwk)
+            .map_err(|_| TrustServiceError::InvalidDid(did.to_string()))?;
+        if !jwk.is_public() {
+            return Err(TrustServiceError::InvalidDid(did.to_string()))
+        }
+        Self::single_key_document(did, jwk, "0")
+    }
+
+    fn single_key_document(did: CoreDID, jwk: Jwk, fragment: &str) -> Result<CoreDocument, TrustServiceError> {
+        let mut document = CoreDocument::builder(Default::default())
+            .id(did.clone())
+            .build()?;
+        let method = VerificationMethod::new_from_jwk(did.clone(), jwk, Some(fragment))?;
+        let method_url: DIDUrl = method.id().clone();
+        document.insert_method(method, MethodScope::VerificationMethod)?;
+        for relationship in [
+            MethodRelationship::Authentication,
+            MethodRelationship::AssertionMethod,
+            MethodRelationship::CapabilityInvocation,
+            MethodRelationship::CapabilityDelegation,
+        ] {
+            document.attach_method_relationship(&method_url, relationship)?;
+        }
+        Ok(document)
+    }
+
+    /// Maps a `did:web` to the URL of its `did.json` as defined by the did:web method specification.
+    pub fn did_web_url(did: &CoreDID, scheme: &str) -> Result<String, TrustServiceError> {
+        let mut segments = did.method_id().split(':');
+        let domain = segments.next()
+            .filter(|domain| !domain.is_empty())
+            .ok_or(TrustServiceError::InvalidDid(did.to_string()))?
+            .replace("%3A", ":")
+            .replace("%3a", ":");
+        let path: Vec<&str> = segments.collect();
+        if path.is_empty() {
+            Ok(format!("{scheme}://{domain}/.well-known/did.json"))
+        } else {
+            Ok(format!("{scheme}://{domain}/{}/did.json", path.join("/")))
+        }
+    }
+
+    /// Downloads the document of a `did:web` within [`DID_WEB_TIMEOUT`] and up to
+    /// [`MAX_DID_WEB_DOCUMENT_LEN`] bytes. Over https the host must resolve to public
+    /// addresses only, the request is then sent to the checked addresses and redirects
+    /// stay on the same host.
+    async fn fetch_did_web(scheme: &str, did: CoreDID) -> Result<CoreDocument, TrustServiceError> {
+        let url = Self::did_web_url(&did, scheme)?;
+        let parsed_url = reqwest::Url::parse(url.as_str()).map_err(|err| TrustServiceError::DidWebFetchError(err.to_string()))?;
+        let host = parsed_url.host_str().ok_or(TrustServiceError::InvalidDid(did.to_string()))?.to_owned();
+        log::info!("Fetching did:web document from {}", url);
+
+        let mut client_builder = reqwest::Client::builder().timeout(DID_WEB_TIMEOUT);
+        if scheme != "http" {
+            let port = parsed_url.port_or_known_default().unwrap_or(443);
+            let addrs: Vec<SocketAddr> = match host.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>() {
+                Ok(ip) => vec![SocketAddr::new(ip, port)],
+                Err(_) => tokio::net::lookup_host((host.as_str(), port))
+                    .await
+                    .map_err(|err| TrustServiceError::DidWebFetchError(format!("{host}: {err}")))?
+                    .collect(),
+            };
+            if addrs.is_empty() || !addrs.iter().all(|addr| is_public_address(addr.ip())) {
+                return Err(TrustServiceError::DidWebFetchError(format!("{host} does not resolve to public addresses only")))
+            }
+            // the addresses checked are the ones connected to
+            client_builder = client_builder.resolve_to_addrs(host.as_str(), &addrs);
+        }
+        let redirect_host = host.clone();
+        let redirect_policy = Policy::custom(move |attempt| {
+            if attempt.url().host_str() != Some(redirect_host.as_str()) {
+                attempt.error("redirected to another host")
+            } else if attempt.previous().len() > DID_WEB_MAX_REDIRECTS {
+                attempt.error("too many redirects")
+            } else {
+                attempt.follow()
+            }
+        });
+        let http_client = client_builder.redirect(redirect_policy)
+            .build()
+            .map_err(|err| TrustServiceError::DidWebFetchError(err.to_string()))?;
+
+        let mut response = http_client.get(parsed_url)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|err| TrustServiceError::DidWebFetchError(err.to_string()))?;
+        if response.content_length().map_or(false, |length| length > MAX_DID_WEB_DOCUMENT_LEN as u64) {
+            return Err(TrustServiceError::DidWebFetchError(format!("the document of {did} is longer than {MAX_DID_WEB_DOCUMENT_LEN} bytes")))
+        }
+        // the declared length can be missing or wrong
+        let mut body = vec![];
+        while let Some(chunk) = response.chunk().await.map_err(|err| TrustServiceError::DidWebFetchError(err.to_string()))? {
+            if body.len() + chunk.len() > MAX_DID_WEB_DOCUMENT_LEN {
+                return Err(TrustServiceError::DidWebFetchError(format!("the document of {did} is longer than {MAX_DID_WEB_DOCUMENT_LEN} bytes")))
+            }
+            body.extend_from_slice(&chunk);
+        }
+
+        let document: CoreDocument = serde_json::from_slice(&body)?;
+        if document.id() != &did {
+            return Err(TrustServiceError::DidWebFetchError(format!("{url} does not contain the document of {did}")))
+        }
+        Ok(document)
+    }
+}
+
+/// Whether `ip` can be reached on the internet: loopback, private, link-local, shared,
+/// documentation and unspecified addresses are not, nor their IPv4-mapped forms.
+pub fn is_public_address(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [first, second, ..] = ip.octets();
+            let shared = first == 100 && (second & 0xc0) == 64;
+            !(ip.is_private() || ip.is_loopback() || ip.is_link_local() || ip.is_unspecified()
+                || ip.is_broadcast() || ip.is_documentation() || ip.is_multicast() || shared || first == 0)
+        },
+        IpAddr::V6(ip) => {
+            if let Some(mapped) = ip.to_ipv4_mapped() {
+                return is_public_address(IpAddr::V4(mapped))
+            }
+            let first_segment = ip.segments()[0];
+            let unique_local = (first_segment & 0xfe00) == 0xfc00;
+            let link_local = (first_segment & 0xffc0) == 0xfe80;
+            !(ip.is_loopback() || ip.is_unspecified() || ip.is_multicast() || unique_local || link_local)
+        },
+    }
+}
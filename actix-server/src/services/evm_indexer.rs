@@ -0,0 +1,212 @@
+// SPDX-FileCopyrightText: 2024 Fondazione LINKS
+//
+// SPDX-License-Identifier: APACHE-2.0
+
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Duration;
+
+use actix_web::rt::time::sleep;
+use ethers::abi::RawLog;
+use ethers::contract::EthEvent;
+use ethers::providers::{Http, Middleware, Provider};
+use ethers::types::{Address, Filter, Log};
+
+use crate::contracts::asset::{Asset as AssetContract, MetadataUpdateFilter, TransferFilter};
+use crate::contracts::assetfactory::{AssetContractUpdatedFilter, AssetFactory, NftMintedFilter};
+use crate::errors::TrustServiceError;
+use crate::models::asset::Asset;
+use crate::services::evm_networks::{EvmNetwork, EvmNetworks};
+use crate::services::repository::Repository;
+use crate::utils::env_u64;
+
+const DEFAULT_CONFIRMATIONS: u64 = 12;
+const DEFAULT_BATCH_SIZE: u64 = 1000;
+const DEFAULT_POLL_SECONDS: u64 = 15;
+
+/// Follows the events of the AssetFactory of a network and of the asset NFTs it created,
+/// keeping the NFT addresses, the ownership, the burns, the metadata URIs and the `Asset`
+/// implementation of the factory recorded in Mongo in sync with the chain.
+///
+/// Only blocks with `EVM_INDEXER_CONFIRMATIONS` confirmations are indexed, so that
+/// reorganisations shallower than that never reach the db. The last indexed block is
+/// persisted per network, after a restart the indexer resumes from it.
+pub struct EvmIndexer {
+    network: String,
+    /// Assets without a network are on the default one
+    default_network: bool,
+    provider: Provider<Http>,
+    mongo_repo: Arc<dyn Repository>,
+    factory_address: Address,
+    start_block: u64,
+    confirmations: u64,
+    batch_size: u64,
+    poll_interval: Duration,
+    /// NFT contracts created by the factory, loaded from `getAssets` at the first run
+    nft_addresses: HashSet<Address>,
+    /// Whether `getAssets` was read, the factory may have no NFT yet
+    nft_addresses_loaded: bool,
+}
+
+impl EvmIndexer {
+
+    /// Returns `None` when `EVM_INDEXER_ENABLED` is not `true`. The start block and the
+    /// confirmations can be set per network, e.g. `SEPOLIA_EVM_INDEXER_START_BLOCK`.
+    pub fn from_env(evm_networks: &EvmNetworks, network: &EvmNetwork, mongo_repo: Arc<dyn Repository>) -> Result<Option<Self>, TrustServiceError> {
+        if std::env::var("EVM_INDEXER_ENABLED").map(|enabled| enabled != "true").unwrap_or(true) {
+            return Ok(None)
+        }
+        Ok(Some(EvmIndexer {
+            network: network.name.clone(),
+            default_network: evm_networks.default_network().name == network.name,
+            provider: network.provider().clone(),
+            mongo_repo,
+            factory_address: network.factory_address,
+            start_block: network.env_u64("EVM_INDEXER_START_BLOCK", 0)?,
+            confirmations: network.env_u64("EVM_INDEXER_CONFIRMATIONS", DEFAULT_CONFIRMATIONS)?,
+            batch_size: env_u64("EVM_INDEXER_BATCH_SIZE", DEFAULT_BATCH_SIZE)?.max(1),
+            poll_interval: Duration::from_secs(env_u64("EVM_INDEXER_POLL_SECONDS", DEFAULT_POLL_SECONDS)?),
+            nft_addresses: HashSet::new(),
+            nft_addresses_loaded: false,
+        }))
+    }
+
+    /// Indexes the chain forever, a failed batch is logged and retried at the next poll.
+    pub async fn run(mut self) {
+        log::info!("Starting EVM indexer on factory {:#x} of network {}", self.factory_address, self.network);
+        loop {
+            match self.index_next_batch().await {
+                // catching up, the next batch is already confirmed
+                Ok(true) => continue,
+                Ok(false) => {},
+                Err(err) => log::error!("EVM indexer error on network {}: {}", self.network, err),
+            }
+            sleep(self.poll_interval).await;
+        }
+    }
+
+    /// Indexes the next batch of confirmed blocks, returns whether more confirmed blocks are left.
+    pub async fn index_next_batch(&mut self) -> Result<bool, TrustServiceError> {
+        // the later contracts come with their NftMinted event
+        if !self.nft_addresses_loaded {
+            let asset_factory_sc = AssetFactory::new(self.factory_address, Arc::new(self.provider.clone()));
+            let nft_addresses = asset_factory_sc.get_assets().await.map_err(|err| TrustServiceError::ContractError(err.to_string()))?;
+            self.nft_addresses.extend(nft_addresses);
+            self.nft_addresses_loaded = true;
+        }
+
+        let head = self.provider.get_block_number().await.map_err(|err| TrustServiceError::ContractError(err.to_string()))?.as_u64();
+        let confirmed = match head.checked_sub(self.confirmations) {
+            Some(confirmed) => confirmed,
+            None => return Ok(false),
+        };
+        let from = match self.mongo_repo.get_indexer_cursor(self.network.as_str()).await? {
+            Some(cursor) => cursor + 1,
+            None => self.start_block,
+        };
+        if from > confirmed {
+            return Ok(false)
+        }
+        let to = confirmed.min(from + self.batch_size - 1);
+        log::debug!("Indexing blocks {}..={} of network {}", from, to, self.network);
+
+        // the factory logs come first, the contracts they create are followed from the same batch
+        let factory_filter = Filter::new()
+            .address(self.factory_address)
+            .topic0(vec![NftMintedFilter::signature(), AssetContractUpdatedFilter::signature()])
+            .from_block(from)
+            .to_block(to);
+        for log in self.get_logs(&factory_filter).await? {
+            self.index_log(&log).await?;
+        }
+
+        if !self.nft_addresses.is_empty() {
+            let asset_filter = Filter::new()
+                .address(self.nft_addresses.iter().copied().collect::<Vec<_>>())
+                .topic0(vec![TransferFilter::signature(), MetadataUpdateFilter::signature()])
+                .from_block(from)
+                .to_block(to);
+            for log in self.get_logs(&asset_filter).await? {
+                self.index_log(&log).await?;
+            }
+        }
+
+        self.mongo_repo.store_indexer_cursor(self.network.as_str(), to).await?;
+        Ok(to < confirmed)
+    }
+
+    async fn get_logs(&self, filter: &Filter) -> Result<Vec<Log>, TrustServiceError> {
+        self.provider.get_logs(filter).await.map_err(|err| TrustServiceError::ContractError(err.to_string()))
+    }
+
+    /// Applies a log of the factory or of one of its NFT contracts, the logs of other contracts are ignored.
+    pub async fn index_log(&mut self, log: &Log) -> Result<(), TrustServiceError> {
+        if log.address == self.factory_address {
+            self.handle_factory_log(log).await
+        } else if self.nft_addresses.contains(&log.address) {
+            self.handle_asset_log(log).await
+        } else {
+            Ok(())
+        }
+    }
+
+    async fn handle_factory_log(&mut self, log: &Log) -> Result<(), TrustServiceError> {
+        let raw_log = RawLog { topics: log.topics.clone(), data: log.data.to_vec() };
+        if let Ok(event) = <NftMintedFilter as EthEvent>::decode_log(&raw_log) {
+            self.nft_addresses.insert(event.istance_address);
+            let nft_addr = format!("{:#x}", event.istance_address);
+            let mint_block = log.block_number.map(|block_number| block_number.as_u64());
+            match self.mongo_repo.find_asset_by_proof(event.proof_id.as_str()).await? {
+                // minted by the service, but the address was lost before being stored
+                Some((_, asset)) if asset.nft_addr.is_none() => {
+                    log::info!("Recovered NFT {} of asset {}", nft_addr, asset.asset_id);
+                    self.mongo_repo.store_nft_addr(asset.asset_id, nft_addr, self.network.as_str(), mint_block).await?;
+                },
+                // minted before the mint block was recorded
+                Some((_, asset)) if asset.nft_addr.as_deref() == Some(nft_addr.as_str()) && asset.mint_block.is_none() && self.is_on_network(&asset) => {
+                    self.mongo_repo.store_nft_addr(asset.asset_id, nft_addr, self.network.as_str(), mint_block).await?;
+                },
+                Some(_) => {},
+                None => log::info!("NFT {:#x} minted for unknown proof {}", event.istance_address, event.proof_id),
+            }
+        } else if let Ok(event) = <AssetContractUpdatedFilter as EthEvent>::decode_log(&raw_log) {
+            // the NFTs minted from now on are clones of the new implementation
+            log::info!("Asset contract of the factory of network {} updated to {:#x}", self.network, event.asset_contract);
+            self.mongo_repo.store_asset_contract(self.network.as_str(), format!("{:#x}", event.asset_contract).as_str()).await?;
+        }
+        Ok(())
+    }
+
+    async fn handle_asset_log(&self, log: &Log) -> Result<(), TrustServiceError> {
+        let raw_log = RawLog { topics: log.topics.clone(), data: log.data.to_vec() };
+        let nft_addr = format!("{:#x}", log.address);
+        // the same address can be taken by a contract on another network
+        let asset = match self.mongo_repo.get_asset_by_nft_addr(nft_addr.as_str(), self.network.as_str(), self.default_network).await? {
+            Some((_, asset)) => asset,
+            None => return Ok(()),
+        };
+        if let Ok(event) = <TransferFilter as EthEvent>::decode_log(&raw_log) {
+            if event.to == Address::zero() {
+                log::info!("NFT {} of asset {} burned", nft_addr, asset.asset_id);
+                self.mongo_repo.store_nft_burned(asset.asset_id.as_str()).await?;
+            } else {
+                self.mongo_repo.store_nft_ownership(asset.asset_id.as_str(), event.token_id.to_string(), format!("{:#x}", event.to)).await?;
+            }
+        } else if let Ok(event) = <MetadataUpdateFilter as EthEvent>::decode_log(&raw_log) {
+            log::info!("Metadata of token {} of NFT {} updated", event.token_id, nft_addr);
+            let asset_sc = AssetContract::new(log.address, Arc::new(self.provider.clone()));
+            match asset_sc.token_uri(event.token_id).call().await {
+                Ok(token_uri) if !token_uri.is_empty() => self.mongo_repo.store_nft_metadata_uri(asset.asset_id.as_str(), token_uri).await?,
+                Ok(_) => {},
+                // burnt since, the event is stale
+                Err(err) if err.is_revert() => log::warn!("Token {} of NFT {} has no URI: {}", event.token_id, nft_addr, err),
+                Err(err) => return Err(TrustServiceError::ContractError(err.to_string())),
+            }
+        }
+        Ok(())
+    }
+
+    fn is_on_network(&self, asset: &Asset) -> bool {
+        asset.is_on_network(self.network.as_str(), self.default_network)
+    }
+}
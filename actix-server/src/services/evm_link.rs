@@ -0,0 +1,201 @@
+// SPDX-FileCopyrightText: 2024 Fondazione LINKS
+//
+// SPDX-License-Identifier: APACHE-2.0
+
+use std::str::FromStr;
+
+use ethers::core::k256::ecdsa::{RecoveryId, Signature as K256Signature, VerifyingKey};
+use ethers::types::{Address, Signature};
+use ethers::utils::{hash_message, public_key_to_address};
+use identity_eddsa_verifier::EdDSAJwsVerifier;
+use identity_iota::core::Object;
+use identity_iota::did::DID;
+use identity_iota::document::verifiable::JwsVerificationOptions;
+use identity_iota::iota::IotaDocument;
+use identity_iota::verification::jwk::{Jwk, JwkParamsEc};
+use identity_iota::verification::jwu;
+use identity_iota::verification::{MethodData, MethodScope, MethodType, VerificationMethod};
+use identity_stronghold::StrongholdStorage;
+
+use crate::dtos::EvmLinkRequest;
+use crate::errors::TrustServiceError;
+use crate::models::user::EvmAccount;
+use crate::services::did_resolver::DidResolver;
+use crate::services::evm_signer::StrongholdEvmSigner;
+use crate::services::iota_state::IotaState;
+use crate::services::repository::Repository;
+
+pub const EVM_METHOD_FRAGMENT: &str = "evm-account";
+pub const EVM_METHOD_TYPE: &str = "EcdsaSecp256k1RecoveryMethod2020";
+
+/// Chain id of the default EVM network, the one accounts are linked on.
+pub fn chain_id() -> Result<u64, TrustServiceError> {
+    std::env::var("CHAIN_ID").map_err(|_| TrustServiceError::CustomError("$CHAIN_ID must be set".to_owned()))?
+        .parse::<u64>().map_err(|_| TrustServiceError::CustomError("CHAIN_ID is not a number".to_owned()))
+}
+
+/// Links an EVM account to a registered DID.
+///
+/// With `address` and `signature` the user proves control of its own account by signing
+/// the [`link_message`], otherwise a custodial account is derived in the key storage.
+/// DIDs holding their own keys also prove control of the DID with `did_proof`, a JWS signed
+/// by one of their verification methods over the [`did_link_message`], kept with the account.
+/// DIDs controlled by the service also get a verification method for the account.
+///
+/// A linked account is replaced only with `replace`, the signature nonce carries over.
+pub async fn link_evm_account(
+    iota_state: &IotaState,
+    did_resolver: &DidResolver,
+    mongo_repo: &dyn Repository,
+    did: &str,
+    request: &EvmLinkRequest,
+) -> Result<EvmAccount, TrustServiceError> {
+    let user = mongo_repo.get_user(did).await?;
+    if user.evm_account.is_some() && !request.replace {
+        return Err(TrustServiceError::EvmAccountAlreadyLinked)
+    }
+    let chain_id = chain_id()?;
+    let self_custodied_did = user.is_self_custodied();
+
+    let signature = request.signature.as_deref();
+    let own_address = match (request.address.as_deref(), signature) {
+        (Some(address), Some(_)) => Some(Address::from_str(address).map_err(|_| TrustServiceError::AddressError)?),
+        (None, None) => None,
+        _ => return Err(TrustServiceError::InvalidEvmSignature),
+    };
+    // checked before a custodial address index is spent
+    let did_proof = match (self_custodied_did, request.did_proof.as_deref()) {
+        (true, Some(did_proof)) => {
+            verify_did_proof(did_resolver, did, did_link_message(did, own_address.as_ref(), chain_id).as_str(), did_proof).await?;
+            Some(did_proof.to_owned())
+        },
+        (true, None) => return Err(TrustServiceError::InvalidDidProof(format!("{did} does not hold its keys in the service, didProof is required"))),
+        (false, _) => None,
+    };
+
+    let (address, verifying_key, address_index) = match (own_address, signature) {
+        (Some(address), Some(signature)) => {
+            let verifying_key = recover_linked_key(did, &address, chain_id, signature)?;
+            (address, verifying_key, None)
+        },
+        _ => {
+            let address_index = mongo_repo.allocate_evm_address_index().await?;
+            let stronghold_storage = iota_state.stronghold_storage_for(user.tenant_id.as_deref()).await?;
+            let (signer, verifying_key) = StrongholdEvmSigner::new(StrongholdStorage::clone(&stronghold_storage), address_index, chain_id).await?;
+            (ethers::signers::Signer::address(&signer), verifying_key, Some(address_index))
+        },
+    };
+
+    let verification_method = if self_custodied_did {
+        None
+    } else {
+        log::info!("Adding EVM verification method to {}...", did);
+        let mut document = iota_state.resolve_did(did).await?;
+        let method = evm_verification_method(&document, &verifying_key, &address, chain_id)?;
+        let method_id = method.id().to_string();
+        // the method of a replaced account has the same fragment
+        if document.remove_method(method.id()).is_some() {
+            log::info!("Replacing the EVM verification method of {}...", did);
+        }
+        document.insert_method(method, MethodScope::VerificationMethod)?;
+        iota_state.update_did(document).await?;
+        Some(method_id)
+    };
+
+    let evm_account = EvmAccount {
+        address: format!("{address:#x}"),
+        custodial: address_index.is_some(),
+        address_index,
+        verification_method,
+        // signatures of a previous account must not become valid again
+        signature_nonce: user.evm_account.map(|previous| previous.signature_nonce).unwrap_or(0),
+        did_proof,
+    };
+    mongo_repo.store_evm_account(did, evm_account.clone()).await?;
+    Ok(evm_account)
+}
+
+/// Message the owner of an EVM account signs (EIP-191 personal sign) to link it to a DID.
+pub fn link_message(did: &str, address: &Address, chain_id: u64) -> String {
+    format!("Link EVM account {address:#x} on chain {chain_id} to {did}")
+}
+
+/// Message a DID holding its own keys signs to link an account, `None` for a custodial account.
+pub fn did_link_message(did: &str, address: Option<&Address>, chain_id: u64) -> String {
+    match address {
+        Some(address) => link_message(did, address, chain_id),
+        None => format!("Link a custodial EVM account on chain {chain_id} to {did}"),
+    }
+}
+
+/// Checks that `did_proof` is a JWS over `message` signed by a verification method of `did`.
+pub async fn verify_did_proof(
+    did_resolver: &DidResolver,
+    did: &str,
+    message: &str,
+    did_proof: &str
+) -> Result<(), TrustServiceError> {
+    let document = did_resolver.resolve(did).await?;
+    let decoded = document.verify_jws(did_proof, None, &EdDSAJwsVerifier::default(), &JwsVerificationOptions::default())
+        .map_err(|err| TrustServiceError::InvalidDidProof(err.to_string()))?;
+    if &*decoded.claims != message.as_bytes() {
+        return Err(TrustServiceError::InvalidDidProof("the proof does not sign the link message".to_owned()))
+    }
+    Ok(())
+}
+
+/// CAIP-10 account id of an EVM account.
+pub fn blockchain_account_id(address: &Address, chain_id: u64) -> String {
+    format!("eip155:{chain_id}:{address:#x}")
+}
+
+/// Recovers the public key that signed the link message and checks it belongs to `address`.
+pub fn recover_linked_key(
+    did: &str,
+    address: &Address,
+    chain_id: u64,
+    signature: &str
+) -> Result<VerifyingKey, TrustServiceError> {
+    let signature = Signature::from_str(signature).map_err(|_| TrustServiceError::InvalidEvmSignature)?;
+    let message_hash = hash_message(link_message(did, address, chain_id));
+
+    let recovery_id = match signature.v {
+        27 | 28 => RecoveryId::try_from((signature.v - 27) as u8),
+        0 | 1 => RecoveryId::try_from(signature.v as u8),
+        _ => return Err(TrustServiceError::InvalidEvmSignature),
+    }.map_err(|_| TrustServiceError::InvalidEvmSignature)?;
+    let signature_bytes: [u8; 65] = signature.into();
+    let k256_signature = K256Signature::from_slice(&signature_bytes[..64]).map_err(|_| TrustServiceError::InvalidEvmSignature)?;
+
+    let verifying_key = VerifyingKey::recover_from_prehash(message_hash.as_bytes(), &k256_signature, recovery_id)
+        .map_err(|_| TrustServiceError::InvalidEvmSignature)?;
+    if &public_key_to_address(&verifying_key) != address {
+        return Err(TrustServiceError::InvalidEvmSignature)
+    }
+    Ok(verifying_key)
+}
+
+/// Builds the secp256k1 verification method that ties the DID to the EVM account.
+pub fn evm_verification_method(
+    document: &IotaDocument,
+    verifying_key: &VerifyingKey,
+    address: &Address,
+    chain_id: u64
+) -> Result<VerificationMethod, TrustServiceError> {
+    let point = verifying_key.to_encoded_point(false);
+    let mut params = JwkParamsEc::new();
+    params.crv = "secp256k1".to_owned();
+    params.x = jwu::encode_b64(point.x().ok_or(TrustServiceError::InvalidEvmSignature)?);
+    params.y = jwu::encode_b64(point.y().ok_or(TrustServiceError::InvalidEvmSignature)?);
+
+    let mut properties = Object::new();
+    properties.insert("blockchainAccountId".to_owned(), blockchain_account_id(address, chain_id).into());
+
+    let method = VerificationMethod::builder(properties)
+        .id(document.id().to_url().join(format!("#{EVM_METHOD_FRAGMENT}"))?)
+        .controller(document.id().clone().into())
+        .type_(MethodType::from_str(EVM_METHOD_TYPE)?)
+        .data(MethodData::PublicKeyJwk(Jwk::from_params(params)))
+        .build()?;
+    Ok(method)
+}
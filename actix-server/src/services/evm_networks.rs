@@ -0,0 +1,204 @@
+// SPDX-FileCopyrightText: 2024 Fondazione LINKS
+//
+// SPDX-License-Identifier: APACHE-2.0
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use ethers::middleware::SignerMiddleware;
+use ethers::providers::{Http, Middleware, Provider};
+use ethers::signers::{LocalWallet, Signer};
+use ethers::types::{Address, U256};
+use tokio::sync::{Mutex as AsyncMutex, OwnedMutexGuard};
+
+use crate::contracts::asset::Asset;
+use crate::contracts::assetfactory::AssetFactory;
+use crate::errors::TrustServiceError;
+use crate::models::asset::Asset as AssetRecord;
+use crate::services::repository::Repository;
+use crate::services::nft_service::ServiceSigner;
+use crate::services::tx_manager::{self, TransactionManager};
+use crate::utils::env_u64;
+
+/// Name of the network configured by the unprefixed variables, unless `EVM_DEFAULT_NETWORK` is set.
+const DEFAULT_NETWORK: &str = "default";
+/// ERC-165 identifier of ERC-721
+const ERC721_INTERFACE_ID: [u8; 4] = [0x80, 0xac, 0x58, 0xcd];
+
+/// EVM network the asset NFTs can be minted on.
+pub struct EvmNetwork {
+    pub name: String,
+    pub chain_id: u64,
+    pub factory_address: Address,
+    pub signer: Arc<ServiceSigner>,
+    pub tx_manager: TransactionManager,
+    /// Prefix of the variables of the network, empty for the default one
+    env_prefix: String,
+    /// Locks of the custodial accounts, held while one of their transactions is in progress
+    account_locks: Mutex<HashMap<Address, Arc<AsyncMutex<()>>>>,
+}
+
+impl EvmNetwork {
+
+    /// Reads `<PREFIX>RPC_PROVIDER`, `<PREFIX>CHAIN_ID`, `<PREFIX>ASSET_FACTORY_ADDR` and,
+    /// optionally, `<PREFIX>L2_PRIVATE_KEY` and `<PREFIX>TX_CONFIRMATIONS`.
+    fn from_env(name: &str, env_prefix: &str, mongo_repo: Arc<dyn Repository>) -> Result<Self, TrustServiceError> {
+        let var = |key: &str| {
+            let name = format!("{env_prefix}{key}");
+            std::env::var(&name).map_err(|_| TrustServiceError::CustomError(format!("${name} must be set")))
+        };
+        let chain_id = var("CHAIN_ID")?.parse::<u64>()
+            .map_err(|_| TrustServiceError::CustomError(format!("{env_prefix}CHAIN_ID is not a number")))?;
+        let factory_address = var("ASSET_FACTORY_ADDR")?.parse().map_err(|_| TrustServiceError::ContractAddressRecoveryError)?;
+
+        // networks without a key of their own share the wallet of the default one
+        let private_key = match var("L2_PRIVATE_KEY") {
+            Ok(private_key) => private_key,
+            Err(_) => std::env::var("L2_PRIVATE_KEY").map_err(|_| TrustServiceError::CustomError("$L2_PRIVATE_KEY must be set".to_owned()))?,
+        };
+        let wallet = private_key.parse::<LocalWallet>()
+            .map_err(|err| TrustServiceError::EvmSignerError(err.to_string()))?
+            .with_chain_id(chain_id);
+        let provider = Provider::<Http>::try_from(var("RPC_PROVIDER")?)
+            .map_err(|err| TrustServiceError::CustomError(err.to_string()))?;
+        let signer = Arc::new(SignerMiddleware::new(provider, wallet));
+        Self::new(name, env_prefix, signer, factory_address, mongo_repo)
+    }
+
+    /// Network reached through `signer`, on the chain of its wallet. The remaining settings
+    /// are read from the variables prefixed with `env_prefix`.
+    pub fn new(
+        name: &str,
+        env_prefix: &str,
+        signer: Arc<ServiceSigner>,
+        factory_address: Address,
+        mongo_repo: Arc<dyn Repository>,
+    ) -> Result<Self, TrustServiceError> {
+        let chain_id = signer.signer().chain_id();
+        let confirmations = prefixed_u64(env_prefix, "TX_CONFIRMATIONS", tx_manager::DEFAULT_CONFIRMATIONS)?;
+
+        log::info!("EVM network {} on chain {}, factory {:#x}", name, chain_id, factory_address);
+        Ok(EvmNetwork {
+            name: name.to_owned(),
+            chain_id,
+            factory_address,
+            tx_manager: TransactionManager::from_env(signer.clone(), mongo_repo, confirmations)?,
+            signer,
+            env_prefix: env_prefix.to_owned(),
+            account_locks: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Waits until no other transaction of the custodial `account` is in progress on this network.
+    /// Their managers are built for each operation, the lock keeps their nonces apart.
+    pub async fn lock_account(&self, account: Address) -> OwnedMutexGuard<()> {
+        let lock = self.account_locks.lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .entry(account)
+            .or_default()
+            .clone();
+        lock.lock_owned().await
+    }
+
+    /// Whether this is the default network, the one of the assets recorded without a network.
+    pub fn is_default(&self) -> bool {
+        self.env_prefix.is_empty()
+    }
+
+    pub fn provider(&self) -> &Provider<Http> {
+        self.signer.inner()
+    }
+
+    /// Numeric setting of the network, falling back to the unprefixed variable and then to `default`.
+    pub fn env_u64(&self, key: &str, default: u64) -> Result<u64, TrustServiceError> {
+        prefixed_u64(self.env_prefix.as_str(), key, default)
+    }
+
+    /// Checks that the node is on the configured chain and that the factory answers the calls
+    /// of the generated bindings. The first NFT contract of the factory, if any, must be an ERC-721.
+    pub async fn check_contracts(&self) -> Result<(), TrustServiceError> {
+        let incompatible = |reason: String| TrustServiceError::IncompatibleContract(format!("{}: {reason}", self.name));
+        let chain_id = self.provider().get_chainid().await.map_err(|err| incompatible(err.to_string()))?;
+        if chain_id != U256::from(self.chain_id) {
+            return Err(incompatible(format!("the node is on chain {chain_id}, not {}", self.chain_id)))
+        }
+        let code = self.provider().get_code(self.factory_address, None).await.map_err(|err| incompatible(err.to_string()))?;
+        if code.is_empty() {
+            return Err(incompatible(format!("no contract at {:#x}", self.factory_address)))
+        }
+
+        let asset_factory_sc = AssetFactory::new(self.factory_address, self.signer.clone());
+        let count = asset_factory_sc.get_count().call().await
+            .map_err(|err| incompatible(format!("{:#x} does not answer getCount: {err}", self.factory_address)))?;
+        if count.is_zero() {
+            return Ok(())
+        }
+        let nft_address = asset_factory_sc.assets(U256::zero()).call().await
+            .map_err(|err| incompatible(format!("{:#x} does not answer assets: {err}", self.factory_address)))?;
+        let asset_sc = Asset::new(nft_address, self.signer.clone());
+        match asset_sc.supports_interface(ERC721_INTERFACE_ID).call().await {
+            Ok(true) => Ok(()),
+            Ok(false) => Err(incompatible(format!("NFT contract {nft_address:#x} is not an ERC-721"))),
+            Err(err) => Err(incompatible(format!("NFT contract {nft_address:#x} does not answer supportsInterface: {err}"))),
+        }
+    }
+}
+
+fn prefixed_u64(env_prefix: &str, key: &str, default: u64) -> Result<u64, TrustServiceError> {
+    env_u64(format!("{env_prefix}{key}").as_str(), env_u64(key, default)?)
+}
+
+/// Registry of the configured EVM networks.
+///
+/// The default network is configured as before by `RPC_PROVIDER`, `CHAIN_ID`,
+/// `ASSET_FACTORY_ADDR` and `L2_PRIVATE_KEY`. `EVM_NETWORKS` lists further networks by
+/// name, each configured by the same variables prefixed with its name in upper case,
+/// e.g. `SEPOLIA_RPC_PROVIDER` for `sepolia`.
+pub struct EvmNetworks {
+    default_network: String,
+    networks: HashMap<String, EvmNetwork>,
+}
+
+impl EvmNetworks {
+
+    /// Registry holding only `default_network`.
+    pub fn new(default_network: EvmNetwork) -> Self {
+        let name = default_network.name.clone();
+        EvmNetworks { default_network: name.clone(), networks: HashMap::from([(name, default_network)]) }
+    }
+
+    pub fn from_env(mongo_repo: Arc<dyn Repository>) -> Result<Self, TrustServiceError> {
+        let default_network = std::env::var("EVM_DEFAULT_NETWORK").unwrap_or_else(|_| DEFAULT_NETWORK.to_owned());
+        let mut networks = HashMap::new();
+        networks.insert(default_network.clone(), EvmNetwork::from_env(&default_network, "", mongo_repo.clone())?);
+
+        let names = std::env::var("EVM_NETWORKS").unwrap_or_default();
+        for name in names.split(',').map(str::trim).filter(|name| !name.is_empty()) {
+            if networks.contains_key(name) {
+                return Err(TrustServiceError::CustomError(format!("EVM network {name} is configured twice")))
+            }
+            let env_prefix = format!("{}_", name.to_uppercase().replace('-', "_"));
+            networks.insert(name.to_owned(), EvmNetwork::from_env(name, &env_prefix, mongo_repo.clone())?);
+        }
+        Ok(EvmNetworks { default_network, networks })
+    }
+
+    /// Network called `name`, the default one when `None`.
+    pub fn get(&self, name: Option<&str>) -> Result<&EvmNetwork, TrustServiceError> {
+        let name = name.unwrap_or(self.default_network.as_str());
+        self.networks.get(name).ok_or(TrustServiceError::UnknownEvmNetwork(name.to_owned()))
+    }
+
+    pub fn default_network(&self) -> &EvmNetwork {
+        &self.networks[&self.default_network]
+    }
+
+    /// Network of the NFT of an asset, those minted before the networks were recorded are on the default one.
+    pub fn of_asset(&self, asset: &AssetRecord) -> Result<&EvmNetwork, TrustServiceError> {
+        self.get(asset.network.as_deref())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &EvmNetwork> {
+        self.networks.values()
+    }
+}
@@ -0,0 +1,147 @@
+// SPDX-FileCopyrightText: 2024 Fondazione LINKS
+//
+// SPDX-License-Identifier: APACHE-2.0
+
+use async_trait::async_trait;
+use crypto::keys::bip44::Bip44;
+use crypto::signatures::secp256k1_ecdsa;
+use ethers::core::k256::ecdsa::VerifyingKey;
+use ethers::signers::{to_eip155_v, Signer};
+use ethers::types::transaction::eip2718::TypedTransaction;
+use ethers::types::transaction::eip712::Eip712;
+use ethers::types::{Address, Signature, U256};
+use identity_stronghold::StrongholdStorage;
+use iota_sdk::client::constants::ETHER_COIN_TYPE;
+use iota_sdk::client::secret::SecretManage;
+
+/// Account index of the BIP44 path used for the custodial EVM accounts of the users.
+pub const EVM_ACCOUNT_INDEX: u32 = 0;
+
+#[derive(thiserror::Error, Debug)]
+pub enum StrongholdSignerError {
+    #[error("Stronghold error: {0}")]
+    Stronghold(String),
+    #[error("EIP-712 encoding error: {0}")]
+    Eip712(String),
+    #[error("Invalid public key")]
+    InvalidPublicKey,
+}
+
+/// An ethers [`Signer`] whose secp256k1 key is derived and kept inside the key storage Stronghold.
+///
+/// Each user gets its own address index on the `m/44'/60'/0'/0/index` path, the private
+/// key never leaves the Stronghold vault: Stronghold hashes the payload with Keccak256 and signs it.
+#[derive(Clone)]
+pub struct StrongholdEvmSigner {
+    stronghold_storage: StrongholdStorage,
+    chain: Bip44,
+    address: Address,
+    chain_id: u64,
+}
+
+impl std::fmt::Debug for StrongholdEvmSigner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("StrongholdEvmSigner")
+            .field("address", &self.address)
+            .field("address_index", &self.chain.address_index)
+            .field("chain_id", &self.chain_id)
+            .finish()
+    }
+}
+
+impl StrongholdEvmSigner {
+
+    /// Derives the signer of `address_index`, returning it with the public key of the account.
+    pub async fn new(stronghold_storage: StrongholdStorage, address_index: u32, chain_id: u64) -> Result<(Self, VerifyingKey), StrongholdSignerError> {
+        let chain = Bip44::new(ETHER_COIN_TYPE)
+            .with_account(EVM_ACCOUNT_INDEX)
+            .with_address_index(address_index);
+
+        // Stronghold only exposes the public key together with a signature
+        let (public_key, _) = Self::sign_raw(&stronghold_storage, chain, b"public key").await?;
+        let verifying_key = VerifyingKey::from_sec1_bytes(&public_key.to_bytes())
+            .map_err(|_| StrongholdSignerError::InvalidPublicKey)?;
+        let address = Address::from(<[u8; 20]>::from(public_key.evm_address()));
+
+        Ok((StrongholdEvmSigner { stronghold_storage, chain, address, chain_id }, verifying_key))
+    }
+
+    pub fn address_index(&self) -> u32 {
+        self.chain.address_index
+    }
+
+    async fn sign_raw(
+        stronghold_storage: &StrongholdStorage,
+        chain: Bip44,
+        msg: &[u8]
+    ) -> Result<(secp256k1_ecdsa::PublicKey, secp256k1_ecdsa::RecoverableSignature), StrongholdSignerError> {
+        stronghold_storage.as_secret_manager()
+            .sign_secp256k1_ecdsa(msg, chain)
+            .await
+            .map_err(|err| StrongholdSignerError::Stronghold(err.to_string()))
+    }
+
+    /// Signs the Keccak256 hash of `msg`, `v` is the bare recovery id.
+    async fn sign_keccak256(&self, msg: &[u8]) -> Result<(Signature, u8), StrongholdSignerError> {
+        let (_, signature) = Self::sign_raw(&self.stronghold_storage, self.chain, msg).await?;
+        let bytes = signature.to_bytes();
+        let signature = Signature {
+            r: U256::from_big_endian(&bytes[0..32]),
+            s: U256::from_big_endian(&bytes[32..64]),
+            v: 0,
+        };
+        Ok((signature, bytes[64]))
+    }
+}
+
+#[async_trait]
+impl Signer for StrongholdEvmSigner {
+    type Error = StrongholdSignerError;
+
+    /// EIP-191 personal message signature.
+    async fn sign_message<S: Send + Sync + AsRef<[u8]>>(&self, message: S) -> Result<Signature, Self::Error> {
+        let message = message.as_ref();
+        let mut prefixed = format!("\x19Ethereum Signed Message:\n{}", message.len()).into_bytes();
+        prefixed.extend_from_slice(message);
+
+        let (mut signature, recovery_id) = self.sign_keccak256(&prefixed).await?;
+        signature.v = recovery_id as u64 + 27;
+        Ok(signature)
+    }
+
+    async fn sign_transaction(&self, tx: &TypedTransaction) -> Result<Signature, Self::Error> {
+        let mut tx = tx.clone();
+        let chain_id = tx.chain_id().map(|id| id.as_u64()).unwrap_or(self.chain_id);
+        tx.set_chain_id(chain_id);
+
+        // the sighash is the Keccak256 of the unsigned RLP encoding
+        let (mut signature, recovery_id) = self.sign_keccak256(&tx.rlp()).await?;
+        signature.v = to_eip155_v(recovery_id, chain_id);
+        Ok(signature)
+    }
+
+    async fn sign_typed_data<T: Eip712 + Send + Sync>(&self, payload: &T) -> Result<Signature, Self::Error> {
+        let domain_separator = payload.domain_separator().map_err(|err| StrongholdSignerError::Eip712(err.to_string()))?;
+        let struct_hash = payload.struct_hash().map_err(|err| StrongholdSignerError::Eip712(err.to_string()))?;
+        let mut preimage = vec![0x19, 0x01];
+        preimage.extend_from_slice(&domain_separator);
+        preimage.extend_from_slice(&struct_hash);
+
+        let (mut signature, recovery_id) = self.sign_keccak256(&preimage).await?;
+        signature.v = recovery_id as u64 + 27;
+        Ok(signature)
+    }
+
+    fn address(&self) -> Address {
+        self.address
+    }
+
+    fn chain_id(&self) -> u64 {
+        self.chain_id
+    }
+
+    fn with_chain_id<T: Into<u64>>(mut self, chain_id: T) -> Self {
+        self.chain_id = chain_id.into();
+        self
+    }
+}
@@ -2,17 +2,19 @@
 // SPDX-License-Identifier: APACHE-2.0
 
 use std::path::PathBuf;
+use std::sync::Arc;
 use std::str::FromStr;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use std::fs;
 
 use anyhow::Result;
+use async_trait::async_trait;
 
 use crypto::keys::bip39::Mnemonic;
 use identity_iota::core::ToJson;
 use identity_iota::credential::Jws;
 use identity_iota::iota::IotaDID;
-use identity_iota::iota::block::output::AliasOutput;
+use identity_iota::iota::block::output::{AliasOutput, AliasOutputBuilder};
 use identity_iota::iota::IotaClientExt;
 use identity_iota::iota::IotaDocument;
 use identity_iota::iota::IotaIdentityClientExt;
@@ -43,20 +45,32 @@ use iota_sdk::types::block::output::{BasicOutputBuilder, Feature, OutputId, Unlo
 
 use crate::errors::TrustServiceError;
 use crate::models::tangle_proof::TangleProof;
+use crate::services::did_cache::DidCache;
+use crate::services::key_store::ServiceJwkStorage;
+use crate::services::tenant_storage::{TenantKeyStorage, TenantLease};
 use crate::utils::{request_faucet_funds, sync_print_balance};
 
 
-pub type MemStorage = Storage<StrongholdStorage, StrongholdStorage>;
+pub type MemStorage = Storage<ServiceJwkStorage, StrongholdStorage>;
 pub const MAIN_ACCOUNT: &str = "main-account";
 pub const PROOF_TAG: &str = "trust-service-proofs"; 
 
+/// Reads the proofs published on the Tangle.
+#[async_trait]
+pub trait ProofResolver: Send + Sync {
+  async fn resolve_proof(&self, proof_id: String) -> Result<TangleProof, TrustServiceError>;
+}
+
 pub struct IotaState {
   client: Client,
-  _stronghold_storage: StrongholdStorage,
+  stronghold_storage: StrongholdStorage,
+  jwk_storage: ServiceJwkStorage,
   pub key_storage: MemStorage,
   wallet: Wallet,
   address: Bech32Address,
   faucet: String,
+  did_cache: Arc<DidCache>,
+  tenant_storage: TenantKeyStorage,
 }
 
 impl IotaState {
@@ -112,9 +126,10 @@ impl IotaState {
 
     // Create storage for key-ids and JWKs.
     //
-    // In this example, the same stronghold file that is used to store
-    // key-ids as well as the JWKs.
-    let key_storage = Storage::new(stronghold_storage.clone(), stronghold_storage.clone());
+    // The key-ids are always stored in the stronghold file, the JWKs
+    // in the key store selected with KEY_STORE (the same stronghold by default).
+    let jwk_storage = ServiceJwkStorage::from_env(&stronghold_storage)?;
+    let key_storage = Storage::new(jwk_storage.clone(), stronghold_storage.clone());
 
     let client = Client::builder()
     .with_node(&node_url)?
@@ -138,7 +153,52 @@ impl IotaState {
     request_faucet_funds(&client, service_address.address(), faucet.as_str()).await?;
     let _ = account.sync(None).await?;
 
-    Ok(IotaState{ client, _stronghold_storage: stronghold_storage, key_storage, wallet, faucet, address: service_address.to_owned().into_bech32() })
+    let did_cache = Arc::new(DidCache::from_env()?);
+    let tenant_storage = TenantKeyStorage::from_env();
+
+    Ok(IotaState{ client, stronghold_storage, jwk_storage, key_storage, wallet, faucet, address: service_address.to_owned().into_bech32(), did_cache, tenant_storage })
+  }
+
+  pub fn client(&self) -> &Client {
+    &self.client
+  }
+
+  /// Key storage Stronghold, also holds the custodial EVM keys of the users.
+  pub fn stronghold_storage(&self) -> &StrongholdStorage {
+    &self.stronghold_storage
+  }
+
+  pub fn tenant_storage(&self) -> &TenantKeyStorage {
+    &self.tenant_storage
+  }
+
+  /// Stronghold holding the keys of a tenant, the shared key storage without a tenant.
+  /// The lease must be kept until the keys are no longer used.
+  pub async fn stronghold_storage_for(
+    &self,
+    tenant_id: Option<&str>
+  ) -> Result<TenantLease<StrongholdStorage>, TrustServiceError> {
+    match tenant_id {
+      Some(tenant_id) => self.tenant_storage.stronghold_storage(tenant_id).await,
+      None => Ok(TenantLease::shared(self.stronghold_storage.clone())),
+    }
+  }
+
+  /// Key storage of a tenant, whose key ids are kept in its own Stronghold and the private keys
+  /// in the configured key store. The lease must be kept until the keys are no longer used.
+  pub async fn key_storage_for(
+    &self,
+    tenant_id: Option<&str>
+  ) -> Result<TenantLease<MemStorage>, TrustServiceError> {
+    match tenant_id {
+      Some(tenant_id) => self.tenant_storage.key_storage(tenant_id, &self.jwk_storage).await,
+      None => Ok(TenantLease::shared(Storage::new(self.jwk_storage.clone(), self.stronghold_storage.clone()))),
+    }
+  }
+
+  /// Cache of resolved DID documents, shared with the `DidResolver`.
+  pub fn did_cache(&self) -> Arc<DidCache> {
+    self.did_cache.clone()
   }
 
   /// Creates a DID Document and publishes it in a new Alias Output.
@@ -148,13 +208,22 @@ impl IotaState {
   pub async fn create_did(
     &self,
     // _address: Address
+  ) -> Result<(IotaDocument, String), TrustServiceError> {
+    self.create_did_for(None).await
+  }
+
+  /// Creates and publishes a DID whose key is generated in the key storage of `tenant_id`.
+  pub async fn create_did_for(
+    &self,
+    tenant_id: Option<&str>
   ) -> Result<(IotaDocument, String), TrustServiceError> {
     // TODO: remove this
     // let address: Address = get_address_with_funds(client, secret_manager, FAUCET_ENDPOINT)
     //   .await
     //   .context("failed to get address with funds")?;
     
-    let (document, fragment): (IotaDocument, String) = Self::create_did_document( &self).await?;
+    let key_storage = self.key_storage_for(tenant_id).await?;
+    let (document, fragment): (IotaDocument, String) = Self::create_did_document( &self, &key_storage).await?;
 
     //TODO: here the governor address is always the same, i.e. the service
     let alias_output: AliasOutput = self.client.new_did_output(self.address.into_inner(), document, None).await?;
@@ -173,14 +242,15 @@ impl IotaState {
   /// Its functionality is equivalent to the "create DID" example
   /// and exists for convenient calling from the other examples.
   async fn create_did_document(
-    &self
+    &self,
+    key_storage: &MemStorage
   ) -> Result<(IotaDocument, String), TrustServiceError> {
     let network_name: NetworkName = self.client.network_name().await?;
     let mut document: IotaDocument = IotaDocument::new(&network_name);
 
     let fragment: String = document
       .generate_method(
-        &self.key_storage,
+        key_storage,
         JwkMemStore::ED25519_KEY_TYPE,
         JwsAlgorithm::EdDSA,
         None,
@@ -191,6 +261,47 @@ impl IotaState {
     Ok((document, fragment))
   }
 
+  /// Publishes an updated DID document, replacing the content of its Alias Output.
+  pub async fn update_did(
+    &self,
+    document: IotaDocument
+  ) -> Result<IotaDocument, TrustServiceError> {
+    let did = document.id().to_string();
+    let alias_output: AliasOutput = self.client.update_did_output(document).await?;
+
+    // The storage deposit may change with the size of the document
+    let rent_structure = self.client.get_rent_structure().await?;
+    let alias_output: AliasOutput = AliasOutputBuilder::from(&alias_output)
+      .with_minimum_storage_deposit(rent_structure)
+      .finish()?;
+
+    let secret_manager = self.wallet.get_secret_manager().write().await;
+    let updated: IotaDocument = self.client.publish_did_output(&secret_manager, alias_output).await?;
+    self.did_cache.invalidate(did.as_str());
+
+    Ok(updated)
+  }
+
+  /// Deactivates a DID, its Alias Output is kept but the document is emptied.
+  pub async fn deactivate_did(
+    &self,
+    did: &str
+  ) -> Result<IotaDocument, TrustServiceError> {
+    let iota_did = IotaDID::try_from(did)?;
+    let alias_output: AliasOutput = self.client.deactivate_did_output(&iota_did).await?;
+
+    let rent_structure = self.client.get_rent_structure().await?;
+    let alias_output: AliasOutput = AliasOutputBuilder::from(&alias_output)
+      .with_minimum_storage_deposit(rent_structure)
+      .finish()?;
+
+    let secret_manager = self.wallet.get_secret_manager().write().await;
+    let deactivated: IotaDocument = self.client.publish_did_output(&secret_manager, alias_output).await?;
+    self.did_cache.invalidate(did);
+
+    Ok(deactivated)
+  }
+
   pub async fn resolve_did(
     &self,
     did: &str
@@ -380,4 +491,10 @@ impl IotaState {
   }
 
 }
-  
\ No newline at end of file
+
+#[async_trait]
+impl ProofResolver for IotaState {
+  async fn resolve_proof(&self, proof_id: String) -> Result<TangleProof, TrustServiceError> {
+    IotaState::resolve_proof(self, proof_id).await
+  }
+}
@@ -4,7 +4,7 @@
 
 use std::env;
 use std::fs::File;
-use std::io::Read;
+use std::io::{Cursor, Read};
 use std::path::Path;
 use actix_web::http::uri::Scheme;
 use actix_web::HttpResponse;
@@ -41,6 +41,12 @@ impl IpfsService {
         Ok(res.hash)
     }
 
+    /// Push a document to IPFS, the node pins it
+    pub async fn add_bytes(&self, data: Vec<u8>) -> Result<String, TrustServiceError> {
+        let res = self.client.add(Cursor::new(data)).await.map_err(|_| TrustServiceError::IpfsUploadError)?;
+        Ok(res.hash)
+    }
+
     /// Retrieve a file from IPFS given its CID
     pub async fn get_file(&self, cid: &str) -> Result<Vec<u8>, TrustServiceError> {
         // retrieve from IPFS
@@ -0,0 +1,88 @@
+// SPDX-FileCopyrightText: 2024 Fondazione LINKS
+//
+// SPDX-License-Identifier: APACHE-2.0
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use identity_iota::storage::{JwkGenOutput, JwkStorage, KeyId, KeyStorageResult, KeyType};
+use identity_iota::verification::jwk::Jwk;
+use identity_iota::verification::jws::JwsAlgorithm;
+use identity_stronghold::StrongholdStorage;
+
+use crate::services::pkcs11_storage::Pkcs11Storage;
+
+/// Key store holding the private keys of the DIDs, selected with `KEY_STORE`:
+/// `stronghold` (default) or `pkcs11`.
+///
+/// The key ids are always mapped to the verification methods in the Stronghold
+/// key storage, only the private keys move to the PKCS#11 token.
+#[derive(Clone)]
+pub enum ServiceJwkStorage {
+    Stronghold(StrongholdStorage),
+    Pkcs11(Arc<Pkcs11Storage>),
+}
+
+impl ServiceJwkStorage {
+
+    pub fn from_env(stronghold_storage: &StrongholdStorage) -> anyhow::Result<Self> {
+        match std::env::var("KEY_STORE").unwrap_or_else(|_| "stronghold".to_owned()).as_str() {
+            "stronghold" => Ok(ServiceJwkStorage::Stronghold(stronghold_storage.clone())),
+            "pkcs11" => {
+                let module_path = std::env::var("PKCS11_MODULE").expect("$PKCS11_MODULE must be set.");
+                let token_label = std::env::var("PKCS11_TOKEN_LABEL").expect("$PKCS11_TOKEN_LABEL must be set.");
+                let user_pin = std::env::var("PKCS11_USER_PIN").expect("$PKCS11_USER_PIN must be set.");
+                Ok(ServiceJwkStorage::Pkcs11(Arc::new(Pkcs11Storage::new(&module_path, &token_label, user_pin)?)))
+            },
+            other => anyhow::bail!("unknown key store {other}"),
+        }
+    }
+
+    /// The same key store for the key ids kept in `stronghold_storage`, e.g. the Stronghold
+    /// of a tenant: its private keys go to that Stronghold, or to the shared PKCS#11 token.
+    pub fn for_stronghold(&self, stronghold_storage: StrongholdStorage) -> Self {
+        match self {
+            ServiceJwkStorage::Stronghold(_) => ServiceJwkStorage::Stronghold(stronghold_storage),
+            ServiceJwkStorage::Pkcs11(storage) => ServiceJwkStorage::Pkcs11(storage.clone()),
+        }
+    }
+}
+
+#[async_trait]
+impl JwkStorage for ServiceJwkStorage {
+
+    async fn generate(&self, key_type: KeyType, alg: JwsAlgorithm) -> KeyStorageResult<JwkGenOutput> {
+        match self {
+            ServiceJwkStorage::Stronghold(storage) => storage.generate(key_type, alg).await,
+            ServiceJwkStorage::Pkcs11(storage) => storage.generate(key_type, alg).await,
+        }
+    }
+
+    async fn insert(&self, jwk: Jwk) -> KeyStorageResult<KeyId> {
+        match self {
+            ServiceJwkStorage::Stronghold(storage) => storage.insert(jwk).await,
+            ServiceJwkStorage::Pkcs11(storage) => storage.insert(jwk).await,
+        }
+    }
+
+    async fn sign(&self, key_id: &KeyId, data: &[u8], public_key: &Jwk) -> KeyStorageResult<Vec<u8>> {
+        match self {
+            ServiceJwkStorage::Stronghold(storage) => storage.sign(key_id, data, public_key).await,
+            ServiceJwkStorage::Pkcs11(storage) => storage.sign(key_id, data, public_key).await,
+        }
+    }
+
+    async fn delete(&self, key_id: &KeyId) -> KeyStorageResult<()> {
+        match self {
+            ServiceJwkStorage::Stronghold(storage) => storage.delete(key_id).await,
+            ServiceJwkStorage::Pkcs11(storage) => storage.delete(key_id).await,
+        }
+    }
+
+    async fn exists(&self, key_id: &KeyId) -> KeyStorageResult<bool> {
+        match self {
+            ServiceJwkStorage::Stronghold(storage) => storage.exists(key_id).await,
+            ServiceJwkStorage::Pkcs11(storage) => storage.exists(key_id).await,
+        }
+    }
+}
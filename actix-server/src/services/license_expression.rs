@@ -0,0 +1,192 @@
+// SPDX-FileCopyrightText: 2024 Fondazione LINKS
+//
+// SPDX-License-Identifier: APACHE-2.0
+
+use std::fmt;
+
+use crate::errors::TrustServiceError;
+
+/// Longest expression accepted, it also bounds the nesting of the parentheses
+const MAX_EXPRESSION_LEN: usize = 256;
+
+/// SPDX license expression, e.g. `MIT OR Apache-2.0` or `GPL-2.0-only WITH Classpath-exception-2.0`.
+///
+/// Identifiers are kept as written, a trailing `+` stands for the license or any later version.
+/// `AND` binds tighter than `OR`, `WITH` tighter than both.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LicenseExpression {
+    License(String),
+    /// License with an exception
+    With(String, String),
+    And(Box<LicenseExpression>, Box<LicenseExpression>),
+    Or(Box<LicenseExpression>, Box<LicenseExpression>),
+}
+
+impl LicenseExpression {
+
+    /// Parses `expression`, the operators are written in upper or lower case.
+    pub fn parse(expression: &str) -> Result<Self, TrustServiceError> {
+        if expression.len() > MAX_EXPRESSION_LEN {
+            return Err(TrustServiceError::InvalidLicense(format!("license expressions are at most {MAX_EXPRESSION_LEN} characters")))
+        }
+        let mut parser = Parser { tokens: tokenize(expression), position: 0 };
+        let parsed = parser.or_expression()?;
+        match parser.tokens.get(parser.position) {
+            None => Ok(parsed),
+            Some(token) => Err(invalid(format!("unexpected {token}"))),
+        }
+    }
+
+    /// License identifiers in the order they appear.
+    pub fn license_ids(&self) -> Vec<&str> {
+        match self {
+            LicenseExpression::License(license_id) | LicenseExpression::With(license_id, _) => vec![license_id.as_str()],
+            LicenseExpression::And(left, right) | LicenseExpression::Or(left, right) => {
+                let mut license_ids = left.license_ids();
+                license_ids.extend(right.license_ids());
+                license_ids
+            },
+        }
+    }
+
+    pub fn exception_ids(&self) -> Vec<&str> {
+        match self {
+            LicenseExpression::License(_) => vec![],
+            LicenseExpression::With(_, exception_id) => vec![exception_id.as_str()],
+            LicenseExpression::And(left, right) | LicenseExpression::Or(left, right) => {
+                let mut exception_ids = left.exception_ids();
+                exception_ids.extend(right.exception_ids());
+                exception_ids
+            },
+        }
+    }
+
+    /// Writes the expression with upper case operators, naming the licenses and the exceptions
+    /// with `license` and `exception`.
+    pub fn format_with(&self, license: &dyn Fn(&str) -> String, exception: &dyn Fn(&str) -> String) -> String {
+        match self {
+            LicenseExpression::License(license_id) => license(license_id),
+            LicenseExpression::With(license_id, exception_id) => format!("{} WITH {}", license(license_id), exception(exception_id)),
+            LicenseExpression::And(left, right) => {
+                let operand = |operand: &LicenseExpression| match operand {
+                    LicenseExpression::Or(..) => format!("({})", operand.format_with(license, exception)),
+                    _ => operand.format_with(license, exception),
+                };
+                format!("{} AND {}", operand(left), operand(right))
+            },
+            LicenseExpression::Or(left, right) => {
+                format!("{} OR {}", left.format_with(license, exception), right.format_with(license, exception))
+            },
+        }
+    }
+}
+
+impl fmt::Display for LicenseExpression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.format_with(&|license_id| license_id.to_owned(), &|exception_id| exception_id.to_owned()))
+    }
+}
+
+fn invalid(reason: String) -> TrustServiceError {
+    TrustServiceError::InvalidLicense(format!("invalid license expression: {reason}"))
+}
+
+/// Splits on white space, parentheses are tokens of their own.
+fn tokenize(expression: &str) -> Vec<String> {
+    let mut tokens = vec![];
+    let mut token = String::new();
+    for c in expression.chars() {
+        if c.is_whitespace() || c == '(' || c == ')' {
+            if !token.is_empty() {
+                tokens.push(std::mem::take(&mut token));
+            }
+            if !c.is_whitespace() {
+                tokens.push(c.to_string());
+            }
+        } else {
+            token.push(c);
+        }
+    }
+    if !token.is_empty() {
+        tokens.push(token);
+    }
+    tokens
+}
+
+struct Parser {
+    tokens: Vec<String>,
+    position: usize,
+}
+
+impl Parser {
+
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.position).map(String::as_str)
+    }
+
+    fn next_is(&self, keyword: &str) -> bool {
+        self.peek().map_or(false, |token| token == keyword || token == keyword.to_lowercase())
+    }
+
+    fn or_expression(&mut self) -> Result<LicenseExpression, TrustServiceError> {
+        let mut expression = self.and_expression()?;
+        while self.next_is("OR") {
+            self.position += 1;
+            expression = LicenseExpression::Or(Box::new(expression), Box::new(self.and_expression()?));
+        }
+        Ok(expression)
+    }
+
+    fn and_expression(&mut self) -> Result<LicenseExpression, TrustServiceError> {
+        let mut expression = self.with_expression()?;
+        while self.next_is("AND") {
+            self.position += 1;
+            expression = LicenseExpression::And(Box::new(expression), Box::new(self.with_expression()?));
+        }
+        Ok(expression)
+    }
+
+    fn with_expression(&mut self) -> Result<LicenseExpression, TrustServiceError> {
+        let expression = self.primary()?;
+        if !self.next_is("WITH") {
+            return Ok(expression)
+        }
+        self.position += 1;
+        let exception_id = self.identifier("exception")?;
+        match expression {
+            LicenseExpression::License(license_id) => Ok(LicenseExpression::With(license_id, exception_id)),
+            _ => Err(invalid("WITH follows a single license".to_owned())),
+        }
+    }
+
+    fn primary(&mut self) -> Result<LicenseExpression, TrustServiceError> {
+        if self.peek() != Some("(") {
+            return Ok(LicenseExpression::License(self.identifier("license")?))
+        }
+        self.position += 1;
+        let expression = self.or_expression()?;
+        if self.peek() != Some(")") {
+            return Err(invalid("missing )".to_owned()))
+        }
+        self.position += 1;
+        Ok(expression)
+    }
+
+    /// idstring of the SPDX grammar, licenses may end with `+`
+    fn identifier(&mut self, kind: &str) -> Result<String, TrustServiceError> {
+        let token = self.peek().ok_or_else(|| invalid(format!("missing {kind}")))?;
+        if ["AND", "OR", "WITH"].iter().any(|keyword| token.eq_ignore_ascii_case(keyword)) || token == "(" || token == ")" {
+            return Err(invalid(format!("{token} found where a {kind} was expected")))
+        }
+        let id = match kind {
+            "license" => token.strip_suffix('+').unwrap_or(token),
+            _ => token,
+        };
+        if id.is_empty() || !id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.') {
+            return Err(invalid(format!("{token} is not a {kind} identifier")))
+        }
+        let token = token.to_owned();
+        self.position += 1;
+        Ok(token)
+    }
+}
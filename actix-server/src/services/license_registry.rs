@@ -0,0 +1,314 @@
+// SPDX-FileCopyrightText: 2024 Fondazione LINKS
+//
+// SPDX-License-Identifier: APACHE-2.0
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use crypto::hashes::sha::{SHA256, SHA256_LEN};
+use identity_iota::core::Timestamp;
+use reqwest::redirect::Policy;
+use serde::Deserialize;
+
+use crate::dtos::LicenseRequest;
+use crate::errors::TrustServiceError;
+use crate::models::license::{License, LicenseSource};
+use crate::services::authentication::Caller;
+use crate::services::license_expression::LicenseExpression;
+use crate::services::repository::Repository;
+
+const DEFAULT_SPDX_LICENSES_PATH: &str = "./spdx_licenses.json";
+const CUSTOM_LICENSE_PREFIX: &str = "LicenseRef-";
+/// Limits of the download of the text of a custom license
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+const MAX_REDIRECTS: usize = 3;
+pub const MAX_LICENSE_TEXT_LEN: usize = 256 * 1024;
+
+/// `licenses.json` of the SPDX license list data, followed by the `exceptions`
+/// of `exceptions.json` that can be named after `WITH`.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SpdxLicenseList {
+    license_list_version: String,
+    licenses: Vec<SpdxLicense>,
+    #[serde(default)]
+    exceptions: Vec<SpdxException>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SpdxLicense {
+    license_id: String,
+    name: String,
+    reference: String,
+    #[serde(default)]
+    is_osi_approved: bool,
+    #[serde(default)]
+    is_deprecated_license_id: bool,
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SpdxException {
+    license_exception_id: String,
+    #[serde(default)]
+    is_deprecated_license_id: bool,
+}
+
+/// Licenses the NFTs can be minted with: the SPDX licenses loaded from
+/// `SPDX_LICENSES_PATH` and the custom licenses registered in the db,
+/// alone or combined in SPDX license expressions such as `MIT OR Apache-2.0`.
+pub struct LicenseRegistry {
+    /// By identifier in lower case, SPDX identifiers are matched ignoring case
+    spdx_licenses: HashMap<String, License>,
+    spdx_exceptions: HashMap<String, SpdxException>,
+}
+
+impl LicenseRegistry {
+
+    /// Loads the SPDX license list, in the format of the `licenses.json` file published by SPDX.
+    pub fn init() -> Result<Self, TrustServiceError> {
+        let licenses_path = std::env::var("SPDX_LICENSES_PATH").unwrap_or_else(|_| DEFAULT_SPDX_LICENSES_PATH.to_owned());
+        let licenses_file = std::fs::read(&licenses_path).map_err(|_| TrustServiceError::FileOpenError)?;
+        let license_list: SpdxLicenseList = serde_json::from_slice(&licenses_file)?;
+        log::info!("Loaded {} SPDX licenses (list version {}) from {}", license_list.licenses.len(), license_list.license_list_version, licenses_path);
+
+        let spdx_licenses = license_list.licenses.into_iter()
+            .map(|license| (license.license_id.to_lowercase(), License {
+                license_id: license.license_id,
+                name: license.name,
+                source: LicenseSource::Spdx,
+                url: Some(license.reference),
+                text: None,
+                content_hash: None,
+                osi_approved: license.is_osi_approved,
+                deprecated: license.is_deprecated_license_id,
+                created_at: None,
+                registered_by: None,
+            }))
+            .collect();
+        let spdx_exceptions = license_list.exceptions.into_iter()
+            .map(|exception| (exception.license_exception_id.to_lowercase(), exception))
+            .collect();
+        Ok(LicenseRegistry { spdx_licenses, spdx_exceptions })
+    }
+
+    /// License or license expression, `None` when it names unknown licenses.
+    pub async fn find(&self, mongo_repo: &dyn Repository, expression: &str) -> Result<Option<License>, TrustServiceError> {
+        match self.resolve(mongo_repo, expression).await {
+            Ok(license) => Ok(Some(license)),
+            Err(TrustServiceError::UnknownLicense(_) | TrustServiceError::InvalidLicense(_)) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Single license with `license_id`, ignoring case.
+    async fn find_license(&self, mongo_repo: &dyn Repository, license_id: &str) -> Result<Option<License>, TrustServiceError> {
+        if let Some(license) = self.spdx_licenses.get(&license_id.to_lowercase()) {
+            return Ok(Some(license.clone()))
+        }
+        if is_custom(license_id) {
+            return mongo_repo.get_license(license_id).await
+        }
+        Ok(None)
+    }
+
+    /// License named by `expression`. A single identifier resolves to its license, an expression
+    /// to a record with the normalized expression as identifier, the names of its licenses as name,
+    /// approved by the OSI when all its licenses are and deprecated when any of them is.
+    pub async fn resolve(&self, mongo_repo: &dyn Repository, expression: &str) -> Result<License, TrustServiceError> {
+        let parsed = LicenseExpression::parse(expression)?;
+        let mut licenses: HashMap<&str, License> = HashMap::new();
+        for license_id in parsed.license_ids() {
+            // `GPL-2.0+` is a deprecated identifier of its own, `MIT+` is MIT or any later version
+            let license = match self.find_license(mongo_repo, license_id).await? {
+                Some(license) => license,
+                None => match license_id.strip_suffix('+') {
+                    Some(base_id) => self.find_license(mongo_repo, base_id).await?,
+                    None => None,
+                }.ok_or(TrustServiceError::UnknownLicense(license_id.to_owned()))?,
+            };
+            licenses.insert(license_id, license);
+        }
+        let mut exceptions: HashMap<&str, &SpdxException> = HashMap::new();
+        for exception_id in parsed.exception_ids() {
+            let exception = self.spdx_exceptions.get(&exception_id.to_lowercase())
+                .ok_or(TrustServiceError::UnknownLicense(exception_id.to_owned()))?;
+            exceptions.insert(exception_id, exception);
+        }
+
+        if let LicenseExpression::License(license_id) = &parsed {
+            if let Some(license) = licenses.get(license_id.as_str()).filter(|license| license.license_id.eq_ignore_ascii_case(license_id)) {
+                return Ok(license.clone())
+            }
+        }
+        let canonical_id = |license_id: &str| {
+            let license = &licenses[license_id];
+            match license_id.ends_with('+') && !license.license_id.ends_with('+') {
+                true => format!("{}+", license.license_id),
+                false => license.license_id.clone(),
+            }
+        };
+        let canonical_exception = |exception_id: &str| exceptions[exception_id].license_exception_id.clone();
+        let name = |license_id: &str| match license_id.ends_with('+') && !licenses[license_id].license_id.ends_with('+') {
+            true => format!("{} or later", licenses[license_id].name),
+            false => licenses[license_id].name.clone(),
+        };
+        Ok(License {
+            license_id: parsed.format_with(&canonical_id, &canonical_exception),
+            name: parsed.format_with(&name, &canonical_exception),
+            source: match licenses.values().any(|license| license.source == LicenseSource::Custom) {
+                true => LicenseSource::Custom,
+                false => LicenseSource::Spdx,
+            },
+            url: None,
+            text: None,
+            content_hash: None,
+            osi_approved: licenses.values().all(|license| license.osi_approved),
+            deprecated: licenses.values().any(|license| license.deprecated)
+                || exceptions.values().any(|exception| exception.is_deprecated_license_id),
+            created_at: None,
+            registered_by: None,
+        })
+    }
+
+    /// License a new NFT is minted with, expressions using deprecated SPDX identifiers are refused.
+    pub async fn validate(&self, mongo_repo: &dyn Repository, expression: &str) -> Result<License, TrustServiceError> {
+        let license = self.resolve(mongo_repo, expression).await?;
+        if license.deprecated {
+            return Err(TrustServiceError::InvalidLicense(format!("{} uses a deprecated SPDX identifier", license.license_id)))
+        }
+        Ok(license)
+    }
+
+    /// SPDX licenses sorted by identifier, followed by the custom ones.
+    pub async fn list(&self, mongo_repo: &dyn Repository, source: Option<LicenseSource>) -> Result<Vec<License>, TrustServiceError> {
+        let mut licenses = vec![];
+        if source != Some(LicenseSource::Custom) {
+            let mut spdx_licenses: Vec<License> = self.spdx_licenses.values().cloned().collect();
+            spdx_licenses.sort_by(|a, b| a.license_id.to_lowercase().cmp(&b.license_id.to_lowercase()));
+            licenses.extend(spdx_licenses);
+        }
+        if source != Some(LicenseSource::Spdx) {
+            licenses.extend(mongo_repo.get_licenses().await?);
+        }
+        Ok(licenses)
+    }
+
+    /// Registers a custom license on behalf of the administrator or of a DID registered
+    /// with the service. Its text is given or fetched from `url` and hashed, so that the
+    /// NFTs point to a fixed version of it.
+    pub async fn register(&self, mongo_repo: &dyn Repository, caller: &Caller, request: LicenseRequest) -> Result<License, TrustServiceError> {
+        let registered_by = match caller {
+            Caller::Admin => None,
+            Caller::Did(did) => match mongo_repo.get_user(did).await {
+                Ok(user) => Some(user.did),
+                Err(TrustServiceError::UserDidNotFound) => return Err(TrustServiceError::NotAuthorized(caller.to_string())),
+                Err(err) => return Err(err),
+            },
+        };
+        let license_id = request.license_id;
+        validate_custom_id(license_id.as_str())?;
+        if request.name.trim().is_empty() {
+            return Err(TrustServiceError::InvalidLicense("the name is missing".to_owned()))
+        }
+        if mongo_repo.get_license(license_id.as_str()).await?.is_some() {
+            return Err(TrustServiceError::LicenseAlreadyRegistered(license_id))
+        }
+
+        let content = match (request.text.as_deref(), request.url.as_deref()) {
+            (Some(text), _) if text.len() > MAX_LICENSE_TEXT_LEN => {
+                return Err(TrustServiceError::InvalidLicense(format!("the text is longer than {MAX_LICENSE_TEXT_LEN} bytes")))
+            },
+            (Some(text), _) => text.as_bytes().to_vec(),
+            (None, Some(url)) => fetch_text(url).await?,
+            (None, None) => return Err(TrustServiceError::InvalidLicense("either the text or the url is required".to_owned())),
+        };
+        let content_hash = sha256_hex(&content);
+        if let Some(expected) = request.content_hash.as_deref() {
+            if !expected.eq_ignore_ascii_case(content_hash.as_str()) {
+                return Err(TrustServiceError::InvalidLicense(format!("the text hashes to {content_hash}, not {expected}")))
+            }
+        }
+
+        let license = License {
+            license_id,
+            name: request.name,
+            source: LicenseSource::Custom,
+            url: request.url,
+            text: request.text,
+            content_hash: Some(content_hash),
+            osi_approved: false,
+            deprecated: false,
+            created_at: Some(Timestamp::now_utc().to_rfc3339()),
+            registered_by,
+        };
+        // the unique index refuses a license registered meanwhile
+        mongo_repo.store_license(&license).await?;
+        Ok(license)
+    }
+}
+
+/// Custom identifiers are `LicenseRef-` followed by an idstring of the SPDX license expressions.
+pub fn validate_custom_id(license_id: &str) -> Result<(), TrustServiceError> {
+    let valid_id = license_id.strip_prefix(CUSTOM_LICENSE_PREFIX)
+        .map_or(false, |id| !id.is_empty() && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.'));
+    if !valid_id {
+        return Err(TrustServiceError::InvalidLicense(format!("custom identifiers are {CUSTOM_LICENSE_PREFIX} followed by letters, digits, '-' or '.'")))
+    }
+    Ok(())
+}
+
+fn is_custom(license_id: &str) -> bool {
+    license_id.len() > CUSTOM_LICENSE_PREFIX.len()
+        && license_id.get(..CUSTOM_LICENSE_PREFIX.len()).map_or(false, |prefix| prefix.eq_ignore_ascii_case(CUSTOM_LICENSE_PREFIX))
+}
+
+/// Downloads the text of a license over HTTPS, redirects included, within [`FETCH_TIMEOUT`]
+/// and up to [`MAX_LICENSE_TEXT_LEN`] bytes.
+async fn fetch_text(url: &str) -> Result<Vec<u8>, TrustServiceError> {
+    let fetch_error = |reason: String| TrustServiceError::InvalidLicense(format!("fetching {url}: {reason}"));
+    let parsed_url = reqwest::Url::parse(url).map_err(|err| fetch_error(err.to_string()))?;
+    if parsed_url.scheme() != "https" {
+        return Err(fetch_error("only https URLs are fetched".to_owned()))
+    }
+    log::info!("Fetching license text from {}", url);
+
+    let redirect_policy = Policy::custom(|attempt| {
+        if attempt.url().scheme() != "https" {
+            attempt.error("redirected to a URL that is not https")
+        } else if attempt.previous().len() > MAX_REDIRECTS {
+            attempt.error("too many redirects")
+        } else {
+            attempt.follow()
+        }
+    });
+    let http_client = reqwest::Client::builder()
+        .timeout(FETCH_TIMEOUT)
+        .redirect(redirect_policy)
+        .build()
+        .map_err(|err| fetch_error(err.to_string()))?;
+    let mut response = http_client.get(parsed_url)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|err| fetch_error(err.to_string()))?;
+    if response.content_length().map_or(false, |length| length > MAX_LICENSE_TEXT_LEN as u64) {
+        return Err(fetch_error(format!("the text is longer than {MAX_LICENSE_TEXT_LEN} bytes")))
+    }
+    // the declared length can be missing or wrong
+    let mut body = vec![];
+    while let Some(chunk) = response.chunk().await.map_err(|err| fetch_error(err.to_string()))? {
+        if body.len() + chunk.len() > MAX_LICENSE_TEXT_LEN {
+            return Err(fetch_error(format!("the text is longer than {MAX_LICENSE_TEXT_LEN} bytes")))
+        }
+        body.extend_from_slice(&chunk);
+    }
+    Ok(body)
+}
+
+fn sha256_hex(content: &[u8]) -> String {
+    let mut digest = [0u8; SHA256_LEN];
+    SHA256(content, &mut digest);
+    digest.iter().map(|byte| format!("{byte:02x}")).collect()
+}
@@ -0,0 +1,474 @@
+// SPDX-FileCopyrightText: 2024 Fondazione LINKS
+//
+// SPDX-License-Identifier: APACHE-2.0
+
+use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard};
+
+use async_trait::async_trait;
+
+use crate::errors::TrustServiceError;
+use crate::models::asset::Asset;
+use crate::models::audit_entry::AuditAnchor;
+use crate::models::credential::{CredentialState, IssuedCredential, Issuer};
+use crate::models::evm_transaction::{EvmTransaction, TransactionStatus};
+use crate::models::license::License;
+use crate::models::log_model::Log;
+use crate::models::organisation::{Membership, Organisation};
+use crate::models::proof_revocation::ProofRevocation;
+use crate::models::signed_request::SignedRequest;
+use crate::models::user::{EvmAccount, User};
+use crate::services::repository::Repository;
+
+#[derive(Default)]
+struct Collections {
+    users: Vec<User>,
+    logs: Vec<Log>,
+    issuer: Option<Issuer>,
+    credentials: Vec<IssuedCredential>,
+    organisations: Vec<Organisation>,
+    transactions: Vec<EvmTransaction>,
+    licenses: Vec<License>,
+    evm_address_index: u32,
+    /// Last block processed by the indexer, by network
+    indexer_cursors: HashMap<String, u64>,
+    /// `Asset` implementation cloned by the factory, by network
+    asset_contracts: HashMap<String, String>,
+    audit_anchors: HashMap<String, AuditAnchor>,
+}
+
+impl Collections {
+    fn asset_mut(&mut self, asset_id: &str) -> Option<&mut Asset> {
+        self.users.iter_mut()
+            .flat_map(|user| user.assets.iter_mut())
+            .find(|asset| asset.asset_id == asset_id)
+    }
+
+    fn user_by_asset(&self, asset_id: &str) -> Option<&User> {
+        self.users.iter().find(|user| user.assets.iter().any(|asset| asset.asset_id == asset_id))
+    }
+}
+
+/// Repository keeping the collections of [`MongoRepo`](crate::services::mongodb_repo::MongoRepo)
+/// in memory, with the same matching rules, for the tests.
+#[derive(Default)]
+pub struct InMemoryRepository {
+    collections: Mutex<Collections>,
+}
+
+impl InMemoryRepository {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn collections(&self) -> MutexGuard<'_, Collections> {
+        self.collections.lock().expect("in-memory repository lock poisoned")
+    }
+}
+
+#[async_trait]
+impl Repository for InMemoryRepository {
+
+    async fn store_user(&self, user: User) -> Result<(), TrustServiceError> {
+        self.collections().users.push(User { assets: vec![], ..user });
+        Ok(())
+    }
+
+    async fn get_user(&self, did: &str) -> Result<User, TrustServiceError> {
+        self.collections().users.iter()
+            .find(|user| user.did == did)
+            .cloned()
+            .ok_or(TrustServiceError::UserDidNotFound)
+    }
+
+    async fn get_user_by_access_token(&self, token_hash: &str) -> Result<Option<User>, TrustServiceError> {
+        Ok(self.collections().users.iter().find(|user| user.access_token_hash.as_deref() == Some(token_hash)).cloned())
+    }
+
+    async fn store_access_token(&self, did: &str, token_hash: String) -> Result<(), TrustServiceError> {
+        let mut collections = self.collections();
+        let user = collections.users.iter_mut().find(|user| user.did == did).ok_or(TrustServiceError::UserDidNotFound)?;
+        user.access_token_hash = Some(token_hash);
+        Ok(())
+    }
+
+    async fn get_users(&self) -> Result<Vec<User>, TrustServiceError> {
+        Ok(self.collections().users.clone())
+    }
+
+    async fn get_users_by_tenant(&self, tenant_id: &str) -> Result<Vec<User>, TrustServiceError> {
+        Ok(self.collections().users.iter().filter(|user| user.tenant_id.as_deref() == Some(tenant_id)).cloned().collect())
+    }
+
+    async fn delete_users_by_tenant(&self, tenant_id: &str) -> Result<u64, TrustServiceError> {
+        let mut collections = self.collections();
+        let before = collections.users.len();
+        collections.users.retain(|user| user.tenant_id.as_deref() != Some(tenant_id));
+        Ok((before - collections.users.len()) as u64)
+    }
+
+    async fn get_asset(&self, asset_id: String) -> Result<Asset, TrustServiceError> {
+        self.collections().users.iter()
+            .flat_map(|user| user.assets.iter())
+            .find(|asset| asset.asset_id == asset_id)
+            .cloned()
+            .ok_or(TrustServiceError::AssetIdNotFound(asset_id))
+    }
+
+    async fn get_asset_by_proof(&self, asset_proof: String) -> Result<Asset, TrustServiceError> {
+        self.collections().users.iter()
+            .flat_map(|user| user.assets.iter())
+            .find(|asset| asset.proof_id == asset_proof)
+            .cloned()
+            .ok_or(TrustServiceError::AssetIdNotFound(asset_proof))
+    }
+
+    async fn reserve_nft_mint(&self, asset_id: &str, started_at: String) -> Result<bool, TrustServiceError> {
+        let mut collections = self.collections();
+        match collections.asset_mut(asset_id) {
+            Some(asset) if asset.nft_addr.is_none() && asset.minting.is_none() => {
+                asset.minting = Some(started_at);
+                Ok(true)
+            },
+            _ => Ok(false),
+        }
+    }
+
+    async fn release_nft_mint(&self, asset_id: &str) -> Result<(), TrustServiceError> {
+        if let Some(asset) = self.collections().asset_mut(asset_id) {
+            asset.minting = None;
+        }
+        Ok(())
+    }
+
+    async fn store_nft_addr(&self, asset_id: String, nft_addr: String, network: &str, mint_block: Option<u64>) -> Result<Asset, TrustServiceError> {
+        let mut collections = self.collections();
+        let asset = collections.asset_mut(asset_id.as_str()).ok_or(TrustServiceError::AssetIdNotFound(asset_id.clone()))?;
+        asset.nft_addr = Some(nft_addr);
+        asset.network = Some(network.to_owned());
+        asset.mint_block = mint_block;
+        asset.minting = None;
+        Ok(asset.clone())
+    }
+
+    async fn store_nft_ownership(&self, asset_id: &str, token_id: String, owner_address: String) -> Result<(), TrustServiceError> {
+        if let Some(asset) = self.collections().asset_mut(asset_id) {
+            asset.token_id = Some(token_id);
+            asset.owner_address = Some(owner_address);
+            asset.approved = None;
+            asset.operators.clear();
+        }
+        Ok(())
+    }
+
+    async fn store_nft_burned(&self, asset_id: &str) -> Result<(), TrustServiceError> {
+        if let Some(asset) = self.collections().asset_mut(asset_id) {
+            asset.burned = true;
+            asset.owner_address = None;
+            asset.approved = None;
+            asset.operators.clear();
+        }
+        Ok(())
+    }
+
+    async fn store_nft_token_id(&self, asset_id: &str, token_id: String) -> Result<(), TrustServiceError> {
+        if let Some(asset) = self.collections().asset_mut(asset_id) {
+            asset.token_id = Some(token_id);
+        }
+        Ok(())
+    }
+
+    async fn store_nft_approval(&self, asset_id: &str, approved: Option<String>) -> Result<(), TrustServiceError> {
+        if let Some(asset) = self.collections().asset_mut(asset_id) {
+            asset.approved = approved;
+        }
+        Ok(())
+    }
+
+    async fn store_nft_operator(&self, asset_id: &str, operator: String, approved: bool) -> Result<(), TrustServiceError> {
+        if let Some(asset) = self.collections().asset_mut(asset_id) {
+            asset.operators.retain(|current| *current != operator);
+            if approved {
+                asset.operators.push(operator);
+            }
+        }
+        Ok(())
+    }
+
+    async fn store_nft_metadata_uri(&self, asset_id: &str, metadata_uri: String) -> Result<(), TrustServiceError> {
+        if let Some(asset) = self.collections().asset_mut(asset_id) {
+            asset.metadata_uri = Some(metadata_uri);
+        }
+        Ok(())
+    }
+
+    async fn find_asset_by_proof(&self, proof_id: &str) -> Result<Option<(String, Asset)>, TrustServiceError> {
+        Ok(self.collections().users.iter().find_map(|user| {
+            user.assets.iter().find(|asset| asset.proof_id == proof_id).map(|asset| (user.did.clone(), asset.clone()))
+        }))
+    }
+
+    async fn get_asset_by_nft_addr(&self, nft_addr: &str, network: &str, default_network: bool) -> Result<Option<(String, Asset)>, TrustServiceError> {
+        Ok(self.collections().users.iter().find_map(|user| {
+            user.assets.iter()
+                .find(|asset| asset.nft_addr.as_deref() == Some(nft_addr) && asset.is_on_network(network, default_network))
+                .map(|asset| (user.did.clone(), asset.clone()))
+        }))
+    }
+
+    async fn get_user_by_asset(&self, asset_id: &str) -> Result<User, TrustServiceError> {
+        self.collections().user_by_asset(asset_id).cloned().ok_or(TrustServiceError::AssetIdNotFound(asset_id.to_owned()))
+    }
+
+    async fn get_user_by_evm_address(&self, address: &str) -> Result<Option<User>, TrustServiceError> {
+        Ok(self.collections().users.iter()
+            .find(|user| user.evm_account.as_ref().is_some_and(|evm_account| evm_account.address == address))
+            .cloned())
+    }
+
+    async fn store_evm_account(&self, did: &str, evm_account: EvmAccount) -> Result<(), TrustServiceError> {
+        if let Some(user) = self.collections().users.iter_mut().find(|user| user.did == did) {
+            user.evm_account = Some(evm_account);
+        }
+        Ok(())
+    }
+
+    async fn consume_signature_nonce(&self, did: &str, nonce: u64) -> Result<bool, TrustServiceError> {
+        let mut collections = self.collections();
+        let evm_account = collections.users.iter_mut()
+            .find(|user| user.did == did)
+            .and_then(|user| user.evm_account.as_mut())
+            .filter(|evm_account| evm_account.signature_nonce == nonce);
+        match evm_account {
+            Some(evm_account) => {
+                evm_account.signature_nonce = nonce + 1;
+                Ok(true)
+            },
+            None => Ok(false),
+        }
+    }
+
+    async fn release_signature_nonce(&self, did: &str, nonce: u64) -> Result<bool, TrustServiceError> {
+        let mut collections = self.collections();
+        let evm_account = collections.users.iter_mut()
+            .find(|user| user.did == did)
+            .and_then(|user| user.evm_account.as_mut())
+            .filter(|evm_account| evm_account.signature_nonce == nonce + 1);
+        match evm_account {
+            Some(evm_account) => {
+                evm_account.signature_nonce = nonce;
+                Ok(true)
+            },
+            None => Ok(false),
+        }
+    }
+
+    async fn store_signed_request(&self, asset_id: &str, signed_request: SignedRequest) -> Result<(), TrustServiceError> {
+        if let Some(asset) = self.collections().asset_mut(asset_id) {
+            asset.signed_requests.push(signed_request);
+        }
+        Ok(())
+    }
+
+    async fn store_proof_revocation(&self, proof_id: &str, revocation: ProofRevocation) -> Result<(), TrustServiceError> {
+        let mut collections = self.collections();
+        let asset = collections.users.iter_mut()
+            .flat_map(|user| user.assets.iter_mut())
+            .find(|asset| asset.proof_id == proof_id);
+        if let Some(asset) = asset {
+            asset.revocation = Some(revocation);
+        }
+        Ok(())
+    }
+
+    async fn allocate_evm_address_index(&self) -> Result<u32, TrustServiceError> {
+        let mut collections = self.collections();
+        let address_index = collections.evm_address_index;
+        collections.evm_address_index += 1;
+        Ok(address_index)
+    }
+
+    async fn get_indexer_cursor(&self, network: &str) -> Result<Option<u64>, TrustServiceError> {
+        Ok(self.collections().indexer_cursors.get(network).copied())
+    }
+
+    async fn store_indexer_cursor(&self, network: &str, block: u64) -> Result<(), TrustServiceError> {
+        self.collections().indexer_cursors.insert(network.to_owned(), block);
+        Ok(())
+    }
+
+    async fn get_asset_contract(&self, network: &str) -> Result<Option<String>, TrustServiceError> {
+        Ok(self.collections().asset_contracts.get(network).cloned())
+    }
+
+    async fn store_asset_contract(&self, network: &str, asset_contract: &str) -> Result<(), TrustServiceError> {
+        self.collections().asset_contracts.insert(network.to_owned(), asset_contract.to_owned());
+        Ok(())
+    }
+
+    async fn get_audit_anchor(&self, log: &str) -> Result<Option<AuditAnchor>, TrustServiceError> {
+        Ok(self.collections().audit_anchors.get(log).cloned())
+    }
+
+    async fn store_audit_anchor(&self, anchor: &AuditAnchor) -> Result<(), TrustServiceError> {
+        self.collections().audit_anchors.insert(anchor.log.clone(), anchor.clone());
+        Ok(())
+    }
+
+    async fn store_transaction(&self, transaction: &EvmTransaction) -> Result<(), TrustServiceError> {
+        let mut collections = self.collections();
+        let stored = collections.transactions.iter_mut().find(|stored| {
+            stored.chain_id == transaction.chain_id && stored.from == transaction.from && stored.nonce == transaction.nonce
+        });
+        match stored {
+            Some(stored) => *stored = transaction.clone(),
+            None => collections.transactions.push(transaction.clone()),
+        }
+        Ok(())
+    }
+
+    async fn get_transactions_by_status(&self, chain_id: u64, from: &str, status: TransactionStatus) -> Result<Vec<EvmTransaction>, TrustServiceError> {
+        Ok(self.collections().transactions.iter()
+            .filter(|transaction| transaction.chain_id == chain_id && transaction.from == from && transaction.status == status)
+            .cloned()
+            .collect())
+    }
+
+    async fn store_proof_relationship(
+        &self,
+        did: &str,
+        proof_id: String,
+        asset_id: String,
+    ) -> Result<(), TrustServiceError> {
+        let mut collections = self.collections();
+        if collections.user_by_asset(asset_id.as_str()).is_some() {
+            return Err(TrustServiceError::AssetIdAlreadyExists(asset_id))
+        }
+        // as with Mongo, an unknown DID matches no document
+        let user = collections.users.iter_mut().find(|user| user.did == did).ok_or(TrustServiceError::AssetIdAlreadyExists(asset_id.clone()))?;
+        user.assets.push(Asset { proof_id, asset_id, nft_addr: None, token_id: None, owner_address: None, approved: None, operators: vec![], metadata_uri: None, network: None, mint_block: None, minting: None, burned: false, signed_requests: vec![], revocation: None });
+        Ok(())
+    }
+
+    async fn asset_exists(&self, asset_id: &str) -> Result<bool, TrustServiceError> {
+        Ok(self.collections().user_by_asset(asset_id).is_some())
+    }
+
+    async fn store_log_cid(&self, log: Log) -> Result<(), TrustServiceError> {
+        let mut collections = self.collections();
+        collections.logs.retain(|stored| stored.name != log.name);
+        collections.logs.push(log);
+        Ok(())
+    }
+
+    async fn get_log_cid(&self) -> Result<String, TrustServiceError> {
+        let log_filename = std::env::var("LOG_FILE_NAME").unwrap_or_default();
+        self.collections().logs.iter()
+            .find(|log| log.name == log_filename)
+            .map(|log| log.cid.clone())
+            .ok_or(TrustServiceError::MongoFileNotFound)
+    }
+
+    async fn get_issuer(&self) -> Result<Issuer, TrustServiceError> {
+        self.collections().issuer.clone().ok_or(TrustServiceError::IssuerNotFound)
+    }
+
+    async fn store_issuer(&self, issuer: Issuer) -> Result<(), TrustServiceError> {
+        self.collections().issuer = Some(issuer);
+        Ok(())
+    }
+
+    async fn store_credential(&self, credential: &IssuedCredential) -> Result<(), TrustServiceError> {
+        self.collections().credentials.push(credential.clone());
+        Ok(())
+    }
+
+    async fn get_credentials_by_holder(&self, holder_did: &str) -> Result<Vec<IssuedCredential>, TrustServiceError> {
+        let mut credentials: Vec<IssuedCredential> = self.collections().credentials.iter()
+            .filter(|credential| credential.holder_did == holder_did)
+            .cloned()
+            .collect();
+        credentials.sort_by(|first, second| first.issuance_date.cmp(&second.issuance_date));
+        Ok(credentials)
+    }
+
+    async fn allocate_status_index(&self) -> Result<u32, TrustServiceError> {
+        let mut collections = self.collections();
+        let issuer = collections.issuer.as_mut().ok_or(TrustServiceError::IssuerNotFound)?;
+        let status_index = issuer.next_status_index;
+        issuer.next_status_index += 1;
+        Ok(status_index)
+    }
+
+    async fn get_credential_by_status_index(&self, status_index: u32) -> Result<IssuedCredential, TrustServiceError> {
+        self.collections().credentials.iter()
+            .find(|credential| credential.status_index == Some(status_index))
+            .cloned()
+            .ok_or(TrustServiceError::CredentialNotFound(status_index.to_string()))
+    }
+
+    async fn get_credential(&self, credential_id: &str) -> Result<Option<IssuedCredential>, TrustServiceError> {
+        Ok(self.collections().credentials.iter().find(|credential| credential.credential_id == credential_id).cloned())
+    }
+
+    async fn update_credential_status(&self, status_index: u32, status: CredentialState) -> Result<(), TrustServiceError> {
+        let mut collections = self.collections();
+        if let Some(credential) = collections.credentials.iter_mut().find(|credential| credential.status_index == Some(status_index)) {
+            credential.status = status;
+        }
+        Ok(())
+    }
+
+    async fn store_organisation(&self, organisation: &Organisation) -> Result<(), TrustServiceError> {
+        self.collections().organisations.push(organisation.clone());
+        Ok(())
+    }
+
+    async fn get_organisation(&self, did: &str) -> Result<Organisation, TrustServiceError> {
+        self.collections().organisations.iter()
+            .find(|organisation| organisation.did == did)
+            .cloned()
+            .ok_or(TrustServiceError::OrganisationNotFound(did.to_owned()))
+    }
+
+    async fn store_license(&self, license: &License) -> Result<(), TrustServiceError> {
+        let mut collections = self.collections();
+        // as the unique index of MongoRepo, ignoring case
+        if collections.licenses.iter().any(|stored| stored.license_id.eq_ignore_ascii_case(&license.license_id)) {
+            return Err(TrustServiceError::LicenseAlreadyRegistered(license.license_id.clone()))
+        }
+        collections.licenses.push(license.clone());
+        Ok(())
+    }
+
+    async fn get_license(&self, license_id: &str) -> Result<Option<License>, TrustServiceError> {
+        Ok(self.collections().licenses.iter().find(|license| license.license_id.eq_ignore_ascii_case(license_id)).cloned())
+    }
+
+    async fn get_licenses(&self) -> Result<Vec<License>, TrustServiceError> {
+        Ok(self.collections().licenses.clone())
+    }
+
+    async fn get_organisations(&self) -> Result<Vec<Organisation>, TrustServiceError> {
+        Ok(self.collections().organisations.clone())
+    }
+
+    async fn add_membership(&self, organisation_did: &str, membership: &Membership) -> Result<(), TrustServiceError> {
+        let mut collections = self.collections();
+        if let Some(organisation) = collections.organisations.iter_mut().find(|organisation| organisation.did == organisation_did) {
+            organisation.members.push(membership.clone());
+        }
+        Ok(())
+    }
+
+    async fn revoke_membership(&self, organisation_did: &str, member_did: &str, revoked_at: String) -> Result<(), TrustServiceError> {
+        let mut collections = self.collections();
+        let membership = collections.organisations.iter_mut()
+            .filter(|organisation| organisation.did == organisation_did)
+            .flat_map(|organisation| organisation.members.iter_mut())
+            .find(|membership| membership.member_did == member_did && membership.revoked_at.is_none())
+            .ok_or(TrustServiceError::MembershipNotFound(member_did.to_owned()))?;
+        membership.revoked_at = Some(revoked_at);
+        Ok(())
+    }
+}
@@ -3,5 +3,30 @@
 // SPDX-License-Identifier: APACHE-2.0
 
 pub mod iota_state;
+pub mod repository;
 pub mod mongodb_repo;
-pub mod ipfs;
\ No newline at end of file
+pub mod memory_repo;
+pub mod ipfs;
+pub mod did_resolver;
+pub mod did_cache;
+pub mod credential_issuer;
+pub mod presentation_verifier;
+pub mod evm_signer;
+pub mod evm_link;
+pub mod organisation_service;
+pub mod snapshot_backup;
+pub mod tenant_storage;
+pub mod key_store;
+pub mod pkcs11_storage;
+pub mod nft_service;
+pub mod nft_integrity;
+pub mod nft_metadata;
+pub mod evm_indexer;
+pub mod tx_manager;
+pub mod evm_networks;
+pub mod license_registry;
+pub mod license_expression;
+pub mod nft_authorization;
+pub mod proof_revocation;
+pub mod audit_log;
+pub mod authentication;
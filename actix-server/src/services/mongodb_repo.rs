@@ -1,47 +1,59 @@
 use std::env;
-use std::fs::{self, File};
-use std::path::PathBuf;
-use std::io::Write;
 use anyhow::Result;
+use async_trait::async_trait;
 
-use mongodb::options::UpdateOptions;
+use mongodb::options::{IndexOptions, UpdateOptions};
 use mongodb::Collection;
 use mongodb::Client as MongoClient;
-use mongodb::bson::doc;
+use mongodb::IndexModel;
+use mongodb::error::{ErrorKind, WriteFailure};
+use mongodb::bson::{doc, Bson, Document};
 use mongodb::options::FindOneOptions;
-use mongodb::results::InsertOneResult;
+use mongodb::options::{Collation, CollationStrength};
+use futures_util::TryStreamExt;
 use serde::Deserializer;
 use serde_json::Value;
 
 use crate::errors::TrustServiceError;
 use crate::models::asset::Asset;
-use crate::models::user::User;
+use crate::models::audit_entry::AuditAnchor;
+use crate::models::credential::{CredentialState, IssuedCredential, Issuer};
+use crate::models::evm_transaction::{EvmTransaction, TransactionStatus};
+use crate::models::license::License;
+use crate::models::proof_revocation::ProofRevocation;
+use crate::models::signed_request::SignedRequest;
+use crate::models::organisation::{Membership, Organisation};
+use crate::models::user::{EvmAccount, User};
 use crate::models::log_model::Log;
-use crate::controllers::log_controller::publish_log_internal;
+use crate::services::repository::Repository;
 
 pub struct MongoRepo {
     user_collection: Collection<User>,
-    log_collection: Collection<Log>
+    log_collection: Collection<Log>,
+    issuer_collection: Collection<Issuer>,
+    credential_collection: Collection<IssuedCredential>,
+    organisation_collection: Collection<Organisation>,
+    counter_collection: Collection<Document>,
+    transaction_collection: Collection<EvmTransaction>,
+    license_collection: Collection<License>,
+    audit_anchor_collection: Collection<AuditAnchor>,
 }
 
 pub const USER_COLL_NAME: &str = "Users";
 pub const LOG_COLL_NAME: &str = "Log_IPFS";
+pub const ISSUER_COLL_NAME: &str = "Issuer";
+pub const CREDENTIAL_COLL_NAME: &str = "Credentials";
+pub const ORGANISATION_COLL_NAME: &str = "Organisations";
+pub const COUNTER_COLL_NAME: &str = "Counters";
+pub const TRANSACTION_COLL_NAME: &str = "Transactions";
+pub const LICENSE_COLL_NAME: &str = "Licenses";
+pub const AUDIT_ANCHOR_COLL_NAME: &str = "AuditAnchors";
+pub const EVM_ADDRESS_INDEX_COUNTER: &str = "evmAddressIndex";
+pub const EVM_INDEXER_CURSOR: &str = "evmIndexerCursor";
+pub const EVM_ASSET_CONTRACT: &str = "evmAssetContract";
+const DUPLICATE_KEY_CODE: i32 = 11000;
 
 impl MongoRepo {
-    fn ensure_parent_dir_exists(file_path: &str) -> Result<(), std::io::Error> {
-        let path = PathBuf::from(file_path);
-        if let Some(parent_dir) = path.parent() {
-            if !parent_dir.exists() {
-                fs::create_dir_all(parent_dir)?;
-            }
-        }
-        Ok(())
-    }
-
-    fn get_log_file_path() -> String {
-        std::env::var("LOG_FILE_NAME").unwrap_or_else(|_| "dlog.log".to_string())
-    }
-
     pub async fn init() -> Self {
         log::info!("Init mongo");
         
@@ -72,20 +84,58 @@ impl MongoRepo {
         let db = mongo_client.database(mongo_database.as_str());
         let user_collection: Collection<User> = db.collection(USER_COLL_NAME);
         let log_collection: Collection<Log> = db.collection(LOG_COLL_NAME);
+        let issuer_collection: Collection<Issuer> = db.collection(ISSUER_COLL_NAME);
+        let credential_collection: Collection<IssuedCredential> = db.collection(CREDENTIAL_COLL_NAME);
+        let organisation_collection: Collection<Organisation> = db.collection(ORGANISATION_COLL_NAME);
+        let counter_collection: Collection<Document> = db.collection(COUNTER_COLL_NAME);
+        let transaction_collection: Collection<EvmTransaction> = db.collection(TRANSACTION_COLL_NAME);
+        let license_collection: Collection<License> = db.collection(LICENSE_COLL_NAME);
+        let audit_anchor_collection: Collection<AuditAnchor> = db.collection(AUDIT_ANCHOR_COLL_NAME);
+
+        // the updates of an asset select it by its id, which must be unique across the users.
+        // Unique indexes do not look inside a single array, store_proof_relationship checks that
+        let asset_id_index = IndexModel::builder()
+            .keys(doc! { "assets.assetId": 1 })
+            .options(IndexOptions::builder()
+                .unique(true)
+                .partial_filter_expression(doc! { "assets.assetId": { "$exists": true } })
+                .build())
+            .build();
+        if let Err(err) = user_collection.create_index(asset_id_index).await {
+            log::error!("Failed to create the unique index on the asset ids: {}", err);
+        }
+        // license identifiers are matched ignoring case, so are the duplicates
+        let license_id_index = IndexModel::builder()
+            .keys(doc! { "licenseId": 1 })
+            .options(IndexOptions::builder()
+                .unique(true)
+                .collation(Collation::builder().locale("en").strength(CollationStrength::Secondary).build())
+                .build())
+            .build();
+        if let Err(err) = license_collection.create_index(license_id_index).await {
+            log::error!("Failed to create the unique index on the license ids: {}", err);
+        }
 
-        MongoRepo { user_collection, log_collection }
+        MongoRepo { user_collection, log_collection, issuer_collection, credential_collection, organisation_collection, counter_collection, transaction_collection, license_collection, audit_anchor_collection }
     }
+}
 
-    pub async fn store_user(&self, user: User) -> Result<InsertOneResult, TrustServiceError> {
+#[async_trait]
+impl Repository for MongoRepo {
+
+    async fn store_user(&self, user: User) -> Result<(), TrustServiceError> {
         log::info!("Storing information in db...");
         let new_user = User { 
             did: user.did, 
             fragment: user.fragment, 
-            assets: vec![] 
+            assets: vec![],
+            evm_account: user.evm_account,
+            tenant_id: user.tenant_id,
+            access_token_hash: user.access_token_hash,
         };
        
         match self.user_collection.insert_one(new_user).await {
-            Ok(id) => Ok(id),
+            Ok(_) => Ok(()),
             Err(err) => {
                 log::info!("{}", err.to_string());
                 return Err(TrustServiceError::InsertError)
@@ -94,7 +144,7 @@ impl MongoRepo {
         
     }
 
-    pub async fn get_user(&self, did: &str) -> Result<User, TrustServiceError> {
+    async fn get_user(&self, did: &str) -> Result<User, TrustServiceError> {
         log::info!("Getting User information from db...");
         
         let filter = doc! {"did": did};
@@ -108,11 +158,41 @@ impl MongoRepo {
     
     }
 
-    pub async fn get_asset(&self, asset_id: String) -> Result<Asset, TrustServiceError> {
+    async fn get_user_by_access_token(&self, token_hash: &str) -> Result<Option<User>, TrustServiceError> {
+        let filter = doc! { "accessTokenHash": token_hash };
+        Ok(self.user_collection.find_one(filter).await?)
+    }
 
-        let log_file_path = Self::get_log_file_path();
-        Self::ensure_parent_dir_exists(&log_file_path).map_err(|_| TrustServiceError::FileOpenError)?;
-        let mut file = File::options().create(true).append(true).open(&log_file_path).map_err(|e| TrustServiceError::FileOpenError)?;
+    async fn store_access_token(&self, did: &str, token_hash: String) -> Result<(), TrustServiceError> {
+        log::info!("Updating access token of {}...", did);
+        let filter = doc! { "did": did };
+        let update = doc! { "$set": { "accessTokenHash": token_hash } };
+        let res = self.user_collection.update_one(filter, update).await.map_err(TrustServiceError::MongoDbError)?;
+        if res.matched_count == 0 {
+            return Err(TrustServiceError::UserDidNotFound)
+        }
+        Ok(())
+    }
+
+    async fn get_users(&self) -> Result<Vec<User>, TrustServiceError> {
+        log::info!("Getting all users from db...");
+        let cursor = self.user_collection.find(doc! {}).await?;
+        Ok(cursor.try_collect().await?)
+    }
+
+    async fn get_users_by_tenant(&self, tenant_id: &str) -> Result<Vec<User>, TrustServiceError> {
+        log::info!("Getting users of tenant {} from db...", tenant_id);
+        let cursor = self.user_collection.find(doc! { "tenantId": tenant_id }).await?;
+        Ok(cursor.try_collect().await?)
+    }
+
+    async fn delete_users_by_tenant(&self, tenant_id: &str) -> Result<u64, TrustServiceError> {
+        log::info!("Deleting users of tenant {} from db...", tenant_id);
+        let res = self.user_collection.delete_many(doc! { "tenantId": tenant_id }).await?;
+        Ok(res.deleted_count)
+    }
+
+    async fn get_asset(&self, asset_id: String) -> Result<Asset, TrustServiceError> {
 
         log::info!("Getting Asset information from db...");
         let projected_collection = self.user_collection.clone_with_type::<Value>();
@@ -136,14 +216,6 @@ impl MongoRepo {
         match result {
             Ok(Some(user)) => {
                 log::info!("{} - {}", user["did"], asset_id);
-                //log accessed asset to file
-                file.write_all(format!("{},\"{}\"\n", user["did"], asset_id).as_bytes()).map_err(|e| TrustServiceError::FileWriteError)?;
-                //publish the log to ipfs
-                log::info!("Pushing to IPFS");
-                match publish_log_internal(self).await {
-                    Ok(_) => log::info!("Log successfully published to IPFS"),
-                    Err(e) => log::error!("Failed to publish log to IPFS: {}. Continuing with asset retrieval.", e),
-                }
                 Ok(serde_json::from_value(user["assets"][0].clone())?)
                    
             },
@@ -152,11 +224,7 @@ impl MongoRepo {
         }
     }
 
-    pub async fn get_asset_by_proof(&self, asset_proof: String) -> Result<Asset, TrustServiceError> {
-
-        let log_file_path = Self::get_log_file_path();
-        Self::ensure_parent_dir_exists(&log_file_path).map_err(|_| TrustServiceError::FileOpenError)?;
-        let mut file = File::options().create(true).append(true).open(&log_file_path).map_err(|e| TrustServiceError::FileOpenError)?;
+    async fn get_asset_by_proof(&self, asset_proof: String) -> Result<Asset, TrustServiceError> {
 
         log::info!("Getting Asset information from db...");
         let projected_collection = self.user_collection.clone_with_type::<Value>();
@@ -183,14 +251,6 @@ impl MongoRepo {
             Ok(Some(user)) => {
                 let asset_id = user["assets"][0]["assetId"].clone().as_str().unwrap().to_string();
                 log::info!("{} - {:?}", user["did"], asset_id);
-                //log accessed asset to file
-                file.write_all(format!("{},\"{}\"\n", user["did"], asset_id).as_bytes()).map_err(|e| TrustServiceError::FileWriteError)?;
-                //publish the log to ipfs
-                log::info!("Pushing to IPFS");
-                match publish_log_internal(self).await {
-                    Ok(_) => log::info!("Log successfully published to IPFS"),
-                    Err(e) => log::error!("Failed to publish log to IPFS: {}. Continuing with asset retrieval.", e),
-                }
                 Ok(serde_json::from_value(user["assets"][0].clone())?)
                    
             },
@@ -199,7 +259,32 @@ impl MongoRepo {
         }
     }
 
-    pub async fn store_nft_addr(&self, asset_id: String, nft_addr: String) -> Result<Asset, TrustServiceError> {
+    async fn reserve_nft_mint(&self, asset_id: &str, started_at: String) -> Result<bool, TrustServiceError> {
+        log::info!("Reserving asset {} for the mint of its NFT...", asset_id);
+        // null also matches the assets recorded without the fields
+        let filter = doc! {
+            "assets": {
+                "$elemMatch": {
+                    "assetId": asset_id,
+                    "nftAddr": null,
+                    "minting": null
+                }
+            }
+        };
+        let update = doc! { "$set": { "assets.$.minting": started_at } };
+        let res = self.user_collection.update_one(filter, update).await.map_err(TrustServiceError::MongoDbError)?;
+        Ok(res.modified_count == 1)
+    }
+
+    async fn release_nft_mint(&self, asset_id: &str) -> Result<(), TrustServiceError> {
+        log::info!("Releasing the mint reservation of asset {}...", asset_id);
+        let filter = doc! { "assets.assetId": asset_id };
+        let update = doc! { "$set": { "assets.$.minting": null } };
+        self.user_collection.update_one(filter, update).await.map_err(TrustServiceError::MongoDbError)?;
+        Ok(())
+    }
+
+    async fn store_nft_addr(&self, asset_id: String, nft_addr: String, network: &str, mint_block: Option<u64>) -> Result<Asset, TrustServiceError> {
     
         log::info!("Updating Asset {:#?} information...", asset_id);
         let projected_collection = self.user_collection.clone_with_type::<Value>();
@@ -215,7 +300,10 @@ impl MongoRepo {
 
         // Define the update operation
         let update = doc! { "$set": { 
-                "assets.$.nftAddr": nft_addr
+                "assets.$.nftAddr": nft_addr,
+                "assets.$.network": network,
+                "assets.$.mintBlock": mint_block.map(|mint_block| mint_block as i64),
+                "assets.$.minting": null
             }
         };
 
@@ -225,7 +313,7 @@ impl MongoRepo {
             .await.map_err(TrustServiceError::MongoDbError)?;
 
         println!("Updated documents: {}", res.modified_count);
-        let ass = Asset{ asset_id, proof_id: "todo()!".to_string(), nft_addr:  None };
+        let ass = Asset{ asset_id, proof_id: "todo()!".to_string(), nft_addr:  None, token_id: None, owner_address: None, approved: None, operators: vec![], metadata_uri: None, network: None, mint_block: None, minting: None, burned: false, signed_requests: vec![], revocation: None };
         Ok(ass)
         //  {
         //     Ok(user) => {
@@ -237,7 +325,214 @@ impl MongoRepo {
         // }
     }
 
-    pub async fn store_proof_relationship(
+    async fn store_nft_ownership(&self, asset_id: &str, token_id: String, owner_address: String) -> Result<(), TrustServiceError> {
+        log::info!("Updating ownership of the NFT of asset {}...", asset_id);
+        let filter = doc! {
+            "assets": {
+                "$elemMatch": {
+                    "assetId": asset_id
+                }
+            }
+        };
+        let update = doc! { "$set": {
+                "assets.$.tokenId": token_id,
+                "assets.$.ownerAddress": owner_address,
+                "assets.$.approved": null,
+                "assets.$.operators": []
+            }
+        };
+        self.user_collection.update_one(filter, update).await.map_err(TrustServiceError::MongoDbError)?;
+        Ok(())
+    }
+
+    async fn store_nft_burned(&self, asset_id: &str) -> Result<(), TrustServiceError> {
+        log::info!("Marking the NFT of asset {} as burned...", asset_id);
+        let filter = doc! { "assets.assetId": asset_id };
+        let update = doc! { "$set": {
+                "assets.$.burned": true,
+                "assets.$.ownerAddress": null,
+                "assets.$.approved": null,
+                "assets.$.operators": []
+            }
+        };
+        self.user_collection.update_one(filter, update).await.map_err(TrustServiceError::MongoDbError)?;
+        Ok(())
+    }
+
+    async fn store_nft_token_id(&self, asset_id: &str, token_id: String) -> Result<(), TrustServiceError> {
+        let filter = doc! { "assets.assetId": asset_id };
+        let update = doc! { "$set": { "assets.$.tokenId": token_id } };
+        self.user_collection.update_one(filter, update).await.map_err(TrustServiceError::MongoDbError)?;
+        Ok(())
+    }
+
+    async fn store_nft_approval(&self, asset_id: &str, approved: Option<String>) -> Result<(), TrustServiceError> {
+        log::info!("Updating approval of the NFT of asset {}...", asset_id);
+        let filter = doc! { "assets.assetId": asset_id };
+        let update = doc! { "$set": { "assets.$.approved": approved } };
+        self.user_collection.update_one(filter, update).await.map_err(TrustServiceError::MongoDbError)?;
+        Ok(())
+    }
+
+    async fn store_nft_operator(&self, asset_id: &str, operator: String, approved: bool) -> Result<(), TrustServiceError> {
+        log::info!("Updating operators of the NFT of asset {}...", asset_id);
+        let filter = doc! { "assets.assetId": asset_id };
+        let update = match approved {
+            true => doc! { "$addToSet": { "assets.$.operators": operator } },
+            false => doc! { "$pull": { "assets.$.operators": operator } },
+        };
+        self.user_collection.update_one(filter, update).await.map_err(TrustServiceError::MongoDbError)?;
+        Ok(())
+    }
+
+    async fn store_nft_metadata_uri(&self, asset_id: &str, metadata_uri: String) -> Result<(), TrustServiceError> {
+        log::info!("Updating metadata URI of the NFT of asset {}...", asset_id);
+        let filter = doc! { "assets.assetId": asset_id };
+        let update = doc! { "$set": { "assets.$.metadataUri": metadata_uri } };
+        self.user_collection.update_one(filter, update).await.map_err(TrustServiceError::MongoDbError)?;
+        Ok(())
+    }
+
+    async fn find_asset_by_proof(&self, proof_id: &str) -> Result<Option<(String, Asset)>, TrustServiceError> {
+        let filter = doc! { "assets.proofId": proof_id };
+        match self.user_collection.find_one(filter).await? {
+            Some(User { did, assets, .. }) => {
+                Ok(assets.into_iter().find(|asset| asset.proof_id == proof_id).map(|asset| (did, asset)))
+            },
+            None => Ok(None),
+        }
+    }
+
+    async fn get_asset_by_nft_addr(&self, nft_addr: &str, network: &str, default_network: bool) -> Result<Option<(String, Asset)>, TrustServiceError> {
+        // a null match also selects the assets recorded without a network
+        let networks = match default_network {
+            true => vec![Bson::String(network.to_owned()), Bson::Null],
+            false => vec![Bson::String(network.to_owned())],
+        };
+        let filter = doc! { "assets": { "$elemMatch": { "nftAddr": nft_addr, "network": { "$in": networks } } } };
+        match self.user_collection.find_one(filter).await? {
+            Some(User { did, assets, .. }) => {
+                Ok(assets.into_iter()
+                    .find(|asset| asset.nft_addr.as_deref() == Some(nft_addr) && asset.is_on_network(network, default_network))
+                    .map(|asset| (did, asset)))
+            },
+            None => Ok(None),
+        }
+    }
+
+    async fn get_user_by_asset(&self, asset_id: &str) -> Result<User, TrustServiceError> {
+        log::info!("Getting owner of asset {} from db...", asset_id);
+        let filter = doc! { "assets.assetId": asset_id };
+        match self.user_collection.find_one(filter).await? {
+            Some(user) => Ok(user),
+            None => Err(TrustServiceError::AssetIdNotFound(asset_id.to_owned())),
+        }
+    }
+
+    async fn get_user_by_evm_address(&self, address: &str) -> Result<Option<User>, TrustServiceError> {
+        let filter = doc! { "evmAccount.address": address };
+        Ok(self.user_collection.find_one(filter).await?)
+    }
+
+    async fn store_evm_account(&self, did: &str, evm_account: EvmAccount) -> Result<(), TrustServiceError> {
+        log::info!("Linking EVM account {} to {}...", evm_account.address, did);
+        let filter = doc! { "did": did };
+        let update = doc! { "$set": { "evmAccount": mongodb::bson::to_bson(&evm_account).map_err(|err| TrustServiceError::CustomError(err.to_string()))? } };
+        self.user_collection.update_one(filter, update).await.map_err(TrustServiceError::MongoDbError)?;
+        Ok(())
+    }
+
+    async fn consume_signature_nonce(&self, did: &str, nonce: u64) -> Result<bool, TrustServiceError> {
+        let expected = if nonce == 0 {
+            // accounts linked before the signed requests have no nonce yet
+            doc! { "$in": [0i64, Bson::Null] }
+        } else {
+            doc! { "$eq": nonce as i64 }
+        };
+        let filter = doc! { "did": did, "evmAccount.signatureNonce": expected };
+        let update = doc! { "$set": { "evmAccount.signatureNonce": (nonce + 1) as i64 } };
+        let res = self.user_collection.update_one(filter, update).await.map_err(TrustServiceError::MongoDbError)?;
+        Ok(res.modified_count == 1)
+    }
+
+    async fn release_signature_nonce(&self, did: &str, nonce: u64) -> Result<bool, TrustServiceError> {
+        let filter = doc! { "did": did, "evmAccount.signatureNonce": (nonce + 1) as i64 };
+        let update = doc! { "$set": { "evmAccount.signatureNonce": nonce as i64 } };
+        let res = self.user_collection.update_one(filter, update).await.map_err(TrustServiceError::MongoDbError)?;
+        Ok(res.modified_count == 1)
+    }
+
+    async fn store_signed_request(&self, asset_id: &str, signed_request: SignedRequest) -> Result<(), TrustServiceError> {
+        let filter = doc! { "assets.assetId": asset_id };
+        let update = doc! { "$push": { "assets.$.signedRequests": signed_request } };
+        self.user_collection.update_one(filter, update).await.map_err(TrustServiceError::MongoDbError)?;
+        Ok(())
+    }
+
+    async fn store_proof_revocation(&self, proof_id: &str, revocation: ProofRevocation) -> Result<(), TrustServiceError> {
+        log::info!("Recording revocation of proof {}...", proof_id);
+        let filter = doc! { "assets.proofId": proof_id };
+        let update = doc! { "$set": { "assets.$.revocation": revocation } };
+        self.user_collection.update_one(filter, update).await.map_err(TrustServiceError::MongoDbError)?;
+        Ok(())
+    }
+
+    async fn allocate_evm_address_index(&self) -> Result<u32, TrustServiceError> {
+        let filter = doc! { "name": EVM_ADDRESS_INDEX_COUNTER };
+        let update = doc! { "$inc": { "value": 1 } };
+        // the document before the update holds the reserved index, none the first time
+        let counter = self.counter_collection.find_one_and_update(filter, update).upsert(true).await?;
+        Ok(counter.and_then(|counter| counter.get_i32("value").ok()).unwrap_or(0) as u32)
+    }
+
+    async fn get_indexer_cursor(&self, network: &str) -> Result<Option<u64>, TrustServiceError> {
+        let filter = doc! { "name": EVM_INDEXER_CURSOR, "network": network };
+        let cursor = self.counter_collection.find_one(filter).await?;
+        Ok(cursor.and_then(|cursor| cursor.get_i64("value").ok()).map(|block| block as u64))
+    }
+
+    async fn store_indexer_cursor(&self, network: &str, block: u64) -> Result<(), TrustServiceError> {
+        let filter = doc! { "name": EVM_INDEXER_CURSOR, "network": network };
+        let update = doc! { "$set": { "value": block as i64 } };
+        self.counter_collection.update_one(filter, update).upsert(true).await?;
+        Ok(())
+    }
+
+    async fn get_asset_contract(&self, network: &str) -> Result<Option<String>, TrustServiceError> {
+        let filter = doc! { "name": EVM_ASSET_CONTRACT, "network": network };
+        let asset_contract = self.counter_collection.find_one(filter).await?;
+        Ok(asset_contract.and_then(|asset_contract| asset_contract.get_str("value").ok().map(str::to_owned)))
+    }
+
+    async fn store_asset_contract(&self, network: &str, asset_contract: &str) -> Result<(), TrustServiceError> {
+        let filter = doc! { "name": EVM_ASSET_CONTRACT, "network": network };
+        let update = doc! { "$set": { "value": asset_contract } };
+        self.counter_collection.update_one(filter, update).upsert(true).await?;
+        Ok(())
+    }
+
+    async fn get_audit_anchor(&self, log: &str) -> Result<Option<AuditAnchor>, TrustServiceError> {
+        Ok(self.audit_anchor_collection.find_one(doc! { "log": log }).await?)
+    }
+
+    async fn store_audit_anchor(&self, anchor: &AuditAnchor) -> Result<(), TrustServiceError> {
+        self.audit_anchor_collection.replace_one(doc! { "log": anchor.log.as_str() }, anchor).upsert(true).await?;
+        Ok(())
+    }
+
+    async fn store_transaction(&self, transaction: &EvmTransaction) -> Result<(), TrustServiceError> {
+        let filter = doc! { "chainId": transaction.chain_id as i64, "from": transaction.from.as_str(), "nonce": transaction.nonce as i64 };
+        self.transaction_collection.replace_one(filter, transaction).upsert(true).await?;
+        Ok(())
+    }
+
+    async fn get_transactions_by_status(&self, chain_id: u64, from: &str, status: TransactionStatus) -> Result<Vec<EvmTransaction>, TrustServiceError> {
+        let status = mongodb::bson::to_bson(&status).map_err(|err| TrustServiceError::CustomError(err.to_string()))?;
+        let filter = doc! { "chainId": chain_id as i64, "from": from, "status": status };
+        Ok(self.transaction_collection.find(filter).await?.try_collect().await?)
+    }
+
+    async fn store_proof_relationship(
         &self, 
         did: &str,
         proof_id: String, 
@@ -245,30 +540,32 @@ impl MongoRepo {
     ) -> Result<(), TrustServiceError> {
 
         log::info!("Storing proof-asset relationship...");
-        let filter = doc! {"did": did};
+        // the positional updates of the asset select it by its id, a second asset with the same id would never be reached
+        let filter = doc! {"did": did, "assets.assetId": { "$ne": asset_id.as_str() }};
 
-        let asset = Asset { proof_id, asset_id, nft_addr: None };
+        let asset = Asset { proof_id, asset_id: asset_id.clone(), nft_addr: None, token_id: None, owner_address: None, approved: None, operators: vec![], metadata_uri: None, network: None, mint_block: None, minting: None, burned: false, signed_requests: vec![], revocation: None };
         let update = doc! {
             "$push": {
                 "assets": asset
             }
         };
 
-        self.user_collection.update_one(filter, update).await.map_err(TrustServiceError::MongoDbError)?;
+        // another user holding the id is refused by the unique index
+        let res = self.user_collection.update_one(filter, update).await.map_err(|err| match is_duplicate_key(&err) {
+            true => TrustServiceError::AssetIdAlreadyExists(asset_id.clone()),
+            false => TrustServiceError::MongoDbError(err),
+        })?;
+        if res.matched_count == 0 {
+            return Err(TrustServiceError::AssetIdAlreadyExists(asset_id))
+        }
         Ok(())
     }
 
-    /// This function takes a log object and stores it in the Mongo DB.
-    /// The purpose of the function is to update the CID of the log file
-    /// stored in the DB. The DB matches a name to a CID.
-    /// There should be only one document in the DB where the field, name,
-    /// does not change, while the CID field is updated on each request.
-    ///
-    /// If the document does not exist, this function will create it.
-    ///
-    /// # Parameters
-    /// * log - the document to save
-    pub async fn store_log_cid(&self, log: Log) -> Result<(), TrustServiceError> {
+    async fn asset_exists(&self, asset_id: &str) -> Result<bool, TrustServiceError> {
+        Ok(self.user_collection.count_documents(doc! { "assets.assetId": asset_id }).await? > 0)
+    }
+
+    async fn store_log_cid(&self, log: Log) -> Result<(), TrustServiceError> {
         log::info!("Storing information in db...");
         log::info!("File name: {}, CID: {}", log.name, log.cid);
 
@@ -296,13 +593,7 @@ impl MongoRepo {
 
     }
 
-    /// This function returns the CID of the log file.
-    /// There must be only one document in the DB inside the Log_IPFS collection.
-    /// The name of this document is fixed in the .mongo.env file.
-    /// This function reads the name of the file, looks for it in the DB and then
-    /// returns the CID field present in that document.
-    /// The CID is the identifier of the file within IPFS.
-    pub async fn get_log_cid(&self) -> Result<String, TrustServiceError>{
+    async fn get_log_cid(&self) -> Result<String, TrustServiceError>{
 
         // read the filename of the log file from .mongo.env
         let log_filename = std::env::var("LOG_FILE_NAME")
@@ -327,4 +618,146 @@ impl MongoRepo {
             }
         }
     }
-}
\ No newline at end of file
+
+    async fn get_issuer(&self) -> Result<Issuer, TrustServiceError> {
+        log::info!("Getting issuer information from db...");
+        match self.issuer_collection.find_one(doc! {}).await? {
+            Some(issuer) => Ok(issuer),
+            None => Err(TrustServiceError::IssuerNotFound),
+        }
+    }
+
+    async fn store_issuer(&self, issuer: Issuer) -> Result<(), TrustServiceError> {
+        log::info!("Storing issuer information in db...");
+        self.issuer_collection.insert_one(issuer).await.map_err(TrustServiceError::MongoDbError)?;
+        Ok(())
+    }
+
+    async fn store_credential(&self, credential: &IssuedCredential) -> Result<(), TrustServiceError> {
+        log::info!("Storing issued credential {}...", credential.credential_id);
+        match self.credential_collection.insert_one(credential).await {
+            Ok(_) => Ok(()),
+            Err(err) => {
+                log::info!("{}", err.to_string());
+                Err(TrustServiceError::InsertError)
+            }
+        }
+    }
+
+    async fn get_credentials_by_holder(&self, holder_did: &str) -> Result<Vec<IssuedCredential>, TrustServiceError> {
+        log::info!("Getting credentials of {} from db...", holder_did);
+        let filter = doc! { "holderDid": holder_did };
+        let cursor = self.credential_collection.find(filter)
+            .sort(doc! { "issuanceDate": 1 })
+            .await?;
+        Ok(cursor.try_collect().await?)
+    }
+
+    async fn allocate_status_index(&self) -> Result<u32, TrustServiceError> {
+        let update = doc! { "$inc": { "nextStatusIndex": 1 } };
+        // the document before the update holds the reserved index
+        match self.issuer_collection.find_one_and_update(doc! {}, update).await? {
+            Some(issuer) => Ok(issuer.next_status_index),
+            None => Err(TrustServiceError::IssuerNotFound),
+        }
+    }
+
+    async fn get_credential_by_status_index(&self, status_index: u32) -> Result<IssuedCredential, TrustServiceError> {
+        log::info!("Getting credential with status index {} from db...", status_index);
+        let filter = doc! { "statusIndex": status_index };
+        match self.credential_collection.find_one(filter).await? {
+            Some(credential) => Ok(credential),
+            None => Err(TrustServiceError::CredentialNotFound(status_index.to_string())),
+        }
+    }
+
+    async fn get_credential(&self, credential_id: &str) -> Result<Option<IssuedCredential>, TrustServiceError> {
+        Ok(self.credential_collection.find_one(doc! { "credentialId": credential_id }).await?)
+    }
+
+    async fn update_credential_status(&self, status_index: u32, status: CredentialState) -> Result<(), TrustServiceError> {
+        log::info!("Updating status of credential with index {}...", status_index);
+        let filter = doc! { "statusIndex": status_index };
+        let update = doc! { "$set": { "status": mongodb::bson::to_bson(&status).map_err(|err| TrustServiceError::CustomError(err.to_string()))? } };
+        self.credential_collection.update_one(filter, update).await.map_err(TrustServiceError::MongoDbError)?;
+        Ok(())
+    }
+
+    async fn store_organisation(&self, organisation: &Organisation) -> Result<(), TrustServiceError> {
+        log::info!("Storing organisation {}...", organisation.did);
+        match self.organisation_collection.insert_one(organisation).await {
+            Ok(_) => Ok(()),
+            Err(err) => {
+                log::info!("{}", err.to_string());
+                Err(TrustServiceError::InsertError)
+            }
+        }
+    }
+
+    async fn get_organisation(&self, did: &str) -> Result<Organisation, TrustServiceError> {
+        log::info!("Getting organisation {} from db...", did);
+        match self.organisation_collection.find_one(doc! { "did": did }).await? {
+            Some(organisation) => Ok(organisation),
+            None => Err(TrustServiceError::OrganisationNotFound(did.to_owned())),
+        }
+    }
+
+    async fn store_license(&self, license: &License) -> Result<(), TrustServiceError> {
+        log::info!("Storing license {}...", license.license_id);
+        match self.license_collection.insert_one(license).await {
+            Ok(_) => Ok(()),
+            Err(err) if is_duplicate_key(&err) => Err(TrustServiceError::LicenseAlreadyRegistered(license.license_id.clone())),
+            Err(err) => {
+                log::info!("{}", err.to_string());
+                Err(TrustServiceError::InsertError)
+            }
+        }
+    }
+
+    async fn get_license(&self, license_id: &str) -> Result<Option<License>, TrustServiceError> {
+        let case_insensitive = Collation::builder().locale("en").strength(CollationStrength::Secondary).build();
+        Ok(self.license_collection.find_one(doc! { "licenseId": license_id }).collation(case_insensitive).await?)
+    }
+
+    async fn get_licenses(&self) -> Result<Vec<License>, TrustServiceError> {
+        let cursor = self.license_collection.find(doc! {}).await?;
+        Ok(cursor.try_collect().await?)
+    }
+
+    async fn get_organisations(&self) -> Result<Vec<Organisation>, TrustServiceError> {
+        log::info!("Getting all organisations from db...");
+        let cursor = self.organisation_collection.find(doc! {}).await?;
+        Ok(cursor.try_collect().await?)
+    }
+
+    async fn add_membership(&self, organisation_did: &str, membership: &Membership) -> Result<(), TrustServiceError> {
+        log::info!("Adding member {} to {}...", membership.member_did, organisation_did);
+        let filter = doc! { "did": organisation_did };
+        let update = doc! { "$push": { "members": mongodb::bson::to_bson(membership).map_err(|err| TrustServiceError::CustomError(err.to_string()))? } };
+        self.organisation_collection.update_one(filter, update).await.map_err(TrustServiceError::MongoDbError)?;
+        Ok(())
+    }
+
+    async fn revoke_membership(&self, organisation_did: &str, member_did: &str, revoked_at: String) -> Result<(), TrustServiceError> {
+        log::info!("Removing member {} from {}...", member_did, organisation_did);
+        let filter = doc! {
+            "did": organisation_did,
+            "members": {
+                "$elemMatch": {
+                    "memberDid": member_did,
+                    "revokedAt": null
+                }
+            }
+        };
+        let update = doc! { "$set": { "members.$.revokedAt": revoked_at } };
+        let res = self.organisation_collection.update_one(filter, update).await.map_err(TrustServiceError::MongoDbError)?;
+        if res.matched_count == 0 {
+            return Err(TrustServiceError::MembershipNotFound(member_did.to_owned()))
+        }
+        Ok(())
+    }
+}
+
+fn is_duplicate_key(err: &mongodb::error::Error) -> bool {
+    matches!(*err.kind, ErrorKind::Write(WriteFailure::WriteError(ref write_error)) if write_error.code == DUPLICATE_KEY_CODE)
+}
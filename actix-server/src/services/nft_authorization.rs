@@ -0,0 +1,203 @@
+// SPDX-FileCopyrightText: 2024 Fondazione LINKS
+//
+// SPDX-License-Identifier: APACHE-2.0
+
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::str::FromStr;
+
+use ethers::types::transaction::eip712::{EIP712Domain, Eip712, Eip712DomainType, TypedData, Types};
+use ethers::types::{Address, Signature, H256};
+use identity_iota::core::Timestamp;
+use serde_json::Value;
+
+use crate::dtos::NftAuthorization;
+use crate::errors::TrustServiceError;
+use crate::models::signed_request::SignedRequest;
+use crate::services::repository::Repository;
+
+const DOMAIN_NAME: &str = "Trust Service";
+const DOMAIN_VERSION: &str = "1";
+const DOMAIN_FIELDS: &[(&str, &str)] = &[("name", "string"), ("version", "string"), ("chainId", "uint256"), ("verifyingContract", "address")];
+const MINT_REQUEST_FIELDS: &[(&str, &str)] = &[
+    ("did", "string"), ("assetId", "string"), ("nftAlias", "string"), ("nftSymbol", "string"), ("license", "string"),
+    ("nonce", "uint256"), ("deadline", "uint256"),
+];
+const TRANSFER_REQUEST_FIELDS: &[(&str, &str)] = &[
+    ("did", "string"), ("assetId", "string"), ("to", "address"), ("nonce", "uint256"), ("deadline", "uint256"),
+];
+const APPROVAL_REQUEST_FIELDS: &[(&str, &str)] = &[
+    ("did", "string"), ("assetId", "string"), ("operator", "address"), ("all", "bool"), ("approved", "bool"),
+    ("nonce", "uint256"), ("deadline", "uint256"),
+];
+
+/// NFT request a user can sign with the EVM account linked to its DID.
+///
+/// The typed data are in the domain `Trust Service` version `1`, with the chain id of the
+/// network and the AssetFactory as verifying contract.
+pub enum NftRequestData<'a> {
+    Mint { did: &'a str, asset_id: &'a str, nft_alias: &'a str, nft_symbol: &'a str, license: &'a str },
+    Transfer { did: &'a str, asset_id: &'a str, to: Address },
+    Approval { did: &'a str, asset_id: &'a str, operator: Address, all: bool, approved: bool },
+}
+
+impl NftRequestData<'_> {
+    fn did(&self) -> &str {
+        match self {
+            NftRequestData::Mint { did, .. } | NftRequestData::Transfer { did, .. } | NftRequestData::Approval { did, .. } => did,
+        }
+    }
+
+    fn primary_type(&self) -> &'static str {
+        match self {
+            NftRequestData::Mint { .. } => "MintRequest",
+            NftRequestData::Transfer { .. } => "TransferRequest",
+            NftRequestData::Approval { .. } => "ApprovalRequest",
+        }
+    }
+
+    fn fields(&self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            NftRequestData::Mint { .. } => MINT_REQUEST_FIELDS,
+            NftRequestData::Transfer { .. } => TRANSFER_REQUEST_FIELDS,
+            NftRequestData::Approval { .. } => APPROVAL_REQUEST_FIELDS,
+        }
+    }
+
+    fn message(&self, nonce: u64, deadline: u64) -> BTreeMap<String, Value> {
+        let mut message: Vec<(&str, Value)> = match *self {
+            NftRequestData::Mint { did, asset_id, nft_alias, nft_symbol, license } => vec![
+                ("did", did.into()),
+                ("assetId", asset_id.into()),
+                ("nftAlias", nft_alias.into()),
+                ("nftSymbol", nft_symbol.into()),
+                ("license", license.into()),
+            ],
+            NftRequestData::Transfer { did, asset_id, to } => vec![
+                ("did", did.into()),
+                ("assetId", asset_id.into()),
+                ("to", format!("{to:#x}").into()),
+            ],
+            NftRequestData::Approval { did, asset_id, operator, all, approved } => vec![
+                ("did", did.into()),
+                ("assetId", asset_id.into()),
+                ("operator", format!("{operator:#x}").into()),
+                ("all", all.into()),
+                ("approved", approved.into()),
+            ],
+        };
+        // uint256 values are written as decimal strings, as wallets do
+        message.push(("nonce", nonce.to_string().into()));
+        message.push(("deadline", deadline.to_string().into()));
+        message.into_iter().map(|(name, value)| (name.to_owned(), value)).collect()
+    }
+}
+
+/// Signing domain of the requests relayed on the chain `chain_id` through `verifying_contract`.
+pub fn domain(chain_id: u64, verifying_contract: Address) -> EIP712Domain {
+    EIP712Domain {
+        name: Some(DOMAIN_NAME.to_owned()),
+        version: Some(DOMAIN_VERSION.to_owned()),
+        chain_id: Some(chain_id.into()),
+        verifying_contract: Some(verifying_contract),
+        salt: None,
+    }
+}
+
+/// EIP-712 typed data of `request`, as passed to `eth_signTypedData_v4`.
+pub fn typed_data(domain: &EIP712Domain, request: &NftRequestData, nonce: u64, deadline: u64) -> TypedData {
+    let fields = |fields: &[(&str, &str)]| -> Vec<Eip712DomainType> {
+        fields.iter()
+            .map(|(name, field_type)| Eip712DomainType { name: (*name).to_owned(), r#type: (*field_type).to_owned() })
+            .collect()
+    };
+    let types: Types = BTreeMap::from([
+        ("EIP712Domain".to_owned(), fields(DOMAIN_FIELDS)),
+        (request.primary_type().to_owned(), fields(request.fields())),
+    ]);
+    TypedData {
+        domain: domain.clone(),
+        types,
+        primary_type: request.primary_type().to_owned(),
+        message: request.message(nonce, deadline),
+    }
+}
+
+/// EIP-712 digest of `request` in `domain`.
+pub fn digest(domain: &EIP712Domain, request: &NftRequestData, nonce: u64, deadline: u64) -> Result<H256, TrustServiceError> {
+    typed_data(domain, request, nonce, deadline).encode_eip712()
+        .map(H256)
+        .map_err(|err| TrustServiceError::InvalidSignedRequest(err.to_string()))
+}
+
+/// Checks that `request` was signed by the EVM account linked to its DID, before the
+/// deadline and with the next nonce of the account, which is then consumed.
+/// Returns the evidence to keep with the asset once the request is relayed with [`relay`].
+pub async fn verify(
+    mongo_repo: &dyn Repository,
+    domain: &EIP712Domain,
+    request: &NftRequestData<'_>,
+    authorization: &NftAuthorization
+) -> Result<SignedRequest, TrustServiceError> {
+    let now = Timestamp::now_utc();
+    if authorization.deadline < now.to_unix() as u64 {
+        return Err(TrustServiceError::InvalidSignedRequest("the deadline has passed".to_owned()))
+    }
+    let did = request.did();
+    let evm_account = mongo_repo.get_user(did).await?.evm_account
+        .ok_or(TrustServiceError::EvmAccountNotLinked(did.to_owned()))?;
+    if authorization.nonce != evm_account.signature_nonce {
+        return Err(TrustServiceError::InvalidSignedRequest(format!("expected nonce {}", evm_account.signature_nonce)))
+    }
+
+    let digest = digest(domain, request, authorization.nonce, authorization.deadline)?;
+    let signature = Signature::from_str(authorization.signature.as_str())
+        .map_err(|_| TrustServiceError::InvalidSignedRequest("malformed signature".to_owned()))?;
+    let signer = signature.recover(digest)
+        .map_err(|err| TrustServiceError::InvalidSignedRequest(err.to_string()))?;
+    if format!("{signer:#x}") != evm_account.address {
+        return Err(TrustServiceError::InvalidSignedRequest(format!("signed by {signer:#x}, not by the account linked to {did}")))
+    }
+
+    // a concurrent request with the same nonce loses here
+    if !mongo_repo.consume_signature_nonce(did, authorization.nonce).await? {
+        return Err(TrustServiceError::InvalidSignedRequest(format!("nonce {} already used", authorization.nonce)))
+    }
+    Ok(SignedRequest {
+        primary_type: request.primary_type().to_owned(),
+        signer: format!("{signer:#x}"),
+        signature: authorization.signature.clone(),
+        digest: format!("{digest:#x}"),
+        chain_id: domain.chain_id.map_or(0, |chain_id| chain_id.as_u64()),
+        nonce: authorization.nonce,
+        deadline: authorization.deadline,
+        transaction_hash: None,
+        verified_at: now.to_rfc3339(),
+    })
+}
+
+/// Whether a transaction that failed with `err` was broadcast and can still be mined.
+pub fn may_be_mined(err: &TrustServiceError) -> bool {
+    matches!(err, TrustServiceError::TransactionPending(..) | TrustServiceError::TransactionTimeout(_) | TrustServiceError::TransactionFeeCap(_))
+}
+
+/// Awaits the transaction of a verified request. When it fails without a transaction that
+/// can still be mined, the nonce of `did` is given back, so that the request can be sent again.
+pub async fn relay<T>(
+    mongo_repo: &dyn Repository,
+    did: &str,
+    signed_request: Option<&SignedRequest>,
+    transaction: impl Future<Output = Result<T, TrustServiceError>>
+) -> Result<T, TrustServiceError> {
+    let result = transaction.await;
+    if let (Err(err), Some(signed_request)) = (&result, signed_request) {
+        if !may_be_mined(err) {
+            match mongo_repo.release_signature_nonce(did, signed_request.nonce).await {
+                Ok(true) => log::info!("Nonce {} of {} released", signed_request.nonce, did),
+                Ok(false) => log::warn!("Nonce {} of {} not released, a later one was used", signed_request.nonce, did),
+                Err(release_err) => log::error!("Failed to release nonce {} of {}: {}", signed_request.nonce, did, release_err),
+            }
+        }
+    }
+    result
+}
@@ -0,0 +1,169 @@
+// SPDX-FileCopyrightText: 2024 Fondazione LINKS
+//
+// SPDX-License-Identifier: APACHE-2.0
+
+use ethers::types::Address;
+
+use crate::contracts::asset::Asset;
+use crate::contracts::assetfactory::AssetFactory;
+use crate::dtos::{IntegrityCheck, NftIntegrityReport};
+use crate::errors::TrustServiceError;
+use crate::models::asset::Asset as AssetRecord;
+use crate::services::did_resolver::DidResolver;
+use crate::services::evm_networks::{EvmNetwork, EvmNetworks};
+use crate::services::iota_state::ProofResolver;
+use crate::services::repository::Repository;
+use crate::services::nft_service;
+use crate::services::organisation_service::verify_delegation;
+
+/// Checks that the NFT of an asset, its proof on the Tangle and the owner DID agree.
+///
+/// Every check is run and reported, a failure does not stop the following ones.
+pub async fn check_asset_integrity(
+    proof_resolver: &dyn ProofResolver,
+    did_resolver: &DidResolver,
+    mongo_repo: &dyn Repository,
+    evm_networks: &EvmNetworks,
+    asset_id: &str
+) -> Result<NftIntegrityReport, TrustServiceError> {
+    let owner = mongo_repo.get_user_by_asset(asset_id).await?;
+    let asset = owner.assets.iter()
+        .find(|asset| asset.asset_id == asset_id)
+        .ok_or(TrustServiceError::AssetIdNotFound(asset_id.to_owned()))?;
+
+    let mut checks = proof_checks(proof_resolver, did_resolver, mongo_repo, asset, owner.did.as_str()).await;
+    match asset.nft_addr.as_deref() {
+        Some(nft_addr) => checks.extend(nft_checks(evm_networks.of_asset(asset)?, asset, owner.did.as_str(), nft_addr).await),
+        None => checks.push(IntegrityCheck::outcome("nftMinted", Err("no NFT minted for the asset".to_owned()))),
+    }
+
+    Ok(NftIntegrityReport {
+        asset_id: asset_id.to_owned(),
+        nft_address: asset.nft_addr.clone(),
+        consistent: checks.iter().all(|check| check.passed),
+        checks,
+    })
+}
+
+/// Checks run before minting, `did` being the authenticated caller: `did` owns the asset,
+/// which has no NFT yet and a current proof, and the proof of the asset resolves and verifies
+/// against `did`. The checks of the NFT itself can only run once it is minted.
+pub async fn validate_mint(
+    proof_resolver: &dyn ProofResolver,
+    did_resolver: &DidResolver,
+    mongo_repo: &dyn Repository,
+    asset_id: &str,
+    did: &str
+) -> Result<AssetRecord, TrustServiceError> {
+    let owner = mongo_repo.get_user_by_asset(asset_id).await?;
+    if owner.did != did {
+        return Err(TrustServiceError::AssetNotOwned(did.to_owned()))
+    }
+    let asset = owner.assets.into_iter()
+        .find(|asset| asset.asset_id == asset_id)
+        .ok_or(TrustServiceError::AssetIdNotFound(asset_id.to_owned()))?;
+    if asset.nft_addr.is_some() {
+        return Err(TrustServiceError::NftAlreadyMinted(asset_id.to_owned()))
+    }
+    if asset.revocation.is_some() {
+        return Err(TrustServiceError::ProofRevoked(asset.proof_id))
+    }
+
+    let report = NftIntegrityReport {
+        asset_id: asset_id.to_owned(),
+        checks: proof_checks(proof_resolver, did_resolver, mongo_repo, &asset, did).await,
+        ..Default::default()
+    };
+    if report.checks.iter().any(|check| !check.passed) {
+        return Err(TrustServiceError::NftIntegrityError(report.failures()))
+    }
+    Ok(asset)
+}
+
+/// The proof of the asset is on the Tangle, was published by `did` and its signature
+/// verifies against the DID document.
+async fn proof_checks(
+    proof_resolver: &dyn ProofResolver,
+    did_resolver: &DidResolver,
+    mongo_repo: &dyn Repository,
+    asset: &AssetRecord,
+    did: &str
+) -> Vec<IntegrityCheck> {
+    let proof = match proof_resolver.resolve_proof(asset.proof_id.clone()).await {
+        Ok(proof) => proof,
+        Err(err) => return vec![IntegrityCheck::outcome("proofResolution", Err(err.to_string()))],
+    };
+    let mut checks = vec![
+        IntegrityCheck::outcome("proofResolution", Ok(())),
+        IntegrityCheck::compare("proofPublisher", did, proof.did_publisher.as_str()),
+    ];
+
+    let signature = match did_resolver.resolve(did).await {
+        Ok(document) => proof.verify(&document).map_err(|err| err.to_string()),
+        Err(err) => Err(format!("resolving {did}: {err}")),
+    };
+    checks.push(IntegrityCheck::outcome("proofSignature", signature));
+    if proof.organisation_did.is_some() {
+        let delegation = verify_delegation(did_resolver, mongo_repo, &proof).await.map_err(|err| err.to_string());
+        checks.push(IntegrityCheck::outcome("proofDelegation", delegation));
+    }
+    checks
+}
+
+/// The values stored in the NFT contract match the asset record and its owner.
+async fn nft_checks(
+    network: &EvmNetwork,
+    asset: &AssetRecord,
+    did: &str,
+    nft_addr: &str
+) -> Vec<IntegrityCheck> {
+    let nft_address: Address = match nft_addr.parse() {
+        Ok(nft_address) => nft_address,
+        Err(_) => return vec![IntegrityCheck::outcome("nftAddress", Err(format!("invalid address {nft_addr}")))],
+    };
+    let asset_sc = Asset::new(nft_address, network.signer.clone());
+    let mut checks = vec![];
+
+    checks.push(match asset_sc.get_asset_id().await {
+        Ok(asset_id) => IntegrityCheck::compare("nftAssetId", asset.asset_id.as_str(), asset_id.as_str()),
+        Err(err) => IntegrityCheck::outcome("nftAssetId", Err(err.to_string())),
+    });
+    checks.push(match asset_sc.get_did().await {
+        Ok(nft_did) => IntegrityCheck::compare("nftDid", did, nft_did.as_str()),
+        Err(err) => IntegrityCheck::outcome("nftDid", Err(err.to_string())),
+    });
+    checks.push(match minted_proof_id(network, asset, nft_address).await {
+        Ok(Some(proof_id)) => IntegrityCheck::compare("nftProofId", asset.proof_id.as_str(), proof_id.as_str()),
+        Ok(None) => IntegrityCheck::outcome("nftProofId", Err("no NftMinted event for the contract in its mint block".to_owned())),
+        Err(err) => IntegrityCheck::outcome("nftProofId", Err(err.to_string())),
+    });
+
+    // the recorded holder drifts when the token is moved outside the service
+    if let Some(owner_address) = asset.owner_address.as_deref() {
+        // the token id is recorded with the holder
+        let holder = match nft_service::recorded_token_id(asset) {
+            Ok(Some(token_id)) => asset_sc.owner_of(token_id).await.map_err(|err| TrustServiceError::ContractError(err.to_string())),
+            Ok(None) => Err(TrustServiceError::CustomError("no token id recorded".to_owned())),
+            Err(err) => Err(err),
+        };
+        checks.push(match holder {
+            Ok(holder) => IntegrityCheck::compare("nftOwner", owner_address, format!("{holder:#x}").as_str()),
+            Err(err) => IntegrityCheck::outcome("nftOwner", Err(err.to_string())),
+        });
+    }
+    checks
+}
+
+/// The contract has no getter for the proof id, it is read from the `NftMinted` event of the factory
+/// in the block recorded at mint, the event has no indexed field to filter on.
+async fn minted_proof_id(network: &EvmNetwork, asset: &AssetRecord, nft_address: Address) -> Result<Option<String>, TrustServiceError> {
+    // recorded by the indexer for the NFTs minted before the block was kept
+    let mint_block = asset.mint_block
+        .ok_or(TrustServiceError::CustomError("the mint block of the NFT is not recorded".to_owned()))?;
+    let asset_factory_sc = AssetFactory::new(network.factory_address, network.signer.clone());
+    let minted = asset_factory_sc.nft_minted_filter().from_block(mint_block).to_block(mint_block).query().await
+        .map_err(|err| TrustServiceError::ContractError(err.to_string()))?;
+    Ok(minted.into_iter()
+        .find(|event| event.istance_address == nft_address)
+        .map(|event| event.proof_id))
+}
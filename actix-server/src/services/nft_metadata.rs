@@ -0,0 +1,124 @@
+// SPDX-FileCopyrightText: 2024 Fondazione LINKS
+//
+// SPDX-License-Identifier: APACHE-2.0
+
+use std::sync::Arc;
+
+use ethers::types::Address;
+use serde_json::{json, Value};
+
+use crate::contracts::asset::Asset;
+use crate::errors::TrustServiceError;
+use crate::models::asset::Asset as AssetRecord;
+use crate::services::evm_networks::EvmNetworks;
+use crate::services::iota_state::ProofResolver;
+use crate::services::ipfs::IpfsService;
+use crate::services::repository::Repository;
+use crate::services::nft_service::ServiceSigner;
+
+const IPFS_URI_SCHEME: &str = "ipfs://";
+
+/// Stated with the published metadata: the deployed `Asset` contract has no setter for `tokenURI`
+/// and `tokenize` takes no URI.
+pub const TOKEN_URI_NOTICE: &str = "the Asset contract cannot store a token URI, tokenURI stays empty on-chain and the metadata URI is recorded by the service only";
+
+/// Builds and pins the ERC-721 metadata of the NFTs, with links to the explorer at `EXPLORER_URL`.
+pub struct MetadataPublisher {
+    explorer_url: String,
+}
+
+impl MetadataPublisher {
+
+    pub fn new(explorer_url: &str) -> Self {
+        MetadataPublisher { explorer_url: explorer_url.trim_end_matches('/').to_owned() }
+    }
+
+    /// Reads `EXPLORER_URL`, at startup rather than on the first mint.
+    pub fn from_env() -> Result<Self, TrustServiceError> {
+        let explorer_url = std::env::var("EXPLORER_URL")
+            .map_err(|_| TrustServiceError::CustomError("$EXPLORER_URL must be set".to_owned()))?;
+        Ok(MetadataPublisher::new(explorer_url.as_str()))
+    }
+
+    /// Builds the ERC-721 metadata of the NFT of an asset from the contract and the proof on the Tangle.
+    pub async fn build_metadata(
+        &self,
+        proof_resolver: &dyn ProofResolver,
+        service: Arc<ServiceSigner>,
+        asset: &AssetRecord,
+        did: &str
+    ) -> Result<Value, TrustServiceError> {
+        let nft_address: Address = asset.nft_addr.as_deref()
+            .ok_or(TrustServiceError::MissingNftAddress)?
+            .parse().map_err(|_| TrustServiceError::ContractAddressRecoveryError)?;
+        let asset_sc = Asset::new(nft_address, service);
+        let name = asset_sc.name().await.map_err(|err| TrustServiceError::ContractError(err.to_string()))?;
+        let license = asset_sc.get_license().await.map_err(|err| TrustServiceError::ContractError(err.to_string()))?;
+        let proof = proof_resolver.resolve_proof(asset.proof_id.clone()).await?;
+
+        let proof_url = format!("{}/output/{}", self.explorer_url, asset.proof_id);
+        let did_url = format!("{}/identity-resolver/{}", self.explorer_url, did);
+
+        Ok(json!({
+            "name": name,
+            "description": format!("Asset {} notarized on the IOTA Tangle by {}, proof {}.", asset.asset_id, did, asset.proof_id),
+            "external_url": proof_url,
+            "attributes": [
+                { "trait_type": "Asset ID", "value": asset.asset_id },
+                { "trait_type": "License", "value": license },
+                { "trait_type": "DID", "value": did },
+                { "trait_type": "Proof ID", "value": asset.proof_id },
+                { "trait_type": "Dataset digest", "value": proof.dataset_digest() },
+                { "trait_type": "Metadata digest", "value": proof.metadata_digest() }
+            ],
+            "properties": {
+                "nftAddress": format!("{:#x}", nft_address),
+                "links": {
+                    "proof": proof_url,
+                    "did": did_url
+                }
+            }
+        }))
+    }
+
+    /// Pins the metadata of the NFT of `asset_id` on IPFS and records its `ipfs://` URI.
+    ///
+    /// The asset contract has no setter for `tokenURI`, the URI is kept in the asset record.
+    pub async fn publish_metadata(
+        &self,
+        proof_resolver: &dyn ProofResolver,
+        mongo_repo: &dyn Repository,
+        evm_networks: &EvmNetworks,
+        asset_id: &str
+    ) -> Result<(String, Value), TrustServiceError> {
+        let owner = mongo_repo.get_user_by_asset(asset_id).await?;
+        let asset = owner.assets.iter()
+            .find(|asset| asset.asset_id == asset_id)
+            .ok_or(TrustServiceError::AssetIdNotFound(asset_id.to_owned()))?;
+        let network = evm_networks.of_asset(asset)?;
+        let metadata = self.build_metadata(proof_resolver, network.signer.clone(), asset, owner.did.as_str()).await?;
+
+        let ipfs_service = IpfsService::new();
+        let cid = ipfs_service.add_bytes(serde_json::to_vec_pretty(&metadata)?).await?;
+        let metadata_uri = format!("{IPFS_URI_SCHEME}{cid}");
+        log::info!("Metadata of the NFT of asset {} pinned at {}", asset_id, metadata_uri);
+        mongo_repo.store_nft_metadata_uri(asset_id, metadata_uri.clone()).await?;
+
+        // unchanged metadata get the same CID, only a different one is released
+        if let Some(previous_cid) = asset.metadata_uri.as_deref().and_then(|uri| uri.strip_prefix(IPFS_URI_SCHEME)) {
+            if previous_cid != cid {
+                ipfs_service.delete_file(previous_cid).await?;
+            }
+        }
+        Ok((metadata_uri, metadata))
+    }
+}
+
+/// Reads back the metadata pinned for the NFT of an asset.
+pub async fn read_metadata(asset: &AssetRecord) -> Result<Value, TrustServiceError> {
+    let cid = asset.metadata_uri.as_deref()
+        .and_then(|uri| uri.strip_prefix(IPFS_URI_SCHEME))
+        .ok_or(TrustServiceError::MongoFileNotFound)?;
+    let data = IpfsService::new().get_file(cid).await?;
+    Ok(serde_json::from_slice(&data)?)
+}
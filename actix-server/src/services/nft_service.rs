@@ -0,0 +1,302 @@
+// SPDX-FileCopyrightText: 2024 Fondazione LINKS
+//
+// SPDX-License-Identifier: APACHE-2.0
+
+use std::str::FromStr;
+use std::sync::Arc;
+
+use ethers::abi::RawLog;
+use ethers::contract::{ContractCall, EthEvent};
+use ethers::core::k256::ecdsa::SigningKey;
+use ethers::middleware::SignerMiddleware;
+use ethers::providers::{Http, Middleware, Provider};
+use ethers::signers::{Signer, Wallet};
+use ethers::types::{Address, Log, H256, U256};
+use identity_stronghold::StrongholdStorage;
+
+use crate::contracts::asset::{Asset, TransferFilter};
+use crate::contracts::assetfactory::NftMintedFilter;
+use crate::errors::TrustServiceError;
+use crate::models::asset::Asset as AssetRecord;
+use crate::models::user::{EvmAccount, User};
+use crate::services::evm_networks::{EvmNetwork, EvmNetworks};
+use crate::services::evm_signer::StrongholdEvmSigner;
+use crate::services::iota_state::IotaState;
+use crate::services::repository::Repository;
+
+/// Middleware signing with the wallet of the service.
+pub type ServiceSigner = SignerMiddleware<Provider<Http>, Wallet<SigningKey>>;
+
+/// Change to the ownership or to the approvals of an asset NFT.
+pub enum NftOperation {
+    Transfer { to: Address },
+    /// `Address::zero()` clears the approval
+    Approve { to: Address },
+    ApproveAll { operator: Address, approved: bool },
+}
+
+impl NftOperation {
+    /// Name of the operation in the transaction records.
+    pub fn label(&self) -> &'static str {
+        match self {
+            NftOperation::Transfer { .. } => "nftTransfer",
+            NftOperation::Approve { .. } => "nftApprove",
+            NftOperation::ApproveAll { .. } => "nftApproveAll",
+        }
+    }
+}
+
+/// NFT contract created by a `tokenize` transaction.
+pub struct MintedNft {
+    pub nft_address: Address,
+    /// Token id and first holder, from the `Transfer` event of the mint
+    pub token: Option<(U256, Address)>,
+}
+
+/// Values the NFT contract was initialized with.
+pub struct NftContractData {
+    pub license: String,
+    pub did: String,
+    pub asset_id: String,
+}
+
+/// Decodes the `NftMinted` event of the factory and the mint `Transfer` of the new
+/// contract from the logs of the `tokenize` receipt.
+pub fn decode_mint_logs(logs: &[Log]) -> Result<MintedNft, TrustServiceError> {
+    let mut nft_address = None;
+    let mut token = None;
+    for log in logs {
+        let raw_log = RawLog {
+            topics: log.topics.clone(),
+            data: log.data.to_vec(),
+        };
+        if let Ok(event) = <NftMintedFilter as EthEvent>::decode_log(&raw_log) {
+            nft_address = Some(event.istance_address);
+        } else if let Ok(event) = <TransferFilter as EthEvent>::decode_log(&raw_log) {
+            token = Some((event.token_id, event.to));
+        }
+    }
+    let nft_address = nft_address.ok_or(TrustServiceError::CustomError("no NftMinted event found in the receipt".to_owned()))?;
+    Ok(MintedNft { nft_address, token })
+}
+
+/// Address of the NFT of the asset, `MissingNftAddress` when none was minted.
+pub fn nft_address(asset: &AssetRecord) -> Result<Address, TrustServiceError> {
+    asset.nft_addr.as_deref()
+        .ok_or(TrustServiceError::MissingNftAddress)?
+        .parse().map_err(|_| TrustServiceError::ContractAddressRecoveryError)
+}
+
+/// Reads license, DID and asset id from the NFT contract.
+pub async fn read_nft_data<M: Middleware + 'static>(asset_sc: &Asset<M>) -> Result<NftContractData, TrustServiceError> {
+    Ok(NftContractData {
+        license: asset_sc.get_license().call().await.map_err(|err| TrustServiceError::ContractError(err.to_string()))?,
+        did: asset_sc.get_did().call().await.map_err(|err| TrustServiceError::ContractError(err.to_string()))?,
+        asset_id: asset_sc.get_asset_id().call().await.map_err(|err| TrustServiceError::ContractError(err.to_string()))?,
+    })
+}
+
+/// Current holder of the NFT of an asset.
+pub struct NftHolder {
+    /// Network the NFT lives on
+    pub network: String,
+    pub nft_address: Address,
+    pub token_id: U256,
+    pub owner_address: Address,
+    /// User entitled to manage the NFT: the owner of the asset while the service holds
+    /// the token, otherwise the user whose EVM account holds it
+    pub owner: Option<User>,
+    /// Proof of the asset, set when the NFT is flagged because the proof is no longer current
+    pub revoked_proof: Option<String>,
+}
+
+impl NftHolder {
+    pub fn owner_did(&self) -> Option<&str> {
+        self.owner.as_ref().map(|owner| owner.did.as_str())
+    }
+}
+
+/// Reads the holder of the NFT of `asset_id` from the contract, on the network it was minted on.
+pub async fn nft_holder(
+    mongo_repo: &dyn Repository,
+    evm_networks: &EvmNetworks,
+    asset_id: &str
+) -> Result<NftHolder, TrustServiceError> {
+    let asset_owner = mongo_repo.get_user_by_asset(asset_id).await?;
+    let asset = asset_owner.assets.iter()
+        .find(|asset| asset.asset_id == asset_id)
+        .ok_or(TrustServiceError::AssetIdNotFound(asset_id.to_owned()))?;
+    let nft_address = nft_address(asset)?;
+
+    let network = evm_networks.of_asset(asset)?;
+    let service_address = network.signer.address();
+    let asset_sc = Asset::new(nft_address, network.signer.clone());
+    let token_id = token_id(mongo_repo, &asset_sc, asset).await?;
+    let owner_address = asset_sc.owner_of(token_id).call().await.map_err(|err| TrustServiceError::ContractError(err.to_string()))?;
+    let revoked_proof = asset.revocation.is_some().then(|| asset.proof_id.clone());
+
+    let owner = if owner_address == service_address {
+        Some(asset_owner)
+    } else {
+        mongo_repo.get_user_by_evm_address(format!("{owner_address:#x}").as_str()).await?
+    };
+    Ok(NftHolder { network: network.name.clone(), nft_address, token_id, owner_address, owner, revoked_proof })
+}
+
+/// Token id of the NFT as recorded at mint time, `None` for NFTs minted before it was recorded.
+pub fn recorded_token_id(asset: &AssetRecord) -> Result<Option<U256>, TrustServiceError> {
+    asset.token_id.as_deref()
+        .map(|token_id| U256::from_dec_str(token_id).map_err(|_| TrustServiceError::CustomError(format!("invalid token id {token_id}"))))
+        .transpose()
+}
+
+/// Token id of the NFT of the asset. NFTs minted before the token id was recorded are
+/// looked up once in the mint event of the contract, from the mint block when it is known,
+/// and the id is recorded.
+pub async fn token_id<M: Middleware + 'static>(
+    mongo_repo: &dyn Repository,
+    asset_sc: &Asset<M>,
+    asset: &AssetRecord
+) -> Result<U256, TrustServiceError> {
+    if let Some(token_id) = recorded_token_id(asset)? {
+        return Ok(token_id)
+    }
+    let transfers = asset_sc.transfer_filter().from_block(asset.mint_block.unwrap_or_default()).query().await
+        .map_err(|err| TrustServiceError::ContractError(err.to_string()))?;
+    let token_id = transfers.into_iter()
+        .find(|transfer| transfer.from == Address::zero())
+        .map(|transfer| transfer.token_id)
+        .ok_or(TrustServiceError::ContractError("no token minted by the contract".to_owned()))?;
+    mongo_repo.store_nft_token_id(asset.asset_id.as_str(), token_id.to_string()).await?;
+    Ok(token_id)
+}
+
+/// Address given directly or through the EVM account linked to a DID.
+pub async fn target_address(
+    mongo_repo: &dyn Repository,
+    address: Option<&str>,
+    did: Option<&str>
+) -> Result<Address, TrustServiceError> {
+    let address = match (address, did) {
+        (Some(address), None) => Address::from_str(address).map_err(|_| TrustServiceError::InvalidNftTarget(address.to_owned()))?,
+        (None, Some(did)) => {
+            let evm_account = mongo_repo.get_user(did).await?.evm_account
+                .ok_or(TrustServiceError::EvmAccountNotLinked(did.to_owned()))?;
+            evm_account.address.parse().map_err(|_| TrustServiceError::AddressError)?
+        },
+        _ => return Err(TrustServiceError::InvalidNftTarget("exactly one of address and DID is required".to_owned())),
+    };
+    if address == Address::zero() {
+        return Err(TrustServiceError::InvalidNftTarget(format!("{address:#x}")))
+    }
+    Ok(address)
+}
+
+/// Refuses the operations the service cannot send for the holder of the NFT, before anything is signed.
+///
+/// `setApprovalForAll` acts on every token of the holder, so it must be sent by the holder:
+/// the service sends it only when it holds the token or for a custodial account.
+pub fn check_operation(network: &EvmNetwork, holder: &NftHolder, operation: &NftOperation) -> Result<(), TrustServiceError> {
+    match operation {
+        NftOperation::ApproveAll { .. } if self_custodied(network, holder) => {
+            Err(TrustServiceError::UnsupportedNftOperation(format!(
+                "setApprovalForAll must be sent by {:#x} itself, the service cannot approve operators for a self-custodied account",
+                holder.owner_address
+            )))
+        },
+        _ => Ok(()),
+    }
+}
+
+/// The token is held by an account whose key the service does not keep.
+pub fn self_custodied(network: &EvmNetwork, holder: &NftHolder) -> bool {
+    holder.owner_address != network.signer.address() && custodial_owner(holder).is_none()
+}
+
+/// Owner of the NFT and its account, when the account holding the token is custodial.
+fn custodial_owner(holder: &NftHolder) -> Option<(&User, &EvmAccount)> {
+    holder.owner.as_ref()
+        .and_then(|owner| owner.evm_account.as_ref().map(|evm_account| (owner, evm_account)))
+        .filter(|(_, evm_account)| evm_account.custodial && evm_account.address == format!("{:#x}", holder.owner_address))
+}
+
+/// Sends `operation` on behalf of the holder of the NFT and records it in the db,
+/// returning the hash of the transaction.
+///
+/// The service signs through the transaction manager when it holds the token or the holder
+/// approved it as operator. Custodial accounts sign with their key in the Stronghold through a
+/// manager of their own, with the policies of the network: the transactions they left pending
+/// are settled first, then the service wallet tops them up with the gas of the call.
+pub async fn execute(
+    iota_state: &IotaState,
+    mongo_repo: &dyn Repository,
+    network: &EvmNetwork,
+    asset_id: &str,
+    holder: &NftHolder,
+    operation: NftOperation
+) -> Result<H256, TrustServiceError> {
+    let service = network.signer.clone();
+    let tx_manager = &network.tx_manager;
+    let service_address = service.address();
+    check_operation(network, holder, &operation)?;
+    let custodial_account = custodial_owner(holder);
+
+    let transaction_hash = if holder.owner_address == service_address {
+        let call = operation_call(&Asset::new(holder.nft_address, service), holder, &operation);
+        tx_manager.send(operation.label(), call.tx).await?.transaction_hash
+    } else if let Some((owner, evm_account)) = custodial_account {
+        let address_index = evm_account.address_index
+            .ok_or(TrustServiceError::EvmSignerError("missing address index of the custodial account".to_owned()))?;
+        let stronghold_storage = iota_state.stronghold_storage_for(owner.tenant_id.as_deref()).await?;
+        // the lease is kept until the transaction is settled
+        let (signer, _) = StrongholdEvmSigner::new(StrongholdStorage::clone(&stronghold_storage), address_index, network.chain_id).await?;
+        let _account_guard = network.lock_account(signer.address()).await;
+        let account_manager = tx_manager.for_account(Arc::new(SignerMiddleware::new(service.inner().clone(), signer)));
+        account_manager.recover().await?;
+
+        let call = operation_call(&Asset::new(holder.nft_address, service), holder, &operation).tx;
+        tx_manager.fund(account_manager.address(), &call).await?;
+        account_manager.send(operation.label(), call).await?.transaction_hash
+    } else {
+        // a self-custodied account, the service can only act as its operator
+        let asset_sc = Asset::new(holder.nft_address, service);
+        let approved = match operation {
+            // refused by check_operation
+            NftOperation::ApproveAll { .. } => false,
+            NftOperation::Approve { .. } => is_operator(&asset_sc, holder.owner_address, service_address).await?,
+            NftOperation::Transfer { .. } => {
+                is_operator(&asset_sc, holder.owner_address, service_address).await?
+                    || asset_sc.get_approved(holder.token_id).call().await.map_err(|err| TrustServiceError::ContractError(err.to_string()))? == service_address
+            },
+        };
+        if !approved {
+            return Err(TrustServiceError::NftNotManaged(format!("{:#x}", holder.owner_address)))
+        }
+        tx_manager.send(operation.label(), operation_call(&asset_sc, holder, &operation).tx).await?.transaction_hash
+    };
+
+    match operation {
+        NftOperation::Transfer { to } => {
+            mongo_repo.store_nft_ownership(asset_id, holder.token_id.to_string(), format!("{to:#x}")).await?
+        },
+        NftOperation::Approve { to } => {
+            mongo_repo.store_nft_approval(asset_id, (to != Address::zero()).then(|| format!("{to:#x}"))).await?
+        },
+        NftOperation::ApproveAll { operator, approved } => {
+            mongo_repo.store_nft_operator(asset_id, format!("{operator:#x}"), approved).await?
+        },
+    }
+    Ok(transaction_hash)
+}
+
+async fn is_operator<M: Middleware + 'static>(asset_sc: &Asset<M>, owner: Address, operator: Address) -> Result<bool, TrustServiceError> {
+    asset_sc.is_approved_for_all(owner, operator).call().await.map_err(|err| TrustServiceError::ContractError(err.to_string()))
+}
+
+fn operation_call<M: Middleware + 'static>(asset_sc: &Asset<M>, holder: &NftHolder, operation: &NftOperation) -> ContractCall<M, ()> {
+    match *operation {
+        NftOperation::Transfer { to } => asset_sc.safe_transfer_from(holder.owner_address, to, holder.token_id),
+        NftOperation::Approve { to } => asset_sc.approve(to, holder.token_id),
+        NftOperation::ApproveAll { operator, approved } => asset_sc.set_approval_for_all(operator, approved),
+    }
+}
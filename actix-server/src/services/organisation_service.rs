@@ -0,0 +1,154 @@
+// SPDX-FileCopyrightText: 2024 Fondazione LINKS
+//
+// SPDX-License-Identifier: APACHE-2.0
+
+use identity_eddsa_verifier::EdDSAJwsVerifier;
+use identity_iota::core::{Duration, FromJson, Object, Timestamp, Url};
+use identity_iota::credential::{
+    Credential, CredentialBuilder, FailFast, Jwt, JwtCredentialValidationOptions, JwtCredentialValidator,
+    StatusCheck, Subject,
+};
+use identity_iota::storage::{JwkDocumentExt, JwsSignatureOptions};
+use serde_json::json;
+
+use crate::errors::TrustServiceError;
+use crate::models::organisation::{Membership, Organisation};
+use crate::models::tangle_proof::TangleProof;
+use crate::services::credential_issuer::new_credential_id;
+use crate::services::did_resolver::DidResolver;
+use crate::services::iota_state::IotaState;
+use crate::services::repository::Repository;
+
+pub const DELEGATION_CREDENTIAL_TYPE: &str = "ProofPublishingDelegation";
+
+/// Creates and publishes the DID of a new organisation.
+pub async fn create_organisation(
+    iota_state: &IotaState,
+    mongo_repo: &dyn Repository,
+    name: String,
+) -> Result<Organisation, TrustServiceError> {
+    let (document, fragment) = iota_state.create_did().await?;
+    let organisation = Organisation { did: document.id().to_string(), fragment, name, members: vec![] };
+    mongo_repo.store_organisation(&organisation).await?;
+    Ok(organisation)
+}
+
+/// Delegates the proof-publishing rights of the organisation to a registered DID
+/// with a credential signed by the organisation DID.
+pub async fn add_member(
+    iota_state: &IotaState,
+    did_resolver: &DidResolver,
+    mongo_repo: &dyn Repository,
+    organisation_did: &str,
+    member_did: &str,
+    validity_days: Option<u32>,
+) -> Result<Membership, TrustServiceError> {
+    let organisation = mongo_repo.get_organisation(organisation_did).await?;
+    mongo_repo.get_user(member_did).await?;
+    if active_membership(&organisation, member_did, Timestamp::now_utc()).is_some() {
+        return Err(TrustServiceError::MemberAlreadyActive(member_did.to_owned()))
+    }
+
+    let credential_id = new_credential_id()?;
+    let valid_from = Timestamp::now_utc();
+    let valid_until = match validity_days {
+        Some(days) => Some(valid_from.checked_add(Duration::days(days)).ok_or(TrustServiceError::CustomError("invalid validity period".to_owned()))?),
+        None => None,
+    };
+
+    let subject = Subject::from_json_value(json!({
+        "id": member_did,
+        "organisation": organisation.did,
+        "organisationName": organisation.name,
+        "permission": "publishProof",
+    }))?;
+    let mut builder = CredentialBuilder::default()
+        .id(Url::parse(credential_id.as_str())?)
+        .issuer(Url::parse(organisation.did.as_str())?)
+        .type_(DELEGATION_CREDENTIAL_TYPE)
+        .subject(subject)
+        .issuance_date(valid_from);
+    if let Some(valid_until) = valid_until {
+        builder = builder.expiration_date(valid_until);
+    }
+    let credential: Credential = builder.build()?;
+
+    log::info!("Signing delegation credential {}...", credential_id);
+    let organisation_document = did_resolver.resolve(organisation.did.as_str()).await?;
+    let jwt: Jwt = organisation_document.create_credential_jwt(
+        &credential,
+        &iota_state.key_storage,
+        &organisation.fragment,
+        &JwsSignatureOptions::default(),
+        None
+    ).await?;
+
+    let membership = Membership {
+        member_did: member_did.to_owned(),
+        credential_id,
+        delegation: jwt.as_str().to_owned(),
+        valid_from: valid_from.to_rfc3339(),
+        valid_until: valid_until.map(|date| date.to_rfc3339()),
+        revoked_at: None,
+    };
+    mongo_repo.add_membership(organisation_did, &membership).await?;
+    Ok(membership)
+}
+
+/// Ends the delegation of a member, proofs it signed before stay valid.
+pub async fn remove_member(
+    mongo_repo: &dyn Repository,
+    organisation_did: &str,
+    member_did: &str,
+) -> Result<(), TrustServiceError> {
+    mongo_repo.revoke_membership(organisation_did, member_did, Timestamp::now_utc().to_rfc3339()).await
+}
+
+pub fn active_membership<'a>(organisation: &'a Organisation, member_did: &str, at: Timestamp) -> Option<&'a Membership> {
+    organisation.members.iter()
+        .find(|membership| membership.member_did == member_did && membership.is_active_at(at))
+}
+
+/// Checks that the publisher of an organisation proof held a valid delegation when it signed:
+/// the credential is signed by the organisation, names the publisher as subject and
+/// covers the signing time, and the membership was not ended before it.
+pub async fn verify_delegation(
+    did_resolver: &DidResolver,
+    mongo_repo: &dyn Repository,
+    proof: &TangleProof,
+) -> Result<(), TrustServiceError> {
+    let (organisation_did, delegation, signed_at) = match (&proof.organisation_did, &proof.delegation, &proof.signed_at) {
+        (Some(organisation_did), Some(delegation), Some(signed_at)) => (organisation_did, delegation, signed_at),
+        (None, None, _) => return Ok(()),
+        _ => return Err(TrustServiceError::DelegationNotValid("incomplete delegation".to_owned())),
+    };
+    log::info!("Verifying delegation of {} by {}...", proof.did_publisher, organisation_did);
+    let signed_at = Timestamp::parse(signed_at).map_err(|_| TrustServiceError::DelegationNotValid("invalid signing time".to_owned()))?;
+
+    let organisation_document = did_resolver.resolve(organisation_did.as_str()).await?;
+    // the dates are checked against the signing time, the membership record holds the revocation
+    let options = JwtCredentialValidationOptions::default()
+        .earliest_expiry_date(signed_at)
+        .latest_issuance_date(signed_at)
+        .status_check(StatusCheck::SkipAll);
+    let decoded_credential = JwtCredentialValidator::with_signature_verifier(EdDSAJwsVerifier::default())
+        .validate::<_, Object>(&Jwt::from(delegation.clone()), &organisation_document, &options, FailFast::FirstError)
+        .map_err(|err| TrustServiceError::DelegationNotValid(err.to_string()))?;
+    let credential = decoded_credential.credential;
+
+    if !credential.types.iter().any(|credential_type| credential_type == DELEGATION_CREDENTIAL_TYPE) {
+        return Err(TrustServiceError::DelegationNotValid("not a delegation credential".to_owned()))
+    }
+    let subject_id = credential.credential_subject.iter().next().and_then(|subject| subject.id.as_ref());
+    if subject_id.map(|id| id.as_str()) != Some(proof.did_publisher.as_str()) {
+        return Err(TrustServiceError::DelegationNotValid("the publisher is not the delegate".to_owned()))
+    }
+
+    let credential_id = credential.id.as_ref().map(|id| id.to_string()).unwrap_or_default();
+    let organisation = mongo_repo.get_organisation(organisation_did).await?;
+    match organisation.members.iter().find(|membership| membership.credential_id == credential_id) {
+        Some(membership) if membership.is_active_at(signed_at) => Ok(()),
+        Some(_) => Err(TrustServiceError::DelegationNotValid("membership ended before signing".to_owned())),
+        None => Err(TrustServiceError::DelegationNotValid("unknown delegation".to_owned())),
+    }
+}
@@ -0,0 +1,181 @@
+// SPDX-FileCopyrightText: 2024 Fondazione LINKS
+//
+// SPDX-License-Identifier: APACHE-2.0
+
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use cryptoki::context::{CInitializeArgs, Pkcs11};
+use cryptoki::mechanism::Mechanism;
+use cryptoki::object::{Attribute, AttributeType, ObjectClass, ObjectHandle};
+use cryptoki::session::{Session, UserType};
+use cryptoki::types::AuthPin;
+use identity_iota::storage::{JwkGenOutput, JwkStorage, KeyId, KeyStorageError, KeyStorageErrorKind, KeyStorageResult, KeyType};
+use identity_iota::verification::jwk::{EdCurve, Jwk, JwkParamsOkp};
+use identity_iota::verification::jws::JwsAlgorithm;
+use identity_iota::verification::jwu;
+
+/// DER encoding of the Ed25519 curve OID (1.3.101.112), the CKA_EC_PARAMS of the keys.
+const ED25519_EC_PARAMS: &[u8] = &[0x06, 0x03, 0x2b, 0x65, 0x70];
+const ED25519_PUBLIC_KEY_LEN: usize = 32;
+const LABEL_PREFIX: &str = "trust-service-";
+
+/// [`JwkStorage`] backed by a PKCS#11 token, e.g. an HSM or SoftHSM.
+///
+/// Ed25519 keys are generated inside the token as non extractable objects, the service
+/// only keeps the key id (the CKA_ID of the pair) and asks the token to sign.
+/// The calls to the token block, they run on the blocking thread pool.
+pub struct Pkcs11Storage {
+    // sessions are not Sync, the token serializes the operations anyway
+    session: Arc<Mutex<Session>>,
+    _pkcs11: Pkcs11,
+}
+
+impl Pkcs11Storage {
+
+    /// Opens a session on the token with `token_label`, loading the PKCS#11 module at `module_path`.
+    pub fn new(module_path: &str, token_label: &str, user_pin: String) -> anyhow::Result<Self> {
+        let pkcs11 = Pkcs11::new(module_path)?;
+        pkcs11.initialize(CInitializeArgs::OsThreads)?;
+
+        let mut token_slot = None;
+        for slot in pkcs11.get_slots_with_token()? {
+            if pkcs11.get_token_info(slot)?.label() == token_label {
+                token_slot = Some(slot);
+                break;
+            }
+        }
+        let slot = token_slot.ok_or_else(|| anyhow::anyhow!("PKCS#11 token {token_label} not found"))?;
+
+        let session = pkcs11.open_rw_session(slot)?;
+        session.login(UserType::User, Some(&AuthPin::new(user_pin)))?;
+        log::info!("PKCS#11 session opened on token {}", token_label);
+        Ok(Pkcs11Storage { session: Arc::new(Mutex::new(session)), _pkcs11: pkcs11 })
+    }
+
+    /// Runs `operation` on the session in the blocking thread pool.
+    async fn with_session<T, F>(&self, operation: F) -> KeyStorageResult<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Session) -> KeyStorageResult<T> + Send + 'static,
+    {
+        let session = self.session.clone();
+        tokio::task::spawn_blocking(move || {
+            let session = session.lock().map_err(lock_error)?;
+            operation(&session)
+        }).await.map_err(|err| KeyStorageError::new(KeyStorageErrorKind::Unspecified).with_custom_message(format!("PKCS#11 task failed: {err}")))?
+    }
+
+    fn find_key(session: &Session, class: ObjectClass, key_id: &KeyId) -> KeyStorageResult<Option<ObjectHandle>> {
+        let template = [Attribute::Class(class), Attribute::Id(key_id.as_str().as_bytes().to_vec())];
+        let objects = session.find_objects(&template).map_err(pkcs11_error)?;
+        Ok(objects.first().copied())
+    }
+
+    /// The EC point of Ed25519 public keys may be wrapped in a DER OCTET STRING.
+    fn public_key_bytes(ec_point: &[u8]) -> KeyStorageResult<&[u8]> {
+        match ec_point.len() {
+            ED25519_PUBLIC_KEY_LEN => Ok(ec_point),
+            len if len == ED25519_PUBLIC_KEY_LEN + 2 && ec_point[0] == 0x04 && ec_point[1] == ED25519_PUBLIC_KEY_LEN as u8 => Ok(&ec_point[2..]),
+            _ => Err(KeyStorageError::new(KeyStorageErrorKind::Unspecified).with_custom_message("unexpected Ed25519 public key encoding")),
+        }
+    }
+}
+
+fn pkcs11_error(err: cryptoki::error::Error) -> KeyStorageError {
+    KeyStorageError::new(KeyStorageErrorKind::Unspecified).with_custom_message(format!("PKCS#11 error: {err}"))
+}
+
+fn lock_error<T>(_: T) -> KeyStorageError {
+    KeyStorageError::new(KeyStorageErrorKind::Unspecified).with_custom_message("PKCS#11 session poisoned")
+}
+
+#[async_trait]
+impl JwkStorage for Pkcs11Storage {
+
+    async fn generate(&self, key_type: KeyType, alg: JwsAlgorithm) -> KeyStorageResult<JwkGenOutput> {
+        if key_type.as_str() != "Ed25519" {
+            return Err(KeyStorageError::new(KeyStorageErrorKind::UnsupportedKeyType))
+        }
+        if alg != JwsAlgorithm::EdDSA {
+            return Err(KeyStorageError::new(KeyStorageErrorKind::KeyAlgorithmMismatch))
+        }
+
+        let mut random_id = [0u8; 16];
+        crypto::utils::rand::fill(&mut random_id)
+            .map_err(|err| KeyStorageError::new(KeyStorageErrorKind::Unspecified).with_custom_message(err.to_string()))?;
+        let key_id = KeyId::new(random_id.iter().map(|byte| format!("{byte:02x}")).collect::<String>());
+        let id = key_id.as_str().as_bytes().to_vec();
+        let label = format!("{LABEL_PREFIX}{}", key_id.as_str()).into_bytes();
+
+        let public_template = [
+            Attribute::Token(true),
+            Attribute::Verify(true),
+            Attribute::EcParams(ED25519_EC_PARAMS.to_vec()),
+            Attribute::Id(id.clone()),
+            Attribute::Label(label.clone()),
+        ];
+        let private_template = [
+            Attribute::Token(true),
+            Attribute::Private(true),
+            Attribute::Sensitive(true),
+            Attribute::Extractable(false),
+            Attribute::Sign(true),
+            Attribute::Id(id),
+            Attribute::Label(label),
+        ];
+
+        let ec_point = self.with_session(move |session| {
+            let (public_key, _private_key) = session
+                .generate_key_pair(&Mechanism::EccEdwardsKeyPairGen, &public_template, &private_template)
+                .map_err(pkcs11_error)?;
+            let attributes = session.get_attributes(public_key, &[AttributeType::EcPoint]).map_err(pkcs11_error)?;
+            match attributes.into_iter().next() {
+                Some(Attribute::EcPoint(ec_point)) => Ok(ec_point),
+                _ => Err(KeyStorageError::new(KeyStorageErrorKind::Unspecified).with_custom_message("missing public key")),
+            }
+        }).await?;
+
+        let mut params = JwkParamsOkp::new();
+        params.crv = EdCurve::Ed25519.name().to_owned();
+        params.x = jwu::encode_b64(Self::public_key_bytes(&ec_point)?);
+        let mut jwk = Jwk::from_params(params);
+        jwk.set_alg(alg.name());
+        jwk.set_kid(jwk.thumbprint_sha256_b64());
+
+        Ok(JwkGenOutput::new(key_id, jwk))
+    }
+
+    /// Private keys never enter the token from outside.
+    async fn insert(&self, _jwk: Jwk) -> KeyStorageResult<KeyId> {
+        Err(KeyStorageError::new(KeyStorageErrorKind::Unspecified).with_custom_message("importing keys in the PKCS#11 token is not supported"))
+    }
+
+    async fn sign(&self, key_id: &KeyId, data: &[u8], _public_key: &Jwk) -> KeyStorageResult<Vec<u8>> {
+        let key_id = key_id.clone();
+        let data = data.to_vec();
+        self.with_session(move |session| {
+            let private_key = Self::find_key(session, ObjectClass::PRIVATE_KEY, &key_id)?
+                .ok_or(KeyStorageError::new(KeyStorageErrorKind::KeyNotFound))?;
+            session.sign(&Mechanism::Eddsa, private_key, &data).map_err(pkcs11_error)
+        }).await
+    }
+
+    async fn delete(&self, key_id: &KeyId) -> KeyStorageResult<()> {
+        let key_id = key_id.clone();
+        self.with_session(move |session| {
+            let private_key = Self::find_key(session, ObjectClass::PRIVATE_KEY, &key_id)?
+                .ok_or(KeyStorageError::new(KeyStorageErrorKind::KeyNotFound))?;
+            session.destroy_object(private_key).map_err(pkcs11_error)?;
+            if let Some(public_key) = Self::find_key(session, ObjectClass::PUBLIC_KEY, &key_id)? {
+                session.destroy_object(public_key).map_err(pkcs11_error)?;
+            }
+            Ok(())
+        }).await
+    }
+
+    async fn exists(&self, key_id: &KeyId) -> KeyStorageResult<bool> {
+        let key_id = key_id.clone();
+        self.with_session(move |session| Ok(Self::find_key(session, ObjectClass::PRIVATE_KEY, &key_id)?.is_some())).await
+    }
+}
@@ -0,0 +1,172 @@
+// SPDX-FileCopyrightText: 2024 Fondazione LINKS
+//
+// SPDX-License-Identifier: APACHE-2.0
+
+use identity_eddsa_verifier::EdDSAJwsVerifier;
+use identity_iota::core::{Object, ToJson};
+use identity_iota::credential::{
+    DecodedJwtPresentation, FailFast, Jwt, JwtCredentialValidationOptions, JwtCredentialValidator,
+    JwtCredentialValidatorUtils, JwtPresentationValidationOptions, JwtPresentationValidator,
+    JwtPresentationValidatorUtils, JwtValidationError, StatusCheck, SubjectHolderRelationship,
+};
+use identity_iota::did::{CoreDID, DID};
+use identity_iota::document::verifiable::JwsVerificationOptions;
+
+use crate::dtos::{CredentialCheck, CredentialVerificationResult, PresentationVerificationResponse};
+use crate::models::credential::CredentialState;
+use crate::services::did_resolver::DidResolver;
+use crate::services::repository::Repository;
+
+/// Verifies a JWT Verifiable Presentation and each of the credentials it embeds.
+///
+/// Failures never abort the verification, they are collected in the
+/// returned report so that the caller can see every failed check.
+pub async fn verify_presentation(
+    did_resolver: &DidResolver,
+    mongo_repo: &dyn Repository,
+    presentation_jwt: &Jwt,
+    challenge: Option<&str>,
+    audience: Option<&str>,
+) -> PresentationVerificationResponse {
+    let mut response = PresentationVerificationResponse::default();
+
+    let holder_did: CoreDID = match JwtPresentationValidatorUtils::extract_holder(presentation_jwt) {
+        Ok(holder_did) => holder_did,
+        Err(err) => {
+            response.errors.push(format!("malformed presentation: {err}"));
+            return response
+        }
+    };
+    response.holder = Some(holder_did.to_string());
+
+    let holder_document = match did_resolver.resolve(holder_did.as_str()).await {
+        Ok(document) => document,
+        Err(err) => {
+            response.errors.push(format!("holder resolution: {err}"));
+            return response
+        }
+    };
+
+    // the nonce of the presentation JWS binds it to the challenge of the verifier
+    let mut verifier_options = JwsVerificationOptions::default();
+    if let Some(challenge) = challenge {
+        verifier_options = verifier_options.nonce(challenge.to_owned());
+    }
+    let presentation_options = JwtPresentationValidationOptions::default()
+        .presentation_verifier_options(verifier_options);
+
+    let decoded_presentation: DecodedJwtPresentation<Jwt> = match JwtPresentationValidator::with_signature_verifier(EdDSAJwsVerifier::default())
+        .validate(presentation_jwt, &holder_document, &presentation_options) {
+        Ok(decoded_presentation) => decoded_presentation,
+        Err(err) => {
+            response.errors.push(format!("presentation: {err}"));
+            return response
+        }
+    };
+
+    if let Some(audience) = audience {
+        match decoded_presentation.aud.as_ref() {
+            Some(aud) if aud.as_str() == audience => (),
+            Some(aud) => response.errors.push(format!("audience mismatch: expected {audience}, found {aud}")),
+            None => response.errors.push("audience missing".to_owned()),
+        }
+    }
+
+    for (index, credential_jwt) in decoded_presentation.presentation.verifiable_credential.iter().enumerate() {
+        let result = verify_credential(did_resolver, mongo_repo, credential_jwt, Some(&holder_did), index).await;
+        response.credentials.push(result);
+    }
+
+    response.valid = response.errors.is_empty() && response.credentials.iter().all(|credential| credential.valid);
+    response
+}
+
+/// Verifies a JWT credential: signature against the issuer DID document,
+/// expiration and issuance dates, revocation status and, when a holder
+/// is given, that the holder is the subject of the credential.
+///
+/// The status is read from the revocation bitmap of the issuer document and, for the
+/// credentials issued by the service, from their record as well.
+pub async fn verify_credential(
+    did_resolver: &DidResolver,
+    mongo_repo: &dyn Repository,
+    credential_jwt: &Jwt,
+    holder_did: Option<&CoreDID>,
+    index: usize,
+) -> CredentialVerificationResult {
+    let mut result = CredentialVerificationResult { index, ..Default::default() };
+
+    let issuer_did: CoreDID = match JwtCredentialValidatorUtils::extract_issuer_from_jwt(credential_jwt) {
+        Ok(issuer_did) => issuer_did,
+        Err(err) => {
+            result.errors.push(CredentialCheck::new("structure", err.to_string()));
+            return result
+        }
+    };
+    result.issuer = Some(issuer_did.to_string());
+
+    let issuer_document = match did_resolver.resolve(issuer_did.as_str()).await {
+        Ok(document) => document,
+        Err(err) => {
+            result.errors.push(CredentialCheck::new("issuerResolution", err.to_string()));
+            return result
+        }
+    };
+
+    // the status is checked against the revocation bitmap services of the issuer document
+    let mut credential_options = JwtCredentialValidationOptions::default()
+        .status_check(StatusCheck::Strict);
+    if let Some(holder_did) = holder_did {
+        credential_options = credential_options
+            .subject_holder_relationship(holder_did.to_url().into(), SubjectHolderRelationship::AlwaysSubject);
+    }
+
+    let credential_validator = JwtCredentialValidator::with_signature_verifier(EdDSAJwsVerifier::default());
+    match credential_validator.validate::<_, Object>(credential_jwt, &issuer_document, &credential_options, FailFast::AllErrors) {
+        Ok(decoded_credential) => {
+            let credential = decoded_credential.credential;
+            result.credential_id = credential.id.as_ref().map(|id| id.to_string());
+            result.types = credential.types.iter().cloned().collect();
+            result.subject = credential.credential_subject.to_json_value().ok();
+            result.valid = true;
+        },
+        Err(compound_error) => {
+            for err in compound_error.validation_errors.iter() {
+                result.errors.push(CredentialCheck::new(check_name(err), err.to_string()));
+            }
+        }
+    }
+
+    // the issuer document may come from the cache, from before the bitmap was updated
+    if let Some(credential_id) = result.credential_id.clone() {
+        match mongo_repo.get_credential(credential_id.as_str()).await {
+            Ok(Some(issued)) if issued.issuer_did == issuer_did.as_str() && issued.status != CredentialState::Active => {
+                let status = match issued.status {
+                    CredentialState::Suspended => "suspended",
+                    _ => "revoked",
+                };
+                result.errors.push(CredentialCheck::new("status", format!("credential {credential_id} is {status}")));
+                result.valid = false;
+            },
+            Ok(_) => (),
+            Err(err) => {
+                result.errors.push(CredentialCheck::new("status", err.to_string()));
+                result.valid = false;
+            },
+        }
+    }
+    result
+}
+
+/// Groups validation errors by the check that failed.
+fn check_name(err: &JwtValidationError) -> &'static str {
+    match err {
+        JwtValidationError::Signature { .. } | JwtValidationError::MethodDataLookupError { .. } => "signature",
+        JwtValidationError::ExpirationDate => "expirationDate",
+        JwtValidationError::IssuanceDate => "issuanceDate",
+        JwtValidationError::SubjectHolderRelationship => "holderBinding",
+        JwtValidationError::Revoked | JwtValidationError::InvalidStatus(_) => "status",
+        JwtValidationError::DocumentMismatch(_) => "issuer",
+        _ => "structure",
+    }
+}
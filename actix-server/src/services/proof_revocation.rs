@@ -0,0 +1,72 @@
+// SPDX-FileCopyrightText: 2024 Fondazione LINKS
+//
+// SPDX-License-Identifier: APACHE-2.0
+
+use identity_iota::core::Timestamp;
+
+use crate::errors::TrustServiceError;
+use crate::models::asset::Asset;
+use crate::models::proof_revocation::{NftAction, ProofRevocation, ProofState};
+use crate::services::repository::Repository;
+
+/// Longest chain of supersessions followed to find the current proof.
+const MAX_SUPERSESSIONS: usize = 64;
+
+/// Asset notarized by `proof_id`, which `did` owns and which is still current.
+pub async fn revocable_asset(mongo_repo: &dyn Repository, proof_id: &str, did: &str) -> Result<Asset, TrustServiceError> {
+    let (owner_did, asset) = mongo_repo.find_asset_by_proof(proof_id).await?
+        .ok_or(TrustServiceError::ProofIdNotFound)?;
+    if owner_did != did {
+        return Err(TrustServiceError::AssetNotOwned(did.to_owned()))
+    }
+    if asset.revocation.is_some() {
+        return Err(TrustServiceError::ProofRevoked(proof_id.to_owned()))
+    }
+    Ok(asset)
+}
+
+/// Marks the proof of `asset` as revoked, or as superseded by `superseded_by`, and flags its NFT.
+///
+/// The deployed `Asset` contract exposes neither `burn` nor a pause, so the token stays
+/// on-chain: the service reports it as stale and refuses to mint, transfer or approve it.
+pub async fn revoke(
+    mongo_repo: &dyn Repository,
+    asset: &Asset,
+    reason: Option<String>,
+    superseded_by: Option<String>
+) -> Result<ProofRevocation, TrustServiceError> {
+    let nft_action = asset.nft_addr.as_ref().map(|nft_addr| {
+        log::warn!("Flagging NFT {} of asset {}, the contract cannot burn or freeze it", nft_addr, asset.asset_id);
+        NftAction::Flagged
+    });
+    let revocation = ProofRevocation {
+        state: if superseded_by.is_some() { ProofState::Superseded } else { ProofState::Revoked },
+        reason,
+        superseded_by,
+        revoked_at: Timestamp::now_utc().to_rfc3339(),
+        nft_action,
+    };
+    mongo_repo.store_proof_revocation(asset.proof_id.as_str(), revocation.clone()).await?;
+    Ok(revocation)
+}
+
+/// Latest proof of the dataset of `asset`, following the supersessions.
+/// None when the chain ends with a revoked proof.
+pub async fn current_proof(mongo_repo: &dyn Repository, asset: &Asset) -> Result<Option<String>, TrustServiceError> {
+    let mut proof_id = asset.proof_id.clone();
+    let mut revocation = asset.revocation.clone();
+    for _ in 0..MAX_SUPERSESSIONS {
+        let superseded_by = match revocation {
+            None => return Ok(Some(proof_id)),
+            Some(ProofRevocation { superseded_by: Some(superseded_by), .. }) => superseded_by,
+            Some(_) => return Ok(None),
+        };
+        revocation = match mongo_repo.find_asset_by_proof(superseded_by.as_str()).await? {
+            Some((_, next)) => next.revocation,
+            // the replacing proof is not tracked by the service
+            None => None,
+        };
+        proof_id = superseded_by;
+    }
+    Err(TrustServiceError::CustomError(format!("more than {MAX_SUPERSESSIONS} supersessions from proof {}", asset.proof_id)))
+}
@@ -0,0 +1,195 @@
+// SPDX-FileCopyrightText: 2024 Fondazione LINKS
+//
+// SPDX-License-Identifier: APACHE-2.0
+
+use async_trait::async_trait;
+
+use crate::errors::TrustServiceError;
+use crate::models::asset::Asset;
+use crate::models::audit_entry::AuditAnchor;
+use crate::models::credential::{CredentialState, IssuedCredential, Issuer};
+use crate::models::evm_transaction::{EvmTransaction, TransactionStatus};
+use crate::models::license::License;
+use crate::models::log_model::Log;
+use crate::models::organisation::{Membership, Organisation};
+use crate::models::proof_revocation::ProofRevocation;
+use crate::models::signed_request::SignedRequest;
+use crate::models::user::{EvmAccount, User};
+
+/// Storage of the service. [`MongoRepo`](crate::services::mongodb_repo::MongoRepo) is the one
+/// used by the server, [`InMemoryRepository`](crate::services::memory_repo::InMemoryRepository)
+/// keeps everything in memory for the tests.
+#[async_trait]
+pub trait Repository: Send + Sync {
+    async fn store_user(&self, user: User) -> Result<(), TrustServiceError>;
+
+    async fn get_user(&self, did: &str) -> Result<User, TrustServiceError>;
+
+    /// Returns the user whose access token hashes to `token_hash`.
+    async fn get_user_by_access_token(&self, token_hash: &str) -> Result<Option<User>, TrustServiceError>;
+
+    /// Replaces the access token of `did`, the previous one stops working.
+    async fn store_access_token(&self, did: &str, token_hash: String) -> Result<(), TrustServiceError>;
+
+    async fn get_users(&self) -> Result<Vec<User>, TrustServiceError>;
+
+    async fn get_users_by_tenant(&self, tenant_id: &str) -> Result<Vec<User>, TrustServiceError>;
+
+    async fn delete_users_by_tenant(&self, tenant_id: &str) -> Result<u64, TrustServiceError>;
+
+    async fn get_asset(&self, asset_id: String) -> Result<Asset, TrustServiceError>;
+
+    async fn get_asset_by_proof(&self, asset_proof: String) -> Result<Asset, TrustServiceError>;
+
+    /// Reserves an asset for the mint of its NFT, `false` when the asset already has an NFT
+    /// or another mint holds it. The reservation ends when the NFT is recorded with
+    /// [`Repository::store_nft_addr`] or with [`Repository::release_nft_mint`].
+    async fn reserve_nft_mint(&self, asset_id: &str, started_at: String) -> Result<bool, TrustServiceError>;
+
+    /// Ends the reservation of a mint whose transaction cannot be mined.
+    async fn release_nft_mint(&self, asset_id: &str) -> Result<(), TrustServiceError>;
+
+    /// Records the NFT of an asset, minted on `network` in `mint_block`, and ends the reservation of its mint.
+    async fn store_nft_addr(&self, asset_id: String, nft_addr: String, network: &str, mint_block: Option<u64>) -> Result<Asset, TrustServiceError>;
+
+    /// Records the token id of the NFT of an asset and the EVM address owning it,
+    /// the approvals granted by the previous owner are dropped.
+    async fn store_nft_ownership(&self, asset_id: &str, token_id: String, owner_address: String) -> Result<(), TrustServiceError>;
+
+    /// Records that the NFT of an asset was burnt, its owner and approvals are dropped.
+    async fn store_nft_burned(&self, asset_id: &str) -> Result<(), TrustServiceError>;
+
+    /// Records the token id of the NFT of an asset minted before the id was recorded at mint time.
+    async fn store_nft_token_id(&self, asset_id: &str, token_id: String) -> Result<(), TrustServiceError>;
+
+    async fn store_nft_approval(&self, asset_id: &str, approved: Option<String>) -> Result<(), TrustServiceError>;
+
+    async fn store_nft_operator(&self, asset_id: &str, operator: String, approved: bool) -> Result<(), TrustServiceError>;
+
+    async fn store_nft_metadata_uri(&self, asset_id: &str, metadata_uri: String) -> Result<(), TrustServiceError>;
+
+    /// Returns the DID owning the asset notarized by `proof_id`, with the asset.
+    /// Unlike [`Repository::get_asset_by_proof`] the access is not logged.
+    async fn find_asset_by_proof(&self, proof_id: &str) -> Result<Option<(String, Asset)>, TrustServiceError>;
+
+    /// Returns the DID owning the asset tokenized by the NFT contract at `nft_addr` on `network`, with the asset.
+    /// The same address can be taken by a contract on another network; assets without a network
+    /// are matched only when `default_network` is set.
+    async fn get_asset_by_nft_addr(&self, nft_addr: &str, network: &str, default_network: bool) -> Result<Option<(String, Asset)>, TrustServiceError>;
+
+    /// Returns the user owning the asset.
+    async fn get_user_by_asset(&self, asset_id: &str) -> Result<User, TrustServiceError>;
+
+    /// Returns the user whose linked EVM account is `address` (lowercase hex).
+    async fn get_user_by_evm_address(&self, address: &str) -> Result<Option<User>, TrustServiceError>;
+
+    async fn store_evm_account(&self, did: &str, evm_account: EvmAccount) -> Result<(), TrustServiceError>;
+
+    /// Moves the signature nonce of the EVM account of `did` past `nonce`, only if `nonce`
+    /// is the expected one. Returns false when it was already used or is out of order.
+    async fn consume_signature_nonce(&self, did: &str, nonce: u64) -> Result<bool, TrustServiceError>;
+
+    /// Moves the signature nonce of the EVM account of `did` back to `nonce`, only if `nonce`
+    /// is the last one consumed. Returns false when a later nonce was consumed meanwhile.
+    async fn release_signature_nonce(&self, did: &str, nonce: u64) -> Result<bool, TrustServiceError>;
+
+    /// Keeps the signed request of an NFT operation with the asset.
+    async fn store_signed_request(&self, asset_id: &str, signed_request: SignedRequest) -> Result<(), TrustServiceError>;
+
+    /// Records that the proof is no longer current.
+    async fn store_proof_revocation(&self, proof_id: &str, revocation: ProofRevocation) -> Result<(), TrustServiceError>;
+
+    /// Reserves the next address index of the custodial EVM accounts.
+    async fn allocate_evm_address_index(&self) -> Result<u32, TrustServiceError>;
+
+    /// Last block of `network` processed by the EVM event indexer.
+    async fn get_indexer_cursor(&self, network: &str) -> Result<Option<u64>, TrustServiceError>;
+
+    async fn store_indexer_cursor(&self, network: &str, block: u64) -> Result<(), TrustServiceError>;
+
+    /// `Asset` implementation the factory of `network` clones, as last seen by the EVM event indexer.
+    async fn get_asset_contract(&self, network: &str) -> Result<Option<String>, TrustServiceError>;
+
+    async fn store_asset_contract(&self, network: &str, asset_contract: &str) -> Result<(), TrustServiceError>;
+
+    /// Last checkpoint of the audit log at `log`.
+    async fn get_audit_anchor(&self, log: &str) -> Result<Option<AuditAnchor>, TrustServiceError>;
+
+    /// Inserts or replaces the anchor of the same log.
+    async fn store_audit_anchor(&self, anchor: &AuditAnchor) -> Result<(), TrustServiceError>;
+
+    /// Inserts or replaces the record of the transaction with the same chain, sender and nonce.
+    async fn store_transaction(&self, transaction: &EvmTransaction) -> Result<(), TrustServiceError>;
+
+    async fn get_transactions_by_status(&self, chain_id: u64, from: &str, status: TransactionStatus) -> Result<Vec<EvmTransaction>, TrustServiceError>;
+
+    async fn store_proof_relationship(
+        &self,
+        did: &str,
+        proof_id: String,
+        asset_id: String,
+    ) -> Result<(), TrustServiceError>;
+
+    /// Whether an asset with this id is already notarized, by any user.
+    async fn asset_exists(&self, asset_id: &str) -> Result<bool, TrustServiceError>;
+
+    /// This function takes a log object and stores it in the Mongo DB.
+    /// The purpose of the function is to update the CID of the log file
+    /// stored in the DB. The DB matches a name to a CID.
+    /// There should be only one document in the DB where the field, name,
+    /// does not change, while the CID field is updated on each request.
+    ///
+    /// If the document does not exist, this function will create it.
+    ///
+    /// # Parameters
+    /// * log - the document to save
+    async fn store_log_cid(&self, log: Log) -> Result<(), TrustServiceError>;
+
+    /// This function returns the CID of the log file.
+    /// There must be only one document in the DB inside the Log_IPFS collection.
+    /// The name of this document is fixed in the .mongo.env file.
+    /// This function reads the name of the file, looks for it in the DB and then
+    /// returns the CID field present in that document.
+    /// The CID is the identifier of the file within IPFS.
+    async fn get_log_cid(&self) -> Result<String, TrustServiceError>;
+
+    /// Returns the DID used by the service to issue credentials.
+    /// There must be only one document in the Issuer collection.
+    async fn get_issuer(&self) -> Result<Issuer, TrustServiceError>;
+
+    async fn store_issuer(&self, issuer: Issuer) -> Result<(), TrustServiceError>;
+
+    async fn store_credential(&self, credential: &IssuedCredential) -> Result<(), TrustServiceError>;
+
+    /// Returns the credentials issued to a DID, oldest first.
+    async fn get_credentials_by_holder(&self, holder_did: &str) -> Result<Vec<IssuedCredential>, TrustServiceError>;
+
+    /// Reserves the next index of the revocation bitmap of the issuer.
+    async fn allocate_status_index(&self) -> Result<u32, TrustServiceError>;
+
+    async fn get_credential_by_status_index(&self, status_index: u32) -> Result<IssuedCredential, TrustServiceError>;
+
+    /// Returns the credential issued by the service with the id `credential_id`, if any.
+    async fn get_credential(&self, credential_id: &str) -> Result<Option<IssuedCredential>, TrustServiceError>;
+
+    async fn update_credential_status(&self, status_index: u32, status: CredentialState) -> Result<(), TrustServiceError>;
+
+    async fn store_organisation(&self, organisation: &Organisation) -> Result<(), TrustServiceError>;
+
+    async fn get_organisation(&self, did: &str) -> Result<Organisation, TrustServiceError>;
+
+    /// Inserts a custom license, one with the same identifier ignoring case is refused with `LicenseAlreadyRegistered`.
+    async fn store_license(&self, license: &License) -> Result<(), TrustServiceError>;
+
+    /// Custom license registered with `license_id`, compared ignoring case as SPDX identifiers.
+    async fn get_license(&self, license_id: &str) -> Result<Option<License>, TrustServiceError>;
+
+    async fn get_licenses(&self) -> Result<Vec<License>, TrustServiceError>;
+
+    async fn get_organisations(&self) -> Result<Vec<Organisation>, TrustServiceError>;
+
+    async fn add_membership(&self, organisation_did: &str, membership: &Membership) -> Result<(), TrustServiceError>;
+
+    /// Marks the active membership of `member_did` as revoked at `revoked_at`.
+    async fn revoke_membership(&self, organisation_did: &str, member_did: &str, revoked_at: String) -> Result<(), TrustServiceError>;
+}
@@ -0,0 +1,286 @@
+// SPDX-FileCopyrightText: 2024 Fondazione LINKS
+//
+// SPDX-License-Identifier: APACHE-2.0
+
+use std::fs;
+use std::num::NonZeroU32;
+use std::path::{Path, PathBuf};
+
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use anyhow::{anyhow, bail, Context, Result};
+use base64::{Engine as _, engine::general_purpose};
+use crypto::hashes::sha::{SHA256, SHA256_LEN};
+use crypto::keys::pbkdf::PBKDF2_HMAC_SHA256;
+use identity_iota::core::Timestamp;
+use serde::{Deserialize, Serialize};
+
+const MAGIC: &[u8] = b"TSBACKUP";
+const VERSION: u8 = 1;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+const KDF_ITERATIONS: u32 = 600_000;
+
+/// A file to back up, `name` identifies it inside the archive.
+pub struct BackupSource {
+    pub name: String,
+    pub path: PathBuf,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BackupArchive {
+    created_at: String,
+    entries: Vec<BackupEntry>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BackupEntry {
+    name: String,
+    /// Hex encoded SHA-256 of the content, checked on restore
+    sha256: String,
+    content: String,
+}
+
+/// Snapshots and databases are copied as they are on disk: the service must be stopped
+/// while backing up or restoring them.
+pub fn create_backup(sources: &[BackupSource], passphrase: &str, output: &Path) -> Result<Vec<String>> {
+    let mut files = vec![];
+    for source in sources {
+        for (relative, file) in collect_files(&source.path)? {
+            let content = fs::read(&file).with_context(|| format!("reading {}", file.display()))?;
+            let name = match relative {
+                Some(relative) => format!("{}/{}", source.name, relative),
+                None => source.name.clone(),
+            };
+            files.push((name, content));
+        }
+    }
+    let names = files.iter().map(|(name, _)| name.clone()).collect();
+    let data = encrypt_archive(files, passphrase)?;
+    fs::write(output, data).with_context(|| format!("writing {}", output.display()))?;
+    Ok(names)
+}
+
+/// Packs named files in an archive `MAGIC | VERSION | salt | nonce | AES-256-GCM(json manifest)`,
+/// the key is derived from `passphrase` with PBKDF2-HMAC-SHA256.
+pub fn encrypt_archive(files: Vec<(String, Vec<u8>)>, passphrase: &str) -> Result<Vec<u8>> {
+    let entries = files.into_iter()
+        .map(|(name, content)| BackupEntry { name, sha256: sha256_hex(&content), content: general_purpose::STANDARD.encode(content) })
+        .collect();
+    let archive = BackupArchive { created_at: Timestamp::now_utc().to_rfc3339(), entries };
+
+    let mut salt = [0u8; SALT_LEN];
+    crypto::utils::rand::fill(&mut salt).map_err(|err| anyhow!(err.to_string()))?;
+    let cipher = Aes256Gcm::new(&derive_key(passphrase, &salt));
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let ciphertext = cipher.encrypt(&nonce, serde_json::to_vec(&archive)?.as_slice())
+        .map_err(|_| anyhow!("backup encryption failed"))?;
+
+    let mut data = MAGIC.to_vec();
+    data.push(VERSION);
+    data.extend_from_slice(&salt);
+    data.extend_from_slice(&nonce);
+    data.extend_from_slice(&ciphertext);
+    Ok(data)
+}
+
+/// Decrypts and checks a backup, then writes each source back to its path.
+///
+/// Each source is first written next to its path and then renamed over it, so a failed
+/// restore leaves the previous files in place. With `force` an existing source is replaced
+/// as a whole, files missing from the backup (e.g. stale RocksDB files) are removed,
+/// without it the restore is refused before anything is written.
+pub fn restore_backup(sources: &[BackupSource], passphrase: &str, input: &Path, force: bool) -> Result<Vec<String>> {
+    let data = fs::read(input).with_context(|| format!("reading {}", input.display()))?;
+    let header_len = MAGIC.len() + 1 + SALT_LEN + NONCE_LEN;
+    if data.len() < header_len || &data[..MAGIC.len()] != MAGIC {
+        bail!("{} is not a trust service backup", input.display())
+    }
+    if data[MAGIC.len()] != VERSION {
+        bail!("unsupported backup version {}", data[MAGIC.len()])
+    }
+    let salt = &data[MAGIC.len() + 1..MAGIC.len() + 1 + SALT_LEN];
+    let nonce = Nonce::from_slice(&data[MAGIC.len() + 1 + SALT_LEN..header_len]);
+
+    // the authentication tag also detects any corruption of the archive
+    let cipher = Aes256Gcm::new(&derive_key(passphrase, salt));
+    let plaintext = cipher.decrypt(nonce, &data[header_len..])
+        .map_err(|_| anyhow!("wrong passphrase or corrupted backup"))?;
+    let archive: BackupArchive = serde_json::from_slice(&plaintext)?;
+    log::info!("Backup created at {}", archive.created_at);
+
+    // files of each source, by position in `sources`
+    let mut restores: Vec<(&BackupSource, Vec<(Option<String>, Vec<u8>)>)> = vec![];
+    for entry in archive.entries.iter() {
+        let content = general_purpose::STANDARD.decode(&entry.content)?;
+        if sha256_hex(&content) != entry.sha256 {
+            bail!("checksum mismatch for {}", entry.name)
+        }
+        let (source, relative) = entry_source(sources, &entry.name)?;
+        match restores.iter_mut().find(|(restored, _)| restored.name == source.name) {
+            Some((_, files)) => files.push((relative, content)),
+            None => restores.push((source, vec![(relative, content)])),
+        }
+    }
+    for (source, _) in restores.iter() {
+        if source.path.exists() && !force {
+            bail!("{} already exists, use --force to replace it", source.path.display())
+        }
+    }
+
+    let mut staged = vec![];
+    for (source, files) in restores.iter() {
+        match stage(&source.path, files) {
+            Ok(staging) => staged.push((*source, staging)),
+            Err(err) => {
+                for (_, staging) in staged.iter() {
+                    let _ = remove_path(staging);
+                }
+                return Err(err)
+            },
+        }
+    }
+    let mut swapped = vec![];
+    for (index, (source, staging)) in staged.iter().enumerate() {
+        match swap_in(&source.path, staging) {
+            Ok(replaced) => swapped.push((*source, replaced)),
+            Err(err) => {
+                // puts back the sources already swapped
+                for (source, replaced) in swapped.iter().rev() {
+                    let _ = remove_path(&source.path);
+                    if let Some(replaced) = replaced {
+                        let _ = fs::rename(replaced, &source.path);
+                    }
+                }
+                for (_, staging) in staged[index..].iter() {
+                    let _ = remove_path(staging);
+                }
+                return Err(err)
+            },
+        }
+    }
+    for (_, replaced) in swapped.iter() {
+        if let Some(replaced) = replaced {
+            remove_path(replaced).with_context(|| format!("removing {}", replaced.display()))?;
+        }
+    }
+
+    let mut restored = vec![];
+    for (source, files) in restores.iter() {
+        for (relative, _) in files.iter() {
+            let destination = match relative {
+                Some(relative) => source.path.join(relative),
+                None => source.path.clone(),
+            };
+            restored.push(destination.display().to_string());
+        }
+    }
+    Ok(restored)
+}
+
+/// Writes the files of a source to `<path>.restoring`, next to its path.
+fn stage(path: &Path, files: &[(Option<String>, Vec<u8>)]) -> Result<PathBuf> {
+    let staging = sibling(path, ".restoring");
+    remove_path(&staging).with_context(|| format!("removing {}", staging.display()))?;
+    for (relative, content) in files.iter() {
+        let destination = match relative {
+            Some(relative) => staging.join(relative),
+            None => staging.clone(),
+        };
+        if let Some(parent) = destination.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&destination, content).with_context(|| format!("writing {}", destination.display()))?;
+    }
+    Ok(staging)
+}
+
+/// Renames `staging` to `path`, moving the previous content of `path` to `<path>.replaced`,
+/// which is returned to be removed once every source is in place.
+fn swap_in(path: &Path, staging: &Path) -> Result<Option<PathBuf>> {
+    let replaced = if path.exists() {
+        let replaced = sibling(path, ".replaced");
+        remove_path(&replaced).with_context(|| format!("removing {}", replaced.display()))?;
+        fs::rename(path, &replaced).with_context(|| format!("moving {} aside", path.display()))?;
+        Some(replaced)
+    } else {
+        None
+    };
+    if let Err(err) = fs::rename(staging, path) {
+        if let Some(replaced) = replaced.as_ref() {
+            let _ = fs::rename(replaced, path);
+        }
+        return Err(err).with_context(|| format!("replacing {}", path.display()))
+    }
+    Ok(replaced)
+}
+
+fn sibling(path: &Path, suffix: &str) -> PathBuf {
+    let mut sibling = path.as_os_str().to_owned();
+    sibling.push(suffix);
+    PathBuf::from(sibling)
+}
+
+fn remove_path(path: &Path) -> std::io::Result<()> {
+    if path.is_dir() {
+        fs::remove_dir_all(path)
+    } else if path.exists() {
+        fs::remove_file(path)
+    } else {
+        Ok(())
+    }
+}
+
+fn derive_key(passphrase: &str, salt: &[u8]) -> Key<Aes256Gcm> {
+    let mut key = [0u8; 32];
+    PBKDF2_HMAC_SHA256(passphrase.as_bytes(), salt, NonZeroU32::new(KDF_ITERATIONS).unwrap(), &mut key);
+    key.into()
+}
+
+fn sha256_hex(content: &[u8]) -> String {
+    let mut digest = [0u8; SHA256_LEN];
+    SHA256(content, &mut digest);
+    digest.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// Files of a source, with their path relative to it when the source is a directory.
+fn collect_files(path: &Path) -> Result<Vec<(Option<String>, PathBuf)>> {
+    if path.is_file() {
+        return Ok(vec![(None, path.to_path_buf())])
+    }
+    if !path.is_dir() {
+        bail!("{} does not exist", path.display())
+    }
+    let mut files = vec![];
+    let mut directories = vec![path.to_path_buf()];
+    while let Some(directory) = directories.pop() {
+        for entry in fs::read_dir(&directory)? {
+            let entry_path = entry?.path();
+            if entry_path.is_dir() {
+                directories.push(entry_path);
+            } else {
+                let relative = entry_path.strip_prefix(path)?.to_string_lossy().replace('\\', "/");
+                files.push((Some(relative), entry_path));
+            }
+        }
+    }
+    Ok(files)
+}
+
+/// Source of an archive entry, with the path of the entry inside it when the source is a directory.
+fn entry_source<'a>(sources: &'a [BackupSource], name: &str) -> Result<(&'a BackupSource, Option<String>)> {
+    for source in sources {
+        if name == source.name {
+            return Ok((source, None))
+        }
+        if let Some(relative) = name.strip_prefix(&format!("{}/", source.name)) {
+            if relative.split('/').any(|component| component == ".." || component == "." || component.is_empty()) {
+                bail!("invalid entry {name}")
+            }
+            return Ok((source, Some(relative.to_owned())))
+        }
+    }
+    Err(anyhow!("unknown entry {name}"))
+}
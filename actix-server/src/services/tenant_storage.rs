@@ -0,0 +1,196 @@
+// SPDX-FileCopyrightText: 2024 Fondazione LINKS
+//
+// SPDX-License-Identifier: APACHE-2.0
+
+use std::collections::HashMap;
+use std::fs;
+use std::ops::Deref;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use crypto::macs::hmac::HMAC_SHA256;
+use identity_iota::storage::Storage;
+use identity_stronghold::StrongholdStorage;
+use iota_sdk::client::secret::stronghold::StrongholdSecretManager;
+use iota_sdk::client::{Client, Password};
+use tokio::sync::{Mutex, OwnedRwLockReadGuard, RwLock};
+
+use crate::errors::TrustServiceError;
+use crate::services::iota_state::MemStorage;
+use crate::services::key_store::ServiceJwkStorage;
+use crate::services::snapshot_backup::encrypt_archive;
+
+pub const DEFAULT_TENANT_STORAGE_DIR: &str = "./tenants";
+const MAX_TENANT_ID_LEN: usize = 64;
+
+/// Key storage partitioned per tenant: each tenant has its own Stronghold snapshot,
+/// opened the first time it is needed and kept open afterwards.
+///
+/// The password of a snapshot is derived from `TENANT_STORAGE_MASTER_PASSWORD` and the
+/// tenant id, so exporting one tenant does not disclose the password of the others.
+///
+/// Each tenant has a lock: the storage is handed out in a [`TenantLease`] holding it shared,
+/// while the export and the removal of the tenant hold it exclusively, so they wait for the
+/// operations in progress and no snapshot is written while it is read or deleted.
+pub struct TenantKeyStorage {
+    directory: PathBuf,
+    master_password: String,
+    snapshots: Mutex<HashMap<String, StrongholdStorage>>,
+    // the flag is set once the tenant is removed, leases waiting for the lock are then refused
+    locks: Mutex<HashMap<String, Arc<RwLock<bool>>>>,
+}
+
+/// Key storage of a tenant lent for the duration of an operation, see [`TenantKeyStorage`].
+/// The shared key storage is lent without a lock.
+pub struct TenantLease<T> {
+    storage: T,
+    _guard: Option<OwnedRwLockReadGuard<bool>>,
+}
+
+impl<T> TenantLease<T> {
+    pub fn shared(storage: T) -> Self {
+        TenantLease { storage, _guard: None }
+    }
+}
+
+impl<T> Deref for TenantLease<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.storage
+    }
+}
+
+impl TenantKeyStorage {
+
+    pub fn from_env() -> Self {
+        let directory = std::env::var("TENANT_STORAGE_DIR").unwrap_or_else(|_| DEFAULT_TENANT_STORAGE_DIR.to_owned());
+        let master_password = std::env::var("TENANT_STORAGE_MASTER_PASSWORD")
+            .expect("$TENANT_STORAGE_MASTER_PASSWORD must be set.");
+        TenantKeyStorage {
+            directory: PathBuf::from(directory),
+            master_password,
+            snapshots: Mutex::new(HashMap::new()),
+            locks: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn directory(&self) -> &PathBuf {
+        &self.directory
+    }
+
+    /// Tenant ids end up in file names, only letters, digits, `-` and `_` are accepted.
+    pub fn validate_tenant_id(tenant_id: &str) -> Result<(), TrustServiceError> {
+        if tenant_id.is_empty()
+            || tenant_id.len() > MAX_TENANT_ID_LEN
+            || !tenant_id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+            return Err(TrustServiceError::InvalidTenant(tenant_id.to_owned()))
+        }
+        Ok(())
+    }
+
+    pub fn snapshot_path(&self, tenant_id: &str) -> PathBuf {
+        self.directory.join(format!("{tenant_id}.stronghold"))
+    }
+
+    fn password(&self, tenant_id: &str) -> String {
+        let mut mac = [0u8; 32];
+        HMAC_SHA256(tenant_id.as_bytes(), self.master_password.as_bytes(), &mut mac);
+        mac.iter().map(|byte| format!("{byte:02x}")).collect()
+    }
+
+    async fn lock(&self, tenant_id: &str) -> Arc<RwLock<bool>> {
+        self.locks.lock().await.entry(tenant_id.to_owned()).or_default().clone()
+    }
+
+    /// Lends the Stronghold of the tenant, creating its snapshot the first time.
+    pub async fn stronghold_storage(&self, tenant_id: &str) -> Result<TenantLease<StrongholdStorage>, TrustServiceError> {
+        Self::validate_tenant_id(tenant_id)?;
+        let guard = self.lock(tenant_id).await.read_owned().await;
+        if *guard {
+            return Err(TrustServiceError::TenantNotFound(tenant_id.to_owned()))
+        }
+        let stronghold_storage = self.open(tenant_id).await?;
+        Ok(TenantLease { storage: stronghold_storage, _guard: Some(guard) })
+    }
+
+    async fn open(&self, tenant_id: &str) -> Result<StrongholdStorage, TrustServiceError> {
+        let mut snapshots = self.snapshots.lock().await;
+        if let Some(stronghold_storage) = snapshots.get(tenant_id) {
+            return Ok(stronghold_storage.clone())
+        }
+
+        fs::create_dir_all(&self.directory).map_err(|_| TrustServiceError::FileWriteError)?;
+        let snapshot_path = self.snapshot_path(tenant_id);
+        let exists = snapshot_path.exists();
+        let stronghold = StrongholdSecretManager::builder()
+            .password(Password::from(self.password(tenant_id)))
+            .build(&snapshot_path)
+            .map_err(|err| TrustServiceError::CustomError(err.to_string()))?;
+        if !exists {
+            // the mnemonic seeds the custodial EVM accounts of the tenant
+            log::info!("Creating key storage of tenant {}...", tenant_id);
+            stronghold.store_mnemonic(Client::generate_mnemonic()?).await
+                .map_err(|err| TrustServiceError::CustomError(err.to_string()))?;
+        }
+
+        let stronghold_storage = StrongholdStorage::new(stronghold);
+        snapshots.insert(tenant_id.to_owned(), stronghold_storage.clone());
+        Ok(stronghold_storage)
+    }
+
+    /// Key storage of the tenant: the key ids are kept in its Stronghold, the private keys
+    /// in the key store configured for the service, see [`ServiceJwkStorage::for_stronghold`].
+    pub async fn key_storage(
+        &self,
+        tenant_id: &str,
+        jwk_storage: &ServiceJwkStorage
+    ) -> Result<TenantLease<MemStorage>, TrustServiceError> {
+        let TenantLease { storage: stronghold_storage, _guard } = self.stronghold_storage(tenant_id).await?;
+        let key_storage = Storage::new(jwk_storage.for_stronghold(stronghold_storage.clone()), stronghold_storage);
+        Ok(TenantLease { storage: key_storage, _guard })
+    }
+
+    /// Encrypted archive with the snapshot of the tenant, its password and `extra_files`.
+    pub async fn export(
+        &self,
+        tenant_id: &str,
+        extra_files: Vec<(String, Vec<u8>)>,
+        passphrase: &str
+    ) -> Result<Vec<u8>, TrustServiceError> {
+        Self::validate_tenant_id(tenant_id)?;
+        // no lease is out while the snapshot is read
+        let lock = self.lock(tenant_id).await;
+        let removed = lock.write().await;
+        let snapshot_path = self.snapshot_path(tenant_id);
+        if *removed || !snapshot_path.exists() {
+            return Err(TrustServiceError::TenantNotFound(tenant_id.to_owned()))
+        }
+        let snapshot = fs::read(&snapshot_path).map_err(|_| TrustServiceError::FileOpenError)?;
+
+        let mut files = vec![
+            (format!("{tenant_id}.stronghold"), snapshot),
+            ("password".to_owned(), self.password(tenant_id).into_bytes()),
+        ];
+        files.extend(extra_files);
+        Ok(encrypt_archive(files, passphrase)?)
+    }
+
+    /// Closes and deletes the snapshot of the tenant once the leases in progress are returned,
+    /// the leases requested meanwhile are refused. The other snapshots are not touched.
+    pub async fn remove(&self, tenant_id: &str) -> Result<(), TrustServiceError> {
+        Self::validate_tenant_id(tenant_id)?;
+        let lock = self.lock(tenant_id).await;
+        let mut removed = lock.write().await;
+        let snapshot_path = self.snapshot_path(tenant_id);
+        if *removed || !snapshot_path.exists() {
+            return Err(TrustServiceError::TenantNotFound(tenant_id.to_owned()))
+        }
+        self.snapshots.lock().await.remove(tenant_id);
+        fs::remove_file(&snapshot_path).map_err(|_| TrustServiceError::FileWriteError)?;
+        *removed = true;
+        // a tenant created again with the same id gets a new lock
+        self.locks.lock().await.remove(tenant_id);
+        Ok(())
+    }
+}
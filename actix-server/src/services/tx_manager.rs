@@ -0,0 +1,507 @@
+// SPDX-FileCopyrightText: 2024 Fondazione LINKS
+//
+// SPDX-License-Identifier: APACHE-2.0
+
+use std::collections::BTreeSet;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use actix_web::rt::time::sleep;
+use ethers::core::k256::ecdsa::SigningKey;
+use ethers::middleware::SignerMiddleware;
+use ethers::providers::{Http, Middleware, Provider};
+use ethers::signers::{Signer, Wallet};
+use ethers::types::transaction::eip2718::TypedTransaction;
+use ethers::types::{Address, BlockNumber, Bytes, Eip1559TransactionRequest, TransactionReceipt, TransactionRequest, H256, U256};
+use identity_iota::core::Timestamp;
+use tokio::sync::Mutex;
+
+use crate::errors::TrustServiceError;
+use crate::models::evm_transaction::{EvmTransaction, TransactionStatus};
+use crate::services::repository::Repository;
+use crate::utils::env_u64;
+
+const RECEIPT_POLL_INTERVAL: Duration = Duration::from_secs(3);
+const GWEI: u64 = 1_000_000_000;
+const DEFAULT_GAS_LIMIT_PERCENT: u64 = 120;
+// nodes refuse replacements raising the fees by less than 10%
+const MIN_BUMP_PERCENT: u64 = 10;
+const DEFAULT_BUMP_PERCENT: u64 = 15;
+pub const DEFAULT_CONFIRMATIONS: u64 = 1;
+const DEFAULT_RESUBMIT_SECONDS: u64 = 60;
+const DEFAULT_MAX_RESUBMISSIONS: u64 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Fees {
+    Legacy { gas_price: U256 },
+    Eip1559 { max_fee: U256, priority_fee: U256 },
+}
+
+/// Contract call of a transaction, broadcast again at the same nonce when it is replaced.
+#[derive(Debug, Clone)]
+struct Call {
+    to: Address,
+    data: Bytes,
+    value: U256,
+    gas: U256,
+}
+
+#[derive(Clone)]
+struct GasPolicy {
+    eip1559: bool,
+    /// Cap of the gas price, or of the max fee per gas with EIP-1559
+    max_fee: Option<U256>,
+    /// Fixed priority fee, the one suggested by the node when missing
+    priority_fee: Option<U256>,
+    gas_limit_percent: u64,
+    bump_percent: u64,
+}
+
+impl GasPolicy {
+
+    fn cap(&self, fee: U256) -> U256 {
+        match self.max_fee {
+            Some(max_fee) => fee.min(max_fee),
+            None => fee,
+        }
+    }
+
+    /// Fees replacing `fees`, at least the `current` ones of the network.
+    /// `None` when the cap leaves no room to raise every fee by the minimum the nodes accept.
+    fn bumped(&self, fees: Fees, current: Fees) -> Option<Fees> {
+        let bump = |fee: U256| fee * (100 + self.bump_percent) / 100;
+        let accepted = |fee: U256, bumped: U256| bumped >= fee * (100 + MIN_BUMP_PERCENT) / 100;
+        let bumped = match (fees, current) {
+            (Fees::Legacy { gas_price }, Fees::Legacy { gas_price: current_gas_price }) => {
+                let bumped_gas_price = self.cap(bump(gas_price).max(current_gas_price));
+                accepted(gas_price, bumped_gas_price).then_some(Fees::Legacy { gas_price: bumped_gas_price })
+            },
+            (Fees::Eip1559 { max_fee, priority_fee }, Fees::Eip1559 { max_fee: current_max_fee, .. }) => {
+                let bumped_max_fee = self.cap(bump(max_fee).max(current_max_fee));
+                // the priority fee is part of the max fee
+                let bumped_priority_fee = bump(priority_fee).min(bumped_max_fee);
+                (accepted(max_fee, bumped_max_fee) && accepted(priority_fee, bumped_priority_fee))
+                    .then_some(Fees::Eip1559 { max_fee: bumped_max_fee, priority_fee: bumped_priority_fee })
+            },
+            // GAS_STRATEGY changed since the broadcast, the replacement uses the new one
+            _ => Some(current),
+        };
+        bumped.filter(|bumped| *bumped != fees)
+    }
+
+    /// Highest price per gas a transaction sent with `fees` reaches after `resubmissions` replacements.
+    fn highest_fee(&self, fees: Fees, resubmissions: u64) -> U256 {
+        let fee = match fees {
+            Fees::Legacy { gas_price } => gas_price,
+            Fees::Eip1559 { max_fee, .. } => max_fee,
+        };
+        self.cap((0..resubmissions).fold(fee, |fee, _| fee * (100 + self.bump_percent) / 100))
+    }
+}
+
+/// Sends the transactions of an account, the service wallet unless built by [`TransactionManager::for_account`].
+///
+/// Each transaction takes the next nonce of the wallet known to the node that is not held by a
+/// transaction still followed by the manager, so concurrent requests do not collide and the nonces
+/// of the transactions dropped by the node are taken again. Each transaction is recorded in Mongo
+/// and replaced with higher fees when it is not mined within `TX_RESUBMIT_SECONDS`.
+/// Receipts are returned after the confirmations of the network.
+pub struct TransactionManager<S = Wallet<SigningKey>> {
+    signer: Arc<SignerMiddleware<Provider<Http>, S>>,
+    mongo_repo: Arc<dyn Repository>,
+    gas_policy: GasPolicy,
+    confirmations: u64,
+    resubmit_after: Duration,
+    max_resubmissions: u64,
+    /// Nonces of the transactions sent and not yet confirmed or given up
+    in_flight: Mutex<BTreeSet<U256>>,
+}
+
+impl<S: Signer + 'static> TransactionManager<S> {
+
+    /// Reads the gas policy from the environment, `confirmations` is set per network.
+    pub fn from_env(signer: Arc<SignerMiddleware<Provider<Http>, S>>, mongo_repo: Arc<dyn Repository>, confirmations: u64) -> Result<Self, TrustServiceError> {
+        let eip1559 = match std::env::var("GAS_STRATEGY").unwrap_or_else(|_| "legacy".to_owned()).as_str() {
+            "legacy" => false,
+            "eip1559" => true,
+            other => return Err(TrustServiceError::CustomError(format!("unknown gas strategy {other}"))),
+        };
+        let gwei = |name: &str| -> Result<Option<U256>, TrustServiceError> {
+            match std::env::var(name) {
+                Ok(_) => Ok(Some(U256::from(env_u64(name, 0)?) * GWEI)),
+                Err(_) => Ok(None),
+            }
+        };
+        let bump_percent = env_u64("GAS_BUMP_PERCENT", DEFAULT_BUMP_PERCENT)?;
+        if bump_percent < MIN_BUMP_PERCENT {
+            return Err(TrustServiceError::CustomError(format!("GAS_BUMP_PERCENT must be at least {MIN_BUMP_PERCENT}")))
+        }
+        let gas_policy = GasPolicy {
+            eip1559,
+            max_fee: gwei("GAS_MAX_FEE_GWEI")?,
+            priority_fee: gwei("GAS_PRIORITY_FEE_GWEI")?,
+            gas_limit_percent: env_u64("GAS_LIMIT_PERCENT", DEFAULT_GAS_LIMIT_PERCENT)?,
+            bump_percent,
+        };
+        Ok(TransactionManager {
+            signer,
+            mongo_repo,
+            gas_policy,
+            confirmations: confirmations.max(1),
+            resubmit_after: Duration::from_secs(env_u64("TX_RESUBMIT_SECONDS", DEFAULT_RESUBMIT_SECONDS)?),
+            max_resubmissions: env_u64("TX_MAX_RESUBMISSIONS", DEFAULT_MAX_RESUBMISSIONS)?,
+            in_flight: Mutex::new(BTreeSet::new()),
+        })
+    }
+
+    /// Manager of the transactions of another account on the same network, with the same policies.
+    ///
+    /// Its nonces are tracked by the returned manager only: the sends of the account must not
+    /// overlap with those of another manager of the same account.
+    pub fn for_account<T: Signer + 'static>(&self, signer: Arc<SignerMiddleware<Provider<Http>, T>>) -> TransactionManager<T> {
+        TransactionManager {
+            signer,
+            mongo_repo: self.mongo_repo.clone(),
+            gas_policy: self.gas_policy.clone(),
+            confirmations: self.confirmations,
+            resubmit_after: self.resubmit_after,
+            max_resubmissions: self.max_resubmissions,
+            in_flight: Mutex::new(BTreeSet::new()),
+        }
+    }
+
+    pub fn address(&self) -> Address {
+        self.signer.address()
+    }
+
+    fn provider(&self) -> &Provider<Http> {
+        self.signer.inner()
+    }
+
+    fn chain_id(&self) -> u64 {
+        self.signer.signer().chain_id()
+    }
+
+    /// Settles the transactions left pending by a previous run, before new ones are sent.
+    ///
+    /// Those mined meanwhile are completed, the others are broadcast again at their nonce and
+    /// followed until they are confirmed or given up.
+    /// Stuck transactions mined after they were given up are completed as well.
+    pub async fn recover(&self) -> Result<(), TrustServiceError> {
+        let from = format!("{:#x}", self.address());
+        for mut transaction in self.mongo_repo.get_transactions_by_status(self.chain_id(), from.as_str(), TransactionStatus::Stuck).await? {
+            if let Some(receipt) = self.find_receipt(&transaction).await? {
+                log::info!("Stuck transaction {} with nonce {} was mined", transaction.hash, transaction.nonce);
+                self.complete(&mut transaction, &receipt).await?;
+            }
+        }
+
+        let mut pending = self.mongo_repo.get_transactions_by_status(self.chain_id(), from.as_str(), TransactionStatus::Pending).await?;
+        pending.sort_by_key(|transaction| transaction.nonce);
+        for mut transaction in pending {
+            if let Some(receipt) = self.find_receipt(&transaction).await? {
+                self.complete(&mut transaction, &receipt).await?;
+                continue
+            }
+            let mined_nonce = self.transaction_count(BlockNumber::Latest).await?;
+            if U256::from(transaction.nonce) < mined_nonce {
+                log::warn!("Nonce {} of transaction {} was taken by another transaction", transaction.nonce, transaction.hash);
+                self.give_up(&mut transaction).await?;
+                continue
+            }
+            let call = call_of_record(&transaction)?;
+
+            let nonce = U256::from(transaction.nonce);
+            let current = self.current_fees().await?;
+            let fees = match fees_of_record(&transaction) {
+                Some(fees) => self.gas_policy.bumped(fees, current).unwrap_or(fees),
+                None => current,
+            };
+            self.in_flight.lock().await.insert(nonce);
+            match self.sign_and_send(&self.build(&call, nonce, fees)).await {
+                Ok(hash) => {
+                    log::warn!("Transaction {} with nonce {} sent again as {:#x}", transaction.hash, transaction.nonce, hash);
+                    self.record_broadcast(&mut transaction, hash, fees).await?;
+                },
+                // the node may still hold the previous broadcast
+                Err(err) => log::warn!("Transaction {} with nonce {} not sent again: {}", transaction.hash, transaction.nonce, err),
+            }
+            let settled = self.watch(&mut transaction, &call, fees).await;
+            self.in_flight.lock().await.remove(&nonce);
+            match settled {
+                Ok(_) => log::info!("Transaction {} with nonce {} recovered", transaction.hash, transaction.nonce),
+                Err(err) => log::warn!("Transaction {} with nonce {} not recovered: {}", transaction.hash, transaction.nonce, err),
+            }
+        }
+        Ok(())
+    }
+
+    /// Sends a contract call, `label` identifies the operation in the transaction records.
+    pub async fn send(&self, label: &str, tx: TypedTransaction) -> Result<TransactionReceipt, TrustServiceError> {
+        let call = self.call(self.address(), &tx).await?;
+        let fees = self.current_fees().await?;
+
+        let (nonce, hash) = self.broadcast_next(&call, fees).await?;
+        log::info!("{} transaction {:#x} sent with nonce {}", label, hash, nonce);
+        let now = Timestamp::now_utc().to_rfc3339();
+        let mut record = EvmTransaction {
+            label: label.to_owned(),
+            chain_id: self.chain_id(),
+            from: format!("{:#x}", self.address()),
+            to: format!("{:#x}", to),
+            nonce: nonce.as_u64(),
+            hash: format!("{:#x}", hash),
+            replaced: vec![],
+            data: call.data.to_string(),
+            value: call.value.to_string(),
+            gas: call.gas.to_string(),
+            max_fee: None,
+            priority_fee: None,
+            status: TransactionStatus::Pending,
+            block_number: None,
+            created_at: now.clone(),
+            updated_at: now,
+        };
+        set_fees(&mut record, fees);
+
+        let settled = match self.mongo_repo.store_transaction(&record).await {
+            Ok(()) => self.watch(&mut record, &call, fees).await,
+            Err(err) => Err(err),
+        };
+        self.in_flight.lock().await.remove(&nonce);
+        // but for a revert, a failure after the broadcast leaves a transaction that can still be mined
+        settled.map_err(|err| match err {
+            TrustServiceError::TransactionTimeout(_) | TrustServiceError::TransactionFeeCap(_) => err,
+            _ if record.status == TransactionStatus::Reverted => err,
+            err => TrustServiceError::TransactionPending(record.hash.clone(), err.to_string()),
+        })
+    }
+
+    /// Tops up `account` from the wallet of the manager, so that it can pay for `tx` even at the
+    /// highest fees its replacements may reach. Returns the receipt of the funding transaction,
+    /// `None` when the balance of the account is already enough.
+    pub async fn fund(&self, account: Address, tx: &TypedTransaction) -> Result<Option<TransactionReceipt>, TrustServiceError> {
+        let call = self.call(account, tx).await?;
+        let fee = self.gas_policy.highest_fee(self.current_fees().await?, self.max_resubmissions);
+        let cost = call.gas * fee + call.value;
+        let balance = self.provider().get_balance(account, None).await
+            .map_err(|err| TrustServiceError::ContractError(err.to_string()))?;
+        if balance >= cost {
+            return Ok(None)
+        }
+        log::info!("Funding account {:#x} with {} wei", account, cost - balance);
+        let funding: TypedTransaction = TransactionRequest::new().to(account).value(cost - balance).into();
+        self.send("accountFunding", funding).await.map(Some)
+    }
+
+    /// Contract call of `tx` sent from `from`, with the estimated gas and the margin of the policy.
+    async fn call(&self, from: Address, tx: &TypedTransaction) -> Result<Call, TrustServiceError> {
+        let to = *tx.to_addr().ok_or(TrustServiceError::CustomError("contract deployments are not supported".to_owned()))?;
+        let data = tx.data().cloned().unwrap_or_default();
+        let value = tx.value().cloned().unwrap_or_default();
+
+        let estimate: TypedTransaction = TransactionRequest::new().from(from).to(to).data(data.clone()).value(value).into();
+        let gas = self.provider().estimate_gas(&estimate, None).await
+            .map_err(|err| TrustServiceError::ContractError(err.to_string()))? * self.gas_policy.gas_limit_percent / 100;
+        Ok(Call { to, data, value, gas })
+    }
+
+    /// Polls the receipt of a recorded transaction, replacing it with higher fees while it is not mined.
+    async fn watch(&self, record: &mut EvmTransaction, call: &Call, mut fees: Fees) -> Result<TransactionReceipt, TrustServiceError> {
+        let nonce = U256::from(record.nonce);
+        let mut resubmissions = 0;
+        let mut sent_at = Instant::now();
+        loop {
+            sleep(RECEIPT_POLL_INTERVAL).await;
+            if let Some(receipt) = self.find_receipt(record).await? {
+                // a reorganisation can drop the transaction while waiting, polling starts again
+                if let Some(receipt) = self.wait_confirmations(receipt).await? {
+                    self.complete(record, &receipt).await?;
+                    if record.status == TransactionStatus::Reverted {
+                        return Err(TrustServiceError::ContractError(format!("transaction {} reverted", record.hash)))
+                    }
+                    return Ok(receipt)
+                }
+                continue
+            }
+            if sent_at.elapsed() < self.resubmit_after {
+                continue
+            }
+            if resubmissions == self.max_resubmissions {
+                self.give_up(record).await?;
+                return Err(TrustServiceError::TransactionTimeout(record.hash.clone()))
+            }
+
+            let bumped = match self.gas_policy.bumped(fees, self.current_fees().await?) {
+                Some(bumped) => bumped,
+                None => {
+                    log::warn!("Fees of transaction {} are at the cap, it cannot be replaced", record.hash);
+                    self.give_up(record).await?;
+                    return Err(TrustServiceError::TransactionFeeCap(record.hash.clone()))
+                },
+            };
+            match self.sign_and_send(&self.build(call, nonce, bumped)).await {
+                Ok(hash) => {
+                    log::warn!("Transaction {} replaced by {:#x} with higher fees", record.hash, hash);
+                    self.record_broadcast(record, hash, bumped).await?;
+                    fees = bumped;
+                },
+                // the previous broadcast may have been mined in the meantime
+                Err(err) => log::warn!("Replacement of transaction {} failed: {}", record.hash, err),
+            }
+            resubmissions += 1;
+            sent_at = Instant::now();
+        }
+    }
+
+    /// Assigns the next nonce and broadcasts, the nonce is held until the transaction is settled.
+    ///
+    /// The nonce is the first one from the pending transaction count of the node that is not held
+    /// by another transaction: the count skips the transactions dropped by the node, whose nonces
+    /// would otherwise be a gap blocking every later transaction of the wallet.
+    async fn broadcast_next(&self, call: &Call, fees: Fees) -> Result<(U256, H256), TrustServiceError> {
+        let mut in_flight = self.in_flight.lock().await;
+        let mut nonce = self.transaction_count(BlockNumber::Pending).await?;
+        while in_flight.contains(&nonce) {
+            nonce += U256::one();
+        }
+        let hash = self.sign_and_send(&self.build(call, nonce, fees)).await?;
+        in_flight.insert(nonce);
+        Ok((nonce, hash))
+    }
+
+    async fn transaction_count(&self, block: BlockNumber) -> Result<U256, TrustServiceError> {
+        self.provider().get_transaction_count(self.address(), Some(block.into())).await
+            .map_err(|err| TrustServiceError::ContractError(err.to_string()))
+    }
+
+    fn build(&self, call: &Call, nonce: U256, fees: Fees) -> TypedTransaction {
+        let chain_id = self.chain_id();
+        match fees {
+            Fees::Legacy { gas_price } => TransactionRequest::new()
+                .from(self.address()).to(call.to).data(call.data.clone()).value(call.value)
+                .gas(call.gas).gas_price(gas_price).nonce(nonce).chain_id(chain_id)
+                .into(),
+            Fees::Eip1559 { max_fee, priority_fee } => Eip1559TransactionRequest::new()
+                .from(self.address()).to(call.to).data(call.data.clone()).value(call.value)
+                .gas(call.gas).max_fee_per_gas(max_fee).max_priority_fee_per_gas(priority_fee).nonce(nonce).chain_id(chain_id)
+                .into(),
+        }
+    }
+
+    async fn sign_and_send(&self, tx: &TypedTransaction) -> Result<H256, TrustServiceError> {
+        let signature = self.signer.signer().sign_transaction(tx).await
+            .map_err(|err| TrustServiceError::EvmSignerError(err.to_string()))?;
+        let pending = self.provider().send_raw_transaction(tx.rlp_signed(&signature)).await
+            .map_err(|err| TrustServiceError::ContractError(err.to_string()))?;
+        Ok(pending.tx_hash())
+    }
+
+    async fn current_fees(&self) -> Result<Fees, TrustServiceError> {
+        if self.gas_policy.eip1559 {
+            let (max_fee, suggested_priority_fee) = self.provider().estimate_eip1559_fees(None).await
+                .map_err(|err| TrustServiceError::ContractError(err.to_string()))?;
+            let priority_fee = self.gas_policy.priority_fee.unwrap_or(suggested_priority_fee);
+            Ok(Fees::Eip1559 { max_fee: self.gas_policy.cap(max_fee.max(priority_fee)), priority_fee: self.gas_policy.cap(priority_fee) })
+        } else {
+            let gas_price = self.provider().get_gas_price().await
+                .map_err(|err| TrustServiceError::ContractError(err.to_string()))?;
+            Ok(Fees::Legacy { gas_price: self.gas_policy.cap(gas_price) })
+        }
+    }
+
+    async fn record_broadcast(&self, record: &mut EvmTransaction, hash: H256, fees: Fees) -> Result<(), TrustServiceError> {
+        let replaced = std::mem::replace(&mut record.hash, format!("{:#x}", hash));
+        record.replaced.push(replaced);
+        set_fees(record, fees);
+        record.updated_at = Timestamp::now_utc().to_rfc3339();
+        self.mongo_repo.store_transaction(record).await
+    }
+
+    /// Marks a transaction no longer followed, it is completed by `recover` if it is mined later.
+    async fn give_up(&self, record: &mut EvmTransaction) -> Result<(), TrustServiceError> {
+        record.status = TransactionStatus::Stuck;
+        record.updated_at = Timestamp::now_utc().to_rfc3339();
+        self.mongo_repo.store_transaction(record).await
+    }
+
+    /// Receipt of the last broadcast or of one it replaced.
+    async fn find_receipt(&self, record: &EvmTransaction) -> Result<Option<TransactionReceipt>, TrustServiceError> {
+        for hash in std::iter::once(&record.hash).chain(record.replaced.iter()) {
+            let hash: H256 = hash.parse().map_err(|_| TrustServiceError::CustomError(format!("invalid transaction hash {hash}")))?;
+            let receipt = self.provider().get_transaction_receipt(hash).await
+                .map_err(|err| TrustServiceError::ContractError(err.to_string()))?;
+            if receipt.is_some() {
+                return Ok(receipt)
+            }
+        }
+        Ok(None)
+    }
+
+    /// Waits until the block of the receipt has the configured depth,
+    /// returns `None` if the transaction left the chain meanwhile.
+    async fn wait_confirmations(&self, mut receipt: TransactionReceipt) -> Result<Option<TransactionReceipt>, TrustServiceError> {
+        loop {
+            let block_number = match receipt.block_number {
+                Some(block_number) => block_number.as_u64(),
+                None => return Ok(None),
+            };
+            let head = self.provider().get_block_number().await
+                .map_err(|err| TrustServiceError::ContractError(err.to_string()))?.as_u64();
+            if head + 1 >= block_number + self.confirmations {
+                // read again at depth, a reorganisation may have moved or dropped the transaction
+                match self.provider().get_transaction_receipt(receipt.transaction_hash).await
+                    .map_err(|err| TrustServiceError::ContractError(err.to_string()))? {
+                    Some(current) if current.block_hash == receipt.block_hash => return Ok(Some(current)),
+                    Some(current) => receipt = current,
+                    None => return Ok(None),
+                }
+                continue
+            }
+            sleep(RECEIPT_POLL_INTERVAL).await;
+        }
+    }
+
+    async fn complete(&self, record: &mut EvmTransaction, receipt: &TransactionReceipt) -> Result<(), TrustServiceError> {
+        let mined = format!("{:#x}", receipt.transaction_hash);
+        if mined != record.hash {
+            record.replaced.retain(|hash| hash != &mined);
+            let replaced = std::mem::replace(&mut record.hash, mined);
+            record.replaced.push(replaced);
+        }
+        record.status = match receipt.status {
+            Some(status) if status.is_zero() => TransactionStatus::Reverted,
+            _ => TransactionStatus::Confirmed,
+        };
+        record.block_number = receipt.block_number.map(|block_number| block_number.as_u64());
+        record.updated_at = Timestamp::now_utc().to_rfc3339();
+        self.mongo_repo.store_transaction(record).await
+    }
+}
+
+fn call_of_record(record: &EvmTransaction) -> Result<Call, TrustServiceError> {
+    let invalid = || TrustServiceError::CustomError(format!("transaction {} has an invalid record", record.hash));
+    Ok(Call {
+        to: record.to.parse().map_err(|_| invalid())?,
+        data: record.data.parse().map_err(|_| invalid())?,
+        value: U256::from_dec_str(record.value.as_str()).map_err(|_| invalid())?,
+        gas: U256::from_dec_str(record.gas.as_str()).map_err(|_| invalid())?,
+    })
+}
+
+fn fees_of_record(record: &EvmTransaction) -> Option<Fees> {
+    let max_fee = U256::from_dec_str(record.max_fee.as_deref()?).ok()?;
+    match record.priority_fee.as_deref() {
+        Some(priority_fee) => Some(Fees::Eip1559 { max_fee, priority_fee: U256::from_dec_str(priority_fee).ok()? }),
+        None => Some(Fees::Legacy { gas_price: max_fee }),
+    }
+}
+
+fn set_fees(record: &mut EvmTransaction, fees: Fees) {
+    let (max_fee, priority_fee) = match fees {
+        Fees::Legacy { gas_price } => (gas_price, None),
+        Fees::Eip1559 { max_fee, priority_fee } => (max_fee, Some(priority_fee)),
+    };
+    record.max_fee = Some(max_fee.to_string());
+    record.priority_fee = priority_fee.map(|priority_fee| priority_fee.to_string());
+}
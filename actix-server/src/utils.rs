@@ -95,4 +95,20 @@ pub async fn print_addresses_with_funds(account: &Account) -> Result<()> {
         }
     }
     Ok(())
-}
\ No newline at end of file
+}
+
+/// Reads a numeric setting, `default` when the variable is not set.
+pub fn env_u64(name: &str, default: u64) -> std::result::Result<u64, crate::errors::TrustServiceError> {
+    match std::env::var(name) {
+        Ok(value) => value.parse().map_err(|_| crate::errors::TrustServiceError::CustomError(format!("{name} is not a number"))),
+        Err(_) => Ok(default),
+    }
+}
+
+/// Reads a size setting, `default` when the variable is not set.
+pub fn env_usize(name: &str, default: usize) -> std::result::Result<usize, crate::errors::TrustServiceError> {
+    match std::env::var(name) {
+        Ok(value) => value.parse().map_err(|_| crate::errors::TrustServiceError::CustomError(format!("{name} is not a number"))),
+        Err(_) => Ok(default),
+    }
+}
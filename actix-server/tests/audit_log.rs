@@ -0,0 +1,248 @@
+// SPDX-FileCopyrightText: 2024 Fondazione LINKS
+//
+// SPDX-License-Identifier: APACHE-2.0
+
+//! Tampering with the audit log file: modified, removed, reordered and truncated entries
+//! must fail the verification and keep the log from being opened again.
+//! The checkpoints are signed by a `did:key`, the anchors are kept by `InMemoryRepository`.
+
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use actix_web::http::StatusCode;
+use actix_web::{test, web, App};
+use async_trait::async_trait;
+use crypto::signatures::ed25519::SecretKey;
+use identity_iota::core::BaseEncoding;
+use identity_iota::verification::jwu;
+use iota_sdk::client::Client;
+use serde_json::{json, Value};
+
+use trust_server::controllers::log_controller;
+use trust_server::errors::TrustServiceError;
+use trust_server::services::audit_log::{AuditLog, CheckpointSigner};
+use trust_server::services::did_cache::DidCache;
+use trust_server::services::did_resolver::DidResolver;
+use trust_server::services::memory_repo::InMemoryRepository;
+use trust_server::services::repository::Repository;
+
+/// A checkpoint every two accesses
+const CHECKPOINT_ENTRIES: u64 = 2;
+
+/// Ed25519 key of a `did:key`, signing the checkpoints in place of the issuer DID.
+struct DidKey {
+    secret_key: SecretKey,
+    did: String,
+}
+
+impl DidKey {
+    fn generate() -> Self {
+        let secret_key = SecretKey::generate().unwrap();
+        let mut multicodec_key = vec![0xed, 0x01];
+        multicodec_key.extend_from_slice(&secret_key.public_key().to_bytes());
+        let did = format!("did:key:{}", BaseEncoding::encode_multibase(&multicodec_key, None));
+        DidKey { secret_key, did }
+    }
+}
+
+#[async_trait]
+impl CheckpointSigner for DidKey {
+    fn did(&self) -> &str {
+        self.did.as_str()
+    }
+
+    async fn sign(&self, payload: &[u8]) -> Result<String, TrustServiceError> {
+        // the method of a did:key document is named after the multibase key
+        let kid = format!("{}#{}", self.did, self.did.trim_start_matches("did:key:"));
+        let header = json!({ "alg": "EdDSA", "kid": kid });
+        let signing_input = format!("{}.{}", jwu::encode_b64(header.to_string()), jwu::encode_b64(payload));
+        let signature = self.secret_key.sign(signing_input.as_bytes());
+        Ok(format!("{}.{}", signing_input, jwu::encode_b64(signature.to_bytes())))
+    }
+}
+
+struct LogFile {
+    path: PathBuf,
+    signer: Arc<DidKey>,
+    did_resolver: Arc<DidResolver>,
+    repo: Arc<InMemoryRepository>,
+}
+
+impl LogFile {
+
+    async fn new() -> Self {
+        let signer = Arc::new(DidKey::generate());
+        let path = std::env::temp_dir().join(format!("audit-log-{}.log", signer.did.trim_start_matches("did:key:")));
+        // read when the log is published to IPFS, which is not reachable here
+        std::env::set_var("LOG_FILE_NAME", std::env::temp_dir().join("audit-log-unpublished.log"));
+        // did:key documents are expanded locally, the client reaches no node
+        let client = Client::builder().finish().await.unwrap();
+        let did_resolver = Arc::new(DidResolver::new(client, Arc::new(DidCache::new(Duration::from_secs(60), 16))));
+        LogFile { path, signer, did_resolver, repo: Arc::new(InMemoryRepository::new()) }
+    }
+
+    async fn open(&self) -> Result<AuditLog, TrustServiceError> {
+        AuditLog::open(self.path.clone(), CHECKPOINT_ENTRIES, self.signer.clone(), self.did_resolver.clone(), self.repo.clone()).await
+    }
+
+    /// Opens the log and records `accesses` reads of assets.
+    async fn open_with_accesses(&self, accesses: usize) -> AuditLog {
+        let audit_log = self.open().await.unwrap();
+        for access in 0..accesses {
+            let proof_id = format!("0x{access:02}");
+            let req = test::TestRequest::get().uri(format!("/api/proofs/{proof_id}").as_str()).to_http_request();
+            let result: Result<(), TrustServiceError> = Ok(());
+            audit_log.record_access(&req, Some("id-asset-1"), Some(proof_id.as_str()), &result).await.unwrap();
+        }
+        audit_log
+    }
+
+    async fn reopen_error(&self) -> TrustServiceError {
+        match self.open().await {
+            Ok(_) => panic!("the broken log was opened"),
+            Err(err) => err,
+        }
+    }
+
+    fn lines(&self) -> Vec<String> {
+        std::fs::read_to_string(&self.path).unwrap().lines().map(|line| line.to_owned()).collect()
+    }
+
+    fn write_lines(&self, lines: &[String]) {
+        std::fs::write(&self.path, lines.iter().map(|line| format!("{line}\n")).collect::<String>()).unwrap();
+    }
+}
+
+impl Drop for LogFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+#[actix_web::test]
+async fn intact_log_is_verified_and_opened_again() {
+    let log_file = LogFile::new().await;
+    let audit_log = log_file.open_with_accesses(3).await;
+
+    // two accesses, the checkpoint over them and the third access
+    let report = audit_log.verify().await.unwrap();
+    assert!(report.valid, "{:?}", report.failures);
+    assert_eq!(report.entries, 4);
+    assert_eq!(report.checkpoints, 1);
+    assert_eq!(report.last_checkpoint, Some(2));
+    assert_eq!(report.unsigned_entries, 1);
+    let anchor = log_file.repo.get_audit_anchor(log_file.path.display().to_string().as_str()).await.unwrap().unwrap();
+    assert_eq!(anchor.seq, 2);
+    assert_eq!(anchor.keys.len(), 1);
+    drop(audit_log);
+
+    // the access after the checkpoint is sealed when the log is opened again
+    let audit_log = log_file.open().await.unwrap();
+    let report = audit_log.verify().await.unwrap();
+    assert!(report.valid, "{:?}", report.failures);
+    assert_eq!(report.last_checkpoint, Some(4));
+    assert_eq!(report.unsigned_entries, 0);
+    let anchor = log_file.repo.get_audit_anchor(log_file.path.display().to_string().as_str()).await.unwrap().unwrap();
+    assert_eq!(anchor.seq, 4);
+}
+
+#[actix_web::test]
+async fn modified_entry_is_detected() {
+    let log_file = LogFile::new().await;
+    let audit_log = log_file.open_with_accesses(3).await;
+
+    let mut lines = log_file.lines();
+    lines[1] = lines[1].replace("0x01", "0x09");
+    log_file.write_lines(&lines);
+
+    let report = audit_log.verify().await.unwrap();
+    assert!(!report.valid);
+    assert!(report.failures.iter().any(|failure| failure.contains("entry 1 was modified")), "{:?}", report.failures);
+    drop(audit_log);
+    assert!(matches!(log_file.reopen_error().await, TrustServiceError::AuditLogCorrupted(_)));
+}
+
+#[actix_web::test]
+async fn removed_entry_is_detected() {
+    let log_file = LogFile::new().await;
+    let audit_log = log_file.open_with_accesses(3).await;
+
+    let mut lines = log_file.lines();
+    lines.remove(1);
+    log_file.write_lines(&lines);
+
+    let report = audit_log.verify().await.unwrap();
+    assert!(!report.valid);
+    assert!(report.failures.iter().any(|failure| failure.contains("entry 2 found, expected 1")), "{:?}", report.failures);
+    drop(audit_log);
+    assert!(matches!(log_file.reopen_error().await, TrustServiceError::AuditLogCorrupted(_)));
+}
+
+#[actix_web::test]
+async fn reordered_entries_are_detected() {
+    let log_file = LogFile::new().await;
+    let audit_log = log_file.open_with_accesses(3).await;
+
+    let mut lines = log_file.lines();
+    lines.swap(0, 1);
+    log_file.write_lines(&lines);
+
+    let report = audit_log.verify().await.unwrap();
+    assert!(!report.valid);
+    assert!(report.failures.iter().any(|failure| failure.contains("entry 1 found, expected 0")), "{:?}", report.failures);
+    drop(audit_log);
+    assert!(matches!(log_file.reopen_error().await, TrustServiceError::AuditLogCorrupted(_)));
+}
+
+#[actix_web::test]
+async fn truncated_log_is_detected() {
+    let log_file = LogFile::new().await;
+    let audit_log = log_file.open_with_accesses(3).await;
+
+    // the remaining entries still form a valid chain, only the anchor tells them apart
+    let lines = log_file.lines();
+    log_file.write_lines(&lines[..2]);
+
+    let report = audit_log.verify().await.unwrap();
+    assert!(!report.valid);
+    assert!(report.failures.iter().any(|failure| failure.contains("anchored checkpoint 2 is missing")), "{:?}", report.failures);
+    drop(audit_log);
+    assert!(matches!(log_file.reopen_error().await, TrustServiceError::AuditLogCorrupted(_)));
+}
+
+#[actix_web::test]
+async fn anchored_log_is_not_replaced() {
+    let log_file = LogFile::new().await;
+    drop(log_file.open_with_accesses(2).await);
+
+    std::fs::write(&log_file.path, "not an audit entry\n").unwrap();
+    assert!(matches!(log_file.reopen_error().await, TrustServiceError::AuditLogCorrupted(_)));
+
+    std::fs::remove_file(&log_file.path).unwrap();
+    assert!(matches!(log_file.reopen_error().await, TrustServiceError::AuditLogCorrupted(_)));
+}
+
+#[actix_web::test]
+async fn verify_endpoint_fails_on_a_broken_log() {
+    let log_file = LogFile::new().await;
+    let audit_log = web::Data::new(log_file.open_with_accesses(3).await);
+    let app = test::init_service(
+        App::new()
+            .app_data(audit_log.clone())
+            .service(web::scope("/api").configure(log_controller::scoped_config))
+    ).await;
+
+    let req = test::TestRequest::get().uri("/api/log/verify").to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+
+    let mut lines = log_file.lines();
+    lines.swap(1, 3);
+    log_file.write_lines(&lines);
+
+    let req = test::TestRequest::get().uri("/api/log/verify").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    let report: Value = test::read_body_json(resp).await;
+    assert_eq!(report["valid"], false);
+}
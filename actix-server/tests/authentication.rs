@@ -0,0 +1,215 @@
+// SPDX-FileCopyrightText: 2024 Fondazione LINKS
+//
+// SPDX-License-Identifier: APACHE-2.0
+
+//! Callers authenticated by `Authenticator` from the administrator key, the access tokens
+//! of the DIDs and the requests signed by a `did:key`, which resolves without a node.
+
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use actix_web::dev::ServiceResponse;
+use actix_web::http::{header, StatusCode};
+use actix_web::{test, web, App};
+use crypto::signatures::ed25519::SecretKey;
+use identity_iota::core::BaseEncoding;
+use identity_iota::verification::jwu;
+use iota_sdk::client::Client;
+use serde_json::{json, Value};
+
+use trust_server::errors::TrustServiceError;
+use trust_server::models::user::User;
+use trust_server::services::authentication::{self, Authenticator, Caller, DEFAULT_AUTH_MAX_AGE_SECS};
+use trust_server::services::did_cache::DidCache;
+use trust_server::services::did_resolver::DidResolver;
+use trust_server::services::memory_repo::InMemoryRepository;
+use trust_server::services::repository::Repository;
+
+const USER_DID: &str = "did:iota:tst:0x1111111111111111111111111111111111111111111111111111111111111111";
+const ADMIN_API_KEY: &str = "authentication-admin-key";
+const PATH: &str = "/api/tenants/acme/export";
+
+/// Ed25519 key of a `did:key`, signing compact JWS as the wallets of the users do.
+struct DidKey {
+    secret_key: SecretKey,
+    did: String,
+}
+
+impl DidKey {
+    fn generate() -> Self {
+        let secret_key = SecretKey::generate().unwrap();
+        let mut multicodec_key = vec![0xed, 0x01];
+        multicodec_key.extend_from_slice(&secret_key.public_key().to_bytes());
+        let did = format!("did:key:{}", BaseEncoding::encode_multibase(&multicodec_key, None));
+        DidKey { secret_key, did }
+    }
+
+    /// The method of a `did:key` document is named after the multibase key.
+    fn kid(&self) -> String {
+        format!("{}#{}", self.did, self.did.trim_start_matches("did:key:"))
+    }
+
+    fn jws(&self, payload: &[u8]) -> String {
+        let header = json!({ "alg": "EdDSA", "kid": self.kid() });
+        let signing_input = format!("{}.{}", jwu::encode_b64(header.to_string()), jwu::encode_b64(payload));
+        let signature = self.secret_key.sign(signing_input.as_bytes());
+        format!("{}.{}", signing_input, jwu::encode_b64(signature.to_bytes()))
+    }
+
+    /// `Authorization` header of a request signed with `claims`.
+    fn authorization(&self, claims: Value) -> String {
+        format!("DID {}", self.jws(claims.to_string().as_bytes()))
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+}
+
+fn claims(htm: &str, htu: &str, iat: u64, jti: &str) -> Value {
+    json!({ "htm": htm, "htu": htu, "iat": iat, "jti": jti })
+}
+
+async fn authenticator(repo: Arc<InMemoryRepository>) -> web::Data<Authenticator> {
+    let client = Client::builder().finish().await.unwrap();
+    let did_resolver = Arc::new(DidResolver::new(client, Arc::new(DidCache::new(Duration::from_secs(60), 16))));
+    web::Data::new(Authenticator::from_env(did_resolver, repo).unwrap())
+}
+
+/// Service answering `PATH` with the authenticated caller.
+macro_rules! caller_service {
+    ($authenticator:expr) => {
+        test::init_service(
+            App::new()
+                .app_data($authenticator)
+                .route(PATH, web::route().to(|caller: Caller| async move { caller.to_string() }))
+        ).await
+    };
+}
+
+/// Caller authenticated for a request to `PATH` with an `Authorization` header, or the status of the refusal.
+macro_rules! call {
+    ($app:expr, $authorization:expr) => {
+        caller(test::call_service(&$app, request($authorization).to_request()).await).await
+    };
+}
+
+fn request(authorization: Option<&str>) -> test::TestRequest {
+    let req = test::TestRequest::post().uri(PATH);
+    match authorization {
+        Some(authorization) => req.insert_header((header::AUTHORIZATION, authorization)),
+        None => req,
+    }
+}
+
+async fn caller(res: ServiceResponse) -> Result<String, StatusCode> {
+    match res.status() {
+        StatusCode::OK => Ok(String::from_utf8(test::read_body(res).await.to_vec()).unwrap()),
+        status => Err(status),
+    }
+}
+
+#[actix_web::test]
+async fn bearer_tokens_authenticate_the_administrator_and_the_dids() {
+    let repo = Arc::new(InMemoryRepository::new());
+    let (token, token_hash) = authentication::new_access_token().unwrap();
+    repo.store_user(User { did: USER_DID.to_owned(), fragment: "key-1".to_owned(), assets: vec![], evm_account: None, tenant_id: None, access_token_hash: Some(token_hash) }).await.unwrap();
+
+    // the only test reading the administrator key
+    std::env::set_var("ADMIN_API_KEY", ADMIN_API_KEY);
+    let app = caller_service!(authenticator(repo.clone()).await);
+    assert_eq!(call!(app, Some(format!("Bearer {ADMIN_API_KEY}").as_str())).await, Ok("the administrator".to_owned()));
+    assert_eq!(call!(app, Some(format!("Bearer {token}").as_str())).await, Ok(USER_DID.to_owned()));
+    // the token is stored hashed, its hash is not a token
+    let user = repo.get_user(USER_DID).await.unwrap();
+    assert_eq!(call!(app, Some(format!("Bearer {}", user.access_token_hash.unwrap()).as_str())).await, Err(StatusCode::UNAUTHORIZED));
+    for authorization in [None, Some("Bearer"), Some("Bearer unknown-token"), Some(format!("Basic {ADMIN_API_KEY}").as_str())] {
+        assert_eq!(call!(app, authorization).await, Err(StatusCode::UNAUTHORIZED), "{authorization:?}");
+    }
+
+    // without a key the administrator cannot authenticate, an empty key included
+    for admin_key in [None, Some("")] {
+        match admin_key {
+            Some(admin_key) => std::env::set_var("ADMIN_API_KEY", admin_key),
+            None => std::env::remove_var("ADMIN_API_KEY"),
+        }
+        let app = caller_service!(authenticator(repo.clone()).await);
+        assert_eq!(call!(app, Some(format!("Bearer {ADMIN_API_KEY}").as_str())).await, Err(StatusCode::UNAUTHORIZED));
+        assert_eq!(call!(app, Some("Bearer ")).await, Err(StatusCode::UNAUTHORIZED));
+    }
+}
+
+#[actix_web::test]
+async fn signed_requests_authenticate_their_did_once() {
+    let app = caller_service!(authenticator(Arc::new(InMemoryRepository::new())).await);
+    let signer = DidKey::generate();
+    let authorization = signer.authorization(claims("POST", PATH, now(), "jti-1"));
+
+    assert_eq!(call!(app, Some(authorization.as_str())).await, Ok(signer.did.clone()));
+    // replayed
+    assert_eq!(call!(app, Some(authorization.as_str())).await, Err(StatusCode::UNAUTHORIZED));
+    // the jti is scoped to the DID
+    let other_signer = DidKey::generate();
+    let authorization = other_signer.authorization(claims("post", PATH, now(), "jti-1"));
+    assert_eq!(call!(app, Some(authorization.as_str())).await, Ok(other_signer.did.clone()));
+}
+
+#[actix_web::test]
+async fn signatures_of_other_requests_are_refused() {
+    let app = caller_service!(authenticator(Arc::new(InMemoryRepository::new())).await);
+    let signer = DidKey::generate();
+
+    for claims in [
+        claims("GET", PATH, now(), "jti-1"),
+        claims("POST", "/api/tenants/other/export", now(), "jti-2"),
+        json!({ "htm": "POST", "htu": PATH, "iat": now(), "jti": "jti-3", "aud": "trust-service" }),
+        json!({ "htm": "POST", "htu": PATH, "iat": now() }),
+    ] {
+        let authorization = signer.authorization(claims.clone());
+        assert_eq!(call!(app, Some(authorization.as_str())).await, Err(StatusCode::UNAUTHORIZED), "{claims}");
+    }
+
+    // signed by a key other than the one named by the kid
+    let forger = DidKey::generate();
+    let header = json!({ "alg": "EdDSA", "kid": signer.kid() });
+    let signing_input = format!("{}.{}", jwu::encode_b64(header.to_string()), jwu::encode_b64(claims("POST", PATH, now(), "jti-4").to_string()));
+    let signature = forger.secret_key.sign(signing_input.as_bytes());
+    let authorization = format!("DID {}.{}", signing_input, jwu::encode_b64(signature.to_bytes()));
+    assert_eq!(call!(app, Some(authorization.as_str())).await, Err(StatusCode::UNAUTHORIZED));
+    assert_eq!(call!(app, Some("DID not-a-jws")).await, Err(StatusCode::UNAUTHORIZED));
+}
+
+#[actix_web::test]
+async fn signatures_outside_their_validity_are_refused() {
+    let app = caller_service!(authenticator(Arc::new(InMemoryRepository::new())).await);
+    let signer = DidKey::generate();
+    let max_age = DEFAULT_AUTH_MAX_AGE_SECS;
+
+    // the extremes of iat are refused, not overflowed
+    for iat in [now() - max_age - 10, now() + max_age + 10, 0, u64::MAX] {
+        let authorization = signer.authorization(claims("POST", PATH, iat, format!("jti-{iat}").as_str()));
+        assert_eq!(call!(app, Some(authorization.as_str())).await, Err(StatusCode::UNAUTHORIZED), "{iat}");
+    }
+    // a small clock skew is tolerated
+    let authorization = signer.authorization(claims("POST", PATH, now() + 10, "jti-skew"));
+    assert_eq!(call!(app, Some(authorization.as_str())).await, Ok(signer.did.clone()));
+}
+
+#[actix_web::test]
+async fn callers_are_authorized_by_did_tenant_or_administrator() {
+    let repo = InMemoryRepository::new();
+    repo.store_user(User { did: USER_DID.to_owned(), fragment: "key-1".to_owned(), assets: vec![], evm_account: None, tenant_id: Some("acme".to_owned()), access_token_hash: None }).await.unwrap();
+    let (admin, user, stranger) = (Caller::Admin, Caller::Did(USER_DID.to_owned()), Caller::Did("did:key:z6Mk".to_owned()));
+
+    assert!(user.require_did(USER_DID).is_ok());
+    assert!(matches!(admin.require_did(USER_DID), Err(TrustServiceError::NotAuthorized(_))));
+    assert!(admin.require_did_or_admin(USER_DID).is_ok());
+    assert!(matches!(stranger.require_did_or_admin(USER_DID), Err(TrustServiceError::NotAuthorized(_))));
+    assert!(admin.require_admin().is_ok());
+    assert!(matches!(user.require_admin(), Err(TrustServiceError::NotAuthorized(_))));
+
+    assert!(user.require_tenant(&repo, "acme").await.is_ok());
+    assert!(admin.require_tenant(&repo, "other").await.is_ok());
+    assert!(matches!(user.require_tenant(&repo, "other").await, Err(TrustServiceError::NotAuthorized(_))));
+    assert!(matches!(stranger.require_tenant(&repo, "acme").await, Err(TrustServiceError::NotAuthorized(_))));
+}
@@ -0,0 +1,243 @@
+// SPDX-FileCopyrightText: 2024 Fondazione LINKS
+//
+// SPDX-License-Identifier: APACHE-2.0
+
+//! Verifiable Credentials issued from the templates of the service, and their verification
+//! against `did:key` issuers and holders, which resolve without a node.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crypto::signatures::ed25519::SecretKey;
+use identity_iota::core::{BaseEncoding, Duration as CredentialDuration, FromJson, Object, Timestamp, Url};
+use identity_iota::credential::{Credential, CredentialBuilder, Jwt, JwtPresentationOptions, Presentation, Subject};
+use identity_iota::did::CoreDID;
+use identity_iota::verification::jwu;
+use iota_sdk::client::Client;
+use serde_json::{json, Map, Value};
+
+use trust_server::dtos::CredentialVerificationResult;
+use trust_server::errors::TrustServiceError;
+use trust_server::models::credential::{CredentialState, CredentialTemplate, IssuedCredential};
+use trust_server::services::credential_issuer;
+use trust_server::services::did_cache::DidCache;
+use trust_server::services::did_resolver::DidResolver;
+use trust_server::services::memory_repo::InMemoryRepository;
+use trust_server::services::presentation_verifier;
+use trust_server::services::repository::Repository;
+
+const HOLDER_DID: &str = "did:iota:tst:0x1111111111111111111111111111111111111111111111111111111111111111";
+const CREDENTIAL_ID: &str = "urn:uuid:7b7a4e0c-3c1f-4f4e-9a57-2f0d0d8f6b11";
+
+/// Ed25519 key of a `did:key`, signing compact JWS as the wallets of the users do.
+struct DidKey {
+    secret_key: SecretKey,
+    did: String,
+}
+
+impl DidKey {
+    fn generate() -> Self {
+        let secret_key = SecretKey::generate().unwrap();
+        let mut multicodec_key = vec![0xed, 0x01];
+        multicodec_key.extend_from_slice(&secret_key.public_key().to_bytes());
+        let did = format!("did:key:{}", BaseEncoding::encode_multibase(&multicodec_key, None));
+        DidKey { secret_key, did }
+    }
+
+    fn core_did(&self) -> CoreDID {
+        CoreDID::parse(self.did.as_str()).unwrap()
+    }
+
+    /// The method of a `did:key` document is named after the multibase key.
+    fn kid(&self) -> String {
+        format!("{}#{}", self.did, self.did.trim_start_matches("did:key:"))
+    }
+
+    fn jws(&self, payload: &[u8]) -> Jwt {
+        let header = json!({ "alg": "EdDSA", "kid": self.kid() });
+        let signing_input = format!("{}.{}", jwu::encode_b64(header.to_string()), jwu::encode_b64(payload));
+        let signature = self.secret_key.sign(signing_input.as_bytes());
+        Jwt::from(format!("{}.{}", signing_input, jwu::encode_b64(signature.to_bytes())))
+    }
+}
+
+async fn did_resolver() -> DidResolver {
+    // did:key documents are expanded locally, the client is never called
+    let client = Client::builder().finish().await.unwrap();
+    DidResolver::new(client, Arc::new(DidCache::new(Duration::from_secs(60), 16)))
+}
+
+/// JWT credential without a status, issued by `issuer` to `holder_did`.
+fn credential_jwt(issuer: &DidKey, holder_did: &str, expiration_date: Option<Timestamp>) -> Jwt {
+    let mut builder = CredentialBuilder::default()
+        .id(Url::parse(CREDENTIAL_ID).unwrap())
+        .issuer(Url::parse(issuer.did.as_str()).unwrap())
+        .type_("AssetOwnerCredential".to_owned())
+        .subject(Subject::from_json_value(json!({ "id": holder_did, "assetId": "id-asset-1" })).unwrap())
+        .issuance_date(Timestamp::now_utc().checked_sub(CredentialDuration::days(2)).unwrap());
+    if let Some(expiration_date) = expiration_date {
+        builder = builder.expiration_date(expiration_date);
+    }
+    let credential: Credential = builder.build().unwrap();
+    issuer.jws(credential.serialize_jwt(None).unwrap().as_bytes())
+}
+
+/// Record of the credential, as kept by the service for the credentials it issues.
+fn issued_credential(issuer_did: &str, holder_did: &str, jwt: &Jwt, status: CredentialState) -> IssuedCredential {
+    IssuedCredential {
+        credential_id: CREDENTIAL_ID.to_owned(),
+        issuer_did: issuer_did.to_owned(),
+        holder_did: holder_did.to_owned(),
+        template: "assetOwner".to_owned(),
+        types: vec!["VerifiableCredential".to_owned(), "AssetOwnerCredential".to_owned()],
+        issuance_date: Timestamp::now_utc().to_rfc3339(),
+        expiration_date: None,
+        jwt: jwt.as_str().to_owned(),
+        status_index: Some(0),
+        status,
+    }
+}
+
+fn failed_checks(result: &CredentialVerificationResult) -> Vec<&str> {
+    result.errors.iter().map(|error| error.check.as_str()).collect()
+}
+
+fn asset_owner_template() -> CredentialTemplate {
+    CredentialTemplate {
+        types: vec!["AssetOwnerCredential".to_owned()],
+        contexts: vec![],
+        required_claims: vec!["assetId".to_owned()],
+        static_claims: claims(json!({ "role": "assetOwner", "issuedBy": "Trust Service" })),
+        validity_days: Some(365),
+        self_attested: true,
+    }
+}
+
+fn claims(value: Value) -> Map<String, Value> {
+    match value {
+        Value::Object(claims) => claims,
+        _ => unreachable!(),
+    }
+}
+
+#[test]
+fn request_claims_are_added_to_the_static_ones() {
+    let subject = credential_issuer::subject_claims(&asset_owner_template(), HOLDER_DID, claims(json!({ "assetId": "id-asset-1", "name": "Dataset" }))).unwrap();
+    assert_eq!(Value::Object(subject), json!({
+        "id": HOLDER_DID,
+        "role": "assetOwner",
+        "issuedBy": "Trust Service",
+        "assetId": "id-asset-1",
+        "name": "Dataset",
+    }));
+}
+
+#[test]
+fn static_claims_are_not_overridden() {
+    let template = asset_owner_template();
+    for claim in ["role", "issuedBy", "id"] {
+        let mut request_claims = claims(json!({ "assetId": "id-asset-1" }));
+        request_claims.insert(claim.to_owned(), json!("did:iota:tst:0x2222"));
+        let result = credential_issuer::subject_claims(&template, HOLDER_DID, request_claims);
+        assert!(matches!(result, Err(TrustServiceError::ReservedClaim(reserved)) if reserved == claim), "{claim}");
+    }
+
+    let result = credential_issuer::subject_claims(&template, HOLDER_DID, claims(json!({ "name": "Dataset" })));
+    assert!(matches!(result, Err(TrustServiceError::MissingClaim(claim)) if claim == "assetId"));
+}
+
+#[test]
+fn only_holder_attestable_templates_are_self_attested() {
+    let templates = std::fs::read(concat!(env!("CARGO_MANIFEST_DIR"), "/credential_templates.json")).unwrap();
+    let templates: HashMap<String, CredentialTemplate> = serde_json::from_slice(&templates).unwrap();
+    // the ownership of the asset is checked on issuance, a data provider is vouched for by the administrator
+    assert!(templates["assetOwner"].self_attested);
+    assert!(!templates["registeredDataProvider"].self_attested);
+}
+
+#[actix_web::test]
+async fn valid_credentials_are_verified() {
+    let (issuer, holder) = (DidKey::generate(), DidKey::generate());
+    let repo = InMemoryRepository::new();
+    let jwt = credential_jwt(&issuer, holder.did.as_str(), None);
+
+    let result = presentation_verifier::verify_credential(&did_resolver().await, &repo, &jwt, Some(&holder.core_did()), 0).await;
+    assert!(result.valid, "{:?}", result.errors);
+    assert_eq!(result.credential_id.as_deref(), Some(CREDENTIAL_ID));
+    assert_eq!(result.issuer.as_deref(), Some(issuer.did.as_str()));
+    assert_eq!(result.subject.unwrap()["assetId"], "id-asset-1");
+}
+
+#[actix_web::test]
+async fn failed_checks_are_reported() {
+    let (issuer, holder) = (DidKey::generate(), DidKey::generate());
+    let (did_resolver, repo) = (did_resolver().await, InMemoryRepository::new());
+    let jwt = credential_jwt(&issuer, holder.did.as_str(), None);
+
+    let other_holder = DidKey::generate().core_did();
+    let result = presentation_verifier::verify_credential(&did_resolver, &repo, &jwt, Some(&other_holder), 0).await;
+    assert!(!result.valid);
+    assert_eq!(failed_checks(&result), ["holderBinding"]);
+
+    // signed by another key than the one of the issuer document
+    let (signing_input, _) = jwt.as_str().rsplit_once('.').unwrap();
+    let other_jws = DidKey::generate().jws(signing_input.as_bytes());
+    let forged = Jwt::from(format!("{signing_input}.{}", other_jws.as_str().rsplit('.').next().unwrap()));
+    let result = presentation_verifier::verify_credential(&did_resolver, &repo, &forged, None, 0).await;
+    assert!(!result.valid);
+    assert_eq!(failed_checks(&result), ["signature"]);
+
+    let expired = credential_jwt(&issuer, holder.did.as_str(), Some(Timestamp::now_utc().checked_sub(CredentialDuration::days(1)).unwrap()));
+    let result = presentation_verifier::verify_credential(&did_resolver, &repo, &expired, None, 0).await;
+    assert!(!result.valid);
+    assert_eq!(failed_checks(&result), ["expirationDate"]);
+}
+
+#[actix_web::test]
+async fn status_of_the_issued_credentials_is_checked() {
+    let (issuer, holder) = (DidKey::generate(), DidKey::generate());
+    let did_resolver = did_resolver().await;
+    let jwt = credential_jwt(&issuer, holder.did.as_str(), None);
+
+    for (status, message) in [(CredentialState::Revoked, "revoked"), (CredentialState::Suspended, "suspended")] {
+        let repo = InMemoryRepository::new();
+        repo.store_credential(&issued_credential(issuer.did.as_str(), holder.did.as_str(), &jwt, status)).await.unwrap();
+        let result = presentation_verifier::verify_credential(&did_resolver, &repo, &jwt, Some(&holder.core_did()), 0).await;
+        assert!(!result.valid);
+        assert_eq!(failed_checks(&result), ["status"]);
+        assert_eq!(result.errors[0].message, format!("credential {CREDENTIAL_ID} is {message}"));
+    }
+
+    let repo = InMemoryRepository::new();
+    repo.store_credential(&issued_credential(issuer.did.as_str(), holder.did.as_str(), &jwt, CredentialState::Active)).await.unwrap();
+    assert!(presentation_verifier::verify_credential(&did_resolver, &repo, &jwt, None, 0).await.valid);
+
+    // a record with the same id from another issuer does not count
+    let repo = InMemoryRepository::new();
+    let other_issuer = DidKey::generate();
+    repo.store_credential(&issued_credential(other_issuer.did.as_str(), holder.did.as_str(), &jwt, CredentialState::Revoked)).await.unwrap();
+    assert!(presentation_verifier::verify_credential(&did_resolver, &repo, &jwt, None, 0).await.valid);
+}
+
+#[actix_web::test]
+async fn presentations_carry_the_status_of_their_credentials() {
+    let (issuer, holder) = (DidKey::generate(), DidKey::generate());
+    let did_resolver = did_resolver().await;
+    let repo = InMemoryRepository::new();
+    let credential = credential_jwt(&issuer, holder.did.as_str(), None);
+    let presentation: Presentation<Jwt> = Presentation::builder(Url::parse(holder.did.as_str()).unwrap(), Object::new())
+        .credential(credential.clone())
+        .build()
+        .unwrap();
+    let presentation_jwt = holder.jws(presentation.serialize_jwt(&JwtPresentationOptions::default()).unwrap().as_bytes());
+
+    let response = presentation_verifier::verify_presentation(&did_resolver, &repo, &presentation_jwt, None, None).await;
+    assert!(response.valid, "{:?}", response.errors);
+    assert_eq!(response.holder.as_deref(), Some(holder.did.as_str()));
+
+    repo.store_credential(&issued_credential(issuer.did.as_str(), holder.did.as_str(), &credential, CredentialState::Revoked)).await.unwrap();
+    let response = presentation_verifier::verify_presentation(&did_resolver, &repo, &presentation_jwt, None, None).await;
+    assert!(!response.valid);
+    assert_eq!(failed_checks(&response.credentials[0]), ["status"]);
+}
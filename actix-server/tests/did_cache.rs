@@ -0,0 +1,116 @@
+// SPDX-FileCopyrightText: 2024 Fondazione LINKS
+//
+// SPDX-License-Identifier: APACHE-2.0
+
+//! Expiry, eviction and invalidation of the documents kept by `DidCache`, its counters and settings.
+
+use std::thread::sleep;
+use std::time::Duration;
+
+use identity_iota::did::CoreDID;
+use identity_iota::document::CoreDocument;
+
+use trust_server::errors::TrustServiceError;
+use trust_server::services::did_cache::DidCache;
+
+const TTL: Duration = Duration::from_millis(100);
+
+fn document(did: &str) -> CoreDocument {
+    CoreDocument::builder(Default::default())
+        .id(CoreDID::parse(did).unwrap())
+        .build()
+        .unwrap()
+}
+
+/// Caches the documents of `dids`, one after the other so that their insertion times differ.
+fn fill(cache: &DidCache, dids: &[&str]) {
+    for did in dids {
+        cache.insert(did, document(did));
+        sleep(Duration::from_millis(2));
+    }
+}
+
+#[test]
+fn documents_expire_after_the_ttl() {
+    let cache = DidCache::new(TTL, 16);
+    fill(&cache, &["did:example:1"]);
+    assert_eq!(cache.get("did:example:1").unwrap().id().as_str(), "did:example:1");
+
+    sleep(TTL);
+    assert!(cache.get("did:example:1").is_none());
+    let stats = cache.stats();
+    assert_eq!((stats.hits, stats.misses, stats.evictions, stats.entries), (1, 1, 1, 0));
+
+    // inserting again restarts the ttl
+    fill(&cache, &["did:example:1"]);
+    assert!(cache.get("did:example:1").is_some());
+}
+
+#[test]
+fn oldest_document_is_evicted_when_full() {
+    let cache = DidCache::new(Duration::from_secs(60), 2);
+    fill(&cache, &["did:example:1", "did:example:2"]);
+    // a document already cached is replaced without evicting another one, and becomes the newest
+    fill(&cache, &["did:example:1"]);
+    assert_eq!(cache.stats().evictions, 0);
+
+    fill(&cache, &["did:example:3"]);
+    assert!(cache.get("did:example:2").is_none());
+    assert!(cache.get("did:example:1").is_some());
+    assert!(cache.get("did:example:3").is_some());
+    let stats = cache.stats();
+    assert_eq!((stats.evictions, stats.entries, stats.max_size), (1, 2, 2));
+}
+
+#[test]
+fn expired_documents_are_evicted_first() {
+    let cache = DidCache::new(TTL, 3);
+    fill(&cache, &["did:example:1", "did:example:2"]);
+    sleep(TTL);
+    fill(&cache, &["did:example:3"]);
+
+    // both expired documents make room, not only the oldest one
+    fill(&cache, &["did:example:4"]);
+    let stats = cache.stats();
+    assert_eq!((stats.evictions, stats.entries), (2, 2));
+    for did in ["did:example:3", "did:example:4"] {
+        assert!(cache.get(did).is_some(), "{did}");
+    }
+}
+
+#[test]
+fn invalidated_documents_are_dropped() {
+    let cache = DidCache::new(Duration::from_secs(60), 16);
+    fill(&cache, &["did:example:1", "did:example:2", "did:example:3"]);
+
+    cache.invalidate("did:example:1");
+    // unknown DIDs are not counted
+    cache.invalidate("did:example:9");
+    assert!(cache.get("did:example:1").is_none());
+    assert_eq!(cache.stats().invalidations, 1);
+
+    cache.clear();
+    let stats = cache.stats();
+    assert_eq!((stats.invalidations, stats.entries), (3, 0));
+}
+
+#[test]
+fn zero_ttl_or_size_disables_the_cache() {
+    for cache in [DidCache::new(Duration::ZERO, 16), DidCache::new(Duration::from_secs(60), 0)] {
+        fill(&cache, &["did:example:1"]);
+        assert!(cache.get("did:example:1").is_none());
+        assert_eq!(cache.stats().entries, 0);
+    }
+}
+
+#[test]
+fn malformed_settings_are_reported() {
+    // the only test reading the settings of the cache
+    std::env::set_var("DID_CACHE_TTL_SECS", "60");
+    std::env::set_var("DID_CACHE_MAX_SIZE", "lots");
+    assert!(matches!(DidCache::from_env(), Err(TrustServiceError::CustomError(_))));
+
+    std::env::set_var("DID_CACHE_MAX_SIZE", "8");
+    let stats = DidCache::from_env().unwrap().stats();
+    assert_eq!((stats.ttl_secs, stats.max_size), (60, 8));
+}
@@ -0,0 +1,108 @@
+// SPDX-FileCopyrightText: 2024 Fondazione LINKS
+//
+// SPDX-License-Identifier: APACHE-2.0
+
+//! Representations of the DID documents negotiated from the `Accept` header, and served
+//! by `/api/dids/{did}` for a `did:key`, which resolves without a node.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use actix_web::http::{header, StatusCode};
+use actix_web::{test, web, App};
+use crypto::signatures::ed25519::SecretKey;
+use identity_iota::core::BaseEncoding;
+use iota_sdk::client::Client;
+use serde_json::Value;
+
+use trust_server::controllers::did_controller;
+use trust_server::models::did_resolution::{Representation, DID_CONTEXT, DID_JSON, DID_LD_JSON, DID_RESOLUTION};
+use trust_server::services::did_cache::DidCache;
+use trust_server::services::did_resolver::DidResolver;
+
+fn did_key() -> String {
+    let mut multicodec_key = vec![0xed, 0x01];
+    multicodec_key.extend_from_slice(&SecretKey::generate().unwrap().public_key().to_bytes());
+    format!("did:key:{}", BaseEncoding::encode_multibase(&multicodec_key, None))
+}
+
+#[test]
+fn missing_or_generic_accept_gives_did_json() {
+    for accept in [None, Some(""), Some("  "), Some("*/*"), Some("application/*"), Some("application/json")] {
+        assert_eq!(Representation::negotiate(accept), Some(Representation::DidJson), "{accept:?}");
+    }
+    assert_eq!(Representation::negotiate(Some("application/did+ld+json")), Some(Representation::DidLdJson));
+    assert_eq!(Representation::negotiate(Some("application/ld+json")), Some(Representation::DidLdJson));
+    assert_eq!(Representation::negotiate(Some("application/did-resolution")), Some(Representation::DidResolution));
+    // media types are matched ignoring case
+    assert_eq!(Representation::negotiate(Some("Application/DID+LD+JSON")), Some(Representation::DidLdJson));
+}
+
+#[test]
+fn resolution_profile_selects_the_resolution_result() {
+    let accept = r#"application/ld+json;profile="https://w3id.org/did-resolution""#;
+    assert_eq!(Representation::negotiate(Some(accept)), Some(Representation::DidResolution));
+    let accept = r#"application/ld+json; profile="https://www.w3.org/ns/did/v1""#;
+    assert_eq!(Representation::negotiate(Some(accept)), Some(Representation::DidLdJson));
+}
+
+#[test]
+fn highest_quality_wins() {
+    let accept = "application/did+json;q=0.5, application/did+ld+json;q=0.9";
+    assert_eq!(Representation::negotiate(Some(accept)), Some(Representation::DidLdJson));
+    let accept = "application/did+ld+json;q=0.2, */*;q=0.1, application/did-resolution";
+    assert_eq!(Representation::negotiate(Some(accept)), Some(Representation::DidResolution));
+    // among equal qualities the first listed wins
+    let accept = "application/did-resolution, application/did+ld+json";
+    assert_eq!(Representation::negotiate(Some(accept)), Some(Representation::DidResolution));
+    // q=0 and malformed qualities exclude the media range
+    let accept = "application/did+ld+json;q=0, application/did-resolution;q=high, application/did+json;q=0.1";
+    assert_eq!(Representation::negotiate(Some(accept)), Some(Representation::DidJson));
+}
+
+#[test]
+fn unsupported_media_types_fall_back_or_are_refused() {
+    // a supported type with a lower quality is still picked
+    let accept = "text/html, application/xhtml+xml;q=0.9, application/did+ld+json;q=0.5";
+    assert_eq!(Representation::negotiate(Some(accept)), Some(Representation::DidLdJson));
+    for accept in ["text/html", "text/html, application/xml;q=0.9", "application/did+json;q=0, text/plain"] {
+        assert_eq!(Representation::negotiate(Some(accept)), None, "{accept}");
+    }
+}
+
+#[actix_web::test]
+async fn documents_are_served_in_the_negotiated_representation() {
+    let client = Client::builder().finish().await.unwrap();
+    let did_resolver = DidResolver::new(client, Arc::new(DidCache::new(Duration::from_secs(60), 16)));
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(did_resolver))
+            .service(web::scope("/api").configure(did_controller::scoped_config))
+    ).await;
+    let did = did_key();
+    let uri = format!("/api/dids/{did}");
+
+    for (accept, content_type) in [(None, DID_JSON), (Some("application/did+ld+json"), DID_LD_JSON)] {
+        let mut req = test::TestRequest::get().uri(uri.as_str());
+        if let Some(accept) = accept {
+            req = req.insert_header((header::ACCEPT, accept));
+        }
+        let res = test::call_service(&app, req.to_request()).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers().get(header::CONTENT_TYPE).unwrap(), content_type);
+        let document: Value = test::read_body_json(res).await;
+        assert_eq!(document["id"], did.as_str());
+        // only the JSON-LD representation carries a context
+        assert_eq!(document["@context"][0] == DID_CONTEXT, content_type == DID_LD_JSON);
+    }
+
+    let req = test::TestRequest::get().uri(uri.as_str()).insert_header((header::ACCEPT, DID_RESOLUTION)).to_request();
+    let res = test::call_service(&app, req).await;
+    assert_eq!(res.headers().get(header::CONTENT_TYPE).unwrap(), DID_RESOLUTION);
+    let resolution_result: Value = test::read_body_json(res).await;
+    assert_eq!(resolution_result["didDocument"]["id"], did.as_str());
+    assert_eq!(resolution_result["didResolutionMetadata"]["contentType"], DID_LD_JSON);
+
+    let req = test::TestRequest::get().uri(uri.as_str()).insert_header((header::ACCEPT, "text/html")).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::NOT_ACCEPTABLE);
+}
@@ -0,0 +1,177 @@
+// SPDX-FileCopyrightText: 2024 Fondazione LINKS
+//
+// SPDX-License-Identifier: APACHE-2.0
+
+//! `did:web` documents mapped to their URL and fetched from a local stand-in server,
+//! reached over plain http as `DID_WEB_SCHEME` allows, and the limits of the fetch.
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use actix_web::{web, App, HttpRequest, HttpResponse, HttpServer};
+use identity_iota::did::CoreDID;
+use identity_iota::verification::jwu;
+use iota_sdk::client::Client;
+use serde_json::{json, Value};
+
+use trust_server::errors::TrustServiceError;
+use trust_server::services::did_cache::DidCache;
+use trust_server::services::did_resolver::{self, DidResolver, MAX_DID_WEB_DOCUMENT_LEN};
+
+/// Stand-in for the web servers of the DIDs, serving documents by path and counting the requests.
+struct DidWebServer {
+    port: u16,
+    documents: Arc<Mutex<HashMap<String, Value>>>,
+    requests: Arc<AtomicUsize>,
+}
+
+impl DidWebServer {
+    fn start() -> Self {
+        let documents = Arc::new(Mutex::new(HashMap::new()));
+        let requests = Arc::new(AtomicUsize::new(0));
+        let (server_documents, server_requests) = (documents.clone(), requests.clone());
+        let server = HttpServer::new(move || {
+            let (documents, requests) = (server_documents.clone(), server_requests.clone());
+            App::new().default_service(web::to(move |req: HttpRequest| {
+                requests.fetch_add(1, Ordering::SeqCst);
+                let document: Option<Value> = documents.lock().unwrap().get(req.path()).cloned();
+                async move {
+                    match document {
+                        Some(document) => HttpResponse::Ok().json(document),
+                        None => HttpResponse::NotFound().finish(),
+                    }
+                }
+            }))
+        })
+        .workers(1)
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+        let port = server.addrs()[0].port();
+        actix_web::rt::spawn(server.run());
+        DidWebServer { port, documents, requests }
+    }
+
+    /// `did:web` of this server, `path` holds the colon separated segments after the domain.
+    fn did(&self, path: &str) -> String {
+        format!("did:web:127.0.0.1%3A{}{}", self.port, path)
+    }
+
+    fn serve(&self, path: &str, document: Value) {
+        self.documents.lock().unwrap().insert(path.to_owned(), document);
+    }
+
+    fn requests(&self) -> usize {
+        self.requests.load(Ordering::SeqCst)
+    }
+}
+
+fn document(did: &str) -> Value {
+    json!({
+        "id": did,
+        "verificationMethod": [{
+            "id": format!("{did}#key-1"),
+            "controller": did,
+            "type": "JsonWebKey",
+            "publicKeyJwk": { "kty": "OKP", "crv": "Ed25519", "x": jwu::encode_b64([7u8; 32]) },
+        }],
+        "assertionMethod": [format!("{did}#key-1")],
+    })
+}
+
+async fn did_resolver_over(web_scheme: &str) -> DidResolver {
+    // did:web documents are fetched with a client of their own, the node is never called
+    let client = Client::builder().finish().await.unwrap();
+    DidResolver::with_did_web_scheme(client, Arc::new(DidCache::new(Duration::from_secs(60), 16)), web_scheme)
+}
+
+async fn did_resolver() -> DidResolver {
+    did_resolver_over("http").await
+}
+
+#[test]
+fn did_web_urls_follow_the_method_specification() {
+    let url = |did: &str| DidResolver::did_web_url(&CoreDID::parse(did).unwrap(), "https");
+    assert_eq!(url("did:web:example.com").unwrap(), "https://example.com/.well-known/did.json");
+    assert_eq!(url("did:web:example.com:users:alice").unwrap(), "https://example.com/users/alice/did.json");
+    assert_eq!(url("did:web:localhost%3A8443").unwrap(), "https://localhost:8443/.well-known/did.json");
+    assert_eq!(url("did:web:localhost%3a8443:alice").unwrap(), "https://localhost:8443/alice/did.json");
+}
+
+#[actix_web::test]
+async fn did_web_documents_are_fetched_and_cached() {
+    let server = DidWebServer::start();
+    let did_resolver = did_resolver().await;
+    let (root_did, alice_did) = (server.did(""), server.did(":users:alice"));
+    server.serve("/.well-known/did.json", document(root_did.as_str()));
+    server.serve("/users/alice/did.json", document(alice_did.as_str()));
+
+    for did in [root_did.as_str(), alice_did.as_str()] {
+        let document = did_resolver.resolve(did).await.unwrap();
+        assert_eq!(document.id().as_str(), did);
+        assert!(document.resolve_method(format!("{did}#key-1").as_str(), None).is_some());
+    }
+    assert_eq!(server.requests(), 2);
+
+    // read from the cache until invalidated
+    did_resolver.resolve(alice_did.as_str()).await.unwrap();
+    assert_eq!(server.requests(), 2);
+    did_resolver.invalidate(alice_did.as_str());
+    did_resolver.resolve(alice_did.as_str()).await.unwrap();
+    assert_eq!(server.requests(), 3);
+}
+
+#[actix_web::test]
+async fn missing_and_foreign_documents_are_refused() {
+    let server = DidWebServer::start();
+    let did_resolver = did_resolver().await;
+    let (alice_did, mallory_did) = (server.did(":alice"), server.did(":mallory"));
+    // mallory serves the document of alice
+    server.serve("/mallory/did.json", document(alice_did.as_str()));
+
+    for did in [alice_did.as_str(), mallory_did.as_str()] {
+        let err = did_resolver.resolve(did).await.unwrap_err();
+        assert!(matches!(&err, TrustServiceError::DidResolutionError(_)), "{did}: {err}");
+    }
+    // failures are not cached
+    server.serve("/alice/did.json", document(alice_did.as_str()));
+    assert!(did_resolver.resolve(alice_did.as_str()).await.is_ok());
+}
+
+#[actix_web::test]
+async fn oversized_documents_are_refused() {
+    let server = DidWebServer::start();
+    let did_resolver = did_resolver().await;
+    let did = server.did(":alice");
+    let mut oversized = document(did.as_str());
+    oversized["padding"] = json!("0".repeat(MAX_DID_WEB_DOCUMENT_LEN));
+    server.serve("/alice/did.json", oversized);
+
+    let err = did_resolver.resolve(did.as_str()).await.unwrap_err();
+    assert!(matches!(&err, TrustServiceError::DidResolutionError(_)), "{err}");
+}
+
+#[actix_web::test]
+async fn private_hosts_are_reached_only_by_the_local_stand_in() {
+    let server = DidWebServer::start();
+    let did = server.did(":alice");
+    server.serve("/alice/did.json", document(did.as_str()));
+
+    // over https the loopback host is refused before any connection
+    let err = did_resolver_over("https").await.resolve(did.as_str()).await.unwrap_err();
+    assert!(matches!(&err, TrustServiceError::DidResolutionError(_)), "{err}");
+    assert_eq!(server.requests(), 0);
+    assert!(did_resolver().await.resolve(did.as_str()).await.is_ok());
+}
+
+#[test]
+fn only_public_addresses_are_fetched() {
+    for ip in ["127.0.0.1", "10.1.2.3", "172.16.0.1", "192.168.1.1", "169.254.169.254", "100.64.0.1", "0.0.0.0", "::1", "fd00::1", "fe80::1", "::ffff:127.0.0.1"] {
+        assert!(!did_resolver::is_public_address(ip.parse::<IpAddr>().unwrap()), "{ip}");
+    }
+    for ip in ["93.184.216.34", "2606:2800:220:1:248:1893:25c8:1946", "::ffff:93.184.216.34"] {
+        assert!(did_resolver::is_public_address(ip.parse::<IpAddr>().unwrap()), "{ip}");
+    }
+}
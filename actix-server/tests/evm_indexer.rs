@@ -0,0 +1,127 @@
+// SPDX-FileCopyrightText: 2024 Fondazione LINKS
+//
+// SPDX-License-Identifier: APACHE-2.0
+
+//! Logs of the AssetFactory and of its NFT contracts applied by the EVM indexer to the
+//! records of `InMemoryRepository`. The logs are built here, no node is reached.
+
+use std::sync::Arc;
+
+use ethers::abi::{encode, Token};
+use ethers::contract::EthEvent;
+use ethers::middleware::SignerMiddleware;
+use ethers::providers::{Http, Provider};
+use ethers::signers::{LocalWallet, Signer};
+use ethers::types::{Address, Log, H256};
+
+use trust_server::contracts::asset::TransferFilter;
+use trust_server::contracts::assetfactory::{AssetContractUpdatedFilter, NftMintedFilter};
+use trust_server::models::user::User;
+use trust_server::services::evm_indexer::EvmIndexer;
+use trust_server::services::evm_networks::{EvmNetwork, EvmNetworks};
+use trust_server::services::memory_repo::InMemoryRepository;
+use trust_server::services::repository::Repository;
+
+const OWNER_DID: &str = "did:iota:tst:0x1111111111111111111111111111111111111111111111111111111111111111";
+const SERVICE_KEY: &str = "ac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80";
+const FACTORY: Address = Address::repeat_byte(0xfa);
+const NFT: Address = Address::repeat_byte(0x0a);
+const HOLDER: Address = Address::repeat_byte(0x42);
+
+/// Indexer of the default network, with the owner DID holding `id-asset-1` notarized by `0x01`.
+async fn indexer() -> (EvmIndexer, Arc<InMemoryRepository>) {
+    std::env::set_var("EVM_INDEXER_ENABLED", "true");
+    let repo = Arc::new(InMemoryRepository::new());
+    repo.store_user(User { did: OWNER_DID.to_owned(), fragment: "key-1".to_owned(), assets: vec![], evm_account: None, tenant_id: None, access_token_hash: None }).await.unwrap();
+    repo.store_proof_relationship(OWNER_DID, "0x01".to_owned(), "id-asset-1".to_owned()).await.unwrap();
+
+    // nothing listens there, the handled logs need no call
+    let provider = Provider::<Http>::try_from("http://127.0.0.1:9").unwrap();
+    let wallet = SERVICE_KEY.parse::<LocalWallet>().unwrap().with_chain_id(31337u64);
+    let network = EvmNetwork::new("default", "", Arc::new(SignerMiddleware::new(provider, wallet)), FACTORY, repo.clone()).unwrap();
+    let evm_networks = EvmNetworks::new(network);
+    let indexer = EvmIndexer::from_env(&evm_networks, evm_networks.default_network(), repo.clone()).unwrap().unwrap();
+    (indexer, repo)
+}
+
+fn log(address: Address, topics: Vec<H256>, data: &[Token]) -> Log {
+    Log { address, topics, data: encode(data).into(), block_number: Some(7u64.into()), ..Default::default() }
+}
+
+fn nft_minted(proof_id: &str) -> Log {
+    log(FACTORY, vec![NftMintedFilter::signature()], &[
+        Token::Address(NFT),
+        Token::Address(Address::repeat_byte(0xaa)),
+        Token::Address(Address::repeat_byte(0x01)),
+        Token::String("nft-asset-1".to_owned()),
+        Token::String("AST".to_owned()),
+        Token::String(proof_id.to_owned()),
+    ])
+}
+
+fn transfer(from: Address, to: Address) -> Log {
+    log(NFT, vec![TransferFilter::signature(), from.into(), to.into(), H256::from_low_u64_be(1)], &[])
+}
+
+#[actix_web::test]
+async fn minted_nfts_are_recovered_and_followed() {
+    let (mut indexer, repo) = indexer().await;
+
+    // unknown contracts are not followed
+    indexer.index_log(&transfer(Address::zero(), HOLDER)).await.unwrap();
+    assert!(repo.get_asset("id-asset-1".to_owned()).await.unwrap().owner_address.is_none());
+
+    indexer.index_log(&nft_minted("0x01")).await.unwrap();
+    let asset = repo.get_asset("id-asset-1".to_owned()).await.unwrap();
+    assert_eq!(asset.nft_addr, Some(format!("{NFT:#x}")));
+    assert_eq!(asset.mint_block, Some(7));
+
+    indexer.index_log(&transfer(Address::zero(), HOLDER)).await.unwrap();
+    let asset = repo.get_asset("id-asset-1".to_owned()).await.unwrap();
+    assert_eq!(asset.token_id.as_deref(), Some("1"));
+    assert_eq!(asset.owner_address, Some(format!("{HOLDER:#x}")));
+    assert!(!asset.burned);
+}
+
+#[actix_web::test]
+async fn transfers_to_the_zero_address_burn_the_nft() {
+    let (mut indexer, repo) = indexer().await;
+    indexer.index_log(&nft_minted("0x01")).await.unwrap();
+    indexer.index_log(&transfer(Address::zero(), HOLDER)).await.unwrap();
+    repo.store_nft_approval("id-asset-1", Some(format!("{:#x}", Address::repeat_byte(0x43)))).await.unwrap();
+
+    indexer.index_log(&transfer(HOLDER, Address::zero())).await.unwrap();
+    let asset = repo.get_asset("id-asset-1".to_owned()).await.unwrap();
+    assert!(asset.burned);
+    assert!(asset.owner_address.is_none());
+    assert!(asset.approved.is_none());
+    // the zero address is never recorded as an owner
+    assert!(repo.get_user_by_evm_address(format!("{:#x}", Address::zero()).as_str()).await.unwrap().is_none());
+}
+
+#[actix_web::test]
+async fn asset_contract_updates_are_recorded() {
+    let (mut indexer, repo) = indexer().await;
+    assert!(repo.get_asset_contract("default").await.unwrap().is_none());
+
+    let implementation = Address::repeat_byte(0xbb);
+    indexer.index_log(&log(FACTORY, vec![AssetContractUpdatedFilter::signature(), implementation.into()], &[])).await.unwrap();
+    assert_eq!(repo.get_asset_contract("default").await.unwrap(), Some(format!("{implementation:#x}")));
+    assert!(repo.get_asset_contract("sepolia").await.unwrap().is_none());
+}
+
+#[actix_web::test]
+async fn nfts_of_other_networks_are_left_alone() {
+    let (mut indexer, repo) = indexer().await;
+    // the same address taken by a contract on another network
+    repo.store_nft_addr("id-asset-1".to_owned(), format!("{NFT:#x}"), "sepolia", Some(3)).await.unwrap();
+    indexer.index_log(&nft_minted("0x01")).await.unwrap();
+
+    indexer.index_log(&transfer(Address::zero(), HOLDER)).await.unwrap();
+    let asset = repo.get_asset("id-asset-1".to_owned()).await.unwrap();
+    assert_eq!(asset.network.as_deref(), Some("sepolia"));
+    assert_eq!(asset.mint_block, Some(3));
+    assert!(asset.owner_address.is_none());
+    assert!(repo.get_asset_by_nft_addr(format!("{NFT:#x}").as_str(), "default", true).await.unwrap().is_none());
+    assert!(repo.get_asset_by_nft_addr(format!("{NFT:#x}").as_str(), "sepolia", false).await.unwrap().is_some());
+}
@@ -0,0 +1,200 @@
+// SPDX-FileCopyrightText: 2024 Fondazione LINKS
+//
+// SPDX-License-Identifier: APACHE-2.0
+
+//! Licenses of the NFTs: the SPDX list shipped in `spdx_licenses.json`, license expressions
+//! and the custom licenses registered in `InMemoryRepository`.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use actix_web::http::{header, StatusCode};
+use actix_web::{test, web, App};
+use iota_sdk::client::Client;
+use serde_json::{json, Value};
+
+use trust_server::controllers::license_controller;
+use trust_server::dtos::LicenseRequest;
+use trust_server::errors::TrustServiceError;
+use trust_server::models::license::LicenseSource;
+use trust_server::models::user::User;
+use trust_server::services::authentication::{self, Authenticator, Caller};
+use trust_server::services::did_cache::DidCache;
+use trust_server::services::did_resolver::DidResolver;
+use trust_server::services::license_registry::LicenseRegistry;
+use trust_server::services::memory_repo::InMemoryRepository;
+use trust_server::services::repository::Repository;
+
+const PUBLISHER_DID: &str = "did:iota:tst:0x1111111111111111111111111111111111111111111111111111111111111111";
+const LICENSE_TEXT: &str = "Acme data license 1.0";
+/// Hex SHA-256 of `LICENSE_TEXT`
+const LICENSE_HASH: &str = "794d990ad3013b9efb5dcba9a5d8ae566e9b3400cc73462fadab8eae95a56701";
+
+fn registry() -> LicenseRegistry {
+    LicenseRegistry::init().unwrap()
+}
+
+fn custom_license(license_id: &str) -> LicenseRequest {
+    LicenseRequest {
+        license_id: license_id.to_owned(),
+        name: "Acme data license".to_owned(),
+        text: Some(LICENSE_TEXT.to_owned()),
+        url: None,
+        content_hash: None,
+    }
+}
+
+#[actix_web::test]
+async fn spdx_list_is_complete() {
+    let registry = registry();
+    let repo = InMemoryRepository::new();
+    for license_id in ["MIT-0", "Apache-1.0", "BSD-3-Clause", "GPL-2.0-only"] {
+        let license = registry.resolve(&repo, license_id).await.unwrap();
+        assert_eq!(license.license_id, license_id);
+        assert_eq!(license.source, LicenseSource::Spdx);
+    }
+    // identifiers are matched ignoring case
+    assert_eq!(registry.resolve(&repo, "mit-0").await.unwrap().license_id, "MIT-0");
+    assert!(registry.list(&repo, Some(LicenseSource::Spdx)).await.unwrap().len() > 600);
+}
+
+#[actix_web::test]
+async fn expressions_are_resolved_and_normalized() {
+    let registry = registry();
+    let repo = InMemoryRepository::new();
+
+    let license = registry.validate(&repo, "mit or apache-2.0").await.unwrap();
+    assert_eq!(license.license_id, "MIT OR Apache-2.0");
+    assert_eq!(license.name, "MIT License OR Apache License 2.0");
+    assert!(license.osi_approved);
+
+    let license = registry.validate(&repo, "(MIT OR Apache-2.0) AND Apache-1.0").await.unwrap();
+    assert_eq!(license.license_id, "(MIT OR Apache-2.0) AND Apache-1.0");
+    // Apache-1.0 is not approved by the OSI
+    assert!(!license.osi_approved);
+
+    let license = registry.validate(&repo, "GPL-2.0-only WITH classpath-exception-2.0").await.unwrap();
+    assert_eq!(license.license_id, "GPL-2.0-only WITH Classpath-exception-2.0");
+    let license = registry.validate(&repo, "Apache-2.0+").await.unwrap();
+    assert_eq!(license.license_id, "Apache-2.0+");
+}
+
+#[actix_web::test]
+async fn unknown_and_malformed_expressions_are_refused() {
+    let registry = registry();
+    let repo = InMemoryRepository::new();
+
+    assert!(matches!(registry.validate(&repo, "MIT OR Acme-1.0").await, Err(TrustServiceError::UnknownLicense(id)) if id == "Acme-1.0"));
+    assert!(matches!(registry.validate(&repo, "MIT WITH Acme-exception").await, Err(TrustServiceError::UnknownLicense(_))));
+    for expression in ["", "MIT OR", "(MIT AND Apache-2.0", "MIT Apache-2.0", "(MIT OR Apache-2.0) WITH Classpath-exception-2.0", "MIT/Apache-2.0"] {
+        assert!(matches!(registry.validate(&repo, expression).await, Err(TrustServiceError::InvalidLicense(_))), "{expression}");
+    }
+    // deprecated identifiers are listed but refused for new NFTs
+    assert!(registry.find(&repo, "GPL-2.0").await.unwrap().unwrap().deprecated);
+    assert!(matches!(registry.validate(&repo, "MIT OR GPL-2.0").await, Err(TrustServiceError::InvalidLicense(_))));
+    assert!(registry.find(&repo, "MIT OR Acme-1.0").await.unwrap().is_none());
+}
+
+#[actix_web::test]
+async fn custom_identifiers_are_validated() {
+    let registry = registry();
+    let repo = InMemoryRepository::new();
+
+    for license_id in ["Acme-1.0", "LicenseRef-", "LicenseRef-acme 1.0", "LicenseRef-acme/1.0", "LicenseRef-acme+"] {
+        let result = registry.register(&repo, &Caller::Admin, custom_license(license_id)).await;
+        assert!(matches!(result, Err(TrustServiceError::InvalidLicense(_))), "{license_id}");
+    }
+    let license = registry.register(&repo, &Caller::Admin, custom_license("LicenseRef-acme-1.0")).await.unwrap();
+    assert_eq!(license.source, LicenseSource::Custom);
+    assert_eq!(license.registered_by, None);
+
+    // custom licenses take part in expressions
+    let license = registry.validate(&repo, "licenseref-acme-1.0 OR MIT").await.unwrap();
+    assert_eq!(license.license_id, "LicenseRef-acme-1.0 OR MIT");
+    assert_eq!(license.source, LicenseSource::Custom);
+}
+
+#[actix_web::test]
+async fn license_text_is_hashed() {
+    let registry = registry();
+    let repo = InMemoryRepository::new();
+
+    let license = registry.register(&repo, &Caller::Admin, custom_license("LicenseRef-acme-1.0")).await.unwrap();
+    assert_eq!(license.content_hash.as_deref(), Some(LICENSE_HASH));
+    assert_eq!(registry.resolve(&repo, "LicenseRef-acme-1.0").await.unwrap().content_hash.as_deref(), Some(LICENSE_HASH));
+
+    let mut request = custom_license("LicenseRef-acme-2.0");
+    request.content_hash = Some(LICENSE_HASH.to_uppercase());
+    registry.register(&repo, &Caller::Admin, request).await.unwrap();
+
+    let mut request = custom_license("LicenseRef-acme-3.0");
+    request.content_hash = Some("00".repeat(32));
+    assert!(matches!(registry.register(&repo, &Caller::Admin, request).await, Err(TrustServiceError::InvalidLicense(_))));
+    assert!(repo.get_license("LicenseRef-acme-3.0").await.unwrap().is_none());
+}
+
+#[actix_web::test]
+async fn license_text_is_fetched_over_https_only() {
+    let registry = registry();
+    let repo = InMemoryRepository::new();
+    for url in ["http://example.com/license.txt", "file:///etc/passwd", "not a url"] {
+        let mut request = custom_license("LicenseRef-acme-1.0");
+        request.text = None;
+        request.url = Some(url.to_owned());
+        let result = registry.register(&repo, &Caller::Admin, request).await;
+        assert!(matches!(result, Err(TrustServiceError::InvalidLicense(_))), "{url}");
+    }
+}
+
+#[actix_web::test]
+async fn licenses_are_registered_once() {
+    let registry = registry();
+    let repo = InMemoryRepository::new();
+    registry.register(&repo, &Caller::Admin, custom_license("LicenseRef-acme-1.0")).await.unwrap();
+
+    for license_id in ["LicenseRef-acme-1.0", "licenseref-ACME-1.0"] {
+        let result = registry.register(&repo, &Caller::Admin, custom_license(license_id)).await;
+        assert!(matches!(result, Err(TrustServiceError::LicenseAlreadyRegistered(_))), "{license_id}");
+    }
+    // the repository refuses duplicates on its own, as the unique index of the db does
+    let mut license = registry.resolve(&repo, "LicenseRef-acme-1.0").await.unwrap();
+    license.license_id = "LICENSEREF-acme-1.0".to_owned();
+    assert!(matches!(repo.store_license(&license).await, Err(TrustServiceError::LicenseAlreadyRegistered(_))));
+}
+
+#[actix_web::test]
+async fn registering_requires_a_registered_caller() {
+    let repo = Arc::new(InMemoryRepository::new());
+    let (token, token_hash) = authentication::new_access_token().unwrap();
+    repo.store_user(User { did: PUBLISHER_DID.to_owned(), fragment: "key-1".to_owned(), assets: vec![], evm_account: None, tenant_id: None, access_token_hash: Some(token_hash) }).await.unwrap();
+    // the callers use access tokens, no DID is resolved
+    let client = Client::builder().finish().await.unwrap();
+    let did_resolver = Arc::new(DidResolver::new(client, Arc::new(DidCache::new(Duration::from_secs(60), 16))));
+    let authenticator = Authenticator::from_env(did_resolver, repo.clone()).unwrap();
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::from(repo.clone() as Arc<dyn Repository>))
+            .app_data(web::Data::new(authenticator))
+            .app_data(web::Data::new(registry()))
+            .service(web::scope("/api").configure(license_controller::scoped_config))
+    ).await;
+    let request = json!({ "licenseId": "LicenseRef-acme-1.0", "name": "Acme data license", "text": LICENSE_TEXT });
+
+    let req = test::TestRequest::post().uri("/api/licenses").set_json(&request).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::UNAUTHORIZED);
+    assert!(repo.get_license("LicenseRef-acme-1.0").await.unwrap().is_none());
+
+    let req = test::TestRequest::post().uri("/api/licenses")
+        .insert_header((header::AUTHORIZATION, format!("Bearer {token}")))
+        .set_json(&request)
+        .to_request();
+    let license: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(license["licenseId"], "LicenseRef-acme-1.0");
+    assert_eq!(license["contentHash"], LICENSE_HASH);
+    assert_eq!(license["registeredBy"], PUBLISHER_DID);
+
+    // listing and reading stay public
+    let req = test::TestRequest::get().uri("/api/licenses/MIT%20OR%20LicenseRef-acme-1.0").to_request();
+    let license: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(license["licenseId"], "MIT OR LicenseRef-acme-1.0");
+}
@@ -0,0 +1,155 @@
+// SPDX-FileCopyrightText: 2024 Fondazione LINKS
+//
+// SPDX-License-Identifier: APACHE-2.0
+
+//! EIP-712 requests signed by a wallet as `eth_signTypedData_v4` does, verified against the
+//! account linked to the DID in `InMemoryRepository`, and the nonces they consume.
+
+use ethers::abi::{encode, Token};
+use ethers::signers::{LocalWallet, Signer};
+use ethers::types::{Address, H256};
+use ethers::utils::keccak256;
+use identity_iota::core::Timestamp;
+
+use trust_server::dtos::NftAuthorization;
+use trust_server::errors::TrustServiceError;
+use trust_server::models::user::{EvmAccount, User};
+use trust_server::services::memory_repo::InMemoryRepository;
+use trust_server::services::nft_authorization::{self, NftRequestData};
+use trust_server::services::repository::Repository;
+
+const OWNER_DID: &str = "did:iota:tst:0x1111111111111111111111111111111111111111111111111111111111111111";
+const CHAIN_ID: u64 = 31337;
+const OWNER_KEY: &str = "4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318";
+const OTHER_KEY: &str = "ac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80";
+
+fn factory_address() -> Address {
+    Address::repeat_byte(0xfa)
+}
+
+fn wallet(key: &str) -> LocalWallet {
+    key.parse::<LocalWallet>().unwrap().with_chain_id(CHAIN_ID)
+}
+
+fn deadline() -> u64 {
+    Timestamp::now_utc().to_unix() as u64 + 600
+}
+
+/// Repository with the owner DID linked to the account of `OWNER_KEY`.
+async fn repo() -> InMemoryRepository {
+    let repo = InMemoryRepository::new();
+    let evm_account = EvmAccount {
+        address: format!("{:#x}", wallet(OWNER_KEY).address()),
+        custodial: false,
+        address_index: None,
+        verification_method: None,
+        signature_nonce: 0,
+        did_proof: None,
+    };
+    repo.store_user(User { did: OWNER_DID.to_owned(), fragment: "key-1".to_owned(), assets: vec![], evm_account: Some(evm_account), tenant_id: None, access_token_hash: None }).await.unwrap();
+    repo
+}
+
+async fn nonce(repo: &InMemoryRepository) -> u64 {
+    repo.get_user(OWNER_DID).await.unwrap().evm_account.unwrap().signature_nonce
+}
+
+/// Signs `request` with the typed data a wallet is given.
+async fn sign(key: &str, request: &NftRequestData<'_>, nonce: u64, deadline: u64) -> NftAuthorization {
+    let domain = nft_authorization::domain(CHAIN_ID, factory_address());
+    let typed_data = nft_authorization::typed_data(&domain, request, nonce, deadline);
+    let signature = wallet(key).sign_typed_data(&typed_data).await.unwrap();
+    NftAuthorization { signature: format!("0x{signature}"), nonce, deadline }
+}
+
+fn transfer_request() -> NftRequestData<'static> {
+    NftRequestData::Transfer { did: OWNER_DID, asset_id: "id-asset-1", to: Address::repeat_byte(0x42) }
+}
+
+#[test]
+fn digest_follows_the_solidity_encoding() {
+    let hash = |value: &str| Token::FixedBytes(keccak256(value.as_bytes()).to_vec());
+    let domain_separator = keccak256(encode(&[
+        hash("EIP712Domain(string name,string version,uint256 chainId,address verifyingContract)"),
+        hash("Trust Service"),
+        hash("1"),
+        Token::Uint(CHAIN_ID.into()),
+        Token::Address(factory_address()),
+    ]));
+    let struct_hash = keccak256(encode(&[
+        hash("TransferRequest(string did,string assetId,address to,uint256 nonce,uint256 deadline)"),
+        hash(OWNER_DID),
+        hash("id-asset-1"),
+        Token::Address(Address::repeat_byte(0x42)),
+        Token::Uint(7u64.into()),
+        Token::Uint(1893456000u64.into()),
+    ]));
+    let expected = H256(keccak256([&[0x19, 0x01], &domain_separator[..], &struct_hash[..]].concat()));
+
+    let domain = nft_authorization::domain(CHAIN_ID, factory_address());
+    assert_eq!(nft_authorization::digest(&domain, &transfer_request(), 7, 1893456000).unwrap(), expected);
+}
+
+#[actix_web::test]
+async fn wallet_signatures_are_verified_and_consume_the_nonce() {
+    let repo = repo().await;
+    let domain = nft_authorization::domain(CHAIN_ID, factory_address());
+    let requests = [
+        NftRequestData::Mint { did: OWNER_DID, asset_id: "id-asset-1", nft_alias: "nft-asset-1", nft_symbol: "AST-1", license: "MIT OR Apache-2.0" },
+        transfer_request(),
+        NftRequestData::Approval { did: OWNER_DID, asset_id: "id-asset-1", operator: Address::repeat_byte(0x43), all: false, approved: true },
+    ];
+    for (nonce, request) in requests.iter().enumerate() {
+        let authorization = sign(OWNER_KEY, request, nonce as u64, deadline()).await;
+        let signed_request = nft_authorization::verify(&repo, &domain, request, &authorization).await.unwrap();
+        assert_eq!(signed_request.signer, format!("{:#x}", wallet(OWNER_KEY).address()));
+        assert_eq!(signed_request.chain_id, CHAIN_ID);
+        assert_eq!(self::nonce(&repo).await, nonce as u64 + 1);
+
+        // the signature is not accepted twice
+        let err = nft_authorization::verify(&repo, &domain, request, &authorization).await.unwrap_err();
+        assert!(matches!(err, TrustServiceError::InvalidSignedRequest(_)), "{err}");
+    }
+}
+
+#[actix_web::test]
+async fn other_signers_and_requests_are_refused() {
+    let repo = repo().await;
+    let domain = nft_authorization::domain(CHAIN_ID, factory_address());
+
+    let authorization = sign(OTHER_KEY, &transfer_request(), 0, deadline()).await;
+    let err = nft_authorization::verify(&repo, &domain, &transfer_request(), &authorization).await.unwrap_err();
+    assert!(matches!(err, TrustServiceError::InvalidSignedRequest(_)), "{err}");
+
+    // a signature over another recipient
+    let authorization = sign(OWNER_KEY, &transfer_request(), 0, deadline()).await;
+    let request = NftRequestData::Transfer { did: OWNER_DID, asset_id: "id-asset-1", to: Address::repeat_byte(0x44) };
+    assert!(nft_authorization::verify(&repo, &domain, &request, &authorization).await.is_err());
+
+    // on another chain
+    let other_domain = nft_authorization::domain(CHAIN_ID + 1, factory_address());
+    assert!(nft_authorization::verify(&repo, &other_domain, &transfer_request(), &authorization).await.is_err());
+
+    let expired = sign(OWNER_KEY, &transfer_request(), 0, deadline() - 1200).await;
+    assert!(nft_authorization::verify(&repo, &domain, &transfer_request(), &expired).await.is_err());
+    assert_eq!(nonce(&repo).await, 0);
+}
+
+#[actix_web::test]
+async fn failed_relays_give_the_nonce_back() {
+    let repo = repo().await;
+    let domain = nft_authorization::domain(CHAIN_ID, factory_address());
+
+    let authorization = sign(OWNER_KEY, &transfer_request(), 0, deadline()).await;
+    let signed_request = nft_authorization::verify(&repo, &domain, &transfer_request(), &authorization).await.unwrap();
+    let failed = async { Err::<(), _>(TrustServiceError::ContractError("execution reverted".to_owned())) };
+    assert!(nft_authorization::relay(&repo, OWNER_DID, Some(&signed_request), failed).await.is_err());
+    assert_eq!(nonce(&repo).await, 0);
+
+    // a transaction that can still be mined keeps the nonce
+    let signed_request = nft_authorization::verify(&repo, &domain, &transfer_request(), &authorization).await.unwrap();
+    let pending = async { Err::<(), _>(TrustServiceError::TransactionTimeout("0x01".to_owned())) };
+    assert!(nft_authorization::relay(&repo, OWNER_DID, Some(&signed_request), pending).await.is_err());
+    assert_eq!(nonce(&repo).await, 1);
+    assert!(nft_authorization::verify(&repo, &domain, &transfer_request(), &authorization).await.is_err());
+}
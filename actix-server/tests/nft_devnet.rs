@@ -0,0 +1,457 @@
+// SPDX-FileCopyrightText: 2024 Fondazione LINKS
+//
+// SPDX-License-Identifier: APACHE-2.0
+
+//! NFT flow against a local devnet: `Asset` and `AssetFactory` are deployed on
+//! [anvil](https://book.getfoundry.sh/anvil/) from the artifacts in `smart-contracts/`.
+//! The `/api/nfts` handlers are called through the actix service, the records are kept by
+//! `InMemoryRepository` and the proofs, signed by a `did:key`, are served in place of the Tangle.
+//!
+//! The tests needing the devnet are ignored by default, run them with
+//! `cargo test -- --ignored` once `anvil` is in the `PATH`.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use actix_web::http::{header, StatusCode};
+use actix_web::{test, web, App, ResponseError};
+use async_trait::async_trait;
+use crypto::signatures::ed25519::SecretKey;
+use ethers::middleware::SignerMiddleware;
+use ethers::providers::{Http, Provider};
+use ethers::signers::{LocalWallet, Signer};
+use ethers::types::{Address, U256};
+use ethers::utils::{Anvil, AnvilInstance};
+use identity_iota::core::{BaseEncoding, Timestamp};
+use identity_iota::verification::jwu;
+use iota_sdk::client::Client;
+use serde_json::{json, Value};
+
+use trust_server::contracts::asset::Asset;
+use trust_server::contracts::assetfactory::AssetFactory;
+use trust_server::controllers::nft_controller;
+use trust_server::dtos::SignedProofRequest;
+use trust_server::errors::TrustServiceError;
+use trust_server::models::asset::Asset as AssetRecord;
+use trust_server::models::tangle_proof::{SignedProofPayload, TangleProof};
+use trust_server::models::user::User;
+use trust_server::services::audit_log::{AuditLog, CheckpointSigner};
+use trust_server::services::authentication::{self, Authenticator};
+use trust_server::services::did_cache::DidCache;
+use trust_server::services::did_resolver::DidResolver;
+use trust_server::services::evm_networks::{EvmNetwork, EvmNetworks};
+use trust_server::services::iota_state::ProofResolver;
+use trust_server::services::license_registry::LicenseRegistry;
+use trust_server::services::nft_metadata::MetadataPublisher;
+use trust_server::services::memory_repo::InMemoryRepository;
+use trust_server::services::nft_service::{self, ServiceSigner};
+use trust_server::services::repository::Repository;
+
+const METADATA_DIGEST: &str = "0x1111111111111111111111111111111111111111111111111111111111111111";
+const DATASET_DIGEST: &str = "0x2222222222222222222222222222222222222222222222222222222222222222";
+
+/// Ed25519 key of a `did:key`, signing compact JWS as the wallets of the users do.
+struct DidKey {
+    secret_key: SecretKey,
+    did: String,
+}
+
+impl DidKey {
+    fn generate() -> Self {
+        let secret_key = SecretKey::generate().unwrap();
+        let mut multicodec_key = vec![0xed, 0x01];
+        multicodec_key.extend_from_slice(&secret_key.public_key().to_bytes());
+        let did = format!("did:key:{}", BaseEncoding::encode_multibase(&multicodec_key, None));
+        DidKey { secret_key, did }
+    }
+
+    /// The method of a `did:key` document is named after the multibase key.
+    fn kid(&self) -> String {
+        format!("{}#{}", self.did, self.did.trim_start_matches("did:key:"))
+    }
+
+    fn jws(&self, payload: &[u8]) -> String {
+        let header = json!({ "alg": "EdDSA", "kid": self.kid() });
+        let signing_input = format!("{}.{}", jwu::encode_b64(header.to_string()), jwu::encode_b64(payload));
+        let signature = self.secret_key.sign(signing_input.as_bytes());
+        format!("{}.{}", signing_input, jwu::encode_b64(signature.to_bytes()))
+    }
+}
+
+#[async_trait]
+impl CheckpointSigner for DidKey {
+    fn did(&self) -> &str {
+        self.did.as_str()
+    }
+
+    async fn sign(&self, payload: &[u8]) -> Result<String, TrustServiceError> {
+        Ok(self.jws(payload))
+    }
+}
+
+/// Proofs published on the Tangle, by proof id.
+#[derive(Default)]
+struct StaticProofs {
+    proofs: Mutex<HashMap<String, TangleProof>>,
+}
+
+#[async_trait]
+impl ProofResolver for StaticProofs {
+    async fn resolve_proof(&self, proof_id: String) -> Result<TangleProof, TrustServiceError> {
+        self.proofs.lock().unwrap().get(&proof_id).cloned()
+            .ok_or(TrustServiceError::CustomError(format!("proof {proof_id} not found")))
+    }
+}
+
+struct Devnet {
+    // the node is killed when dropped
+    _anvil: AnvilInstance,
+    signer: Arc<ServiceSigner>,
+    factory: AssetFactory<ServiceSigner>,
+    /// Owner of the notarized assets
+    publisher: DidKey,
+    /// Access token of the publisher
+    publisher_token: String,
+    authenticator: web::Data<Authenticator>,
+    repo: Arc<InMemoryRepository>,
+    proofs: Arc<StaticProofs>,
+    did_resolver: web::Data<DidResolver>,
+    evm_networks: web::Data<EvmNetworks>,
+    license_registry: web::Data<LicenseRegistry>,
+    audit_log: web::Data<AuditLog>,
+    log_path: PathBuf,
+}
+
+impl Devnet {
+
+    /// Starts anvil, deploys the `Asset` implementation and the factory and registers the publisher.
+    async fn start() -> Self {
+        let publisher = DidKey::generate();
+        let log_path = std::env::temp_dir().join(format!("nft-devnet-{}.log", publisher.did.trim_start_matches("did:key:")));
+        // read when the log is published to IPFS, which is not reachable here
+        std::env::set_var("LOG_FILE_NAME", &log_path);
+
+        let anvil = Anvil::new().spawn();
+        let provider = Provider::<Http>::try_from(anvil.endpoint()).unwrap().interval(Duration::from_millis(10));
+        let wallet: LocalWallet = anvil.keys()[0].clone().into();
+        let signer = Arc::new(SignerMiddleware::new(provider, wallet.with_chain_id(anvil.chain_id())));
+
+        let asset_implementation = Asset::deploy(signer.clone(), ()).unwrap().send().await.unwrap();
+        let factory = AssetFactory::deploy(signer.clone(), asset_implementation.address()).unwrap().send().await.unwrap();
+
+        let repo = Arc::new(InMemoryRepository::new());
+        let (publisher_token, token_hash) = authentication::new_access_token().unwrap();
+        repo.store_user(User { access_token_hash: Some(token_hash), ..User::self_custodied(publisher.did.as_str()) }).await.unwrap();
+
+        // did:key documents are expanded locally, the client reaches no node
+        let client = Client::builder().finish().await.unwrap();
+        let did_resolver = web::Data::new(DidResolver::new(client, Arc::new(DidCache::new(Duration::from_secs(60), 16))));
+        let network = EvmNetwork::new("default", "", signer.clone(), factory.address(), repo.clone()).unwrap();
+        let audit_log = AuditLog::open(
+            log_path.clone(),
+            100,
+            Arc::new(DidKey::generate()),
+            did_resolver.clone().into_inner(),
+            repo.clone(),
+        ).await.unwrap();
+        let authenticator = Authenticator::from_env(did_resolver.clone().into_inner(), repo.clone()).unwrap();
+
+        Devnet {
+            _anvil: anvil,
+            signer,
+            factory,
+            publisher,
+            publisher_token,
+            authenticator: web::Data::new(authenticator),
+            repo,
+            proofs: Arc::new(StaticProofs::default()),
+            did_resolver,
+            evm_networks: web::Data::new(EvmNetworks::new(network)),
+            license_registry: web::Data::new(LicenseRegistry::init().unwrap()),
+            audit_log: web::Data::new(audit_log),
+            log_path,
+        }
+    }
+
+    /// Notarizes an asset of the publisher, as `create_proof` does once the proof is on the Tangle.
+    async fn store_asset(&self, asset_id: &str, proof_id: &str) {
+        let mut request = SignedProofRequest {
+            asset_id: asset_id.to_owned(),
+            asset_hash: DATASET_DIGEST.to_owned(),
+            metadata_hash: METADATA_DIGEST.to_owned(),
+            did: self.publisher.did.clone(),
+            jws: String::new(),
+            organisation: None,
+            supersedes: None,
+        };
+        let payload = SignedProofPayload::for_request(&request, Timestamp::now_utc().to_unix());
+        request.jws = self.publisher.jws(&serde_json::to_vec(&payload).unwrap());
+        let document = self.did_resolver.resolve(self.publisher.did.as_str()).await.unwrap();
+        let proof = TangleProof::from_signed_jws(&request, &document).unwrap();
+        self.proofs.proofs.lock().unwrap().insert(proof_id.to_owned(), proof);
+        self.repo.store_proof_relationship(self.publisher.did.as_str(), proof_id.to_owned(), asset_id.to_owned()).await.unwrap();
+    }
+
+    fn configure(&self, cfg: &mut web::ServiceConfig) {
+        cfg.app_data(web::Data::from(self.repo.clone() as Arc<dyn Repository>))
+            .app_data(web::Data::from(self.proofs.clone() as Arc<dyn ProofResolver>))
+            .app_data(self.did_resolver.clone())
+            .app_data(self.evm_networks.clone())
+            .app_data(self.license_registry.clone())
+            .app_data(web::Data::new(MetadataPublisher::new("http://explorer.local")))
+            .app_data(self.audit_log.clone())
+            .app_data(self.authenticator.clone())
+            .service(web::scope("/api").configure(nft_controller::scoped_config));
+    }
+
+    /// Mint request authenticated as the publisher.
+    fn mint(&self, body: Value) -> test::TestRequest {
+        test::TestRequest::post().uri("/api/nfts")
+            .insert_header((header::AUTHORIZATION, format!("Bearer {}", self.publisher_token)))
+            .set_json(body)
+    }
+
+    fn mint_request(&self, asset_id: &str, license: &str) -> Value {
+        json!({
+            "assetId": asset_id,
+            "nftAlias": format!("nft-{asset_id}"),
+            "nftSymbol": "AST",
+            "license": license,
+            "did": self.publisher.did,
+        })
+    }
+
+    async fn asset(&self, asset_id: &str) -> AssetRecord {
+        self.repo.get_asset(asset_id.to_owned()).await.unwrap()
+    }
+
+    fn asset_sc(&self, nft_address: Address) -> Asset<ServiceSigner> {
+        Asset::new(nft_address, self.signer.clone())
+    }
+}
+
+impl Drop for Devnet {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.log_path);
+    }
+}
+
+fn asset_record(asset_id: &str, proof_id: &str) -> AssetRecord {
+    AssetRecord {
+        asset_id: asset_id.to_owned(),
+        proof_id: proof_id.to_owned(),
+        nft_addr: None,
+        token_id: None,
+        owner_address: None,
+        approved: None,
+        operators: vec![],
+        metadata_uri: None,
+        network: None,
+        mint_block: None,
+        minting: None,
+        burned: false,
+        signed_requests: vec![],
+        revocation: None,
+    }
+}
+
+#[actix_web::test]
+#[ignore = "needs anvil in the PATH"]
+async fn minting_registers_the_nft_and_records_it() {
+    let devnet = Devnet::start().await;
+    devnet.store_asset("id-asset-1", "0x01").await;
+    let app = test::init_service(App::new().configure(|cfg| devnet.configure(cfg))).await;
+
+    let req = devnet.mint(devnet.mint_request("id-asset-1", "MIT")).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+    let minted: Value = test::read_body_json(resp).await;
+
+    let asset = devnet.asset("id-asset-1").await;
+    let nft_address = nft_service::nft_address(&asset).unwrap();
+    assert_eq!(minted["nftAddress"], json!(asset.nft_addr));
+    assert_eq!(minted["integrity"]["consistent"], true, "{}", minted["integrity"]);
+    assert!(asset.mint_block.is_some());
+    assert_eq!(asset.network.as_deref(), Some("default"));
+    assert_eq!(devnet.factory.get_count().call().await.unwrap(), U256::one());
+    assert_eq!(devnet.factory.get_assets().call().await.unwrap(), vec![nft_address]);
+
+    // the publisher has no EVM account, the token stays with the service
+    let token_id = nft_service::recorded_token_id(&asset).unwrap().expect("token id recorded at mint");
+    assert_eq!(devnet.asset_sc(nft_address).owner_of(token_id).call().await.unwrap(), devnet.signer.address());
+    assert_eq!(asset.owner_address, Some(format!("{:#x}", devnet.signer.address())));
+}
+
+#[actix_web::test]
+#[ignore = "needs anvil in the PATH"]
+async fn minted_nft_is_read_back_by_asset_id() {
+    let devnet = Devnet::start().await;
+    devnet.store_asset("id-asset-1", "0x01").await;
+    devnet.store_asset("id-asset-2", "0x02").await;
+    let app = test::init_service(App::new().configure(|cfg| devnet.configure(cfg))).await;
+
+    for (asset_id, license) in [("id-asset-1", "CC-BY-4.0"), ("id-asset-2", "MIT")] {
+        let req = devnet.mint(devnet.mint_request(asset_id, license)).to_request();
+        assert!(test::call_service(&app, req).await.status().is_success());
+    }
+
+    let req = test::TestRequest::get().uri("/api/nfts?assetId=id-asset-2").to_request();
+    let nft: Value = test::call_and_read_body_json(&app, req).await;
+    let asset = devnet.asset("id-asset-2").await;
+    assert_eq!(nft["assetId"], "id-asset-2");
+    assert_eq!(nft["nftAddress"], json!(asset.nft_addr));
+    assert_eq!(nft["license"], "MIT");
+    assert_eq!(nft["did"], devnet.publisher.did);
+    assert_eq!(nft["network"], "default");
+    assert_eq!(nft["stale"], false);
+    assert_ne!(devnet.asset("id-asset-1").await.nft_addr, asset.nft_addr);
+}
+
+#[actix_web::test]
+#[ignore = "needs anvil in the PATH"]
+async fn unreadable_nfts_do_not_fail_the_page() {
+    let devnet = Devnet::start().await;
+    devnet.store_asset("id-asset-1", "0x01").await;
+    devnet.store_asset("id-asset-2", "0x02").await;
+    let app = test::init_service(App::new().configure(|cfg| devnet.configure(cfg))).await;
+    let req = devnet.mint(devnet.mint_request("id-asset-1", "MIT")).to_request();
+    assert!(test::call_service(&app, req).await.status().is_success());
+    // a record pointing to an address without contract
+    let missing = format!("{:#x}", Address::repeat_byte(0x42));
+    devnet.repo.store_nft_addr("id-asset-2".to_owned(), missing.clone(), "default", None).await.unwrap();
+
+    let req = test::TestRequest::get().uri(&format!("/api/nft-contracts?did={}", devnet.publisher.did)).to_request();
+    let page: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(page["total"], 2);
+    assert_eq!(page["items"].as_array().unwrap().len(), 1);
+    assert_eq!(page["items"][0]["assetId"], "id-asset-1");
+    assert_eq!(page["items"][0]["tokenId"], json!(devnet.asset("id-asset-1").await.token_id));
+    assert_eq!(page["errors"][0]["nftAddress"], missing);
+}
+
+#[actix_web::test]
+#[ignore = "needs anvil in the PATH"]
+async fn asset_is_minted_once() {
+    let devnet = Devnet::start().await;
+    devnet.store_asset("id-asset-1", "0x01").await;
+    let app = test::init_service(App::new().configure(|cfg| devnet.configure(cfg))).await;
+
+    let req = devnet.mint(devnet.mint_request("id-asset-1", "MIT")).to_request();
+    assert!(test::call_service(&app, req).await.status().is_success());
+    let req = devnet.mint(devnet.mint_request("id-asset-1", "MIT")).to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), TrustServiceError::NftAlreadyMinted(String::new()).status_code());
+    assert_eq!(devnet.factory.get_count().call().await.unwrap(), U256::one());
+}
+
+#[actix_web::test]
+#[ignore = "needs anvil in the PATH"]
+async fn asset_being_minted_is_not_minted_again() {
+    let devnet = Devnet::start().await;
+    devnet.store_asset("id-asset-1", "0x01").await;
+    let app = test::init_service(App::new().configure(|cfg| devnet.configure(cfg))).await;
+
+    // a mint already started holds the reservation
+    assert!(devnet.repo.reserve_nft_mint("id-asset-1", Timestamp::now_utc().to_rfc3339()).await.unwrap());
+    let req = devnet.mint(devnet.mint_request("id-asset-1", "MIT")).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), TrustServiceError::NftMintInProgress(String::new()).status_code());
+    assert_eq!(devnet.factory.get_count().call().await.unwrap(), U256::zero());
+
+    devnet.repo.release_nft_mint("id-asset-1").await.unwrap();
+    let req = devnet.mint(devnet.mint_request("id-asset-1", "MIT")).to_request();
+    assert!(test::call_service(&app, req).await.status().is_success());
+    assert_eq!(devnet.factory.get_count().call().await.unwrap(), U256::one());
+}
+
+#[actix_web::test]
+#[ignore = "needs anvil in the PATH"]
+async fn asset_of_another_did_is_not_minted() {
+    let devnet = Devnet::start().await;
+    devnet.store_asset("id-asset-1", "0x01").await;
+    let app = test::init_service(App::new().configure(|cfg| devnet.configure(cfg))).await;
+
+    let mut mint_request = devnet.mint_request("id-asset-1", "MIT");
+    mint_request["did"] = json!(DidKey::generate().did);
+    let req = devnet.mint(mint_request).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+
+    // the DID in the body is not enough
+    let req = test::TestRequest::post().uri("/api/nfts").set_json(devnet.mint_request("id-asset-1", "MIT")).to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+    assert_eq!(devnet.factory.get_count().call().await.unwrap(), U256::zero());
+    assert!(devnet.asset("id-asset-1").await.nft_addr.is_none());
+}
+
+#[actix_web::test]
+#[ignore = "needs anvil in the PATH"]
+async fn metadata_is_refreshed_by_the_owner_only() {
+    let devnet = Devnet::start().await;
+    devnet.store_asset("id-asset-1", "0x01").await;
+    let app = test::init_service(App::new().configure(|cfg| devnet.configure(cfg))).await;
+    let req = devnet.mint(devnet.mint_request("id-asset-1", "MIT")).to_request();
+    assert!(test::call_service(&app, req).await.status().is_success());
+
+    // the DID in the body is not enough
+    let body = json!({ "did": devnet.publisher.did });
+    let req = test::TestRequest::post().uri("/api/nfts/id-asset-1/metadata").set_json(&body).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::UNAUTHORIZED);
+
+    let req = test::TestRequest::post().uri("/api/nfts/id-asset-1/metadata")
+        .insert_header((header::AUTHORIZATION, format!("Bearer {}", devnet.publisher_token)))
+        .set_json(json!({ "did": DidKey::generate().did }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::FORBIDDEN);
+}
+
+#[actix_web::test]
+#[ignore = "needs anvil in the PATH"]
+async fn reading_an_asset_without_nft_fails() {
+    let devnet = Devnet::start().await;
+    devnet.store_asset("id-asset-1", "0x01").await;
+    let app = test::init_service(App::new().configure(|cfg| devnet.configure(cfg))).await;
+
+    let req = test::TestRequest::get().uri("/api/nfts?assetId=id-asset-1").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+    let req = test::TestRequest::get().uri("/api/nfts?assetId=id-asset-unknown").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), TrustServiceError::AssetIdNotFound(String::new()).status_code());
+}
+
+#[actix_web::test]
+#[ignore = "needs anvil in the PATH"]
+async fn reading_an_address_without_contract_fails() {
+    let devnet = Devnet::start().await;
+
+    let err = nft_service::read_nft_data(&devnet.asset_sc(Address::repeat_byte(0x42))).await.err().unwrap();
+    assert!(matches!(err, TrustServiceError::ContractError(_)), "{err:?}");
+}
+
+#[test]
+fn asset_without_nft_has_no_address() {
+    let err = nft_service::nft_address(&asset_record("id-asset-1", "0x01")).err().unwrap();
+    assert!(matches!(err, TrustServiceError::MissingNftAddress), "{err:?}");
+    assert_eq!(err.status_code(), StatusCode::BAD_REQUEST);
+}
+
+#[test]
+fn malformed_nft_address_is_refused() {
+    let mut asset = asset_record("id-asset-1", "0x01");
+    asset.nft_addr = Some("not an address".to_owned());
+    let err = nft_service::nft_address(&asset).err().unwrap();
+    assert!(matches!(err, TrustServiceError::ContractAddressRecoveryError), "{err:?}");
+}
+
+#[test]
+fn receipt_without_mint_event_is_refused() {
+    let err = nft_service::decode_mint_logs(&[]).err().unwrap();
+    assert!(matches!(err, TrustServiceError::CustomError(_)), "{err:?}");
+}
@@ -0,0 +1,85 @@
+// SPDX-FileCopyrightText: 2024 Fondazione LINKS
+//
+// SPDX-License-Identifier: APACHE-2.0
+
+//! Members of the organisations, removed only by the organisation DID or the administrator.
+//! The callers authenticate with access tokens, no DID is resolved.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use actix_web::http::{header, StatusCode};
+use actix_web::{test, web, App};
+use identity_iota::core::Timestamp;
+use iota_sdk::client::Client;
+
+use trust_server::controllers::organisation_controller;
+use trust_server::models::organisation::{Membership, Organisation};
+use trust_server::models::user::User;
+use trust_server::services::authentication::{self, Authenticator};
+use trust_server::services::did_cache::DidCache;
+use trust_server::services::did_resolver::DidResolver;
+use trust_server::services::memory_repo::InMemoryRepository;
+use trust_server::services::repository::Repository;
+
+const ORGANISATION_DID: &str = "did:iota:tst:0x1111111111111111111111111111111111111111111111111111111111111111";
+const MEMBER_DID: &str = "did:iota:tst:0x2222222222222222222222222222222222222222222222222222222222222222";
+const ADMIN_API_KEY: &str = "organisations-admin-key";
+
+/// Access token of a DID registered in `repo`.
+async fn register(repo: &InMemoryRepository, did: &str) -> String {
+    let (token, token_hash) = authentication::new_access_token().unwrap();
+    repo.store_user(User { did: did.to_owned(), fragment: "key-1".to_owned(), assets: vec![], evm_account: None, tenant_id: None, access_token_hash: Some(token_hash) }).await.unwrap();
+    format!("Bearer {token}")
+}
+
+async fn add_organisation(repo: &InMemoryRepository) {
+    let membership = Membership {
+        member_did: MEMBER_DID.to_owned(),
+        credential_id: "urn:uuid:7b7a4e0c-3c1f-4f4e-9a57-2f0d0d8f6b11".to_owned(),
+        delegation: String::new(),
+        valid_from: Timestamp::now_utc().to_rfc3339(),
+        valid_until: None,
+        revoked_at: None,
+    };
+    let organisation = Organisation { did: ORGANISATION_DID.to_owned(), fragment: "key-1".to_owned(), name: "Acme".to_owned(), members: vec![membership] };
+    repo.store_organisation(&organisation).await.unwrap();
+}
+
+async fn authenticator(repo: Arc<InMemoryRepository>) -> web::Data<Authenticator> {
+    let client = Client::builder().finish().await.unwrap();
+    let did_resolver = Arc::new(DidResolver::new(client, Arc::new(DidCache::new(Duration::from_secs(60), 16))));
+    web::Data::new(Authenticator::from_env(did_resolver, repo).unwrap())
+}
+
+#[actix_web::test]
+async fn members_are_removed_by_the_organisation_or_the_administrator() {
+    // the only test reading the administrator key
+    std::env::set_var("ADMIN_API_KEY", ADMIN_API_KEY);
+    let repo = Arc::new(InMemoryRepository::new());
+    let organisation_token = register(&repo, ORGANISATION_DID).await;
+    let member_token = register(&repo, MEMBER_DID).await;
+    add_organisation(&repo).await;
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::from(repo.clone() as Arc<dyn Repository>))
+            .app_data(authenticator(repo.clone()).await)
+            .service(web::scope("/api").configure(organisation_controller::scoped_config))
+    ).await;
+    let uri = format!("/api/organisations/{ORGANISATION_DID}/members/{MEMBER_DID}");
+
+    let req = test::TestRequest::delete().uri(uri.as_str()).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::UNAUTHORIZED);
+    // a member cannot end its own delegation, nor anyone else's
+    let req = test::TestRequest::delete().uri(uri.as_str()).insert_header((header::AUTHORIZATION, member_token)).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::FORBIDDEN);
+    assert!(repo.get_organisation(ORGANISATION_DID).await.unwrap().members[0].revoked_at.is_none());
+
+    let req = test::TestRequest::delete().uri(uri.as_str()).insert_header((header::AUTHORIZATION, organisation_token)).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+    assert!(repo.get_organisation(ORGANISATION_DID).await.unwrap().members[0].revoked_at.is_some());
+
+    // the administrator acts for any organisation, the membership is already ended
+    let req = test::TestRequest::delete().uri(uri.as_str()).insert_header((header::AUTHORIZATION, format!("Bearer {ADMIN_API_KEY}"))).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::FORBIDDEN);
+}
@@ -0,0 +1,114 @@
+// SPDX-FileCopyrightText: 2024 Fondazione LINKS
+//
+// SPDX-License-Identifier: APACHE-2.0
+
+//! `Pkcs11Storage`, and the tenant key storage routed to it, against a
+//! [SoftHSM](https://github.com/opendnssec/SoftHSMv2) token created for the test run in a
+//! temporary directory. SoftHSM is initialized once per process, the tests share the token.
+//!
+//! The tests are ignored by default, run them with `cargo test -- --ignored` once SoftHSM 2.6
+//! or later is installed. `SOFTHSM2_MODULE` overrides the path of the module.
+
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::{Arc, OnceLock};
+
+use crypto::signatures::ed25519::{PublicKey, Signature};
+use futures_util::future::join_all;
+use identity_iota::storage::{JwkStorage, KeyType};
+use identity_iota::verification::jwk::Jwk;
+use identity_iota::verification::jws::JwsAlgorithm;
+use identity_iota::verification::jwu;
+
+use trust_server::services::key_store::ServiceJwkStorage;
+use trust_server::services::pkcs11_storage::Pkcs11Storage;
+use trust_server::services::tenant_storage::TenantKeyStorage;
+
+const DEFAULT_MODULE: &str = "/usr/lib/softhsm/libsofthsm2.so";
+const TOKEN_LABEL: &str = "trust-service-test";
+const USER_PIN: &str = "1234";
+
+static TOKEN: OnceLock<Arc<Pkcs11Storage>> = OnceLock::new();
+
+fn test_dir() -> PathBuf {
+    std::env::temp_dir().join(format!("trust-service-softhsm-{}", std::process::id()))
+}
+
+/// Session on a new SoftHSM token, whose objects are kept in a temporary directory.
+fn token() -> Arc<Pkcs11Storage> {
+    TOKEN.get_or_init(|| {
+        let token_dir = test_dir().join("tokens");
+        std::fs::create_dir_all(&token_dir).unwrap();
+        let config_path = test_dir().join("softhsm2.conf");
+        std::fs::write(&config_path, format!("directories.tokendir = {}\nobjectstore.backend = file\n", token_dir.display())).unwrap();
+        std::env::set_var("SOFTHSM2_CONF", &config_path);
+
+        let status = Command::new("softhsm2-util")
+            .args(["--init-token", "--free", "--label", TOKEN_LABEL, "--so-pin", "5678", "--pin", USER_PIN])
+            .status()
+            .expect("softhsm2-util in the PATH");
+        assert!(status.success());
+
+        let module_path = std::env::var("SOFTHSM2_MODULE").unwrap_or_else(|_| DEFAULT_MODULE.to_owned());
+        Arc::new(Pkcs11Storage::new(&module_path, TOKEN_LABEL, USER_PIN.to_owned()).unwrap())
+    }).clone()
+}
+
+fn ed25519() -> KeyType {
+    KeyType::new("Ed25519")
+}
+
+fn verifies(jwk: &Jwk, data: &[u8], signature: &[u8]) -> bool {
+    let x = jwu::decode_b64(&jwk.try_okp_params().unwrap().x).unwrap();
+    let public_key = PublicKey::try_from_bytes(x.try_into().unwrap()).unwrap();
+    public_key.verify(&Signature::from_bytes(signature.try_into().unwrap()), data)
+}
+
+#[actix_web::test]
+#[ignore = "needs SoftHSM"]
+async fn keys_are_generated_and_used_inside_the_token() {
+    let token = token();
+    let generated = token.generate(ed25519(), JwsAlgorithm::EdDSA).await.unwrap();
+    assert!(generated.jwk.is_public());
+    assert!(token.exists(&generated.key_id).await.unwrap());
+
+    let signature = token.sign(&generated.key_id, b"payload", &generated.jwk).await.unwrap();
+    assert!(verifies(&generated.jwk, b"payload", &signature));
+
+    // private keys are never imported
+    assert!(token.insert(generated.jwk.clone()).await.is_err());
+
+    token.delete(&generated.key_id).await.unwrap();
+    assert!(!token.exists(&generated.key_id).await.unwrap());
+    assert!(token.sign(&generated.key_id, b"payload", &generated.jwk).await.is_err());
+}
+
+#[actix_web::test]
+#[ignore = "needs SoftHSM"]
+async fn concurrent_signatures_share_the_session() {
+    let token = token();
+    let generated = token.generate(ed25519(), JwsAlgorithm::EdDSA).await.unwrap();
+
+    let payloads: Vec<Vec<u8>> = (0..8u8).map(|index| vec![index; 32]).collect();
+    let signatures = join_all(payloads.iter().map(|payload| token.sign(&generated.key_id, payload, &generated.jwk))).await;
+    for (payload, signature) in payloads.iter().zip(signatures) {
+        assert!(verifies(&generated.jwk, payload, &signature.unwrap()));
+    }
+}
+
+#[actix_web::test]
+#[ignore = "needs SoftHSM"]
+async fn tenant_keys_go_to_the_configured_token() {
+    let token = token();
+    std::env::set_var("TENANT_STORAGE_DIR", test_dir().join("tenants"));
+    std::env::set_var("TENANT_STORAGE_MASTER_PASSWORD", "test master password");
+    let tenant_storage = TenantKeyStorage::from_env();
+
+    let key_storage = tenant_storage.key_storage("acme", &ServiceJwkStorage::Pkcs11(token.clone())).await.unwrap();
+    let generated = key_storage.key_storage().generate(ed25519(), JwsAlgorithm::EdDSA).await.unwrap();
+    assert!(token.exists(&generated.key_id).await.unwrap());
+
+    // the Stronghold of the tenant holds no private key
+    let stronghold_storage = tenant_storage.stronghold_storage("acme").await.unwrap();
+    assert!(!stronghold_storage.exists(&generated.key_id).await.unwrap());
+}
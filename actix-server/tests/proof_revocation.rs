@@ -0,0 +1,222 @@
+// SPDX-FileCopyrightText: 2024 Fondazione LINKS
+//
+// SPDX-License-Identifier: APACHE-2.0
+
+//! Revocation and supersession of the proofs, kept by `InMemoryRepository`.
+//! The proofs are not published: their relationships are stored as `create_proof` does
+//! once the proof is on the Tangle.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use actix_web::http::{header, StatusCode};
+use actix_web::{test, web, App};
+use iota_sdk::client::Client;
+use serde_json::{json, Value};
+
+use trust_server::controllers::proof_controller;
+use trust_server::errors::TrustServiceError;
+use trust_server::models::proof_revocation::{NftAction, ProofState};
+use trust_server::models::user::User;
+use trust_server::services::authentication::{self, Authenticator};
+use trust_server::services::did_cache::DidCache;
+use trust_server::services::did_resolver::DidResolver;
+use trust_server::services::memory_repo::InMemoryRepository;
+use trust_server::services::proof_revocation;
+use trust_server::services::repository::Repository;
+
+const PUBLISHER_DID: &str = "did:iota:tst:0x1111111111111111111111111111111111111111111111111111111111111111";
+const OTHER_DID: &str = "did:iota:tst:0x2222222222222222222222222222222222222222222222222222222222222222";
+
+struct Publishers {
+    repo: Arc<InMemoryRepository>,
+    authenticator: web::Data<Authenticator>,
+    /// Access tokens by DID
+    tokens: Vec<(String, String)>,
+}
+
+impl Publishers {
+
+    /// Registers the publisher and another DID, each with an access token.
+    async fn new() -> Self {
+        let repo = Arc::new(InMemoryRepository::new());
+        let mut tokens = vec![];
+        for did in [PUBLISHER_DID, OTHER_DID] {
+            let (token, token_hash) = authentication::new_access_token().unwrap();
+            repo.store_user(User { did: did.to_owned(), fragment: "key-1".to_owned(), assets: vec![], evm_account: None, tenant_id: None, access_token_hash: Some(token_hash) }).await.unwrap();
+            tokens.push((did.to_owned(), token));
+        }
+        // the callers use access tokens, no DID is resolved
+        let client = Client::builder().finish().await.unwrap();
+        let did_resolver = Arc::new(DidResolver::new(client, Arc::new(DidCache::new(Duration::from_secs(60), 16))));
+        let authenticator = Authenticator::from_env(did_resolver, repo.clone()).unwrap();
+        Publishers { repo, authenticator: web::Data::new(authenticator), tokens }
+    }
+
+    fn token(&self, did: &str) -> String {
+        self.tokens.iter().find(|(token_did, _)| token_did == did).map(|(_, token)| format!("Bearer {token}")).unwrap()
+    }
+
+    /// Notarizes `asset_id` with `proof_id` on behalf of the publisher.
+    async fn notarize(&self, asset_id: &str, proof_id: &str) {
+        self.repo.store_proof_relationship(PUBLISHER_DID, proof_id.to_owned(), asset_id.to_owned()).await.unwrap();
+    }
+
+    /// Supersedes `proof_id` with `new_proof_id`, notarizing a new version of the dataset.
+    async fn supersede(&self, proof_id: &str, asset_id: &str, new_proof_id: &str) -> Result<(), TrustServiceError> {
+        let superseded = proof_revocation::revocable_asset(self.repo.as_ref(), proof_id, PUBLISHER_DID).await?;
+        self.notarize(asset_id, new_proof_id).await;
+        proof_revocation::revoke(self.repo.as_ref(), &superseded, None, Some(new_proof_id.to_owned())).await?;
+        Ok(())
+    }
+
+    async fn current_proof(&self, asset_id: &str) -> Option<String> {
+        let asset = self.repo.get_asset(asset_id.to_owned()).await.unwrap();
+        proof_revocation::current_proof(self.repo.as_ref(), &asset).await.unwrap()
+    }
+
+    fn configure(&self, cfg: &mut web::ServiceConfig) {
+        cfg.app_data(web::Data::from(self.repo.clone() as Arc<dyn Repository>))
+            .app_data(self.authenticator.clone())
+            .service(web::scope("/api").configure(proof_controller::scoped_config));
+    }
+}
+
+fn revocation_request(did: &str) -> Value {
+    json!({ "did": did, "reason": "wrong dataset" })
+}
+
+#[actix_web::test]
+async fn revoking_records_the_revocation_of_the_proof() {
+    let publishers = Publishers::new().await;
+    publishers.notarize("id-asset-1", "0x01").await;
+    let app = test::init_service(App::new().configure(|cfg| publishers.configure(cfg))).await;
+
+    let req = test::TestRequest::post().uri("/api/proofs/0x01/revoke")
+        .insert_header((header::AUTHORIZATION, publishers.token(PUBLISHER_DID)))
+        .set_json(revocation_request(PUBLISHER_DID))
+        .to_request();
+    let revocation: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(revocation["state"], "revoked");
+    assert_eq!(revocation["reason"], "wrong dataset");
+    assert_eq!(revocation["supersededBy"], Value::Null);
+    // no NFT was minted for the asset
+    assert_eq!(revocation["nftAction"], Value::Null);
+
+    let asset = publishers.repo.get_asset_by_proof("0x01".to_owned()).await.unwrap();
+    let stored = asset.revocation.expect("revocation stored");
+    assert_eq!(stored.state, ProofState::Revoked);
+    assert_eq!(stored.reason.as_deref(), Some("wrong dataset"));
+    assert_eq!(publishers.current_proof("id-asset-1").await, None);
+}
+
+#[actix_web::test]
+async fn proof_is_revoked_only_by_the_owner_of_the_asset() {
+    let publishers = Publishers::new().await;
+    publishers.notarize("id-asset-1", "0x01").await;
+    let app = test::init_service(App::new().configure(|cfg| publishers.configure(cfg))).await;
+
+    let req = test::TestRequest::post().uri("/api/proofs/0x01/revoke")
+        .set_json(revocation_request(PUBLISHER_DID))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::UNAUTHORIZED);
+
+    // authenticated as another DID
+    let req = test::TestRequest::post().uri("/api/proofs/0x01/revoke")
+        .insert_header((header::AUTHORIZATION, publishers.token(OTHER_DID)))
+        .set_json(revocation_request(PUBLISHER_DID))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::FORBIDDEN);
+
+    // authenticated as the DID it claims, which does not own the asset
+    let req = test::TestRequest::post().uri("/api/proofs/0x01/revoke")
+        .insert_header((header::AUTHORIZATION, publishers.token(OTHER_DID)))
+        .set_json(revocation_request(OTHER_DID))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::FORBIDDEN);
+
+    let asset = publishers.repo.get_asset("id-asset-1".to_owned()).await.unwrap();
+    assert!(asset.revocation.is_none());
+}
+
+#[actix_web::test]
+async fn proof_is_revoked_once() {
+    let publishers = Publishers::new().await;
+    publishers.notarize("id-asset-1", "0x01").await;
+    let app = test::init_service(App::new().configure(|cfg| publishers.configure(cfg))).await;
+
+    for expected in [StatusCode::OK, StatusCode::CONFLICT] {
+        let req = test::TestRequest::post().uri("/api/proofs/0x01/revoke")
+            .insert_header((header::AUTHORIZATION, publishers.token(PUBLISHER_DID)))
+            .set_json(revocation_request(PUBLISHER_DID))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), expected);
+    }
+
+    let req = test::TestRequest::post().uri("/api/proofs/0x02/revoke")
+        .insert_header((header::AUTHORIZATION, publishers.token(PUBLISHER_DID)))
+        .set_json(revocation_request(PUBLISHER_DID))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::NOT_FOUND);
+}
+
+#[actix_web::test]
+async fn revoking_flags_the_nft_of_the_asset() {
+    let publishers = Publishers::new().await;
+    publishers.notarize("id-asset-1", "0x01").await;
+    publishers.repo.store_nft_addr("id-asset-1".to_owned(), "0x3333333333333333333333333333333333333333".to_owned(), "default", Some(1)).await.unwrap();
+
+    let asset = proof_revocation::revocable_asset(publishers.repo.as_ref(), "0x01", PUBLISHER_DID).await.unwrap();
+    let revocation = proof_revocation::revoke(publishers.repo.as_ref(), &asset, None, None).await.unwrap();
+    assert_eq!(revocation.nft_action, Some(NftAction::Flagged));
+
+    let asset = publishers.repo.get_asset("id-asset-1".to_owned()).await.unwrap();
+    assert_eq!(asset.revocation.and_then(|revocation| revocation.nft_action), Some(NftAction::Flagged));
+}
+
+#[actix_web::test]
+async fn superseded_proofs_lead_to_the_current_one() {
+    let publishers = Publishers::new().await;
+    publishers.notarize("id-asset-1", "0x01").await;
+    assert_eq!(publishers.current_proof("id-asset-1").await.as_deref(), Some("0x01"));
+
+    publishers.supersede("0x01", "id-asset-2", "0x02").await.unwrap();
+    publishers.supersede("0x02", "id-asset-3", "0x03").await.unwrap();
+
+    let (_, superseded) = publishers.repo.find_asset_by_proof("0x01").await.unwrap().unwrap();
+    let revocation = superseded.revocation.expect("revocation stored");
+    assert_eq!(revocation.state, ProofState::Superseded);
+    assert_eq!(revocation.superseded_by.as_deref(), Some("0x02"));
+
+    for asset_id in ["id-asset-1", "id-asset-2", "id-asset-3"] {
+        assert_eq!(publishers.current_proof(asset_id).await.as_deref(), Some("0x03"), "{asset_id}");
+    }
+
+    // a superseded proof is not superseded again
+    let err = publishers.supersede("0x01", "id-asset-4", "0x04").await.unwrap_err();
+    assert!(matches!(err, TrustServiceError::ProofRevoked(proof_id) if proof_id == "0x01"));
+}
+
+#[actix_web::test]
+async fn revoking_the_latest_version_leaves_no_current_proof() {
+    let publishers = Publishers::new().await;
+    publishers.notarize("id-asset-1", "0x01").await;
+    publishers.supersede("0x01", "id-asset-2", "0x02").await.unwrap();
+
+    let latest = proof_revocation::revocable_asset(publishers.repo.as_ref(), "0x02", PUBLISHER_DID).await.unwrap();
+    proof_revocation::revoke(publishers.repo.as_ref(), &latest, Some("withdrawn".to_owned()), None).await.unwrap();
+
+    assert_eq!(publishers.current_proof("id-asset-1").await, None);
+    assert_eq!(publishers.current_proof("id-asset-2").await, None);
+}
+
+#[actix_web::test]
+async fn proof_of_another_did_is_not_superseded() {
+    let publishers = Publishers::new().await;
+    publishers.notarize("id-asset-1", "0x01").await;
+
+    let err = proof_revocation::revocable_asset(publishers.repo.as_ref(), "0x01", OTHER_DID).await.unwrap_err();
+    assert!(matches!(err, TrustServiceError::AssetNotOwned(did) if did == OTHER_DID));
+    let err = proof_revocation::revocable_asset(publishers.repo.as_ref(), "0x09", PUBLISHER_DID).await.unwrap_err();
+    assert!(matches!(err, TrustServiceError::ProofIdNotFound));
+}
@@ -0,0 +1,151 @@
+// SPDX-FileCopyrightText: 2024 Fondazione LINKS
+//
+// SPDX-License-Identifier: APACHE-2.0
+
+//! Proofs signed by publishers holding their own keys, checked against the request that
+//! submits them. The publishers are `did:key`, which resolve without a node.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use crypto::signatures::ed25519::SecretKey;
+use identity_iota::core::{BaseEncoding, Timestamp};
+use identity_iota::document::CoreDocument;
+use identity_iota::verification::jwu;
+use iota_sdk::client::Client;
+use serde_json::json;
+
+use trust_server::dtos::SignedProofRequest;
+use trust_server::errors::TrustServiceError;
+use trust_server::models::tangle_proof::{SignedProofPayload, TangleProof, SIGNED_PROOF_MAX_AGE_SECS};
+use trust_server::services::did_cache::DidCache;
+use trust_server::services::did_resolver::DidResolver;
+
+const METADATA_DIGEST: &str = "0x1111111111111111111111111111111111111111111111111111111111111111";
+const DATASET_DIGEST: &str = "0x2222222222222222222222222222222222222222222222222222222222222222";
+const ORGANISATION_DID: &str = "did:iota:tst:0x3333333333333333333333333333333333333333333333333333333333333333";
+
+/// Ed25519 key of a `did:key`, signing compact JWS as the wallets of the users do.
+struct DidKey {
+    secret_key: SecretKey,
+    did: String,
+}
+
+impl DidKey {
+    fn generate() -> Self {
+        let secret_key = SecretKey::generate().unwrap();
+        let mut multicodec_key = vec![0xed, 0x01];
+        multicodec_key.extend_from_slice(&secret_key.public_key().to_bytes());
+        let did = format!("did:key:{}", BaseEncoding::encode_multibase(&multicodec_key, None));
+        DidKey { secret_key, did }
+    }
+
+    /// The method of a `did:key` document is named after the multibase key.
+    fn kid(&self) -> String {
+        format!("{}#{}", self.did, self.did.trim_start_matches("did:key:"))
+    }
+
+    fn jws(&self, payload: &[u8]) -> String {
+        let header = json!({ "alg": "EdDSA", "kid": self.kid() });
+        let signing_input = format!("{}.{}", jwu::encode_b64(header.to_string()), jwu::encode_b64(payload));
+        let signature = self.secret_key.sign(signing_input.as_bytes());
+        format!("{}.{}", signing_input, jwu::encode_b64(signature.to_bytes()))
+    }
+
+    async fn document(&self) -> CoreDocument {
+        let client = Client::builder().finish().await.unwrap();
+        let did_resolver = DidResolver::new(client, Arc::new(DidCache::new(Duration::from_secs(60), 16)));
+        did_resolver.resolve(self.did.as_str()).await.unwrap()
+    }
+}
+
+fn request(publisher: &DidKey, asset_id: &str) -> SignedProofRequest {
+    SignedProofRequest {
+        asset_id: asset_id.to_owned(),
+        asset_hash: DATASET_DIGEST.to_owned(),
+        metadata_hash: METADATA_DIGEST.to_owned(),
+        did: publisher.did.clone(),
+        jws: String::new(),
+        organisation: None,
+        supersedes: None,
+    }
+}
+
+/// Signs the payload of `request` as issued `age` seconds ago.
+fn sign(publisher: &DidKey, request: &mut SignedProofRequest, age: i64) {
+    let payload = SignedProofPayload::for_request(request, Timestamp::now_utc().to_unix() - age);
+    request.jws = publisher.jws(&serde_json::to_vec(&payload).unwrap());
+}
+
+fn is_invalid_payload(result: Result<TangleProof, TrustServiceError>) -> bool {
+    matches!(result, Err(TrustServiceError::InvalidProofPayload(_)))
+}
+
+#[actix_web::test]
+async fn signed_payloads_of_the_request_are_accepted() {
+    let publisher = DidKey::generate();
+    let document = publisher.document().await;
+    let mut request = request(&publisher, "id-asset-1");
+    request.organisation = Some(ORGANISATION_DID.to_owned());
+    request.supersedes = Some("0x01".to_owned());
+    sign(&publisher, &mut request, 10);
+
+    let proof = TangleProof::from_signed_jws(&request, &document).unwrap();
+    assert_eq!(proof.did_publisher, publisher.did);
+    assert_eq!(proof.metadata_digest(), METADATA_DIGEST);
+    assert_eq!(proof.dataset_digest(), DATASET_DIGEST);
+    assert_eq!(proof.jws, request.jws);
+    proof.verify(&document).unwrap();
+}
+
+#[actix_web::test]
+async fn published_signatures_cannot_anchor_other_requests() {
+    let publisher = DidKey::generate();
+    let document = publisher.document().await;
+    let mut signed = request(&publisher, "id-asset-1");
+    sign(&publisher, &mut signed, 0);
+
+    // the JWS of a published proof, sent for another asset, organisation or superseded proof
+    let mut replayed = request(&publisher, "id-asset-2");
+    replayed.jws = signed.jws.clone();
+    assert!(is_invalid_payload(TangleProof::from_signed_jws(&replayed, &document)));
+    let mut replayed = request(&publisher, "id-asset-1");
+    replayed.jws = signed.jws.clone();
+    replayed.organisation = Some(ORGANISATION_DID.to_owned());
+    assert!(is_invalid_payload(TangleProof::from_signed_jws(&replayed, &document)));
+    replayed.organisation = None;
+    replayed.supersedes = Some("0x01".to_owned());
+    assert!(is_invalid_payload(TangleProof::from_signed_jws(&replayed, &document)));
+
+    let mut other_digest = request(&publisher, "id-asset-1");
+    other_digest.jws = signed.jws.clone();
+    other_digest.asset_hash = METADATA_DIGEST.to_owned();
+    assert!(is_invalid_payload(TangleProof::from_signed_jws(&other_digest, &document)));
+}
+
+#[actix_web::test]
+async fn stale_and_incomplete_payloads_are_refused() {
+    let publisher = DidKey::generate();
+    let document = publisher.document().await;
+    let max_age = SIGNED_PROOF_MAX_AGE_SECS as i64;
+
+    for age in [max_age + 10, -max_age - 10, i64::MIN / 2] {
+        let mut request = request(&publisher, "id-asset-1");
+        sign(&publisher, &mut request, age);
+        assert!(is_invalid_payload(TangleProof::from_signed_jws(&request, &document)), "{age}");
+    }
+
+    // the payload of the proofs signed by the service binds no asset
+    let mut request = request(&publisher, "id-asset-1");
+    request.jws = publisher.jws(json!({ "metadataHash": METADATA_DIGEST, "datasetHash": DATASET_DIGEST }).to_string().as_bytes());
+    assert!(is_invalid_payload(TangleProof::from_signed_jws(&request, &document)));
+}
+
+#[actix_web::test]
+async fn payloads_signed_by_another_key_are_refused() {
+    let publisher = DidKey::generate();
+    let mut request = request(&publisher, "id-asset-1");
+    sign(&DidKey::generate(), &mut request, 0);
+    let result = TangleProof::from_signed_jws(&request, &publisher.document().await);
+    assert!(matches!(result, Err(TrustServiceError::ProofSignatureNotValid)));
+}
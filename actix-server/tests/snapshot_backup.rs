@@ -0,0 +1,124 @@
+// SPDX-FileCopyrightText: 2024 Fondazione LINKS
+//
+// SPDX-License-Identifier: APACHE-2.0
+
+//! Backup and restore of the snapshots and of the wallet database, in a temporary directory.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use trust_server::services::snapshot_backup::{create_backup, restore_backup, BackupSource};
+
+const PASSPHRASE: &str = "backup passphrase";
+
+struct Sources {
+    root: PathBuf,
+    sources: Vec<BackupSource>,
+}
+
+impl Sources {
+
+    /// A snapshot file and a database directory, named after the test.
+    fn new(test: &str) -> Self {
+        let root = std::env::temp_dir().join(format!("trust-service-backup-{test}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("wallet-db")).unwrap();
+        fs::write(root.join("key-storage.stronghold"), b"snapshot").unwrap();
+        fs::write(root.join("wallet-db").join("000001.sst"), b"table").unwrap();
+        fs::write(root.join("wallet-db").join("CURRENT"), b"MANIFEST-000001").unwrap();
+        let sources = vec![
+            BackupSource { name: "key-storage".to_owned(), path: root.join("key-storage.stronghold") },
+            BackupSource { name: "wallet-db".to_owned(), path: root.join("wallet-db") },
+        ];
+        Sources { root, sources }
+    }
+
+    fn backup(&self) -> PathBuf {
+        let archive = self.root.with_extension("backup");
+        create_backup(&self.sources, PASSPHRASE, &archive).unwrap();
+        archive
+    }
+
+    fn db_files(&self) -> Vec<String> {
+        let mut files: Vec<String> = fs::read_dir(self.root.join("wallet-db")).unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        files.sort();
+        files
+    }
+
+    /// Nothing but the sources is left next to them.
+    fn assert_no_leftovers(&self) {
+        let mut names: Vec<String> = fs::read_dir(&self.root).unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        assert_eq!(names, vec!["key-storage.stronghold", "wallet-db"]);
+    }
+}
+
+impl Drop for Sources {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.root);
+        let _ = fs::remove_file(self.root.with_extension("backup"));
+    }
+}
+
+fn read(path: &Path) -> Vec<u8> {
+    fs::read(path).unwrap()
+}
+
+#[test]
+fn forced_restore_replaces_the_sources_as_a_whole() {
+    let sources = Sources::new("force");
+    let archive = sources.backup();
+
+    fs::write(sources.root.join("key-storage.stronghold"), b"newer snapshot").unwrap();
+    fs::write(sources.root.join("wallet-db").join("000001.sst"), b"newer table").unwrap();
+    fs::write(sources.root.join("wallet-db").join("000002.sst"), b"stale table").unwrap();
+
+    let restored = restore_backup(&sources.sources, PASSPHRASE, &archive, true).unwrap();
+    assert_eq!(restored.len(), 3);
+    assert_eq!(read(&sources.root.join("key-storage.stronghold")), b"snapshot");
+    assert_eq!(read(&sources.root.join("wallet-db").join("000001.sst")), b"table");
+    // files written after the backup do not survive the restore
+    assert_eq!(sources.db_files(), vec!["000001.sst", "CURRENT"]);
+    sources.assert_no_leftovers();
+}
+
+#[test]
+fn restore_without_force_writes_nothing() {
+    let sources = Sources::new("no-force");
+    let archive = sources.backup();
+    fs::remove_file(sources.root.join("key-storage.stronghold")).unwrap();
+    fs::write(sources.root.join("wallet-db").join("000002.sst"), b"newer table").unwrap();
+
+    let err = restore_backup(&sources.sources, PASSPHRASE, &archive, false).unwrap_err();
+    assert!(err.to_string().contains("already exists"), "{err}");
+    // the missing snapshot is not restored alone
+    assert!(!sources.root.join("key-storage.stronghold").exists());
+    assert_eq!(sources.db_files(), vec!["000001.sst", "000002.sst", "CURRENT"]);
+}
+
+#[test]
+fn missing_sources_are_restored() {
+    let sources = Sources::new("missing");
+    let archive = sources.backup();
+    fs::remove_file(sources.root.join("key-storage.stronghold")).unwrap();
+    fs::remove_dir_all(sources.root.join("wallet-db")).unwrap();
+
+    restore_backup(&sources.sources, PASSPHRASE, &archive, false).unwrap();
+    assert_eq!(read(&sources.root.join("key-storage.stronghold")), b"snapshot");
+    assert_eq!(sources.db_files(), vec!["000001.sst", "CURRENT"]);
+    sources.assert_no_leftovers();
+}
+
+#[test]
+fn wrong_passphrase_is_refused() {
+    let sources = Sources::new("passphrase");
+    let archive = sources.backup();
+
+    let err = restore_backup(&sources.sources, "another passphrase", &archive, true).unwrap_err();
+    assert!(err.to_string().contains("wrong passphrase"), "{err}");
+    assert_eq!(read(&sources.root.join("key-storage.stronghold")), b"snapshot");
+}
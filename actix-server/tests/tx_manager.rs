@@ -0,0 +1,271 @@
+// SPDX-FileCopyrightText: 2024 Fondazione LINKS
+//
+// SPDX-License-Identifier: APACHE-2.0
+
+//! Nonces, fee bumps and recovery of the transaction manager against
+//! [anvil](https://book.getfoundry.sh/anvil/), with the records kept by `InMemoryRepository`.
+//! Without `--no-mining` anvil mines each transaction as it arrives, with it the transactions
+//! stay in the pool until the test mines a block or drops them.
+//!
+//! The tests needing anvil are ignored by default, run them with `cargo test -- --ignored` once `anvil` is in the `PATH`.
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use actix_web::rt::time::sleep;
+use ethers::middleware::SignerMiddleware;
+use ethers::providers::{Http, Middleware, Provider};
+use ethers::signers::{LocalWallet, Signer};
+use ethers::types::transaction::eip2718::TypedTransaction;
+use ethers::types::{Address, TransactionRequest, H256, U256};
+use ethers::utils::{Anvil, AnvilInstance};
+use futures_util::future::join_all;
+use serde_json::Value;
+
+use trust_server::errors::TrustServiceError;
+use trust_server::models::evm_transaction::{EvmTransaction, TransactionStatus};
+use trust_server::services::memory_repo::InMemoryRepository;
+use trust_server::services::nft_service::ServiceSigner;
+use trust_server::services::repository::Repository;
+use trust_server::services::tx_manager::TransactionManager;
+
+const RECIPIENT: Address = Address::repeat_byte(0x42);
+const GWEI: u64 = 1_000_000_000;
+const TX_VARIABLES: [&str; 7] = [
+    "GAS_STRATEGY", "GAS_MAX_FEE_GWEI", "GAS_PRIORITY_FEE_GWEI", "GAS_LIMIT_PERCENT",
+    "GAS_BUMP_PERCENT", "TX_RESUBMIT_SECONDS", "TX_MAX_RESUBMISSIONS",
+];
+
+// the tests run in parallel and the manager reads its settings from the environment
+static ENV: Mutex<()> = Mutex::new(());
+
+struct Chain {
+    // the node is killed when dropped
+    _anvil: AnvilInstance,
+    signer: Arc<ServiceSigner>,
+    repo: Arc<InMemoryRepository>,
+}
+
+impl Chain {
+
+    fn start(args: &[&str]) -> Self {
+        let anvil = Anvil::new().args(args.iter().copied()).spawn();
+        let provider = Provider::<Http>::try_from(anvil.endpoint()).unwrap().interval(Duration::from_millis(10));
+        let wallet: LocalWallet = anvil.keys()[0].clone().into();
+        let signer = Arc::new(SignerMiddleware::new(provider, wallet.with_chain_id(anvil.chain_id())));
+        Chain { _anvil: anvil, signer, repo: Arc::new(InMemoryRepository::new()) }
+    }
+
+    /// Manager of the wallet with the given settings, the others are left to their defaults.
+    fn manager(&self, variables: &[(&str, &str)]) -> Arc<TransactionManager> {
+        let _env = ENV.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        for key in TX_VARIABLES {
+            std::env::remove_var(key);
+        }
+        for (key, value) in variables {
+            std::env::set_var(key, value);
+        }
+        Arc::new(TransactionManager::from_env(self.signer.clone(), self.repo.clone(), 1).unwrap())
+    }
+
+    fn provider(&self) -> &Provider<Http> {
+        self.signer.inner()
+    }
+
+    async fn mine(&self) {
+        self.provider().request::<_, Value>("evm_mine", Vec::<Value>::new()).await.unwrap();
+    }
+
+    async fn drop_transaction(&self, hash: &str) {
+        let hash: H256 = hash.parse().unwrap();
+        self.provider().request::<_, Value>("anvil_dropTransaction", [hash]).await.unwrap();
+    }
+
+    async fn transactions(&self, status: TransactionStatus) -> Vec<EvmTransaction> {
+        let from = format!("{:#x}", self.signer.address());
+        let mut transactions = self.repo.get_transactions_by_status(self.signer.signer().chain_id(), from.as_str(), status).await.unwrap();
+        transactions.sort_by_key(|transaction| transaction.nonce);
+        transactions
+    }
+
+    /// Waits until the manager records a pending transaction.
+    async fn pending_transaction(&self) -> EvmTransaction {
+        for _ in 0..100 {
+            if let Some(transaction) = self.transactions(TransactionStatus::Pending).await.pop() {
+                return transaction
+            }
+            sleep(Duration::from_millis(50)).await;
+        }
+        panic!("no pending transaction recorded");
+    }
+
+    async fn gas_price(&self) -> U256 {
+        self.provider().get_gas_price().await.unwrap()
+    }
+}
+
+fn transfer() -> TypedTransaction {
+    TransactionRequest::new().to(RECIPIENT).value(1).into()
+}
+
+fn bump(fee: U256) -> U256 {
+    fee * 115 / 100
+}
+
+#[actix_web::test]
+#[ignore = "needs anvil in the PATH"]
+async fn concurrent_transactions_take_consecutive_nonces() {
+    let chain = Chain::start(&[]);
+    let manager = chain.manager(&[]);
+
+    let receipts = join_all((0..3).map(|_| manager.send("transfer", transfer()))).await;
+    assert!(receipts.iter().all(Result::is_ok), "{receipts:?}");
+
+    let nonces: Vec<u64> = chain.transactions(TransactionStatus::Confirmed).await.iter().map(|transaction| transaction.nonce).collect();
+    assert_eq!(nonces, vec![0, 1, 2]);
+}
+
+#[actix_web::test]
+#[ignore = "needs anvil in the PATH"]
+async fn nonce_of_a_dropped_transaction_is_taken_again() {
+    let chain = Chain::start(&["--no-mining"]);
+    let manager = chain.manager(&[("TX_RESUBMIT_SECONDS", "0"), ("TX_MAX_RESUBMISSIONS", "0")]);
+
+    let err = manager.send("transfer", transfer()).await.unwrap_err();
+    assert!(matches!(err, TrustServiceError::TransactionTimeout(_)), "{err}");
+    let stuck = chain.transactions(TransactionStatus::Stuck).await.pop().unwrap();
+    assert_eq!(stuck.nonce, 0);
+
+    // left with a gap at nonce 0, no later transaction of the wallet could be mined
+    chain.drop_transaction(stuck.hash.as_str()).await;
+    let sending = actix_web::rt::spawn({
+        let manager = manager.clone();
+        async move { manager.send("transfer", transfer()).await }
+    });
+    let pending = chain.pending_transaction().await;
+    assert_eq!(pending.nonce, 0);
+    assert_ne!(pending.hash, stuck.hash);
+
+    chain.mine().await;
+    sending.await.unwrap().unwrap();
+    let confirmed = chain.transactions(TransactionStatus::Confirmed).await;
+    assert_eq!(confirmed.len(), 1);
+    assert_eq!(confirmed[0].nonce, 0);
+}
+
+#[actix_web::test]
+#[ignore = "needs anvil in the PATH"]
+async fn transaction_not_mined_is_replaced_with_higher_fees() {
+    let chain = Chain::start(&["--no-mining"]);
+    let manager = chain.manager(&[("TX_RESUBMIT_SECONDS", "0"), ("TX_MAX_RESUBMISSIONS", "2")]);
+    let gas_price = chain.gas_price().await;
+
+    let err = manager.send("transfer", transfer()).await.unwrap_err();
+    assert!(matches!(err, TrustServiceError::TransactionTimeout(_)), "{err}");
+
+    let stuck = chain.transactions(TransactionStatus::Stuck).await.pop().unwrap();
+    assert_eq!(stuck.nonce, 0);
+    assert_eq!(stuck.replaced.len(), 2);
+    let replacement_price = bump(bump(gas_price));
+    assert_eq!(stuck.max_fee, Some(replacement_price.to_string()));
+    let last_broadcast = chain.provider().get_transaction(stuck.hash.parse::<H256>().unwrap()).await.unwrap().unwrap();
+    assert_eq!(last_broadcast.gas_price, Some(replacement_price));
+
+    // mined once given up, the record is completed on recovery
+    chain.mine().await;
+    chain.manager(&[]).recover().await.unwrap();
+    let confirmed = chain.transactions(TransactionStatus::Confirmed).await;
+    assert_eq!(confirmed.len(), 1);
+    assert_eq!(confirmed[0].hash, stuck.hash);
+}
+
+#[actix_web::test]
+#[ignore = "needs anvil in the PATH"]
+async fn transaction_at_the_fee_cap_is_not_replaced() {
+    // a base fee of 20 gwei leaves less than 10% between the gas price and the next gwei
+    let chain = Chain::start(&["--no-mining", "--base-fee", "20000000000"]);
+    let gas_price = chain.gas_price().await;
+    let cap = (gas_price + GWEI - 1) / GWEI;
+    assert!(cap * GWEI < gas_price * 110 / 100);
+    let manager = chain.manager(&[
+        ("GAS_MAX_FEE_GWEI", cap.to_string().as_str()),
+        ("TX_RESUBMIT_SECONDS", "0"),
+        ("TX_MAX_RESUBMISSIONS", "3"),
+    ]);
+
+    let err = manager.send("transfer", transfer()).await.unwrap_err();
+    assert!(matches!(err, TrustServiceError::TransactionFeeCap(_)), "{err}");
+
+    let stuck = chain.transactions(TransactionStatus::Stuck).await.pop().unwrap();
+    assert!(stuck.replaced.is_empty());
+    assert_eq!(stuck.max_fee, Some(gas_price.to_string()));
+}
+
+#[actix_web::test]
+#[ignore = "needs anvil in the PATH"]
+async fn pending_transactions_are_sent_again_on_recovery() {
+    let chain = Chain::start(&[]);
+    let gas_price = chain.gas_price().await;
+    let from = format!("{:#x}", chain.signer.address());
+    let left_pending = |nonce: u64, hash: H256| EvmTransaction {
+        label: "transfer".to_owned(),
+        chain_id: chain.signer.signer().chain_id(),
+        from: from.clone(),
+        to: format!("{:#x}", RECIPIENT),
+        nonce,
+        hash: format!("{:#x}", hash),
+        replaced: vec![],
+        data: "0x".to_owned(),
+        value: "1".to_owned(),
+        gas: "21000".to_owned(),
+        max_fee: Some(gas_price.to_string()),
+        priority_fee: None,
+        status: TransactionStatus::Pending,
+        block_number: None,
+        created_at: "2024-01-01T00:00:00Z".to_owned(),
+        updated_at: "2024-01-01T00:00:00Z".to_owned(),
+    };
+    // broadcast by a previous run and lost by the node
+    chain.repo.store_transaction(&left_pending(0, H256::repeat_byte(1))).await.unwrap();
+    chain.repo.store_transaction(&left_pending(1, H256::repeat_byte(2))).await.unwrap();
+
+    let manager = chain.manager(&[]);
+    manager.recover().await.unwrap();
+
+    let mut confirmed = chain.transactions(TransactionStatus::Confirmed).await;
+    confirmed.sort_by_key(|transaction| transaction.nonce);
+    assert_eq!(confirmed.len(), 2);
+    assert_eq!(confirmed[0].replaced, vec![format!("{:#x}", H256::repeat_byte(1))]);
+    assert_eq!(confirmed[1].replaced, vec![format!("{:#x}", H256::repeat_byte(2))]);
+    assert!(chain.transactions(TransactionStatus::Stuck).await.is_empty());
+
+    // the next transaction follows the recovered ones
+    manager.send("transfer", transfer()).await.unwrap();
+    let mut nonces: Vec<u64> = chain.transactions(TransactionStatus::Confirmed).await.iter().map(|transaction| transaction.nonce).collect();
+    nonces.sort();
+    assert_eq!(nonces, vec![0, 1, 2]);
+}
+
+#[actix_web::test]
+#[ignore = "needs anvil in the PATH"]
+async fn accounts_are_funded_by_the_wallet_before_their_transactions() {
+    let chain = Chain::start(&[]);
+    let manager = chain.manager(&[]);
+    // a custodial account without funds, with a manager of its own
+    let account = LocalWallet::from_bytes(&[0x11; 32]).unwrap().with_chain_id(chain.signer.signer().chain_id());
+    let account_manager = manager.for_account(Arc::new(SignerMiddleware::new(chain.provider().clone(), account)));
+    assert!(chain.provider().get_balance(account_manager.address(), None).await.unwrap().is_zero());
+
+    let funding = manager.fund(account_manager.address(), &transfer()).await.unwrap();
+    assert!(funding.is_some());
+    // the balance covers the transaction, no further funding
+    assert!(manager.fund(account_manager.address(), &transfer()).await.unwrap().is_none());
+    account_manager.send("transfer", transfer()).await.unwrap();
+
+    let confirmed = chain.transactions(TransactionStatus::Confirmed).await;
+    assert_eq!(confirmed.iter().map(|transaction| transaction.label.as_str()).collect::<Vec<_>>(), vec!["accountFunding"]);
+    let from = format!("{:#x}", account_manager.address());
+    let sent = chain.repo.get_transactions_by_status(chain.signer.signer().chain_id(), from.as_str(), TransactionStatus::Confirmed).await.unwrap();
+    assert_eq!(sent.len(), 1);
+    assert_eq!(sent[0].nonce, 0);
+}